@@ -0,0 +1,69 @@
+//! How long the rules take on mostly boring log lines, with conf.log-sized
+//! rules of which only about one line in twenty matches any. Run it with
+//! `cargo bench --bench prefilter`; the baseline is the same colourizer
+//! without its prefilter, running every rule on every line.
+
+use std::time::{Duration, Instant};
+
+use rgrcat::{Colourizer, Config, StreamState};
+
+
+const LINES: usize = 300_000;
+
+
+fn get_config() -> Config {
+    let mut rules = vec![];
+    let words = ["error", "fail", "denied", "refused", "timeout", "panic", "segfault", "oom", "killed", "abort"];
+    for (index, word) in words.iter().enumerate() {
+        rules.push(format!("regexp=\\b{}\\w*\\b\ncolours=red\n", word));
+        rules.push(format!("regexp=\\[{}\\]\\s+(\\d+)\ncolours=yellow,bold\n", index));
+    }
+    for pattern in [r"\d+\.\d+\.\d+\.\d+:\d+", r"0x[0-9a-f]{12,}", r"\buid=\d{5,}\b", r"#\d{6,}", r"/dev/sd[a-z]\d+",
+                    r"\bCVE-\d+-\d+\b", r"\bretry \d+/\d+\b", r"\b[A-Z]{4}\d{4}\b", r"\(core dumped\)", r"!!+"] {
+        rules.push(format!("regexp={}\ncolours=cyan\n", pattern));
+    }
+    rules.join("-\n").parse().expect("the benchmark conffile is valid")
+}
+
+
+fn get_lines() -> Vec<String> {
+    (0..LINES)
+        .map(|line| if line % 20 == 0 {
+            format!("Jan 12 10:{:02}:{:02} host app[{}]: connection refused by 10.0.{}.{}:8080", line / 60 % 60, line % 60, line, line % 256, line % 200)
+        } else {
+            format!("Jan 12 10:{:02}:{:02} host app[{}]: request {} served in {} ms", line / 60 % 60, line % 60, line, line * 7, line % 97)
+        })
+        .collect()
+}
+
+
+fn time(mut run: impl FnMut() -> usize) -> (Duration, usize) {
+    let started = Instant::now();
+    let result = run();
+    (started.elapsed(), result)
+}
+
+
+/// The bytes of the coloured lines, one by one as `colourize_line` gives
+/// them and as a stream the way the binary runs the rules.
+fn colour(colourizer: &Colourizer, lines: &[String]) -> [(Duration, usize); 2] {
+    let single = time(|| lines.iter().map(|line| colourizer.colourize_line(line).len()).sum());
+    let mut state = StreamState::new();
+    let stream = time(|| lines.iter()
+        .filter_map(|line| colourizer.process_next_line(line, &mut state, None))
+        .map(|processed| processed.render(true).len())
+        .sum());
+    [single, stream]
+}
+
+
+fn main() {
+    let lines = get_lines();
+    let prefiltered = colour(&Colourizer::new(get_config()), &lines);
+    let baseline = colour(&Colourizer::without_prefilter(get_config()), &lines);
+    assert_eq!(prefiltered.map(|(_, bytes)| bytes), baseline.map(|(_, bytes)| bytes), "the prefilter changed the output");
+    println!("{} lines, {} rules", LINES, get_config().rules().len());
+    for (name, (with, without)) in ["colourize_line", "process_next_line"].iter().zip(prefiltered.iter().zip(&baseline)) {
+        println!("{:<18} {:>8.1} ms with the prefilter, {:>8.1} ms without", name, with.0.as_secs_f64() * 1e3, without.0.as_secs_f64() * 1e3);
+    }
+}
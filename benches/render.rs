@@ -0,0 +1,84 @@
+//! How long and how many allocations colouring a stream takes when most
+//! lines match, so the time goes to building the output rather than to the
+//! prefilter. Run it with `cargo bench --bench render`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Cursor};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rgrcat::{Colourizer, Config};
+
+
+const LINES: usize = 200_000;
+
+
+/// The system allocator, counting every allocation.
+struct Counting;
+
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+
+/// Rules like those of conf.ping and conf.log, most of them matching on
+/// every line.
+fn get_config() -> Config {
+    [
+        "regexp=^\\w{3} \\d+ [\\d:]+\ncolours=blue\n",
+        "regexp=\\b\\d+\\.\\d+\\.\\d+\\.\\d+\\b\ncolours=bold magenta\n",
+        "regexp=\\d+ ms\ncolours=green\n",
+        "regexp=\\[(\\d+)\\]\ncolours=default,yellow\n",
+        "regexp=\\b(error|refused)\\b\ncolours=bold red\n",
+        "regexp=request (\\d+)\ncolours=default,cyan\n",
+    ].join("-\n").parse().expect("the benchmark conffile is valid")
+}
+
+
+fn get_input() -> String {
+    (0..LINES)
+        .map(|line| if line % 20 == 0 {
+            format!("Jan 12 10:{:02}:{:02} host app[{}]: connection refused by 10.0.{}.{}\n", line / 60 % 60, line % 60, line, line % 256, line % 200)
+        } else {
+            format!("Jan 12 10:{:02}:{:02} host app[{}]: request {} from 10.0.0.{} served in {} ms\n", line / 60 % 60, line % 60, line, line * 7, line % 256, line % 97)
+        })
+        .collect()
+}
+
+
+fn main() {
+    let colourizer = Colourizer::new(get_config());
+    let input = get_input();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    colourizer.process(Cursor::new(&input), io::sink()).expect("writing to a sink doesn't fail");
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!("{} lines, {} rules, {} MB", LINES, colourizer.config().rules().len(), input.len() / 1_000_000);
+    println!("process: {:>8.1} ms, {:.0} lines/sec, {:.1} allocations a line",
+             elapsed.as_secs_f64() * 1e3, LINES as f64 / elapsed.as_secs_f64(), allocations as f64 / LINES as f64);
+}
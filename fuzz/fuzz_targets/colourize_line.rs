@@ -0,0 +1,39 @@
+//! Any conffile and line must colour without panicking, keep the text of the
+//! line and grow it by a bounded amount of escape sequences.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rgrcat::{Colourizer, Config};
+
+/// The longest style sequence, with RGB colours and every attribute, plus
+/// the reset after it. Every span covers at least one byte.
+const MAX_GROWTH_PER_BYTE: usize = 80;
+
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find('m').map_or(rest.len(), |end| start + end + 1);
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fuzz_target!(|input: (&str, &str)| {
+    let (config_text, line) = input;
+    // Escape characters of the line itself would confuse the stripping below.
+    if line.contains('\x1b') {
+        return;
+    }
+    let config: Config = match config_text.parse() {
+        Ok(config) => config,
+        Err(_) => return
+    };
+    let colourizer = Colourizer::new(config);
+    let result = colourizer.colourize_line(line);
+    assert_eq!(strip_ansi(&result), line);
+    assert!(result.len() <= line.len() * MAX_GROWTH_PER_BYTE + 1);
+});
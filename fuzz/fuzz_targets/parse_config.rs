@@ -0,0 +1,14 @@
+//! Arbitrary bytes as a conffile must parse or fail, never panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rgrcat::Config;
+
+fuzz_target!(|data: &[u8]| {
+    let from_reader = Config::from_reader(data);
+    if let Ok(text) = std::str::from_utf8(data) {
+        let from_str = text.parse::<Config>();
+        assert_eq!(from_reader.is_ok(), from_str.is_ok());
+    }
+});
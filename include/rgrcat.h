@@ -0,0 +1,48 @@
+#ifndef RGRCAT_H
+#define RGRCAT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An opaque colourizer handle.
+typedef struct RgrcatColourizer RgrcatColourizer;
+
+// Create a colourizer from the conffile at `path`.
+//
+// # Safety
+// `path` must be NULL or point to a NUL-terminated string.
+RgrcatColourizer *rgrcat_colourizer_from_path(const char *path);
+
+// Create a colourizer from conffile text.
+//
+// # Safety
+// `config` must be NULL or point to a NUL-terminated string.
+RgrcatColourizer *rgrcat_colourizer_from_string(const char *config);
+
+// Colour `line`, returning a new string to release with `rgrcat_string_free`.
+//
+// # Safety
+// `colourizer` must come from one of the constructors and not be freed yet,
+// `line` must be NULL or point to a NUL-terminated string.
+char *rgrcat_colourize(const RgrcatColourizer *colourizer, const char *line);
+
+// Release a string returned by `rgrcat_colourize`. NULL is ignored.
+//
+// # Safety
+// `value` must be NULL or come from `rgrcat_colourize`, and not be freed yet.
+void rgrcat_string_free(char *value);
+
+// Release a colourizer. NULL is ignored.
+//
+// # Safety
+// `colourizer` must be NULL or come from one of the constructors, and not be
+// freed yet.
+void rgrcat_colourizer_free(RgrcatColourizer *colourizer);
+
+// The last error of the calling thread, NULL without one. The string stays
+// valid until the next failing call on the same thread.
+const char *rgrcat_last_error(void);
+
+#endif  // RGRCAT_H
@@ -0,0 +1,234 @@
+//! Building rules in code instead of writing grcat conffile text.
+
+use std::time::Duration;
+
+use crate::config::{ColourConfig, Config, CountMode, RuleColour};
+use crate::error::ConfigError;
+use crate::style::{LineEnd, Style};
+
+
+/// Builds a single rule, the same as a block of a conffile.
+///
+/// ```
+/// use rgrcat::{Colourizer, ConfigBuilder, RuleBuilder};
+///
+/// let config = ConfigBuilder::new()
+///     .rule(RuleBuilder::new("ERROR").colour_name("red"))
+///     .build()
+///     .unwrap();
+/// assert_eq!(Colourizer::new(config).colourize_line("ERROR"), "\x1b[31mERROR\x1b[0m");
+/// ```
+#[derive(Clone)]
+pub struct RuleBuilder {
+    rule: ColourConfig,
+}
+
+
+impl RuleBuilder {
+    /// Start a rule matching `regexp`.
+    pub fn new(regexp: &str) -> RuleBuilder {
+        let mut rule = ColourConfig::new();
+        rule.regexp = regexp.to_string();
+        rule.colours.clear();
+        RuleBuilder { rule }
+    }
+
+
+    /// Set the name shown in reports.
+    pub fn name(mut self, name: &str) -> RuleBuilder {
+        self.rule.name = name.to_string();
+        self
+    }
+
+
+    /// Add a colour, in `colours=` order.
+    pub fn colour(mut self, colour: RuleColour) -> RuleBuilder {
+        self.rule.colours.push(colour);
+        self
+    }
+
+
+    /// Add a style, in `colours=` order.
+    pub fn style(self, style: Style) -> RuleBuilder {
+        self.colour(RuleColour::Style(style))
+    }
+
+
+    /// Add a colour by its grcat name like `bold` or `on_red`. `beep`
+    /// rings the bell on a match.
+    pub fn colour_name(mut self, name: &str) -> RuleBuilder {
+        self.rule.beep |= name == "beep";
+        self.colour(RuleColour::from_name(name))
+    }
+
+
+    /// Set how often the rule applies, `CountMode::More` by default.
+    pub fn count(mut self, count: CountMode) -> RuleBuilder {
+        self.rule.count = count;
+        self
+    }
+
+
+    /// Drop the input instead of showing it.
+    pub fn skip(mut self, skip: bool) -> RuleBuilder {
+        self.rule.skip = skip;
+        self
+    }
+
+
+    /// Run the rule before those of a higher priority, see `priority=`.
+    pub fn priority(mut self, priority: i32) -> RuleBuilder {
+        self.rule.priority = priority;
+        self
+    }
+
+
+    /// Leave lines longer than `limit` bytes to the other rules.
+    pub fn max_line_length(mut self, limit: usize) -> RuleBuilder {
+        self.rule.max_line_length = Some(limit);
+        self
+    }
+
+
+    /// Flush the output after every line the rule matches.
+    pub fn flush(mut self, flush: bool) -> RuleBuilder {
+        self.rule.flush = flush;
+        self
+    }
+
+
+    /// Set the replacement for the matched text.
+    pub fn replace(mut self, replace: &str) -> RuleBuilder {
+        self.rule.replace = replace.to_string();
+        self
+    }
+
+
+    /// Set whether the rule adds to what the rules before it did, like
+    /// `concat=` does, `true` by default.
+    pub fn concat(mut self, concat: bool) -> RuleBuilder {
+        self.rule.concat = if concat { String::new() } else { "no".to_string() };
+        self
+    }
+
+
+    /// Make the rule a footer block printing `template` at the end of the
+    /// stream.
+    pub fn footer(mut self, template: &str) -> RuleBuilder {
+        self.rule.footer = template.to_string();
+        self
+    }
+
+
+    /// Put `template` in front of the lines the rule matches.
+    pub fn prefix(mut self, template: &str) -> RuleBuilder {
+        self.rule.prefix = template.to_string();
+        self
+    }
+
+
+    /// Match the regexp without regard to case.
+    pub fn ignorecase(mut self, ignorecase: bool) -> RuleBuilder {
+        self.rule.ignorecase = ignorecase;
+        self
+    }
+
+
+    /// Let `^` and `$` of the regexp match at the start and end of every
+    /// line of the text.
+    pub fn multiline(mut self, multiline: bool) -> RuleBuilder {
+        self.rule.multiline = multiline;
+        self
+    }
+
+
+    /// Let `.` of the regexp match a newline too.
+    pub fn dotall(mut self, dotall: bool) -> RuleBuilder {
+        self.rule.dotall = dotall;
+        self
+    }
+
+
+    /// What the colour of the rule does where it ends the line, see `eol=`.
+    pub fn eol(mut self, eol: LineEnd) -> RuleBuilder {
+        self.rule.eol = eol;
+        self
+    }
+
+
+    /// Run the regexp on `lines` lines, the one being coloured and those
+    /// before it, see `window=`.
+    pub fn window(mut self, lines: usize) -> RuleBuilder {
+        self.rule.window = lines;
+        self
+    }
+
+
+    /// Make the match, or its first group, the timestamp of the line.
+    pub fn timestamp(mut self, timestamp: bool) -> RuleBuilder {
+        self.rule.timestamp = timestamp;
+        self
+    }
+
+
+    /// Ring the bell on a match only if the rule didn't match for `quiet`
+    /// before, see `alert_after_quiet=`.
+    pub fn alert_after_quiet(mut self, quiet: Duration) -> RuleBuilder {
+        self.rule.alert_after_quiet = Some(quiet);
+        self
+    }
+
+
+    /// Add a tag, for `--matched-tags`.
+    pub fn tag(mut self, tag: &str) -> RuleBuilder {
+        self.rule.tags.push(tag.to_string());
+        self
+    }
+
+
+    /// Check the rule and build it.
+    pub fn build(self) -> Result<ColourConfig, ConfigError> {
+        let mut rule = self.rule;
+        if let Err(err) = rule.compile_pattern() {
+            return Err(ConfigError::InvalidRule { rule: rule.label().to_string(), message: err.to_string() });
+        }
+        if rule.colours.is_empty() {
+            rule.colours.push(RuleColour::Style(Style::default()));
+        }
+        Ok(rule)
+    }
+}
+
+
+/// Builds a config out of rules, in evaluation order.
+#[derive(Clone, Default)]
+pub struct ConfigBuilder {
+    rules: Vec<RuleBuilder>,
+}
+
+
+impl ConfigBuilder {
+    /// Start a config without any rule.
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+
+    /// Add a rule after the ones added so far.
+    pub fn rule(mut self, rule: RuleBuilder) -> ConfigBuilder {
+        self.rules.push(rule);
+        self
+    }
+
+
+    /// Check every rule and build the config.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut rules = vec![];
+        for (index, rule) in self.rules.into_iter().enumerate() {
+            let mut rule = rule.build()?;
+            rule.index = index;
+            rules.push(rule);
+        }
+        Ok(Config::from_rules(rules))
+    }
+}
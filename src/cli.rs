@@ -0,0 +1,1608 @@
+//! The `rgrcat` command line on top of the library.
+
+use std::borrow::Cow;
+use std::env;
+use std::fs::File;
+use std::path::PathBuf;
+use std::io::{self, BufReader, IsTerminal, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+
+use rgrcat::width::strip_escapes;
+use rgrcat::{find_config, get_config_name_for_command, get_config_path, get_embedded_config_names, get_tag_list, render_ansi, render_html, unescape_bytes, Attributes,
+             ColourDepth, Colourizer, Config, LineReader, Profile, StreamState, Style, StyledSpan};
+
+mod background;
+mod bench;
+mod check;
+mod columns;
+mod debug;
+mod decompress;
+#[cfg(unix)]
+mod daemon;
+mod emit;
+mod error;
+mod explain;
+mod follow;
+mod footer;
+mod html;
+mod interactive;
+mod journal;
+mod list;
+mod log;
+mod loglevels;
+mod multifile;
+mod normalize;
+mod output;
+mod pager;
+mod parallel;
+mod pipeline;
+mod reload;
+mod run;
+mod sample;
+mod stats;
+mod selftest;
+mod shell;
+mod term;
+mod timerange;
+mod unmatched;
+mod watch;
+pub use error::Error;
+use columns::Fit;
+use decompress::ReadError;
+use emit::Emit;
+use follow::FollowReader;
+use footer::FooterCounts;
+use interactive::SharedToggles;
+use journal::JournalOptions;
+use multifile::FileMapping;
+use normalize::Normalization;
+use output::{AtomicFile, SideOutput, SideOutputs, Sink};
+use pager::Pager;
+use reload::Reload;
+use sample::{Sampler, Sampling};
+use stats::Stats;
+use timerange::TimeRange;
+use unmatched::RuleActivity;
+
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ColourMode {
+    Auto,
+    Always,
+    Never,
+}
+
+
+/// What to write to stdout when `--output` takes the rendered output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputTee {
+    Plain,
+    Coloured,
+    None,
+}
+
+
+/// When the output gets flushed.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum Buffering {
+    /// Every line on a terminal, elsewhere whenever the input runs dry.
+    #[default]
+    Auto,
+    /// After every line, for pipelines such as `tail -f | rgrcat | grep`.
+    Line,
+    /// Only once the buffer is full or the input ends.
+    Block,
+}
+
+
+/// How every line is treated on its way through the rules.
+#[derive(Clone, Copy, Default)]
+struct LineOptions {
+    normalization: Normalization,
+    /// Write JSON instead of coloured text.
+    emit: Option<Emit>,
+    /// Cut or wrap lines wider than the width.
+    fit: Option<Fit>,
+    /// The `--width`, overriding the terminal's.
+    width: Option<usize>,
+    sample: Option<Sampling>,
+    /// Read records ending with NUL instead of lines.
+    null_data: bool,
+    /// End every record written with NUL instead of a newline.
+    null_output: bool,
+    /// Drop the lines from outside `--since` and `--until`.
+    time_range: Option<TimeRange>,
+    /// Bring the colours within this depth, `None` leaves them as they are.
+    colour_depth: Option<ColourDepth>,
+    buffering: Buffering,
+    /// Write HTML instead of escape sequences.
+    html: bool,
+    /// Trace which rules styled what on stderr.
+    debug: bool,
+    /// Take the escape sequences out of the input, `--strip-ansi`.
+    strip_ansi: bool,
+    /// Lines longer than this many bytes pass through as they are.
+    max_line_bytes: Option<usize>,
+    /// Colour on this many threads where the lines allow it, 1 for none.
+    jobs: usize,
+    /// Put the name of the input file in front of every line, `-H`.
+    with_filename: bool,
+    /// Write `==> name <==` before every input file, `--headers`.
+    headers: bool,
+}
+
+
+impl LineOptions {
+    fn input_terminator(&self) -> u8 {
+        if self.null_data { b'\0' } else { b'\n' }
+    }
+
+
+    fn output_terminator(&self) -> u8 {
+        if self.null_output { b'\0' } else { b'\n' }
+    }
+
+
+    /// The ending to write for a line read with `ending`, the same when
+    /// it's `\r\n` or `\r` and lines go out as they come in.
+    fn output_ending<'a>(&self, ending: &'a [u8]) -> &'a [u8] {
+        match ending {
+            b"\r\n" | b"\r" if !self.null_output => ending,
+            _ if self.null_output => b"\0",
+            _ => b"\n",
+        }
+    }
+}
+
+
+/// What carries over from line to line, and from one input file to the
+/// next, along with what gets reported about the rules once the input ends.
+#[derive(Default)]
+struct Progress<'a> {
+    state: StreamState,
+    /// The config loaded again on SIGHUP, which then takes over from the
+    /// one the input started with.
+    reload: Option<Reload<'a>>,
+    profile: Option<Profile>,
+    activity: Option<RuleActivity>,
+    stats: Option<Stats>,
+    /// How many lines `--sample` left out, noted along with the others.
+    sampling: Option<String>,
+    footer: Option<FooterCounts>,
+}
+
+
+/// How the config was chosen, for `--dump-config`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConfigFrom {
+    Argument,
+    /// The `GRC_CONF` environment variable.
+    Environment,
+    /// The grc.conf entry for `--command`.
+    GrcConf,
+    Json,
+    /// The text of `--inline-rules`.
+    Inline,
+    /// The text of the `GRC_RULES` environment variable.
+    RulesEnvironment,
+    /// The built-in rules of `--loglevels`.
+    Loglevels,
+    /// No config at all, so the input passes through as it is.
+    Nothing,
+}
+
+
+/// What the command line asks for.
+enum Invocation {
+    Colour(Box<Options>),
+    /// `--help`, print the usage.
+    Help,
+    /// `--version`, print the version.
+    Version,
+    /// `--list-embedded`, print the names of the conffiles built in.
+    ListEmbedded,
+}
+
+
+struct Options {
+    /// The conffile to use, `None` with `--config-json`.
+    config_name: Option<String>,
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    config_from: ConfigFrom,
+    config_json: Option<String>,
+    /// Conffile text given in place of a conffile.
+    inline_rules: Option<String>,
+    /// What set the colour depth: the option, `GRC_COLOR_DEPTH` or `TERM`.
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    colour_depth_from: &'static str,
+    /// `--loglevels`, with the level from which whole lines get tinted.
+    loglevels: Option<Option<loglevels::Level>>,
+    dump_config: bool,
+    /// `--test`, tell what every rule does to the sample lines in `files`,
+    /// or those of stdin, instead of colouring them.
+    test: bool,
+    /// Report on the conffile instead of colouring anything.
+    check_config: bool,
+    /// `--list`, print the conffiles in the search path instead, with
+    /// `--verbose` their number of rules.
+    list: bool,
+    list_verbose: bool,
+    /// Rules picked by name or index, `--rules` also enabling disabled ones.
+    only_rules: Vec<String>,
+    except_rules: Vec<String>,
+    /// The command the output comes from, for the rules with a `command=`.
+    command: Option<String>,
+    /// Searched for conffiles before `GRC_CONFIG_PATH` and the grc search path.
+    config_dirs: Vec<String>,
+    /// Refuse a conffile with problems instead of warning about them.
+    strict: bool,
+    /// Colour through `rgrcat daemon` when it's running.
+    client: bool,
+    socket: Option<String>,
+    colour: Option<ColourMode>,
+    output: Option<String>,
+    output_tee: OutputTee,
+    record: Option<String>,
+    record_append: bool,
+    matched_file: Option<String>,
+    matched_tags: Vec<String>,
+    plain_copy: Option<String>,
+    plain_copy_append: bool,
+    interactive: bool,
+    bench: Option<String>,
+    bench_json: bool,
+    bench_iterations: usize,
+    profile: bool,
+    stats: bool,
+    /// Sniff stdin for compression too, not only input files.
+    decompress: bool,
+    unmatched_report: bool,
+    /// Print the footers even when stdout isn't a terminal, or never.
+    footer: bool,
+    no_footer: bool,
+    /// The HTML page of `--format html` on a dark background.
+    html_dark: bool,
+    /// Write to a pager instead of stdout.
+    pager: bool,
+    line_options: LineOptions,
+    /// Read the systemd journal instead of stdin.
+    journal: Option<JournalOptions>,
+    /// Colour input files with a config each instead of stdin.
+    mapping: Option<FileMapping>,
+    /// The input files after the conffile, read one after the other.
+    /// Without any, and for `-`, stdin gets read.
+    files: Vec<String>,
+    /// `--follow`, keep colouring what's appended to the input file.
+    follow: bool,
+    number_across_files: bool,
+}
+
+
+const USAGE: &str = "rgrcat [--colour auto|always|never | --transforms-only] [--color-depth 16|256|truecolor] [--output FILE [--output-tee plain|coloured|none]] [--record FILE [--record-append]] [--matched-file FILE [--matched-tags TAGS]] [--plain-copy|--tee FILE [--append]] [--interactive] [--bench FILE [--bench-json] [--bench-iterations N]] [--profile] [--stats] [--unmatched-report] [--footer|--no-footer] [--rules NAMES] [--except NAMES] [--command CMD] [--emit json-spans|jsonl-both] [--normalize nfc|nfd|none] [--truncate|--wrap] [--width N] [--max-line-bytes N] [--jobs N] [--sample N [--sample-matched]] [--since TIME] [--until TIME] [--strict-time] [-z|--null-data] [--null-output] [--line-buffered|--block-buffered] [--debug] [--strip-ansi] [--format ansi|html [--html-dark]] [--pager] [--dump-config [--format json]] [--test] [--check-config] [--list [--verbose]] [--config-dir DIR]... [--strict] [--client [--socket PATH]] [-f|--follow] [--journal [--unit UNIT] [--cursor-file FILE] [--journal-template TEMPLATE] [--journal-priority]] conffile|--config-json FILE|--inline-rules TEXT|--loglevels[=LEVEL+] [--decompress] [-H|--with-filename|--no-filename] [--headers] [FILE...]\n       rgrcat [--auto] [--map PATTERN=CONFFILE]... [--default-config CONFFILE] [--number-across-files] [-H|--with-filename|--no-filename] [--headers] FILE...";
+
+
+/// The size of the buffer in front of stdout when it isn't a terminal.
+const STDOUT_BUFFER_SIZE: usize = 64 * 1024;
+/// The size of the buffer of the input, which gets flushed to the output
+/// whenever it runs dry.
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+
+/// The `--max-line-bytes` unless given, far longer than any line of a log
+/// but still rgrcat's to hold several times over.
+const DEFAULT_MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+
+/// The text of `--help`: the usage of rgrcat and its subcommands.
+fn get_help() -> String {
+    let mut usages = vec![USAGE, watch::WATCH_USAGE, run::RUN_USAGE, selftest::SELF_TEST_USAGE, shell::SHELL_USAGE];
+    #[cfg(unix)]
+    usages.push(daemon::DAEMON_USAGE);
+    let usages: Vec<String> = usages.iter().map(|usage| format!("  {}", usage.replace("\n       ", "\n  "))).collect();
+    format!("rgrcat {}, colours input with grcat conffiles.\n\nUsage:\n{}\n\n  rgrcat --help | --version | --list-embedded\n\nPiped input without a conffile passes through unchanged. \
+             The readme explains all options.", env!("CARGO_PKG_VERSION"), usages.join("\n"))
+}
+
+
+/// Write `text` to stdout, where `print!` would panic once the reader went
+/// away.
+fn print_text(text: &str) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(text.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|err| Error::io("<output>", err))
+}
+
+
+/// Write `text` and a newline to stdout, like `print_text`.
+fn print_line(text: &str) -> Result<(), Error> {
+    print_text(&format!("{}\n", text))
+}
+
+
+/// Get the value of option `name` from `--name value` or `--name=value`,
+/// `None` means `arg` is not this option.
+fn get_option_value(arg: &str, name: &str, args: &mut impl Iterator<Item = String>) -> Result<Option<String>, Error> {
+    if arg == name {
+        match args.next() {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::Usage(USAGE.to_string()))
+        }
+    } else {
+        Ok(arg.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(|value| value.to_string()))
+    }
+}
+
+
+fn get_colour_mode(mode: &str) -> Result<ColourMode, Error> {
+    match mode {
+        "auto" => Ok(ColourMode::Auto),
+        "always" => Ok(ColourMode::Always),
+        "never" => Ok(ColourMode::Never),
+        _ => Err(Error::Usage(USAGE.to_string()))
+    }
+}
+
+
+/// Get the options from `GRCAT_OPTIONS` followed by `args`, so the command
+/// line gets the last word. `GRC_CONF` names the conffile when no argument
+/// does, `GRC_RULES` holds its text otherwise, or else grc.conf picks it
+/// for the command. `--config-dir` goes in
+/// front of `GRC_CONFIG_PATH`. `GRC_COMMAND` gives the
+/// command without `--command`, and
+/// `GRC_COLOR_DEPTH` or else `TERM` gives the colour depth. Without
+/// `--colour`, `CLICOLOR_FORCE` forces colour and `NO_COLOR` turns it off,
+/// see https://no-color.org and https://bixense.com/clicolors.
+/// `GRC_FLUSH=1` flushes every line without `--block-buffered`, and
+/// `GRC_DEBUG=1` traces the rules like `--debug`. Without
+/// any config the input is copied as it is, unless it's a terminal.
+fn get_options(args: impl IntoIterator<Item = String>) -> Result<Invocation, Error> {
+    let default_args: Vec<String> = env::var("GRCAT_OPTIONS").unwrap_or_default()
+        .split_whitespace()
+        .map(|arg| arg.to_string())
+        .collect();
+    let grc_conf = env::var("GRC_CONF").ok().filter(|name| !name.is_empty());
+    let grc_rules = env::var("GRC_RULES").ok().filter(|text| !text.is_empty());
+    let grc_command = env::var("GRC_COMMAND").ok().filter(|command| !command.is_empty());
+    let mut options = match get_options_from_args(default_args, args, grc_conf, grc_rules, grc_command)? {
+        Invocation::Colour(options) => options,
+        invocation => return Ok(invocation),
+    };
+    // Without any config piped input passes through, so a broken alias
+    // doesn't eat the output of the command.
+    if options.config_from == ConfigFrom::Nothing && !options.list && io::stdin().is_terminal() {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+    if !options.config_dirs.is_empty() {
+        // Through the variable every lookup sees them, grc.conf's included.
+        let mut dirs: Vec<PathBuf> = options.config_dirs.iter().map(PathBuf::from).collect();
+        dirs.extend(env::var_os("GRC_CONFIG_PATH").iter().flat_map(env::split_paths));
+        let dirs = env::join_paths(dirs).map_err(|err| Error::Usage(format!("{} (--config-dir: {})", USAGE, err)))?;
+        env::set_var("GRC_CONFIG_PATH", dirs);
+    }
+    if options.colour.is_none() {
+        if env::var_os("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0") {
+            options.colour = Some(ColourMode::Always);
+        } else if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            options.colour = Some(ColourMode::Never);
+        }
+    }
+    if options.line_options.buffering == Buffering::Auto && env::var_os("GRC_FLUSH").is_some_and(|value| !value.is_empty() && value != "0") {
+        options.line_options.buffering = Buffering::Line;
+        if let Some(journal) = &mut options.journal {
+            journal.line_buffered = true;
+        }
+    }
+    if env::var_os("GRC_DEBUG").is_some_and(|value| !value.is_empty() && value != "0") {
+        options.line_options.debug = true;
+    }
+    if options.line_options.colour_depth.is_none() {
+        if let Some(name) = env::var("GRC_COLOR_DEPTH").ok().filter(|name| !name.is_empty()) {
+            let depth = ColourDepth::from_name(&name)
+                .ok_or_else(|| Error::Usage(format!("{} (GRC_COLOR_DEPTH must be 16, 256 or truecolor, not {})", USAGE, name)))?;
+            options.line_options.colour_depth = Some(depth);
+            options.colour_depth_from = "GRC_COLOR_DEPTH";
+        } else {
+            let get_var = |name| env::var(name).unwrap_or_default();
+            options.line_options.colour_depth = ColourDepth::detect(&get_var("TERM"), &get_var("COLORTERM"));
+            options.colour_depth_from = "TERM";
+        }
+    }
+    Ok(Invocation::Colour(options))
+}
+
+
+fn get_options_from_args(default_args: Vec<String>, args: impl IntoIterator<Item = String>,
+                         grc_conf: Option<String>, grc_rules: Option<String>, grc_command: Option<String>) -> Result<Invocation, Error> {
+    let default_count = default_args.len();
+    let consumed = std::cell::Cell::new(0);
+    let mut args = default_args.into_iter().chain(args).inspect(|_| consumed.set(consumed.get() + 1));
+    let mut config_name = None;
+    let mut colour = None;
+    let mut output = None;
+    let mut output_tee = OutputTee::None;
+    let mut record = None;
+    let mut record_append = false;
+    let mut matched_file = None;
+    let mut matched_tags = vec![];
+    let mut plain_copy = None;
+    let mut plain_copy_append = false;
+    let mut interactive = false;
+    let mut bench = None;
+    let mut bench_json = false;
+    let mut bench_iterations = 5;
+    let mut profile = false;
+    let mut stats = false;
+    let mut decompress = false;
+    let mut unmatched_report = false;
+    let mut footer = false;
+    let mut no_footer = false;
+    let mut html_dark = false;
+    let mut pager = false;
+    let mut config_json = None;
+    let mut inline_rules = None;
+    let mut loglevels = None;
+    let mut dump_config = false;
+    let mut test = false;
+    let mut check_config = false;
+    let mut list = false;
+    let mut list_verbose = false;
+    let mut follow = false;
+    let mut client = false;
+    let mut socket = None;
+    let mut config_dirs = vec![];
+    let mut strict = false;
+    let mut only_rules = vec![];
+    let mut except_rules = vec![];
+    let mut command = None;
+    let mut line_options = LineOptions { max_line_bytes: Some(DEFAULT_MAX_LINE_BYTES), jobs: 1, ..LineOptions::default() };
+    let mut sample_matched = false;
+    let mut since = None;
+    let mut until = None;
+    let mut strict_time = false;
+    let mut journal = false;
+    let mut auto = false;
+    let mut maps = vec![];
+    let mut default_config = None;
+    let mut number_across_files = false;
+    let mut with_filename = None;
+    let mut positional = vec![];
+    let mut journal_options = JournalOptions {
+        unit: None,
+        follow: false,
+        cursor_file: None,
+        template: journal::DEFAULT_TEMPLATE.to_string(),
+        priority: false,
+        line_buffered: false,
+    };
+
+    while let Some(arg) = args.next() {
+        // After the conffile they'd be input files.
+        if positional.is_empty() && (arg == "--help" || arg == "-h") {
+            return Ok(Invocation::Help);
+        } else if positional.is_empty() && (arg == "--version" || arg == "-V") {
+            return Ok(Invocation::Version);
+        } else if positional.is_empty() && arg == "--list-embedded" {
+            return Ok(Invocation::ListEmbedded);
+        } else if let Some(mode) = get_option_value(&arg, "--colour", &mut args)? {
+            colour = Some(get_colour_mode(&mode)?);
+        } else if let Some(mode) = get_option_value(&arg, "--color", &mut args)? {
+            colour = Some(get_colour_mode(&mode)?);
+        } else if arg == "--transforms-only" {
+            colour = Some(ColourMode::Never);
+        } else if let Some(depth) = get_option_value(&arg, "--colour-depth", &mut args)? {
+            line_options.colour_depth = Some(ColourDepth::from_name(&depth).ok_or_else(|| Error::Usage(USAGE.to_string()))?);
+        } else if let Some(depth) = get_option_value(&arg, "--color-depth", &mut args)? {
+            line_options.colour_depth = Some(ColourDepth::from_name(&depth).ok_or_else(|| Error::Usage(USAGE.to_string()))?);
+        } else if let Some(path) = get_option_value(&arg, "--output", &mut args)? {
+            output = Some(path);
+        } else if let Some(tee) = get_option_value(&arg, "--output-tee", &mut args)? {
+            output_tee = match tee.as_str() {
+                "plain" => OutputTee::Plain,
+                "coloured" | "colored" => OutputTee::Coloured,
+                "none" => OutputTee::None,
+                _ => return Err(Error::Usage(USAGE.to_string()))
+            };
+        } else if let Some(path) = get_option_value(&arg, "--record", &mut args)? {
+            record = Some(path);
+        } else if arg == "--record-append" {
+            record_append = true;
+        } else if let Some(path) = get_option_value(&arg, "--matched-file", &mut args)? {
+            matched_file = Some(path);
+        } else if let Some(tags) = get_option_value(&arg, "--matched-tags", &mut args)? {
+            matched_tags = get_tag_list(&tags);
+        } else if let Some(path) = get_option_value(&arg, "--plain-copy", &mut args)? {
+            plain_copy = Some(path);
+        } else if let Some(path) = get_option_value(&arg, "--tee", &mut args)? {
+            plain_copy = Some(path);
+        } else if arg == "--append" {
+            plain_copy_append = true;
+        } else if arg == "--interactive" {
+            interactive = true;
+        } else if let Some(path) = get_option_value(&arg, "--bench", &mut args)? {
+            bench = Some(path);
+        } else if arg == "--bench-json" {
+            bench_json = true;
+        } else if let Some(iterations) = get_option_value(&arg, "--bench-iterations", &mut args)? {
+            bench_iterations = match iterations.parse() {
+                Ok(iterations) if iterations > 0 => iterations,
+                _ => return Err(Error::Usage(USAGE.to_string()))
+            };
+        } else if arg == "--profile" {
+            profile = true;
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--decompress" {
+            decompress = true;
+        } else if arg == "--unmatched-report" {
+            unmatched_report = true;
+        } else if arg == "--footer" {
+            footer = true;
+        } else if arg == "--no-footer" {
+            no_footer = true;
+        } else if let Some(path) = get_option_value(&arg, "--config-json", &mut args)? {
+            config_json = Some(path);
+        } else if let Some(text) = get_option_value(&arg, "--inline-rules", &mut args)? {
+            inline_rules = Some(text);
+        } else if arg == "--loglevels" {
+            loglevels = Some(None);
+        } else if let Some(level) = arg.strip_prefix("--loglevels=") {
+            let level = loglevels::get_tint_level(level)
+                .ok_or_else(|| Error::Usage(format!("{} (--loglevels takes a level like warn+)", USAGE)))?;
+            loglevels = Some(Some(level));
+        } else if arg == "--dump-config" {
+            dump_config = true;
+        } else if arg == "--test" {
+            test = true;
+        } else if arg == "--debug" {
+            line_options.debug = true;
+        } else if arg == "--strip-ansi" {
+            line_options.strip_ansi = true;
+        } else if arg == "--check-config" {
+            check_config = true;
+        } else if arg == "--list" {
+            list = true;
+        } else if arg == "--verbose" {
+            list_verbose = true;
+        } else if arg == "--client" {
+            client = true;
+        } else if let Some(path) = get_option_value(&arg, "--socket", &mut args)? {
+            socket = Some(path);
+        } else if arg == "--strict" {
+            strict = true;
+        } else if let Some(dir) = get_option_value(&arg, "--config-dir", &mut args)? {
+            config_dirs.push(dir);
+        } else if let Some(names) = get_option_value(&arg, "--rules", &mut args)? {
+            only_rules = get_tag_list(&names);
+        } else if let Some(names) = get_option_value(&arg, "--except", &mut args)? {
+            except_rules = get_tag_list(&names);
+        } else if let Some(command_line) = get_option_value(&arg, "--command", &mut args)? {
+            command = Some(command_line);
+        } else if let Some(name) = get_option_value(&arg, "--emit", &mut args)? {
+            if cfg!(not(feature = "serde")) {
+                return Err(Error::Usage(format!("{} (--emit needs the serde feature)", USAGE)));
+            }
+            line_options.emit = Some(emit::get_emit(&name).ok_or_else(|| Error::Usage(USAGE.to_string()))?);
+        } else if let Some(name) = get_option_value(&arg, "--normalize", &mut args)? {
+            line_options.normalization = normalize::get_normalization(&name).ok_or_else(|| Error::Usage(USAGE.to_string()))?;
+            if cfg!(not(feature = "normalize")) && line_options.normalization != Normalization::None {
+                return Err(Error::Usage(format!("{} (--normalize needs the normalize feature)", USAGE)));
+            }
+        } else if arg == "--truncate" {
+            line_options.fit = Some(Fit::Truncate);
+        } else if arg == "--wrap" {
+            line_options.fit = Some(Fit::Wrap);
+        } else if let Some(bytes) = get_option_value(&arg, "--max-line-bytes", &mut args)? {
+            let bytes: usize = bytes.parse().map_err(|_| Error::Usage(USAGE.to_string()))?;
+            line_options.max_line_bytes = Some(bytes).filter(|bytes| *bytes > 0);
+        } else if let Some(jobs) = get_option_value(&arg, "--jobs", &mut args)? {
+            line_options.jobs = jobs.parse().ok().filter(|jobs| *jobs > 0).ok_or_else(|| Error::Usage(USAGE.to_string()))?;
+        } else if let Some(width) = get_option_value(&arg, "--width", &mut args)? {
+            line_options.width = Some(width.parse().map_err(|_| Error::Usage(USAGE.to_string()))?);
+        } else if let Some(every) = get_option_value(&arg, "--sample", &mut args)? {
+            let every = every.parse().ok().filter(|every| *every > 0).ok_or_else(|| Error::Usage(USAGE.to_string()))?;
+            line_options.sample = Some(Sampling { every, matched: false });
+        } else if arg == "--sample-matched" {
+            sample_matched = true;
+        } else if let Some(time) = get_option_value(&arg, "--since", &mut args)? {
+            since = Some(time);
+        } else if let Some(time) = get_option_value(&arg, "--until", &mut args)? {
+            until = Some(time);
+        } else if arg == "--strict-time" {
+            strict_time = true;
+        } else if arg == "-z" || arg == "--null-data" {
+            line_options.null_data = true;
+            line_options.null_output = true;
+        } else if arg == "--null-output" {
+            line_options.null_output = true;
+        } else if arg == "--journal" {
+            journal = true;
+        } else if let Some(unit) = get_option_value(&arg, "--unit", &mut args)? {
+            journal_options.unit = Some(unit);
+        } else if arg == "-f" || arg == "--follow" {
+            follow = true;
+        } else if let Some(path) = get_option_value(&arg, "--cursor-file", &mut args)? {
+            journal_options.cursor_file = Some(path);
+        } else if let Some(template) = get_option_value(&arg, "--journal-template", &mut args)? {
+            journal_options.template = template;
+        } else if arg == "--journal-priority" {
+            journal_options.priority = true;
+        } else if let Some(format) = get_option_value(&arg, "--format", &mut args)? {
+            match &format[..] {
+                // The only format of --dump-config so far.
+                "json" => {}
+                "ansi" => line_options.html = false,
+                "html" => line_options.html = true,
+                _ => return Err(Error::Usage(USAGE.to_string()))
+            }
+        } else if arg == "--html-dark" {
+            html_dark = true;
+        } else if arg == "--pager" {
+            pager = true;
+        } else if arg == "--auto" {
+            auto = true;
+        } else if let Some(map) = get_option_value(&arg, "--map", &mut args)? {
+            maps.push(multifile::get_map(&map).ok_or_else(|| Error::Usage(USAGE.to_string()))?);
+        } else if let Some(name) = get_option_value(&arg, "--default-config", &mut args)? {
+            default_config = Some(name);
+        } else if arg == "--number-across-files" {
+            number_across_files = true;
+        } else if arg == "-H" || arg == "--with-filename" {
+            with_filename = Some(true);
+        } else if arg == "--no-filename" {
+            with_filename = Some(false);
+        } else if arg == "--headers" {
+            line_options.headers = true;
+        } else if arg == "--line-buffered" {
+            line_options.buffering = Buffering::Line;
+        } else if arg == "--block-buffered" {
+            line_options.buffering = Buffering::Block;
+        } else if consumed.get() <= default_count {
+            return Err(Error::Usage(format!("{} (GRCAT_OPTIONS can only hold options, {} is none)", USAGE, arg)));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    match &mut line_options.sample {
+        // Left out lines can't be written as JSON.
+        Some(_) if line_options.emit.is_some() => {
+            return Err(Error::Usage(format!("{} (--sample can't be combined with --emit)", USAGE)));
+        }
+        Some(sampling) => sampling.matched = sample_matched,
+        None if sample_matched => return Err(Error::Usage(USAGE.to_string())),
+        None => {}
+    }
+
+    if html_dark && !line_options.html {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+    // These would write lines that aren't HTML, or cut through the tags.
+    if line_options.html && (line_options.emit.is_some() || line_options.sample.is_some() || line_options.fit.is_some() || journal) {
+        return Err(Error::Usage(format!("{} (--format html can't be combined with --emit, --sample, --truncate, --wrap or --journal)", USAGE)));
+    }
+
+    if since.is_some() || until.is_some() {
+        let now = timerange::get_now();
+        let get_time = |spec: Option<String>| match spec {
+            Some(spec) => timerange::get_time(&spec, now).map(Some).ok_or_else(|| {
+                Error::Usage(format!("{} ({} is no time like 10:30, 2024-05-01T11:00Z or -15m)", USAGE, spec))
+            }),
+            None => Ok(None),
+        };
+        line_options.time_range = Some(TimeRange { since: get_time(since)?, until: get_time(until)?, strict: strict_time, now });
+    } else if strict_time {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+
+    if list && (!positional.is_empty() || auto || !maps.is_empty()) {
+        return Err(Error::Usage(format!("{} (--list takes no conffile or files)", USAGE)));
+    }
+    if list_verbose && !list {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+
+    // With --auto or --map every argument is an input file.
+    let (mapping, files) = if auto || !maps.is_empty() {
+        if positional.is_empty() || config_json.is_some() || inline_rules.is_some() || loglevels.is_some() {
+            return Err(Error::Usage(USAGE.to_string()));
+        }
+        if journal || interactive || profile || stats || unmatched_report || bench.is_some() || dump_config || test || check_config || client || !only_rules.is_empty() || !except_rules.is_empty() {
+            return Err(Error::Usage(format!("{} (input files can't be combined with these options)", USAGE)));
+        }
+        (Some(FileMapping { maps, auto, default_config, files: positional, number_across_files }), vec![])
+    } else {
+        // The conffile comes first, unless an option gives the config.
+        if config_json.is_none() && inline_rules.is_none() && loglevels.is_none() && !positional.is_empty() {
+            config_name = Some(positional.remove(0));
+        }
+        // Stdin can't give both the conffile and the input.
+        if config_name.as_deref().is_some_and(|config_name| get_config_names(config_name).iter().any(|name| name == "-"))
+            && (positional.is_empty() || positional.iter().any(|file| file == "-")) {
+            return Err(Error::Usage(format!("{} (a conffile from stdin needs input files)", USAGE)));
+        }
+        if default_config.is_some() || (number_across_files && positional.is_empty()) {
+            return Err(Error::Usage(USAGE.to_string()));
+        }
+        (None, positional)
+    };
+    // As with grep, the lines of several files tell which one they're from.
+    let file_count = mapping.as_ref().map_or(files.len(), |mapping| mapping.files.len());
+    line_options.with_filename = with_filename.unwrap_or(file_count > 1);
+    let command = command.or(grc_command);
+    let config_from = if config_json.is_some() {
+        ConfigFrom::Json
+    } else if loglevels.is_some() {
+        ConfigFrom::Loglevels
+    } else if inline_rules.is_some() {
+        ConfigFrom::Inline
+    } else if config_name.is_none() && mapping.is_none() && grc_conf.is_some() {
+        config_name = grc_conf;
+        ConfigFrom::Environment
+    } else if config_name.is_none() && mapping.is_none() && grc_rules.is_some() {
+        inline_rules = grc_rules;
+        ConfigFrom::RulesEnvironment
+    } else if config_name.is_none() && mapping.is_none() && command.is_some() {
+        ConfigFrom::GrcConf
+    } else if config_name.is_none() && mapping.is_none() {
+        ConfigFrom::Nothing
+    } else {
+        ConfigFrom::Argument
+    };
+    let configs = [config_name.is_some() || config_from == ConfigFrom::GrcConf, config_json.is_some(), inline_rules.is_some(), loglevels.is_some()].iter().filter(|given| **given).count();
+    if mapping.is_none() && (configs > 1 || configs == 0 && journal) {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+    // The journal doesn't come with raw input lines for these to work on.
+    if journal && (!files.is_empty() || test || record.is_some() || matched_file.is_some() || plain_copy.is_some() || interactive || profile || stats || unmatched_report
+                   || bench.is_some() || line_options.sample.is_some() || line_options.null_output || line_options.time_range.is_some()) {
+        return Err(Error::Usage(format!("{} (--journal can't be combined with stdin options)", USAGE)));
+    }
+    journal_options.line_buffered = line_options.buffering == Buffering::Line;
+    journal_options.follow = follow;
+    // Without the journal it's the one input file that's followed.
+    let follow = follow && !journal;
+    if follow && (mapping.is_some() || files.len() != 1 || files[0] == "-") {
+        return Err(Error::Usage(format!("{} (--follow takes one input file)", USAGE)));
+    }
+    let journal = if journal { Some(journal_options) } else { None };
+    Ok(Invocation::Colour(Box::new(Options {
+        config_name, config_from, config_json, inline_rules, colour_depth_from: "--color-depth", loglevels, dump_config, test, check_config, list, list_verbose, only_rules, except_rules, command, config_dirs, strict, client, socket, colour, output, output_tee, record,
+        record_append, matched_file, matched_tags, plain_copy, plain_copy_append, interactive, bench, bench_json, bench_iterations, profile, stats, decompress, unmatched_report, footer,
+        no_footer, html_dark, pager, line_options, journal, mapping, files, follow, number_across_files
+    })))
+}
+
+
+/// The `name:` of `--with-filename` in front of the lines of a file, dim
+/// with its own reset where colours go, empty without the option.
+#[derive(Default)]
+struct FilePrefix {
+    coloured: String,
+    plain: String,
+}
+
+
+impl FilePrefix {
+    fn new(name: &str, line_options: LineOptions) -> FilePrefix {
+        // JSON lines tell their file by the `line` they're on, if at all.
+        if !line_options.with_filename || line_options.emit.is_some() {
+            return FilePrefix::default();
+        }
+        let text = format!("{}:", name);
+        let spans = [StyledSpan { range: 0..text.len(), style: Style { attributes: Attributes::DIM, ..Style::default() } }];
+        if line_options.html {
+            FilePrefix { coloured: render_html(&text, &spans), plain: render_html(&text, &[]) }
+        } else {
+            FilePrefix { coloured: render_ansi(&text, &spans), plain: text }
+        }
+    }
+
+
+    fn get(&self, coloured: bool) -> &str {
+        if coloured { &self.coloured } else { &self.plain }
+    }
+}
+
+
+/// Write the `==> name <==` of `--headers` before the file `name`, after
+/// a blank line unless it's the `first`.
+fn write_header(sinks: &mut [Sink], name: &str, first: bool, line_options: LineOptions) -> Result<(), Error> {
+    let terminator = [line_options.output_terminator()];
+    let header = format!("==> {} <==", name);
+    let header = if line_options.html { render_html(&header, &[]) } else { header };
+    for sink in sinks.iter_mut() {
+        let blank: &[u8] = if first { &[] } else { &terminator };
+        sink.writer.write_all(blank)
+            .and_then(|_| sink.writer.write_all(header.as_bytes()))
+            .and_then(|_| sink.writer.write_all(&terminator))
+            .map_err(|err| Error::io("<output>", err))?;
+    }
+    Ok(())
+}
+
+
+/// Write a line left out by `--sample` to every sink as it came in, only
+/// its ending is the one of the output.
+fn write_raw(sinks: &mut [Sink], raw: &[u8], ending: &[u8], prefix: &FilePrefix, line_options: LineOptions) -> io::Result<()> {
+    let raw = &raw[..raw.len() - ending.len()];
+    for sink in sinks.iter_mut() {
+        sink.writer.write_all(prefix.get(sink.coloured).as_bytes())?;
+        sink.writer.write_all(raw)?;
+        sink.writer.write_all(line_options.output_ending(ending))?;
+    }
+    Ok(())
+}
+
+
+fn flush_sinks(sinks: &mut [Sink]) -> Result<(), Error> {
+    for sink in sinks.iter_mut() {
+        sink.writer.flush().map_err(|err| Error::io("<output>", err))?;
+    }
+    Ok(())
+}
+
+
+/// The bytes of `text` to write out, those not UTF-8 in the input back as
+/// they were if it was `escaped`.
+fn get_output_bytes(text: &str, escaped: bool) -> Cow<'_, [u8]> {
+    if escaped {
+        Cow::Owned(unescape_bytes(text))
+    } else {
+        Cow::Borrowed(text.as_bytes())
+    }
+}
+
+
+fn process_input(input: BufReader<impl Read>, colourizer: &Colourizer, sinks: &mut [Sink],
+                 side_outputs: &mut SideOutputs, toggles: Option<&SharedToggles>,
+                 progress: &mut Progress, line_options: LineOptions) -> Result<(), Error> {
+    let mut reader = LineReader::with_terminator(input, line_options.input_terminator());
+    reader.set_max_length(line_options.max_line_bytes);
+    let mut overlong_warned = false;
+    let mut active_colourizer = None;
+    let mut toggles_generation = 0;
+    // A file read after a reload starts with the rules reloaded.
+    let started_with = progress.reload.as_ref().and_then(Reload::colourizer).unwrap_or(colourizer);
+    let mut sampler = line_options.sample.map(|sampling| Sampler::new(sampling, started_with.config().rules()));
+    // The coloured lines are rendered into the same buffer one after the other.
+    let mut result = String::new();
+    // Lines none of the rules touch go out as they came in, unless they get
+    // reported on or changed on the way out.
+    let copied_as_read = line_options.emit.is_none() && line_options.fit.is_none() && line_options.normalization == Normalization::None
+        && !line_options.html && !line_options.debug && progress.profile.is_none() && progress.activity.is_none();
+    let mut strip_ansi = line_options.strip_ansi || started_with.config().strips_ansi();
+    let mut untouched_copied = !strip_ansi && copied_as_read;
+    let any_coloured = sinks.iter().any(|sink| sink.coloured);
+    // Put in front once the rules are done, so `^` still matches where the
+    // line starts.
+    let prefix = FilePrefix::new(progress.state.file(), line_options);
+    // Whether the last piece of an overlong line left it unfinished.
+    let mut mid_line = false;
+    if line_options.jobs > 1 {
+        // Only lines coloured the same whatever came before can be coloured
+        // apart, and only when nothing else is done with them on the way.
+        let parallel = untouched_copied && toggles.is_none() && sampler.is_none() && line_options.time_range.is_none()
+            && line_options.buffering != Buffering::Line && progress.footer.is_none() && !line_options.with_filename
+            && side_outputs.record.is_none() && side_outputs.matched.is_none() && side_outputs.plain.is_none()
+            && started_with.config().is_line_independent();
+        // Not reloaded on SIGHUP, the threads keep the rules they started with.
+        if parallel {
+            let lines = parallel::process(&mut reader, started_with, sinks, line_options.jobs, line_options)?;
+            tracing::debug!(lines, jobs = line_options.jobs, "coloured in parallel");
+            return Ok(());
+        }
+        tracing::debug!("--jobs left out, colouring on one thread");
+    }
+
+    loop {
+        // Before waiting on more input what came so far gets out, so output
+        // written in blocks still keeps up with a slow command.
+        if reader.get_ref().buffer().is_empty() && line_options.buffering != Buffering::Block {
+            flush_sinks(sinks)?;
+            side_outputs.flush();
+        }
+        if !reader.read().map_err(|err| Error::io("<stdin>", err))? {
+            break;
+        }
+        side_outputs.reopen_if_requested();
+        // The rules reloaded take over at the line just read, with nothing
+        // carried over from the old ones.
+        let reloaded = progress.reload.as_mut().is_some_and(Reload::reload_if_requested);
+        let base = progress.reload.as_ref().and_then(Reload::colourizer).unwrap_or(colourizer);
+        if reloaded {
+            progress.state.reset_rules();
+            active_colourizer = None;
+            strip_ansi = line_options.strip_ansi || base.config().strips_ansi();
+            untouched_copied = !strip_ansi && copied_as_read;
+        }
+        // Rules toggled from the keyboard take effect from the next line on.
+        if let Some(toggles) = toggles {
+            if toggles.generation() != toggles_generation || reloaded {
+                toggles_generation = toggles.generation();
+                let rules = toggles.get_active_rules(base.config().rules());
+                tracing::debug!(active = rules.len(), "rules toggled");
+                active_colourizer = Some(Colourizer::new(Config::from_rules(rules)));
+            }
+        }
+        let active = active_colourizer.as_ref().unwrap_or(base);
+        if let Some(stats) = progress.stats.as_mut().filter(|_| !reader.is_continued()) {
+            stats.record_line();
+        }
+        // Record the raw bytes before any skip, replace or colouring.
+        if let Some(record) = &mut side_outputs.record {
+            record.write_line(reader.raw());
+        }
+        // Too long for the rules, the line goes out piece by piece as it came.
+        if reader.is_overlong() {
+            if !overlong_warned {
+                log::warn(&format!("line {} is longer than {} bytes, passed through uncoloured", progress.state.line() + 1,
+                              line_options.max_line_bytes.unwrap_or_default()));
+                overlong_warned = true;
+            }
+            let ending = if reader.is_continued() { &[][..] } else { line_options.output_ending(reader.ending()) };
+            let piece = &reader.raw()[..reader.raw().len() - reader.ending().len()];
+            for sink in sinks.iter_mut() {
+                let prefix = if mid_line { "" } else { prefix.get(sink.coloured) };
+                sink.writer.write_all(prefix.as_bytes())
+                    .and_then(|_| sink.writer.write_all(piece))
+                    .and_then(|_| sink.writer.write_all(ending))
+                    .map_err(|err| Error::io("<output>", err))?;
+            }
+            mid_line = reader.is_continued();
+            if let Some(plain) = &mut side_outputs.plain {
+                plain.write_line(&[piece, ending].concat());
+            }
+            if !reader.is_continued() {
+                progress.state.next_line();
+            }
+            continue;
+        }
+        if let Some(range) = &line_options.time_range {
+            let line = String::from_utf8_lossy(reader.raw());
+            if !range.contains(active.get_timestamp(&line, range.now)) {
+                progress.state.next_line();
+                continue;
+            }
+        }
+        if let Some(sampler) = &mut sampler {
+            if !sampler.is_sampled(reader.raw()) {
+                progress.state.next_line();
+                write_raw(sinks, reader.raw(), reader.ending(), &prefix, line_options).map_err(|err| Error::io("<output>", err))?;
+                continue;
+            }
+        }
+        // Bytes that aren't UTF-8 go back out as they came in.
+        let input = reader.escaped_line();
+        let escaped = matches!(input, Cow::Owned(_));
+        let ending = line_options.output_ending(reader.ending());
+        // Before anything looks at offsets, so spans fit the text written out.
+        let stripped = if strip_ansi { strip_escapes(&input) } else { Cow::Borrowed(&input[..]) };
+        let input = &normalize::normalize(&stripped, line_options.normalization)[..];
+        if untouched_copied && active.is_untouched(input, &progress.state) {
+            progress.state.next_line();
+            if let Some(footer) = &mut progress.footer {
+                footer.record_untouched();
+            }
+            if let Some(plain) = &mut side_outputs.plain {
+                let mut line = reader.raw()[..reader.raw().len() - reader.ending().len()].to_vec();
+                line.extend_from_slice(ending);
+                plain.write_line(&line);
+            }
+            write_raw(sinks, reader.raw(), reader.ending(), &prefix, line_options).map_err(|err| Error::io("<output>", err))?;
+            if ending == b"\r" || line_options.buffering == Buffering::Line {
+                flush_sinks(sinks)?;
+                side_outputs.flush();
+            }
+            continue;
+        }
+        if let Some(footer) = &mut progress.footer {
+            footer.record(active, input);
+        }
+        // The rules run whether or not anything gets coloured, so skips and
+        // rewrites reach the plain outputs as well.
+        let found = progress.profile.as_ref().map_or(0, Profile::get_found);
+        let processed = if line_options.debug {
+            let traced = active.trace_next_line(input, &mut progress.state, progress.profile.as_mut());
+            let spans = traced.as_ref().map(|(_, spans)| &spans[..]);
+            eprintln!("{}", debug::get_trace(progress.state.line(), active.config().rules(), spans));
+            traced.map(|(processed, _)| processed)
+        } else {
+            active.process_next_line(input, &mut progress.state, progress.profile.as_mut())
+        };
+        if let (Some(stats), Some(profile)) = (&mut progress.stats, &progress.profile) {
+            stats.record_processed(processed.is_none(), profile, found);
+        }
+        let processed = match processed {
+            Some(processed) => processed,
+            None => continue
+        };
+        if let Some(activity) = &mut progress.activity {
+            activity.record(active, input);
+        }
+        if let Some(matched) = &mut side_outputs.matched {
+            if active.is_matched_by_tags(input, &side_outputs.matched_tags) {
+                let mut line = get_output_bytes(input, escaped).into_owned();
+                line.extend_from_slice(ending);
+                matched.write_line(&line);
+            }
+        }
+        if let Some(plain) = &mut side_outputs.plain {
+            let mut line = get_output_bytes(&processed.text, escaped).into_owned();
+            line.extend_from_slice(ending);
+            plain.write_line(&line);
+        }
+        result.clear();
+        if let Some(emit) = line_options.emit {
+            result = emit::get_json_line(active, input, emit);
+        } else if line_options.html {
+            result = html::get_line(&processed, true);
+        } else if any_coloured {
+            processed.render_into(true, &mut result);
+        }
+        // JSON lines are never cut.
+        let fit = if line_options.emit.is_some() { None } else { line_options.fit };
+        let width = fit.and_then(|_| columns::get_width(line_options.width));
+        // Without colour HTML still needs its text escaped.
+        let text = if line_options.html { Cow::Owned(html::get_line(&processed, false)) } else { Cow::Borrowed(&processed.text[..]) };
+        for sink in sinks.iter_mut() {
+            let line = if sink.coloured || line_options.emit.is_some() { &result[..] } else { &text[..] };
+            // Part of the line when it's cut or wrapped.
+            let line = match prefix.get(sink.coloured) {
+                "" => Cow::Borrowed(line),
+                prefix => Cow::Owned(format!("{}{}", prefix, line))
+            };
+            let line = &line[..];
+            if escaped {
+                let mut fitted = vec![];
+                columns::write_fitted(&mut fitted, line, fit, width, ending)
+                    .and_then(|_| sink.writer.write_all(&unescape_bytes(&String::from_utf8_lossy(&fitted))))
+                    .map_err(|err| Error::io("<output>", err))?;
+            } else {
+                columns::write_fitted(sink.writer, line, fit, width, ending).map_err(|err| Error::io("<output>", err))?;
+            }
+        }
+        // The bell only goes to where colours go, a terminal most likely.
+        if line_options.emit.is_none() && active.is_alerted(input, &mut progress.state, Instant::now()) {
+            for sink in sinks.iter_mut().filter(|sink| sink.coloured) {
+                sink.writer.write_all(b"\x07").and_then(|_| sink.writer.flush()).map_err(|err| Error::io("<output>", err))?;
+            }
+        }
+        // Progress bars redraw their line after a lone \r, which has to show.
+        if active.is_flushed(input) || ending == b"\r" || line_options.buffering == Buffering::Line {
+            flush_sinks(sinks)?;
+            side_outputs.flush();
+        }
+    }
+    if let Some(sampler) = sampler {
+        progress.sampling = Some(sampler.report());
+    }
+    Ok(())
+}
+
+
+/// Colour the input files of `options` one after the other, stdin for `-`,
+/// each a stream of its own unless `--number-across-files`. Files that
+/// can't be opened are reported and left out, returning whether any was.
+fn process_files(options: &Options, colourizer: &Colourizer, sinks: &mut [Sink], side_outputs: &mut SideOutputs,
+                 toggles: Option<&SharedToggles>, progress: &mut Progress) -> Result<bool, Error> {
+    let mut all_read = true;
+    let error = ReadError::default();
+    let mut headed = false;
+    for path in &options.files {
+        let input: Box<dyn Read> = if path == "-" && options.decompress {
+            decompress::open(io::stdin().lock(), &error).map_err(|err| Error::io("<stdin>", err))?
+        } else if path == "-" {
+            Box::new(io::stdin().lock())
+        } else if options.follow {
+            let interrupted = follow::get_interrupted_flag().map_err(|err| Error::io("signal handler", err))?;
+            Box::new(FollowReader::open(path, interrupted).map_err(|err| Error::io(path, err))?)
+        } else {
+            match File::open(path).and_then(|file| decompress::open(file, &error)) {
+                Ok(input) => input,
+                Err(err) => {
+                    eprintln!("{}", Error::io(path, err));
+                    all_read = false;
+                    continue;
+                }
+            }
+        };
+        if options.line_options.headers {
+            write_header(sinks, path, !headed, options.line_options)?;
+            headed = true;
+        }
+        if options.number_across_files {
+            progress.state.set_file(path);
+        } else {
+            progress.state = StreamState::for_file(path);
+        }
+        process_input(BufReader::with_capacity(INPUT_BUFFER_SIZE, input), colourizer, sinks, side_outputs, toggles, progress, options.line_options)?;
+        // A corrupt archive ends its file, the others still get read.
+        if let Some(err) = error.take() {
+            eprintln!("{}", Error::io(path, err));
+            all_read = false;
+        }
+    }
+    Ok(all_read)
+}
+
+
+/// The conffiles of a conffile argument, several separated like the
+/// directories of `PATH`, as in `conf.log:conf.mylog`.
+fn get_config_names(config_name: &str) -> Vec<String> {
+    env::split_paths(config_name)
+        .filter(|name| !name.as_os_str().is_empty())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect()
+}
+
+
+/// Load the config given on the command line, returning where it came from.
+/// The rules of several conffiles follow each other in the order given,
+/// their paths separated like those of `PATH`. With `strict` any problem
+/// in them is an error.
+fn load_config(options: &Options, strict: bool) -> Result<(String, Config), Error> {
+    if let Some(tint_from) = options.loglevels {
+        return Ok((String::new(), loglevels::get_config(tint_from)));
+    }
+    if let Some(text) = &options.inline_rules {
+        // Written on one line the rules can be separated by `\n`.
+        let text = text.replace("\\n", "\n");
+        return Ok((String::new(), Config::from_reader_named(text.as_bytes(), "<rules>", strict)?));
+    }
+    let load = |config_name: &str| -> Result<(String, Config), Error> {
+        // `-` is the conffile read from stdin, once all others are loaded.
+        let found = get_config_names(config_name).iter()
+            .map(|name| if name == "-" { Ok(None) } else { find_config(name, strict).map(Some) })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut config_paths = vec![];
+        let mut rules = vec![];
+        for found in found {
+            let (config_path, config) = match found {
+                Some(found) => found,
+                None => ("-".to_string(), Config::from_reader_named(io::stdin().lock(), "<stdin>", strict)?)
+            };
+            config_paths.push(config_path);
+            rules.extend_from_slice(config.rules());
+        }
+        // The paths came out of split_paths, unless a search directory has the separator.
+        let config_path = env::join_paths(&config_paths).map_or_else(|_| config_paths.join(":"), |joined| joined.to_string_lossy().into_owned());
+        Ok((config_path, Config::from_rules(rules)))
+    };
+    match (&options.config_name, &options.config_json) {
+        (Some(config_name), _) => load(config_name),
+        (None, Some(path)) => Ok((path.clone(), load_config_json(path)?)),
+        (None, None) => match (options.config_from, &options.command) {
+            (ConfigFrom::Nothing, _) => Ok((String::new(), Config::from_rules(vec![]))),
+            // Without a matching entry the input passes through uncoloured, as with grc.
+            (ConfigFrom::GrcConf, Some(command)) => match get_config_name_for_command(command)? {
+                Some(config_name) => load(&config_name),
+                None => Ok((String::new(), Config::from_rules(vec![]))),
+            },
+            _ => Err(Error::Usage(USAGE.to_string())),
+        }
+    }
+}
+
+
+/// The config of `load_config` with the rules `--only-rules` and
+/// `--except-rules` select, for the command of `--command`.
+fn get_selected_config(options: &Options, strict: bool) -> Result<(String, Config), Error> {
+    let (config_path, mut config) = load_config(options, strict)?;
+    if !options.only_rules.is_empty() || !options.except_rules.is_empty() {
+        config = config.select(&options.only_rules, &options.except_rules);
+    }
+    Ok((config_path, config.for_command(options.command.as_deref())))
+}
+
+
+/// The colourizer of `config` in the colours of the terminal, lighter ones
+/// on a `light` background.
+fn get_colourizer(mut config: Config, options: &Options, light: bool) -> Colourizer {
+    if light {
+        config = config.for_light_background();
+    }
+    if let Some(depth) = options.line_options.colour_depth {
+        config = config.for_colour_depth(depth);
+    }
+    Colourizer::new(config)
+}
+
+
+#[cfg(feature = "serde")]
+fn load_config_json(path: &str) -> Result<Config, Error> {
+    let content = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    let config: Config = serde_json::from_str(&content)
+        .map_err(|err| rgrcat::ConfigError::Parse { path: path.to_string(), line: err.line(), message: err.to_string() })?;
+    // Edited dumps may have the priorities out of order.
+    Ok(Config::from_rules(config.rules().to_vec()))
+}
+
+
+#[cfg(not(feature = "serde"))]
+fn load_config_json(_path: &str) -> Result<Config, Error> {
+    Err(Error::Usage(format!("{} (--config-json needs the serde feature)", USAGE)))
+}
+
+
+/// Print `config` as JSON, along with where it was loaded from and why. The
+/// extra field is ignored when the dump is loaded with `--config-json`.
+#[cfg(feature = "serde")]
+fn dump_config(config: &Config, options: &Options, config_path: &str) -> Result<(), Error> {
+    let to_io_error = |err: serde_json::Error| Error::io("<stdout>", err.into());
+    let selected_by = match options.config_from {
+        ConfigFrom::Argument => "argument",
+        ConfigFrom::Environment => "GRC_CONF",
+        ConfigFrom::GrcConf => "grc.conf",
+        ConfigFrom::Json => "--config-json",
+        ConfigFrom::Inline => "--inline-rules",
+        ConfigFrom::RulesEnvironment => "GRC_RULES",
+        ConfigFrom::Loglevels => "--loglevels",
+        ConfigFrom::Nothing => "nothing",
+    };
+    let mut json = serde_json::to_value(config).map_err(to_io_error)?;
+    // The colours as they get written, after bringing them within the depth.
+    let depth = options.line_options.colour_depth;
+    let effective = depth.map_or_else(|| config.clone(), |depth| config.for_colour_depth(depth));
+    for (rule, effective) in json["rules"].as_array_mut().into_iter().flatten().zip(effective.rules()) {
+        rule["effective_colours"] = serde_json::to_value(effective.colours()).map_err(to_io_error)?;
+        rule["effective_escapes"] = effective.colours().iter().map(get_escape).collect();
+    }
+    json["provenance"] = serde_json::json!({
+        "conffile": options.config_name.as_ref().or(options.config_json.as_ref()),
+        "path": config_path,
+        "selected_by": selected_by,
+        "colour_depth": depth.map(ColourDepth::name),
+        "colour_depth_from": depth.map(|_| options.colour_depth_from),
+    });
+    print_line(&serde_json::to_string_pretty(&json).map_err(to_io_error)?)
+}
+
+
+/// The escape sequence a colour of `colours=` is written as, none for
+/// `previous`, which depends on the text before.
+#[cfg(feature = "serde")]
+fn get_escape(colour: &rgrcat::RuleColour) -> serde_json::Value {
+    use rgrcat::RuleColour;
+    match colour {
+        RuleColour::Style(style) => style.to_ansi().into(),
+        RuleColour::Unchanged => "".into(),
+        RuleColour::Previous => serde_json::Value::Null,
+    }
+}
+
+
+#[cfg(not(feature = "serde"))]
+fn dump_config(_config: &Config, _options: &Options, _config_path: &str) -> Result<(), Error> {
+    Err(Error::Usage(format!("{} (--dump-config needs the serde feature)", USAGE)))
+}
+
+
+/// Whether the footers of the config get printed at the end: on a terminal
+/// unless `--no-footer`, elsewhere only with `--footer`. JSON output never
+/// gets one.
+fn is_footer_shown(options: &Options) -> bool {
+    let on_terminal = options.output.is_none() && io::stdout().is_terminal();
+    !options.no_footer && options.line_options.emit.is_none() && (options.footer || on_terminal)
+}
+
+
+/// Colour stdin through the daemon for `--client`, `None` when there's no
+/// daemon to talk to or the options need the in-process pipeline.
+#[cfg(unix)]
+fn run_client(options: &Options) -> Result<Option<i32>, Error> {
+    // The daemon loads a single conffile.
+    let config_name = match &options.config_name {
+        Some(config_name) if get_config_names(config_name).len() == 1 && config_name != "-" => config_name,
+        _ => return Ok(None)
+    };
+    let plain_pipeline = options.output.is_none() && options.journal.is_none() && options.files.is_empty() && !options.strict
+        && options.only_rules.is_empty() && options.except_rules.is_empty() && options.command.is_none()
+        && options.line_options.emit.is_none() && !options.line_options.html && !options.line_options.debug && !options.line_options.strip_ansi && options.line_options.normalization == Normalization::None
+        && options.line_options.fit.is_none() && options.line_options.sample.is_none() && !options.line_options.null_output && options.line_options.time_range.is_none()
+        && options.record.is_none() && options.matched_file.is_none() && options.plain_copy.is_none()
+        && !options.interactive && options.bench.is_none() && !options.profile && !options.stats && !options.unmatched_report && !options.footer && !options.dump_config
+        // The daemon colours as the conffile says, a forced depth is up to us.
+        && (options.colour_depth_from == "TERM" || options.line_options.colour_depth.is_none())
+        && match options.colour {
+            Some(ColourMode::Never) => false,
+            Some(ColourMode::Auto) => io::stdout().is_terminal(),
+            Some(ColourMode::Always) | None => true,
+        };
+    if !options.client || !plain_pipeline {
+        return Ok(None);
+    }
+    let socket = match options.socket.as_ref().map(std::path::PathBuf::from).or_else(daemon::get_default_socket) {
+        Some(socket) => socket,
+        None => return Ok(None)
+    };
+    let stream = match std::os::unix::net::UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None)
+    };
+    let stdout = io::stdout();
+    let mut stdout = io::BufWriter::new(stdout.lock());
+    // The daemon's search path may not be ours, so it gets the path found.
+    let config_name = get_config_path(config_name).unwrap_or_else(|_| config_name.clone());
+    daemon::colour_through(stream, &daemon::get_absolute_config_name(&config_name), io::stdin().lock(), &mut stdout)?;
+    stdout.flush().map_err(|err| Error::io("<output>", err))?;
+    Ok(Some(0))
+}
+
+
+pub fn run() -> Result<i32, Error> {
+    log::init();
+    term::enable_escape_sequences();
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(|arg| &arg[..]) == Some("watch") {
+        return watch::run(args.skip(1));
+    }
+    if args.peek().map(|arg| &arg[..]) == Some("run") {
+        return run::run(args.skip(1));
+    }
+    if args.peek().map(|arg| &arg[..]) == Some("self-test") {
+        return selftest::run(args.skip(1));
+    }
+    if args.peek().map(|arg| &arg[..]) == Some("shell-init") {
+        return shell::run(args.skip(1));
+    }
+    #[cfg(unix)]
+    if args.peek().map(|arg| &arg[..]) == Some("daemon") {
+        return daemon::run(args.skip(1));
+    }
+
+    let options = match get_options(args)? {
+        Invocation::Colour(options) => *options,
+        Invocation::Help => {
+            print_line(&get_help())?;
+            return Ok(0);
+        }
+        Invocation::Version => {
+            print_line(&format!("rgrcat {}", env!("CARGO_PKG_VERSION")))?;
+            return Ok(0);
+        }
+        Invocation::ListEmbedded => {
+            for name in get_embedded_config_names() {
+                print_line(name)?;
+            }
+            return Ok(0);
+        }
+    };
+    if options.list {
+        for line in list::get_listing(options.list_verbose) {
+            print_line(&line)?;
+        }
+        return Ok(0);
+    }
+    if options.check_config {
+        let config_name = options.config_name.as_ref()
+            .ok_or_else(|| Error::Usage(format!("{} (--check-config needs a conffile)", USAGE)))?;
+        let config_paths = get_config_names(config_name).iter().map(|name| get_config_path(name)).collect::<Result<Vec<String>, _>>()?;
+        let mut code = 0;
+        for config_path in &config_paths {
+            let (report, checked) = check::get_report(config_path)?;
+            for line in &report {
+                print_line(line)?;
+            }
+            code = code.max(checked);
+        }
+        return Ok(code);
+    }
+    #[cfg(unix)]
+    if let Some(code) = run_client(&options)? {
+        return Ok(code);
+    }
+    // Input files get their configs one by one, later on.
+    let (config_path, config) = match options.mapping {
+        Some(_) => (String::new(), Config::from_rules(vec![])),
+        None => get_selected_config(&options, options.strict)?
+    };
+    if options.dump_config {
+        dump_config(&config, &options, &config_path)?;
+        return Ok(0);
+    }
+    // Only ask the terminal when there's something to choose.
+    let light = config.has_light_colours() && options.colour != Some(ColourMode::Never)
+        && background::get_background(io::stdout().is_terminal()) == Some(background::Background::Light);
+    let colourizer = get_colourizer(config, &options, light);
+    if options.test {
+        return explain::run(&colourizer, &options.files);
+    }
+
+    if let Some(bench_path) = &options.bench {
+        let report = bench::run(bench_path, &colourizer, options.bench_iterations)?;
+        report.print(&config_path, options.bench_json)?;
+        return Ok(0);
+    }
+
+    let footer_shown = is_footer_shown(&options);
+    // A terminal sees every line as it comes, elsewhere the lines are
+    // written in large blocks and flushed once the input ends. The side
+    // outputs are flushed along with stdout.
+    let on_terminal = io::stdout().is_terminal();
+    let line_buffered = options.line_options.buffering == Buffering::Line
+        || (on_terminal && options.line_options.buffering != Buffering::Block && !options.pager);
+    let open_side_output = |path: &String, append: bool| {
+        SideOutput::open(path, append, line_buffered).map_err(|err| Error::io(path, err))
+    };
+    let mut side_outputs = SideOutputs {
+        record: options.record.as_ref().map(|path| open_side_output(path, options.record_append)).transpose()?,
+        matched: options.matched_file.as_ref().map(|path| open_side_output(path, true)).transpose()?,
+        matched_tags: options.matched_tags.clone(),
+        plain: options.plain_copy.as_ref().map(|path| open_side_output(path, options.plain_copy_append)).transpose()?,
+        reopen: Arc::new(AtomicBool::new(false)),
+    };
+    // Reopen the matched file and plain copy on SIGHUP so they play well
+    // with log rotation.
+    #[cfg(unix)]
+    if side_outputs.matched.is_some() || side_outputs.plain.is_some() {
+        if let Err(err) = signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&side_outputs.reopen)) {
+            log::warn(&format!("Can not install SIGHUP handler: {}", err));
+        }
+    }
+
+    if options.line_options.fit.is_some() && options.line_options.width.is_none() {
+        if let Err(err) = columns::watch_resizes() {
+            log::warn(&format!("Can not install SIGWINCH handler: {}", err));
+        }
+    }
+
+    // Declared before stdout, so the pager is waited for once its input
+    // is closed.
+    let (pager, paged) = if options.pager {
+        let command = pager::get_command();
+        match Pager::spawn(&command) {
+            Ok((pager, input)) => (Some(pager), Some(input)),
+            Err(err) => {
+                log::warn(&format!("Can not start the pager {}, writing to stdout: {}", command, err));
+                (None, None)
+            }
+        }
+    } else {
+        (None, None)
+    };
+    let mut stdout: Box<dyn Write> = if let Some(input) = paged {
+        // The pager gets what came so far whenever rgrcat waits for input.
+        Box::new(io::BufWriter::with_capacity(STDOUT_BUFFER_SIZE, input))
+    } else if on_terminal && options.line_options.buffering != Buffering::Block {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(io::BufWriter::with_capacity(STDOUT_BUFFER_SIZE, io::stdout().lock()))
+    };
+    let mut output_file = options.output.as_ref()
+        .map(|path| AtomicFile::create(path).map_err(|err| Error::io(path, err)))
+        .transpose()?;
+    let mut sinks = vec![];
+    match &mut output_file {
+        Some(file) => {
+            // An explicit output file is always coloured, as TTY detection makes no sense for it.
+            let coloured = options.colour != Some(ColourMode::Never);
+            sinks.push(Sink { writer: file, coloured });
+            match options.output_tee {
+                OutputTee::Plain => sinks.push(Sink { writer: &mut stdout, coloured: false }),
+                OutputTee::Coloured => sinks.push(Sink { writer: &mut stdout, coloured }),
+                OutputTee::None => {}
+            }
+        }
+        None => {
+            let coloured = match options.colour {
+                Some(ColourMode::Never) => false,
+                // The pager shows the colours on the terminal.
+                Some(ColourMode::Auto) => on_terminal || pager.is_some(),
+                Some(ColourMode::Always) | None => true,
+            };
+            sinks.push(Sink { writer: &mut stdout, coloured });
+        }
+    }
+
+    if let Some(journal) = &options.journal {
+        let code = journal::run(journal, &colourizer, &mut sinks)?;
+        drop(sinks);
+        stdout.flush().map_err(|err| Error::io("<output>", err))?;
+        if let (Some(file), Some(path)) = (output_file, &options.output) {
+            file.commit().map_err(|err| Error::io(path, err))?;
+        }
+        return Ok(code);
+    }
+
+    #[cfg(unix)]
+    let interactive = if options.interactive {
+        Some(interactive::start(colourizer.config().rules()).map_err(|err| Error::io("/dev/tty", err))?)
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if options.interactive {
+        return Err(Error::Usage(format!("{} (--interactive is only supported on unix)", USAGE)));
+    }
+    #[cfg(unix)]
+    let toggles = interactive.as_ref().map(|interactive| &*interactive.toggles);
+    #[cfg(not(unix))]
+    let toggles = None;
+
+    if options.line_options.html {
+        let header = html::get_header(options.html_dark);
+        for sink in sinks.iter_mut() {
+            sink.writer.write_all(header.as_bytes()).map_err(|err| Error::io("<output>", err))?;
+        }
+    }
+
+    // A reload can't change the rules the reports count by, nor those of
+    // the toggles, nor read a conffile from stdin again.
+    let reloaded = options.mapping.is_none() && !options.interactive && !options.profile && !options.stats && !options.unmatched_report && !footer_shown
+        && matches!(options.config_from, ConfigFrom::Argument | ConfigFrom::Environment | ConfigFrom::GrcConf | ConfigFrom::Json)
+        && !options.config_name.as_deref().is_some_and(|config_name| get_config_names(config_name).iter().any(|name| name == "-"));
+    // Broken rules are kept out of a reload, whether or not --strict.
+    let reload = || get_selected_config(&options, true).map(|(_, config)| get_colourizer(config, &options, light));
+    let rules = colourizer.config().rules();
+    let mut progress = Progress {
+        state: StreamState::new(),
+        reload: if reloaded { Reload::new(&reload) } else { None },
+        // --stats gets the numbers of the rules from the profile.
+        profile: if options.profile || options.stats { Some(Profile::new(rules)) } else { None },
+        activity: if options.unmatched_report { Some(RuleActivity::new(rules)) } else { None },
+        stats: if options.stats { Some(Stats::new()) } else { None },
+        sampling: None,
+        footer: if footer_shown { FooterCounts::new(rules) } else { None },
+    };
+    let mut all_read = true;
+    match &options.mapping {
+        Some(mapping) => multifile::process_files(mapping, &mut sinks, &mut side_outputs, options.command.as_deref(), options.line_options)?,
+        None if options.files.is_empty() => {
+            let error = ReadError::default();
+            // Locked once rather than on every read.
+            let input: Box<dyn Read> = if options.decompress {
+                decompress::open(io::stdin().lock(), &error).map_err(|err| Error::io("<stdin>", err))?
+            } else {
+                Box::new(io::stdin().lock())
+            };
+            process_input(BufReader::with_capacity(INPUT_BUFFER_SIZE, input), &colourizer, &mut sinks, &mut side_outputs, toggles, &mut progress, options.line_options)?;
+            if let Some(err) = error.take() {
+                return Err(Error::io("<stdin>", err));
+            }
+        }
+        None => {
+            all_read = process_files(&options, &colourizer, &mut sinks, &mut side_outputs, toggles, &mut progress)?
+        }
+    }
+    if let Some(footer) = &progress.footer {
+        let terminator = options.line_options.output_terminator();
+        for sink in sinks.iter_mut() {
+            for line in footer.render(rules, sink.coloured && !options.line_options.html) {
+                let line = if options.line_options.html { render_html(&line, &[]) } else { line };
+                write!(sink.writer, "{}", line)
+                    .and_then(|_| sink.writer.write_all(&[terminator]))
+                    .map_err(|err| Error::io("<output>", err))?;
+            }
+        }
+    }
+    if options.line_options.html {
+        for sink in sinks.iter_mut() {
+            sink.writer.write_all(html::FOOTER.as_bytes()).map_err(|err| Error::io("<output>", err))?;
+        }
+    }
+    if let Some(profile) = progress.profile.as_ref().filter(|_| options.profile) {
+        eprint!("{}", profile.report());
+    }
+    if let (Some(stats), Some(profile)) = (&progress.stats, &progress.profile) {
+        eprint!("{}", stats.report(rules, profile));
+    }
+    if let Some(activity) = &progress.activity {
+        eprint!("{}", activity.report(rules));
+    }
+    if progress.profile.is_some() || progress.activity.is_some() {
+        eprint!("{}", progress.sampling.unwrap_or_default());
+    }
+    drop(sinks);
+    stdout.flush().map_err(|err| Error::io("<output>", err))?;
+    side_outputs.flush();
+    if let (Some(file), Some(path)) = (output_file, &options.output) {
+        file.commit().map_err(|err| Error::io(path, err))?;
+    }
+    // Like an I/O error, only it didn't stop the other files.
+    Ok(if all_read { 0 } else { 5 })
+}
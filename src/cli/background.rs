@@ -0,0 +1,146 @@
+//! Telling a light terminal background from a dark one, to pick the
+//! `colours_light=` of the rules. The terminal gets asked with an OSC 11
+//! query, and without an answer `COLORFGBG` decides.
+
+use std::env;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+
+/// The background of a colour by its luminance, components from 0 to 1.
+fn get_background_of(red: f64, green: f64, blue: f64) -> Background {
+    let luminance = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+    if luminance > 0.5 { Background::Light } else { Background::Dark }
+}
+
+
+/// A component of an X11 colour like `ffff` or `8`, scaled to 0..=1.
+fn get_component(hex: &str) -> Option<f64> {
+    if hex.is_empty() || hex.len() > 4 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(f64::from(value) / f64::from((1u32 << (4 * hex.len())) - 1))
+}
+
+
+/// Parse the reply to `\x1b]11;?`, such as `\x1b]11;rgb:ffff/ffff/dddd\x07`,
+/// ended by BEL or ST. Anything else in `reply` is ignored.
+fn parse_reply(reply: &[u8]) -> Option<Background> {
+    let reply = String::from_utf8_lossy(reply);
+    let start = reply.find("\x1b]11;rgb:")? + "\x1b]11;rgb:".len();
+    let rest = &reply[start..];
+    let end = rest.find(['\x07', '\x1b'])?;
+    let components: Vec<&str> = rest[..end].split('/').collect();
+    match components[..] {
+        [red, green, blue] => Some(get_background_of(get_component(red)?, get_component(green)?, get_component(blue)?)),
+        _ => None
+    }
+}
+
+
+/// The background a `COLORFGBG` like `15;0` names in its last field, where
+/// the bright colours and white are light.
+fn get_colorfgbg_background(value: &str) -> Option<Background> {
+    match value.rsplit(';').next()?.parse::<u8>().ok()? {
+        7 | 9..=15 => Some(Background::Light),
+        0..=6 | 8 => Some(Background::Dark),
+        _ => None
+    }
+}
+
+
+/// Ask the terminal for its background. A DA1 query goes right after the
+/// OSC 11 one, since every terminal answers that; reading stops at its
+/// reply, or after 100ms, so a terminal that ignores OSC 11 doesn't hold
+/// us up and keys typed afterwards stay unread.
+#[cfg(unix)]
+fn query_terminal() -> Option<Background> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    let fd = tty.as_raw_fd();
+    let mut termios = super::term::save_termios(fd).ok()?;
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 0;
+    termios.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        super::term::restore();
+        return None;
+    }
+
+    let mut reply = vec![];
+    if tty.write_all(b"\x1b]11;?\x07\x1b[c").and_then(|_| tty.flush()).is_ok() {
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let mut buffer = [0u8; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            if remaining.is_zero() || unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as libc::c_int) } <= 0 {
+                break;
+            }
+            match tty.read(&mut buffer) {
+                Ok(n) if n > 0 => reply.extend_from_slice(&buffer[..n]),
+                _ => break
+            }
+            // The DA1 reply is `\x1b[?...c`, the last thing to come.
+            let is_answered = reply.windows(3).position(|window| window == b"\x1b[?")
+                .is_some_and(|start| reply[start..].contains(&b'c'));
+            if is_answered {
+                break;
+            }
+        }
+    }
+    super::term::restore();
+    parse_reply(&reply)
+}
+
+
+#[cfg(not(unix))]
+fn query_terminal() -> Option<Background> {
+    None
+}
+
+
+/// The background of the terminal, asking it when `tty` says stdout is one
+/// and falling back to `COLORFGBG`.
+pub fn get_background(tty: bool) -> Option<Background> {
+    let answer = if tty { query_terminal() } else { None };
+    answer.or_else(|| env::var("COLORFGBG").ok().and_then(|value| get_colorfgbg_background(&value)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        // xterm with a white background, ended by BEL, then the DA1 reply.
+        assert_eq!(parse_reply(b"\x1b]11;rgb:ffff/ffff/ffff\x07\x1b[?64;1;2c"), Some(Background::Light));
+        // A dark background ended by ST, with two digits per component.
+        assert_eq!(parse_reply(b"\x1b]11;rgb:1e/1e/2e\x1b\\"), Some(Background::Dark));
+        // Solarized light, which is light despite the yellow tint.
+        assert_eq!(parse_reply(b"\x1b]11;rgb:fdfd/f6f6/e3e3\x07"), Some(Background::Light));
+        // Only the DA1 reply: the terminal doesn't know OSC 11.
+        assert_eq!(parse_reply(b"\x1b[?1;2c"), None);
+        assert_eq!(parse_reply(b"\x1b]11;rgb:ffff/ffff\x07"), None);
+    }
+
+    #[test]
+    fn test_classification() {
+        assert_eq!(get_background_of(0.0, 0.8, 0.0), Background::Light);
+        assert_eq!(get_background_of(0.0, 0.0, 1.0), Background::Dark);
+        assert_eq!(get_colorfgbg_background("0;15"), Some(Background::Light));
+        assert_eq!(get_colorfgbg_background("15;default;0"), Some(Background::Dark));
+        assert_eq!(get_colorfgbg_background("15;default"), None);
+    }
+}
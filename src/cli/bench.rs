@@ -0,0 +1,103 @@
+use std::fs;
+use std::io::{self, BufReader, Cursor};
+use std::time::{Duration, Instant};
+
+use rgrcat::Colourizer;
+
+use super::error::Error;
+use super::output::{SideOutputs, Sink};
+use super::{print_line, process_input};
+
+
+pub struct BenchReport {
+    file: String,
+    iterations: usize,
+    lines: usize,
+    bytes: usize,
+    total: Duration,
+}
+
+
+impl BenchReport {
+    fn lines_per_second(&self) -> f64 {
+        (self.lines * self.iterations) as f64 / self.total.as_secs_f64()
+    }
+
+
+    fn megabytes_per_second(&self) -> f64 {
+        (self.bytes * self.iterations) as f64 / 1_000_000.0 / self.total.as_secs_f64()
+    }
+
+
+    fn to_json(&self, config_path: &str) -> String {
+        format!(
+            "{{\"file\":{},\"config\":{},\"iterations\":{},\"lines\":{},\"bytes\":{},\"total_seconds\":{},\"lines_per_second\":{},\"megabytes_per_second\":{}}}",
+            escape_json_string(&self.file), escape_json_string(config_path), self.iterations, self.lines, self.bytes,
+            get_json_number(self.total.as_secs_f64()), get_json_number(self.lines_per_second()),
+            get_json_number(self.megabytes_per_second())
+        )
+    }
+
+
+    pub fn print(&self, config_path: &str, json: bool) -> Result<(), Error> {
+        if json {
+            return print_line(&self.to_json(config_path));
+        }
+        print_line(&format!("{} with {}: {} lines, {} bytes, {} iterations", self.file, config_path, self.lines, self.bytes, self.iterations))?;
+        print_line(&format!("total time: {:.3}s", self.total.as_secs_f64()))?;
+        print_line(&format!("lines/sec: {:.0}", self.lines_per_second()))?;
+        print_line(&format!("MB/sec: {:.2}", self.megabytes_per_second()))
+    }
+}
+
+
+/// JSON has no infinity or NaN, which a run too fast to measure could produce.
+fn get_json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+
+pub fn escape_json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+
+/// Process `path` through the normal pipeline, discarding the output. The
+/// first run is a warmup and doesn't count.
+pub fn run(path: &str, colourizer: &Colourizer, iterations: usize) -> Result<BenchReport, Error> {
+    let content = fs::read(path).map_err(|err| Error::io(path, err))?;
+    let mut lines = content.iter().filter(|byte| **byte == b'\n').count();
+    if content.last().is_some_and(|byte| *byte != b'\n') {
+        lines += 1;
+    }
+    let run_once = || {
+        let mut sink = io::sink();
+        let mut sinks = [Sink { writer: &mut sink, coloured: true }];
+        process_input(BufReader::new(Cursor::new(&content)), colourizer, &mut sinks, &mut SideOutputs::default(), None, &mut Default::default(), Default::default())
+    };
+
+    run_once()?;
+    let started = Instant::now();
+    for _ in 0..iterations {
+        run_once()?;
+    }
+    Ok(BenchReport { file: path.to_string(), iterations, lines, bytes: content.len(), total: started.elapsed() })
+}
@@ -0,0 +1,75 @@
+//! `--check-config`: load a conffile without reading any input, and tell
+//! what its rules do and what's wrong with it.
+
+use rgrcat::{ColourConfig, Config, ConfigError, LineEnd, RuleColour};
+
+use super::selftest::get_style_description;
+
+
+/// How a colour of `colours=` resolved, such as `bold red`.
+fn get_colour_description(colour: &RuleColour) -> String {
+    match colour {
+        RuleColour::Unchanged => "unchanged".to_string(),
+        RuleColour::Previous => "previous".to_string(),
+        RuleColour::Style(style) => get_style_description(style),
+    }
+}
+
+
+/// The summary line of a rule: its index and line, regexp, colours and
+/// what it does besides colouring.
+fn get_rule_summary(rule: &ColourConfig) -> String {
+    let colours: Vec<String> = rule.colours().iter().map(get_colour_description).collect();
+    let mut summary = format!("rule {} (line {}): regexp={}", rule.index(), rule.line(), rule.regexp());
+    if !colours.is_empty() {
+        summary.push_str(&format!(" colours={}", colours.join(",")));
+    }
+    summary.push_str(&format!(" count={}", rule.count().name()));
+    if rule.skip() {
+        summary.push_str(" skip=yes");
+    }
+    if rule.eol() != LineEnd::Reset {
+        summary.push_str(&format!(" eol={}", rule.eol().name()));
+    }
+    if rule.window() > 1 {
+        summary.push_str(&format!(" window={}", rule.window()));
+    }
+    if !rule.replace().is_empty() {
+        summary.push_str(&format!(" replace={}", rule.replace()));
+    }
+    summary
+}
+
+
+/// The report on the conffile at `path` and the exit status: 0 when it's
+/// valid, 1 with the problems listed. Only errors reading it are errors.
+pub fn get_report(path: &str) -> Result<(Vec<String>, i32), ConfigError> {
+    let (config, problems) = match Config::check_path(path) {
+        Ok(checked) => checked,
+        Err(err @ (ConfigError::NotFound { .. } | ConfigError::Io { .. })) => return Err(err),
+        // Problems the rest of the conffile can't be read past.
+        Err(err) => return Ok((vec![err.to_string()], 1))
+    };
+    let mut report = vec![format!("{}: {} rules", path, config.rules().len())];
+    report.extend(config.rules().iter().map(get_rule_summary));
+    if problems.is_empty() {
+        return Ok((report, 0));
+    }
+    report.push(format!("{} problems:", problems.len()));
+    report.extend(problems.iter().map(|(line, problem)| format!("line {}: {}", line, problem)));
+    Ok((report, 1))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::Style;
+
+    #[test]
+    fn test_colour_description() {
+        assert_eq!(get_colour_description(&RuleColour::from_names(&["bold".to_string(), "red".to_string()])), "bold red");
+        assert_eq!(get_colour_description(&RuleColour::Style(Style::default())), "default");
+        assert_eq!(get_colour_description(&RuleColour::Previous), "previous");
+    }
+}
@@ -0,0 +1,84 @@
+//! The width output lines get cut or wrapped to with `--truncate` and
+//! `--wrap`. `--width N` sets it, `--width 0` leaves lines alone, and
+//! otherwise it is the width of the terminal, or `COLUMNS` without one.
+
+use std::env;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rgrcat::width::{truncate, wrap};
+
+
+/// What to do with lines wider than the width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    Truncate,
+    Wrap,
+}
+
+
+/// The width found on the terminal, `usize::MAX` before looking for it and
+/// 0 when there's none.
+static DETECTED: AtomicUsize = AtomicUsize::new(usize::MAX);
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+
+#[cfg(unix)]
+fn get_terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some(usize::from(size.ws_col))
+}
+
+
+#[cfg(not(unix))]
+fn get_terminal_width() -> Option<usize> {
+    None
+}
+
+
+fn detect() -> Option<usize> {
+    get_terminal_width().or_else(|| env::var("COLUMNS").ok()?.trim().parse().ok().filter(|&width| width > 0))
+}
+
+
+/// Look at the terminal again whenever it gets resized. Only needed
+/// without an explicit `--width`, which no resize overrides.
+pub fn watch_resizes() -> io::Result<()> {
+    #[cfg(unix)]
+    unsafe {
+        signal_hook::low_level::register(signal_hook::consts::SIGWINCH, || RESIZED.store(true, Ordering::SeqCst))?;
+    }
+    Ok(())
+}
+
+
+/// The width to fit lines into, `None` for no limit. `explicit` is the
+/// value of `--width`.
+pub fn get_width(explicit: Option<usize>) -> Option<usize> {
+    match explicit {
+        Some(0) => None,
+        Some(width) => Some(width),
+        None => {
+            if RESIZED.swap(false, Ordering::SeqCst) || DETECTED.load(Ordering::SeqCst) == usize::MAX {
+                DETECTED.store(detect().unwrap_or(0), Ordering::SeqCst);
+            }
+            Some(DETECTED.load(Ordering::SeqCst)).filter(|&width| width > 0)
+        }
+    }
+}
+
+
+/// Write `line` fitted into `width` as `fit` says and end it with
+/// `ending`. The parts of a wrapped line are separated by newlines.
+pub fn write_fitted(writer: &mut dyn Write, line: &str, fit: Option<Fit>, width: Option<usize>,
+                    ending: &[u8]) -> io::Result<()> {
+    match (fit, width) {
+        (Some(Fit::Truncate), Some(width)) => write!(writer, "{}", truncate(line, width))?,
+        (Some(Fit::Wrap), Some(width)) => write!(writer, "{}", wrap(line, width).join("\n"))?,
+        _ => write!(writer, "{}", line)?
+    }
+    writer.write_all(ending)
+}
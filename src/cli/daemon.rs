@@ -0,0 +1,334 @@
+//! `rgrcat daemon` keeps parsed configs around and colours lines sent over a
+//! unix socket, so short-lived `rgrcat --client` runs skip the startup cost.
+//!
+//! Every message is a frame: a tag byte, a big-endian u32 payload length and
+//! the payload. A client sends a `C` frame with the config name, then for
+//! every line an `L` frame, answered by an `L` frame with the coloured line or
+//! an empty `S` frame when the config skips it. An `E` frame ends the
+//! session, and an `X` frame from the daemon carries an exit code byte and an
+//! error message.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use rgrcat::width::strip_escapes;
+use rgrcat::{find_config, get_config_path, unescape_bytes, Colourizer, LineReader, StreamState};
+
+use super::error::Error;
+use super::log::warn;
+
+
+pub const DAEMON_USAGE: &str = "rgrcat daemon [--socket PATH] [--preload conffile,...]";
+/// Payloads above this are refused instead of allocated.
+const MAX_FRAME_LENGTH: u32 = 64 * 1024 * 1024;
+
+pub const TAG_CONFIG: u8 = b'C';
+pub const TAG_LINE: u8 = b'L';
+pub const TAG_SKIPPED: u8 = b'S';
+pub const TAG_END: u8 = b'E';
+pub const TAG_ERROR: u8 = b'X';
+
+
+pub fn write_frame(writer: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(payload.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+    writer.write_all(&[tag])?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+
+/// Read the next frame, `None` when the peer closed the connection in between.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut tag = [0; 1];
+    if reader.read(&mut tag)? == 0 {
+        return Ok(None);
+    }
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length);
+    if length > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(Some((tag[0], payload)))
+}
+
+
+fn get_invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+
+/// The default socket, in `$XDG_RUNTIME_DIR`.
+pub fn get_default_socket() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("rgrcat.sock"))
+}
+
+
+struct CachedConfig {
+    modified: Option<SystemTime>,
+    colourizer: Arc<Colourizer>,
+}
+
+
+/// Parsed configs by name, parsed again once their file changes.
+#[derive(Default)]
+pub struct ConfigCache {
+    configs: Mutex<HashMap<String, CachedConfig>>,
+}
+
+
+impl ConfigCache {
+    pub fn get(&self, config_name: &str) -> Result<Arc<Colourizer>, Error> {
+        // A conffile built in never changes.
+        let modified = match get_config_path(config_name) {
+            Ok(path) => fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            Err(_) => Some(SystemTime::UNIX_EPOCH)
+        };
+        let mut configs = self.configs.lock().unwrap();
+        if let Some(cached) = configs.get(config_name) {
+            if cached.modified.is_some() && cached.modified == modified {
+                tracing::debug!(config = config_name, "config cache hit");
+                return Ok(Arc::clone(&cached.colourizer));
+            }
+            tracing::debug!(config = config_name, "config changed, reloading");
+        }
+        // Clients with a command colour in-process, so no rule with one applies.
+        let colourizer = Arc::new(Colourizer::new(find_config(config_name, false)?.1.for_command(None)));
+        configs.insert(config_name.to_string(), CachedConfig { modified, colourizer: Arc::clone(&colourizer) });
+        Ok(colourizer)
+    }
+}
+
+
+/// Serve one client until it ends the session or goes away.
+pub fn handle_connection(stream: UnixStream, cache: &ConfigCache) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let colourizer = match read_frame(&mut reader)? {
+        Some((TAG_CONFIG, name)) => {
+            let name = String::from_utf8(name).map_err(|_| get_invalid_data("config name is not UTF-8"))?;
+            cache.get(&name).map_err(|err| {
+                let mut payload = vec![err.exit_code() as u8];
+                payload.extend_from_slice(err.to_string().as_bytes());
+                payload
+            })
+        }
+        Some(_) => return Err(get_invalid_data("expected a config frame")),
+        None => return Ok(())
+    };
+
+    // The lines of a connection are one stream, for blocks, escalation and
+    // line numbers in prefixes.
+    let mut state = StreamState::new();
+    // Keep reading after a config error, so the client gets it as the answer
+    // to its first line instead of a broken pipe.
+    while let Some((tag, payload)) = read_frame(&mut reader)? {
+        match (tag, &colourizer) {
+            (TAG_LINE, Ok(colourizer)) => {
+                let line = std::str::from_utf8(&payload).map_err(|_| get_invalid_data("line is not UTF-8"))?;
+                let line = if colourizer.config().strips_ansi() { strip_escapes(line) } else { Cow::Borrowed(line) };
+                match colourizer.process_next_line(&line, &mut state, None) {
+                    Some(processed) => write_frame(&mut writer, TAG_LINE, processed.render(true).as_bytes())?,
+                    None => write_frame(&mut writer, TAG_SKIPPED, b"")?,
+                }
+            }
+            (TAG_LINE, Err(error)) => write_frame(&mut writer, TAG_ERROR, error)?,
+            (TAG_END, Ok(_)) => break,
+            (TAG_END, Err(error)) => {
+                write_frame(&mut writer, TAG_ERROR, error)?;
+                break;
+            }
+            _ => return Err(get_invalid_data("unexpected frame"))
+        }
+        // The client waits for every answer before sending on.
+        writer.flush()?;
+    }
+    writer.flush()
+}
+
+
+fn get_daemon_error(payload: &[u8]) -> Error {
+    match payload.split_first() {
+        Some((code, message)) => Error::Daemon { code: *code as i32, message: String::from_utf8_lossy(message).into_owned() },
+        None => Error::Daemon { code: 5, message: "daemon error".to_string() }
+    }
+}
+
+
+/// Colour `input` into `output` through the daemon at the other end of `stream`.
+pub fn colour_through(stream: UnixStream, config_name: &str, input: impl BufRead,
+                      output: &mut impl Write) -> Result<(), Error> {
+    let socket_error = |err| Error::io("<daemon>", err);
+    let mut reader = BufReader::new(stream.try_clone().map_err(socket_error)?);
+    let mut writer = BufWriter::new(stream);
+    write_frame(&mut writer, TAG_CONFIG, config_name.as_bytes()).map_err(socket_error)?;
+
+    let mut lines = LineReader::new(input);
+    while lines.read().map_err(|err| Error::io("<stdin>", err))? {
+        let line = lines.escaped_line();
+        write_frame(&mut writer, TAG_LINE, line.as_bytes()).map_err(socket_error)?;
+        writer.flush().map_err(socket_error)?;
+        match read_frame(&mut reader).map_err(socket_error)? {
+            Some((TAG_LINE, coloured)) => {
+                // Bytes that aren't UTF-8 go back out as they came in.
+                let coloured = match line {
+                    Cow::Borrowed(_) => coloured,
+                    Cow::Owned(_) => unescape_bytes(&String::from_utf8_lossy(&coloured)),
+                };
+                let ending = match lines.ending() {
+                    ending @ (b"\r\n" | b"\r") => ending,
+                    _ => b"\n",
+                };
+                output.write_all(&coloured).and_then(|_| output.write_all(ending))
+                    .map_err(|err| Error::io("<output>", err))?;
+            }
+            Some((TAG_SKIPPED, _)) => {}
+            Some((TAG_ERROR, payload)) => return Err(get_daemon_error(&payload)),
+            _ => return Err(socket_error(get_invalid_data("unexpected answer")))
+        }
+    }
+    write_frame(&mut writer, TAG_END, b"").and_then(|_| writer.flush()).map_err(socket_error)?;
+    // Without any input line, a config error only arrives now.
+    match read_frame(&mut reader) {
+        Ok(Some((TAG_ERROR, payload))) => Err(get_daemon_error(&payload)),
+        _ => Ok(())
+    }
+}
+
+
+/// Config names which are paths get sent as absolute paths, as the daemon
+/// may run in another directory.
+pub fn get_absolute_config_name(config_name: &str) -> String {
+    if config_name.contains('/') && !config_name.starts_with('/') {
+        if let Ok(dir) = std::env::current_dir() {
+            return dir.join(config_name).display().to_string();
+        }
+    }
+    config_name.to_string()
+}
+
+
+/// Run `rgrcat daemon`, serving until killed.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<i32, Error> {
+    let usage = || Error::Usage(DAEMON_USAGE.to_string());
+    let mut args = args.into_iter();
+    let mut socket = None;
+    let mut preload = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--socket" {
+            socket = Some(PathBuf::from(args.next().ok_or_else(usage)?));
+        } else if arg == "--preload" {
+            let names = args.next().ok_or_else(usage)?;
+            preload.extend(names.split(',').filter(|name| !name.is_empty()).map(|name| name.to_string()));
+        } else {
+            return Err(usage());
+        }
+    }
+    let socket = socket.or_else(get_default_socket).ok_or_else(usage)?;
+
+    let cache = Arc::new(ConfigCache::default());
+    for name in &preload {
+        cache.get(name)?;
+    }
+    // A socket left behind by a killed daemon would make bind fail.
+    if UnixStream::connect(&socket).is_err() {
+        let _ = fs::remove_file(&socket);
+    }
+    let socket_name = socket.display().to_string();
+    let listener = UnixListener::bind(&socket).map_err(|err| Error::io(&socket_name, err))?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn(&format!("{}: {}", socket_name, err));
+                continue;
+            }
+        };
+        let cache = Arc::clone(&cache);
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &cache) {
+                warn(&format!("rgrcat daemon: {}", err));
+            }
+        });
+    }
+    Ok(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frames() {
+        let mut buffer = vec![];
+        write_frame(&mut buffer, TAG_LINE, b"a line").unwrap();
+        write_frame(&mut buffer, TAG_END, b"").unwrap();
+        assert_eq!(&buffer[..5], b"L\x00\x00\x00\x06");
+
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_frame(&mut reader).unwrap(), Some((TAG_LINE, b"a line".to_vec())));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some((TAG_END, vec![])));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+
+        let mut truncated = Cursor::new(b"L\x00\x00\x00\x09short".to_vec());
+        assert!(read_frame(&mut truncated).is_err());
+        let mut huge = Cursor::new(b"L\xff\xff\xff\xff".to_vec());
+        assert_eq!(read_frame(&mut huge).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_colour_through_socketpair() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("conf.test");
+        fs::write(&path, "regexp=ERROR\ncolours=red\n").unwrap();
+        let cache = Arc::new(ConfigCache::default());
+
+        let serve = |cache: &Arc<ConfigCache>| {
+            let (client, server) = UnixStream::pair().unwrap();
+            let cache = Arc::clone(cache);
+            (client, thread::spawn(move || handle_connection(server, &cache)))
+        };
+
+        let (client, server) = serve(&cache);
+        let mut output = vec![];
+        colour_through(client, path.to_str().unwrap(), Cursor::new("ERROR one\nfine  \n"), &mut output).unwrap();
+        server.join().unwrap().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\x1b[31mERROR\x1b[0m one\nfine  \n");
+
+        // A rewritten config is picked up instead of the cached one.
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "regexp=fine\ncolours=green\n").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified + std::time::Duration::from_secs(1)).unwrap();
+        let (client, server) = serve(&cache);
+        let mut output = vec![];
+        colour_through(client, path.to_str().unwrap(), Cursor::new("fine\n"), &mut output).unwrap();
+        server.join().unwrap().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\x1b[32mfine\x1b[0m\n");
+
+        let (client, server) = serve(&cache);
+        let err = colour_through(client, "/no/such/conffile", Cursor::new("x\n"), &mut vec![]).unwrap_err();
+        server.join().unwrap().unwrap();
+        assert!(matches!(err, Error::Daemon { code: 3, .. }));
+
+        // Rules that depend on the lines before see the whole connection.
+        fs::write(&path, "regexp=^error:\ncolours=red\ncount=block\n-\nregexp=^$\ncount=unblock\n-\nprefix={line}: \n").unwrap();
+        let (client, server) = serve(&cache);
+        let mut output = vec![];
+        colour_through(client, path.to_str().unwrap(), Cursor::new("error: no\n  here\n\nok\n"), &mut output).unwrap();
+        server.join().unwrap().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1: \x1b[31merror: no\x1b[0m\n2: \x1b[31m  here\x1b[0m\n3: \n4: ok\n");
+    }
+}
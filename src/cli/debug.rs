@@ -0,0 +1,46 @@
+//! `--debug` and `GRC_DEBUG`: tell on stderr which rules styled which parts
+//! of every line, as in `line 42: rule 3 (conf.log:12) [10..17] -> red`.
+
+use rgrcat::{ColourConfig, RuleSpan};
+
+use super::selftest::get_style_description;
+
+
+/// The trace of the line numbered `line`, whose parts `spans` styled with
+/// `rules`. `None` spans mean the rules dropped the line.
+pub fn get_trace(line: u64, rules: &[ColourConfig], spans: Option<&[RuleSpan]>) -> String {
+    let spans = match spans {
+        Some([]) => return format!("line {}: no match", line),
+        Some(spans) => spans,
+        None => return format!("line {}: skipped", line)
+    };
+    let parts: Vec<String> = spans.iter()
+        .map(|span| {
+            let rule = rules.iter().find(|rule| rule.index() == span.rule);
+            let location = rule.map_or_else(String::new, |rule| format!(" ({}:{})", rule.source(), rule.line()));
+            format!("rule {}{} [{}..{}] -> {}", span.rule, location, span.range.start, span.range.end, get_style_description(&span.style))
+        })
+        .collect();
+    format!("line {}: {}", line, parts.join(", "))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::{Colourizer, Config, StreamState};
+
+    #[test]
+    fn test_trace() {
+        let config: Config = "regexp=\\d+\ncolours=red\n-\nregexp=^\\w+\ncolours=bold\n-\nregexp=secret\nskip=yes\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        let mut state = StreamState::new();
+        let trace = |line: &str, state: &mut StreamState| {
+            let traced = colourizer.trace_next_line(line, state, None);
+            get_trace(state.line(), colourizer.config().rules(), traced.as_ref().map(|(_, spans)| &spans[..]))
+        };
+        assert_eq!(trace("eth0 mtu 1500", &mut state), "line 1: rule 1 (<string>:4) [0..3] -> bold, rule 0 (<string>:1) [3..4] -> red, rule 0 (<string>:1) [9..13] -> red");
+        assert_eq!(trace("  ", &mut state), "line 2: no match");
+        assert_eq!(trace("secret", &mut state), "line 3: skipped");
+    }
+}
@@ -0,0 +1,115 @@
+//! Compressed input files, told by their first bytes and read as if they
+//! weren't, so rotated logs need no `zcat` in front.
+
+use std::cell::RefCell;
+use std::io::{self, Cursor, Read};
+use std::rc::Rc;
+
+
+/// Where the error that cut a decompressed input short ends up, since the
+/// line loop takes a read error for the end of all input.
+pub type ReadError = Rc<RefCell<Option<io::Error>>>;
+
+
+/// The first bytes of the formats, gzip's first.
+const MAGIC: [(&str, &[u8]); 3] = [("gzip", &[0x1f, 0x8b]), ("zstd", &[0x28, 0xb5, 0x2f, 0xfd]), ("bzip2", b"BZh")];
+
+
+/// The longest of `MAGIC`.
+const MAGIC_LEN: usize = 4;
+
+
+/// A decompressed input which ends at an error, leaving it in `error`.
+struct Decoded<R> {
+    inner: R,
+    error: ReadError,
+}
+
+
+impl<R: Read> Read for Decoded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Err(err) if err.kind() != io::ErrorKind::Interrupted => {
+                *self.error.borrow_mut() = Some(err);
+                Ok(0)
+            }
+            result => result
+        }
+    }
+}
+
+
+/// Up to `MAGIC_LEN` bytes from the start of `input`, fewer only at its end.
+fn read_start(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut start = vec![0; MAGIC_LEN];
+    let mut len = 0;
+    while len < MAGIC_LEN {
+        match input.read(&mut start[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err)
+        }
+    }
+    start.truncate(len);
+    Ok(start)
+}
+
+
+#[cfg(feature = "gzip")]
+fn get_gzip_decoder<'a>(input: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    // Several members one after the other read as one, as with zcat.
+    Ok(Box::new(flate2::read::MultiGzDecoder::new(input)))
+}
+
+
+#[cfg(not(feature = "gzip"))]
+fn get_gzip_decoder<'a>(_input: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "gzip-compressed input needs the gzip feature, decompress it first"))
+}
+
+
+/// `input` decompressed if its first bytes are those of gzip, as it is if
+/// they're none of `MAGIC`. An error while decompressing ends it and goes
+/// into `error`. zstd and bzip2 can't be read and give an error at once.
+pub fn open<'a>(mut input: impl Read + 'a, error: &ReadError) -> io::Result<Box<dyn Read + 'a>> {
+    let start = read_start(&mut input)?;
+    let format = MAGIC.iter().find(|(_, magic)| start.starts_with(magic)).map(|(format, _)| *format);
+    let input = Cursor::new(start).chain(input);
+    match format {
+        None => Ok(Box::new(input)),
+        Some("gzip") => Ok(Box::new(Decoded { inner: get_gzip_decoder(input)?, error: error.clone() })),
+        Some(format) => Err(io::Error::new(io::ErrorKind::Unsupported, format!("{}-compressed input can't be read, decompress it first", format)))
+    }
+}
+
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn read(input: &[u8]) -> (io::Result<String>, Option<io::Error>) {
+        let error = ReadError::default();
+        let text = open(input, &error).and_then(|mut reader| {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).map(|_| text)
+        });
+        (text, error.take())
+    }
+
+    #[test]
+    fn test_open() {
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(b"one\ntwo\n").unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(read(&gzip).0.unwrap(), "one\ntwo\n");
+        assert_eq!(read(b"plain\n").0.unwrap(), "plain\n");
+        assert_eq!(read(b"a").0.unwrap(), "a");
+
+        // A cut archive gives what came before the cut, then the error.
+        let (text, error) = read(&gzip[..gzip.len() - 6]);
+        assert!(text.is_ok() && error.is_some());
+        assert!(read(&[0x28, 0xb5, 0x2f, 0xfd, 0]).0.unwrap_err().to_string().starts_with("zstd-compressed"));
+    }
+}
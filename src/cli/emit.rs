@@ -0,0 +1,53 @@
+//! `--emit json-spans` writes every line as a JSON object of its text and
+//! styled spans instead of coloured text, for editors and other tools.
+
+use rgrcat::Colourizer;
+
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The text and its spans.
+    JsonSpans,
+    /// The same plus the line rendered with escape sequences.
+    JsonlBoth,
+}
+
+
+pub fn get_emit(name: &str) -> Option<Emit> {
+    match name {
+        "json-spans" => Some(Emit::JsonSpans),
+        "jsonl-both" => Some(Emit::JsonlBoth),
+        _ => None
+    }
+}
+
+
+/// The JSON object of `line`, on a single line. Span offsets are in bytes.
+#[cfg(feature = "serde")]
+pub fn get_json_line(colourizer: &Colourizer, line: &str, emit: Emit) -> String {
+    use serde_json::{json, Value};
+
+    // The spans are those of the text as `replace=` rewrote it.
+    let text = colourizer.process_line(line).text;
+    let spans = colourizer.rule_spans(line);
+    let json_spans: Vec<Value> = spans.iter().map(|span| json!({
+        "start": span.range.start,
+        "end": span.range.end,
+        "rule": span.rule,
+        "fg": span.style.fg,
+        "bg": span.style.bg,
+        "attrs": span.style.attributes,
+    })).collect();
+    let mut object = json!({ "text": text, "spans": json_spans });
+    if emit == Emit::JsonlBoth {
+        object["ansi"] = Value::String(colourizer.colourize_line(line));
+    }
+    // Serializing a Value can't fail, and escapes control characters.
+    object.to_string()
+}
+
+
+#[cfg(not(feature = "serde"))]
+pub fn get_json_line(_colourizer: &Colourizer, _line: &str, _emit: Emit) -> String {
+    unreachable!("--emit is refused without the serde feature")
+}
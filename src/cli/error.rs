@@ -0,0 +1,56 @@
+use std::io;
+use thiserror::Error;
+
+use rgrcat::ConfigError;
+
+
+/// Everything that can make rgrcat give up. Each variant maps to a documented
+/// exit code, so wrappers can tell the failures apart:
+///
+/// | code | meaning                                  |
+/// |------|------------------------------------------|
+/// | 2    | wrong usage                              |
+/// | 3    | config file not found in the search path |
+/// | 4    | config file can not be parsed            |
+/// | 5    | I/O error                                |
+/// | 141  | the reader of the output went away       |
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("You are not supposed to call rgrcat directly, but the usage is: {0}")]
+    Usage(String),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// An error reported by `rgrcat daemon`, with the exit code it maps to.
+    #[error("{message}")]
+    Daemon { code: i32, message: String },
+    #[error("{path}: {source}")]
+    Io { path: String, source: io::Error },
+}
+
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::Config(ConfigError::NotFound { .. }) => 3,
+            Error::Config(ConfigError::Parse { .. } | ConfigError::Invalid { .. } | ConfigError::InvalidRule { .. }) => 4,
+            Error::Config(ConfigError::Io { .. }) => 5,
+            Error::Daemon { code, .. } => *code,
+            Error::Io { .. } if self.is_broken_pipe() => 141,
+            Error::Io { .. } => 5,
+        }
+    }
+
+
+    /// Whether the output was closed early, as `| head` does, which is no
+    /// failure worth a message.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Error::Io { source, .. } if source.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+
+    pub fn io(path: &str, source: io::Error) -> Error {
+        Error::Io { path: path.to_string(), source }
+    }
+}
+
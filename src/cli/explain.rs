@@ -0,0 +1,156 @@
+//! `--test`: how the rules go about a sample line, rule by rule, for
+//! writing a conffile without squinting at escape sequences.
+
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use rgrcat::width::get_width;
+use rgrcat::{Colourizer, ColourConfig, GroupMatch, RuleStep};
+
+use super::error::Error;
+use super::print_line;
+use super::selftest::get_style_description;
+
+
+/// `rule 3 (conf.log:12) regexp`, for the rule of `index` among `rules`.
+fn get_rule_name(rules: &[ColourConfig], index: usize) -> String {
+    match rules.iter().find(|rule| rule.index() == index) {
+        Some(rule) => format!("rule {} ({}:{}) {}", index, rule.source(), rule.line(), rule.regexp()),
+        None => format!("rule {}", index)
+    }
+}
+
+
+/// A line of carets under the `ranges` of `text`, as wide as what they
+/// cover on a terminal.
+fn get_carets(text: &str, ranges: &[Range<usize>]) -> String {
+    let mut carets = String::new();
+    for range in ranges {
+        let start = get_width(&text[..range.start]);
+        let width = get_width(&text[range.clone()]).max(1);
+        while get_width(&carets) < start {
+            carets.push(' ');
+        }
+        carets.push_str(&"^".repeat(width));
+    }
+    carets
+}
+
+
+/// `text` with its escape sequences written out, as in `\e[31m`.
+fn get_visible_escapes(text: &str) -> String {
+    text.replace('\x1b', "\\e")
+}
+
+
+/// `[9..13] "1500" -> red`, with `group 1` in front but for the whole match.
+fn get_group(text: &str, group: &GroupMatch) -> String {
+    let style = group.style.as_ref().map_or_else(|| "unchanged".to_string(), get_style_description);
+    let name = if group.group == 0 { String::new() } else { format!("group {} ", group.group) };
+    format!("{}[{}..{}] {:?} -> {}", name, group.range.start, group.range.end, &text[group.range.clone()], style)
+}
+
+
+/// The lines telling what `step` did, with the text it ran on and its
+/// matches underlined.
+fn get_step(rules: &[ColourConfig], step: &RuleStep) -> Vec<String> {
+    let outcome = match (&step.replaced, step.matches.is_empty()) {
+        (_, true) => "no match",
+        (Some(_), false) => "replaced",
+        (None, false) => "matched"
+    };
+    let stopped = if step.stopped { ", no rule after it runs" } else { "" };
+    let mut lines = vec![format!("{}: {}{}", get_rule_name(rules, step.rule), outcome, stopped)];
+    if step.matches.is_empty() {
+        return lines;
+    }
+    let ranges: Vec<Range<usize>> = step.matches.iter().filter_map(|groups| groups.first()).map(|group| group.range.clone()).collect();
+    lines.push(format!("  {}", step.text));
+    lines.push(format!("  {}", get_carets(&step.text, &ranges)));
+    for group in step.matches.iter().flatten() {
+        lines.push(format!("  {}", get_group(&step.text, group)));
+    }
+    if let Some(replaced) = &step.replaced {
+        lines.push(format!("  after: {}", replaced));
+    }
+    lines
+}
+
+
+/// The report on `sample`: every rule that ran, then the line as it comes
+/// out, coloured and with its escape sequences visible.
+pub fn get_report(colourizer: &Colourizer, sample: &str) -> Vec<String> {
+    let rules = colourizer.config().rules();
+    let explanation = colourizer.explain_line(sample);
+    let mut report = vec![format!("sample: {}", sample)];
+    for step in &explanation.steps {
+        report.extend(get_step(rules, step));
+    }
+    match &explanation.processed {
+        None => {
+            let rule = explanation.skipped_by.map_or_else(String::new, |index| format!(" by {}", get_rule_name(rules, index)));
+            report.push(format!("result: skipped{}", rule));
+        }
+        Some(processed) => {
+            let rendered = processed.render(true);
+            if processed.text != sample {
+                report.push(format!("text: {}", processed.text));
+            }
+            report.push(format!("result: {}", rendered));
+            report.push(format!("escaped: {}", get_visible_escapes(&rendered)));
+        }
+    }
+    report
+}
+
+
+/// Report on every one of `samples`, or on the lines of stdin without any.
+pub fn run(colourizer: &Colourizer, samples: &[String]) -> Result<i32, Error> {
+    let report = |sample: &str| -> Result<(), Error> {
+        for line in get_report(colourizer, sample) {
+            print_line(&line)?;
+        }
+        Ok(())
+    };
+    if !samples.is_empty() {
+        for sample in samples {
+            report(sample)?;
+        }
+        return Ok(0);
+    }
+    for line in io::stdin().lock().lines() {
+        report(&line.map_err(|err| Error::io("<stdin>", err))?)?;
+    }
+    Ok(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::Config;
+
+    #[test]
+    fn test_report() {
+        let config: Config = "regexp=(\\d+)(ms)\ncolours=red,default,bold\n-\nregexp=never\ncolours=blue\n-\nregexp=ping\nreplace=PING\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        assert_eq!(get_report(&colourizer, "ping: 12ms"), [
+            "sample: ping: 12ms",
+            "rule 0 (<string>:1) (\\d+)(ms): matched",
+            "  ping: 12ms",
+            "        ^^^^",
+            "  [6..10] \"12ms\" -> red",
+            "  group 1 [6..8] \"12\" -> default",
+            "  group 2 [8..10] \"ms\" -> bold",
+            "rule 1 (<string>:4) never: no match",
+            "rule 2 (<string>:7) ping: replaced",
+            "  ping: 12ms",
+            "  ^^^^",
+            "  [0..4] \"ping\" -> default",
+            "  after: PING: 12ms",
+            "text: PING: 12ms",
+            "result: PING: 12\x1b[1mms\x1b[0m",
+            "escaped: PING: 12\\e[1mms\\e[0m",
+        ]);
+    }
+}
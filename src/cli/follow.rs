@@ -0,0 +1,138 @@
+//! `--follow`: keep reading a file as it grows, like `tail -f`.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::log::warn;
+
+
+/// How often the file is looked at once all of it is read.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+
+/// What tells a file from the one that replaced it under the same name.
+#[cfg(unix)]
+fn get_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+
+#[cfg(not(unix))]
+fn get_identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+
+/// A file read to its end and then waited on for more, until interrupted.
+/// Only on interruption does it come to an end, so a line the file ends in
+/// stays in the line reader until its newline is written.
+pub struct FollowReader {
+    path: PathBuf,
+    file: File,
+    identity: Option<(u64, u64)>,
+    /// How much of the file is read, to tell when it was truncated.
+    position: u64,
+    interrupted: Arc<AtomicBool>,
+}
+
+
+impl FollowReader {
+    pub fn open(path: &str, interrupted: Arc<AtomicBool>) -> io::Result<FollowReader> {
+        let file = File::open(path)?;
+        let identity = get_identity(&file.metadata()?);
+        Ok(FollowReader { path: PathBuf::from(path), file, identity, position: 0, interrupted })
+    }
+
+
+    /// Start over when the file at the path was rotated, that is another
+    /// one now, or truncated. Whether there's anything new to read.
+    fn reopen_if_replaced(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Between being moved away and created anew.
+            Err(_) => return Ok(false)
+        };
+        if get_identity(&metadata) != self.identity {
+            if let Ok(file) = File::open(&self.path) {
+                warn(&format!("{}: file replaced, following the new one", self.path.display()));
+                self.identity = get_identity(&file.metadata()?);
+                self.file = file;
+                self.position = 0;
+                return Ok(true);
+            }
+        } else if metadata.len() < self.position {
+            warn(&format!("{}: file truncated", self.path.display()));
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.interrupted.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+            if !self.reopen_if_replaced()? {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+
+/// The flag Ctrl-C sets to stop following, ending the output as if the
+/// file had ended.
+pub fn get_interrupted_flag() -> io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&interrupted))?;
+    }
+    Ok(interrupted)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_follow_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "first\n").unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut reader = FollowReader::open(path.to_str().unwrap(), Arc::clone(&interrupted)).unwrap();
+        let mut read = || {
+            let mut buf = [0; 64];
+            let read = reader.read(&mut buf).unwrap();
+            buf[..read].to_vec()
+        };
+        assert_eq!(read(), b"first\n");
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"second\n").unwrap();
+        assert_eq!(read(), b"second\n");
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(read(), b"new\n");
+
+        interrupted.store(true, Ordering::Relaxed);
+        assert_eq!(read(), b"");
+    }
+}
@@ -0,0 +1,107 @@
+//! Footer blocks, `footer=TEMPLATE` in a conffile, printed once the input
+//! ends with the numbers of the run filled in.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rgrcat::{render_ansi, ColourConfig, Colourizer, RuleColour, StyledSpan, Template};
+
+
+/// What the footers of a config count while the input streams past.
+pub struct FooterCounts {
+    started: Instant,
+    lines: u64,
+    /// The lines matched by the rules of every name a footer counts.
+    counts: HashMap<String, u64>,
+}
+
+
+fn get_templates(rules: &[ColourConfig]) -> impl Iterator<Item = (&ColourConfig, Template)> {
+    // The templates were checked when the config was parsed.
+    rules.iter()
+        .filter(|rule| !rule.footer().is_empty())
+        .filter_map(|rule| Template::parse(rule.footer()).ok().map(|template| (rule, template)))
+}
+
+
+/// `elapsed` like `0.42s` or `3m07s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{:.2}s", seconds)
+    } else {
+        format!("{}m{:02}s", elapsed.as_secs() / 60, elapsed.as_secs() % 60)
+    }
+}
+
+
+impl FooterCounts {
+    /// Start counting for the footers of `rules`, `None` without any.
+    pub fn new(rules: &[ColourConfig]) -> Option<FooterCounts> {
+        let mut counts = HashMap::new();
+        let mut has_footer = false;
+        for (_, template) in get_templates(rules) {
+            has_footer = true;
+            for (_, name) in template.fields().filter(|(field, _)| *field == "count") {
+                counts.insert(name.unwrap_or_default().to_string(), 0);
+            }
+        }
+        if has_footer {
+            Some(FooterCounts { started: Instant::now(), lines: 0, counts })
+        } else {
+            None
+        }
+    }
+
+
+    /// Count `line`, and for every counted name whether a rule of that name
+    /// in `colourizer` matches it.
+    pub fn record(&mut self, colourizer: &Colourizer, line: &str) {
+        self.lines += 1;
+        for (name, count) in &mut self.counts {
+            if colourizer.matching_rules(line).any(|rule| rule.name() == name) {
+                *count += 1;
+            }
+        }
+    }
+
+
+    /// Count a line no rule matches.
+    pub fn record_untouched(&mut self) {
+        self.lines += 1;
+    }
+
+
+    /// The footer lines of `rules`, in the colour of their blocks if
+    /// `coloured`.
+    pub fn render(&self, rules: &[ColourConfig], coloured: bool) -> Vec<String> {
+        let elapsed = format_elapsed(self.started.elapsed());
+        get_templates(rules)
+            .map(|(rule, template)| {
+                let text = template.expand(|field, name| match field {
+                    "lines" => self.lines.to_string(),
+                    "elapsed" => elapsed.clone(),
+                    _ => self.counts.get(name.unwrap_or_default()).copied().unwrap_or_default().to_string(),
+                });
+                match rule.colours().first() {
+                    Some(RuleColour::Style(style)) if coloured && !style.is_plain() => {
+                        render_ansi(&text, &[StyledSpan { range: 0..text.len(), style: *style }])
+                    }
+                    _ => text
+                }
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_millis(420)), "0.42s");
+        assert_eq!(format_elapsed(Duration::from_secs(187)), "3m07s");
+    }
+}
@@ -0,0 +1,39 @@
+//! `--format html`: the coloured lines as an HTML page, for build reports
+//! and the like.
+
+use rgrcat::{render_html, ProcessedLine};
+
+
+const HEADER: &str = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n<body>\n";
+pub const FOOTER: &str = "</pre>\n</body>\n</html>\n";
+
+
+/// The page up to the `<pre>` the lines go in, on a dark background with
+/// `dark`.
+pub fn get_header(dark: bool) -> String {
+    let pre = if dark { "<pre style=\"background-color:#000000;color:#e5e5e5\">" } else { "<pre>" };
+    format!("{}{}\n", HEADER, pre)
+}
+
+
+/// The line as HTML, with its colours only if `coloured`.
+pub fn get_line(processed: &ProcessedLine, coloured: bool) -> String {
+    render_html(&processed.text, if coloured { &processed.spans } else { &[] })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::{Colourizer, Config, StreamState};
+
+    #[test]
+    fn test_html_lines() {
+        let config: Config = "regexp=ERROR\ncolours=bold\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        let processed = colourizer.process_next_line("a < b ERROR", &mut StreamState::new(), None).unwrap();
+        assert_eq!(get_line(&processed, true), "a &lt; b <span style=\"font-weight:bold\">ERROR</span>");
+        assert_eq!(get_line(&processed, false), "a &lt; b ERROR");
+        assert!(get_header(true).ends_with("<pre style=\"background-color:#000000;color:#e5e5e5\">\n"));
+    }
+}
@@ -0,0 +1,360 @@
+//! `--interactive`: toggle rules on and off from the keyboard while the input
+//! streams past. Keys are read from the controlling terminal (/dev/tty), which
+//! is separate from the data coming in on stdin.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rgrcat::ColourConfig;
+
+
+const CTRL_T: u8 = 0x14;
+const ESC: u8 = 0x1b;
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Key {
+    CtrlT,
+    Escape,
+    /// F1 is `Function(1)`.
+    Function(u8),
+    Char(char),
+    Other,
+}
+
+
+fn get_function_key_by_code(code: &str) -> Option<u8> {
+    match code {
+        "11" => Some(1),
+        "12" => Some(2),
+        "13" => Some(3),
+        "14" => Some(4),
+        "15" => Some(5),
+        "17" => Some(6),
+        "18" => Some(7),
+        "19" => Some(8),
+        "20" => Some(9),
+        "21" => Some(10),
+        "23" => Some(11),
+        "24" => Some(12),
+        _ => None,
+    }
+}
+
+
+/// Split the bytes of one read from the terminal into keys. Escape sequences
+/// are expected to arrive within a single read, a lone ESC is the Escape key.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        index += 1;
+        if byte == CTRL_T {
+            keys.push(Key::CtrlT);
+        } else if byte == ESC {
+            match bytes.get(index) {
+                // SS3 sequences, F1-F4 on most terminals.
+                Some(b'O') => {
+                    let key = match bytes.get(index + 1) {
+                        Some(c @ b'P'..=b'S') => Key::Function(c - b'P' + 1),
+                        _ => Key::Other,
+                    };
+                    index = (index + 2).min(bytes.len());
+                    keys.push(key);
+                }
+                // CSI sequences, the parameter bytes are ended by a final byte.
+                Some(b'[') => {
+                    let start = index + 1;
+                    let mut end = start;
+                    while end < bytes.len() && !(0x40..=0x7e).contains(&bytes[end]) {
+                        end += 1;
+                    }
+                    let key = match bytes.get(end) {
+                        Some(b'~') => std::str::from_utf8(&bytes[start..end]).ok()
+                            .and_then(get_function_key_by_code)
+                            .map_or(Key::Other, Key::Function),
+                        _ => Key::Other,
+                    };
+                    index = (end + 1).min(bytes.len());
+                    keys.push(key);
+                }
+                _ => keys.push(Key::Escape),
+            }
+        } else if byte.is_ascii() {
+            keys.push(Key::Char(byte as char));
+        } else {
+            keys.push(Key::Other);
+        }
+    }
+    keys
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    ShowMenu,
+    HideMenu,
+    Toggled,
+}
+
+
+/// The on/off state of every rule, plus whether the menu is shown.
+pub struct RuleToggles {
+    names: Vec<String>,
+    enabled: Vec<bool>,
+    menu_open: bool,
+}
+
+
+impl RuleToggles {
+    pub fn new(names: Vec<String>) -> RuleToggles {
+        let enabled = vec![true; names.len()];
+        RuleToggles { names, enabled, menu_open: false }
+    }
+
+
+    pub fn enabled(&self) -> &[bool] {
+        &self.enabled
+    }
+
+
+    fn toggle(&mut self, index: usize) -> Action {
+        match self.enabled.get_mut(index) {
+            Some(enabled) => {
+                *enabled = !*enabled;
+                Action::Toggled
+            }
+            None => Action::None
+        }
+    }
+
+
+    /// F-keys toggle rules at any time, Ctrl-T opens the menu where the digit
+    /// keys toggle rules and Escape, q or Ctrl-T close it again.
+    pub fn handle_key(&mut self, key: Key) -> Action {
+        match key {
+            Key::CtrlT if self.menu_open => {
+                self.menu_open = false;
+                Action::HideMenu
+            }
+            Key::CtrlT => {
+                self.menu_open = true;
+                Action::ShowMenu
+            }
+            Key::Function(n) => self.toggle(n as usize - 1),
+            Key::Escape | Key::Char('q') if self.menu_open => {
+                self.menu_open = false;
+                Action::HideMenu
+            }
+            Key::Char('0') if self.menu_open => self.toggle(9),
+            Key::Char(c @ '1'..='9') if self.menu_open => self.toggle(c as usize - '1' as usize),
+            _ => Action::None,
+        }
+    }
+
+
+    pub fn menu_lines(&self) -> Vec<String> {
+        let mut lines = vec!["rules (1-9/0 or F1-F12 toggle, q close):".to_string()];
+        for (index, name) in self.names.iter().enumerate() {
+            let state = if self.enabled[index] { "on " } else { "off" };
+            let key = if index < 10 { format!("{}", (index + 1) % 10) } else { " ".to_string() };
+            lines.push(format!("{} [{}] {}", key, state, name));
+        }
+        lines
+    }
+}
+
+
+/// Toggle state shared between the keyboard thread and the processing loop,
+/// which only rebuilds its active rule list when `generation` changes.
+pub struct SharedToggles {
+    enabled: Mutex<Vec<bool>>,
+    generation: AtomicUsize,
+    /// Index into the config list of every toggleable rule.
+    targets: Vec<usize>,
+}
+
+
+impl SharedToggles {
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+
+    fn update(&self, enabled: &[bool]) {
+        *self.enabled.lock().unwrap() = enabled.to_vec();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+
+    /// The rules of `config_list`, those toggled off disabled. They stay in
+    /// the list so that every rule keeps its index.
+    pub fn get_active_rules(&self, config_list: &[ColourConfig]) -> Vec<ColourConfig> {
+        let enabled = self.enabled.lock().unwrap();
+        config_list.iter().enumerate()
+            .map(|(index, config)| {
+                let mut config = config.clone();
+                if let Some(position) = self.targets.iter().position(|&target| target == index) {
+                    config.set_enabled(config.enabled() && enabled[position]);
+                }
+                config
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(unix)]
+mod tty {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::sync::Arc;
+    use std::thread;
+
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+
+    use super::{parse_keys, Action, RuleToggles, SharedToggles};
+    use crate::cli::term;
+
+
+    /// Switch off line buffering and echo, keeping ISIG so Ctrl-C still works.
+    fn enter_cbreak_mode(tty: &File) -> io::Result<()> {
+        let fd = tty.as_raw_fd();
+        term::install_cleanup(&[SIGINT, SIGTERM, SIGHUP, SIGQUIT])?;
+        let mut termios = term::save_termios(fd)?;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+
+    fn draw_menu(tty: &mut File, toggles: &RuleToggles) -> io::Result<()> {
+        let mut overlay = String::from("\x1b7");
+        for (row, line) in toggles.menu_lines().iter().enumerate() {
+            overlay.push_str(&format!("\x1b[{};1H\x1b[7m {} \x1b[0m\x1b[K", row + 1, line));
+        }
+        overlay.push_str("\x1b8");
+        tty.write_all(overlay.as_bytes())
+    }
+
+
+    fn clear_menu(tty: &mut File, toggles: &RuleToggles) -> io::Result<()> {
+        let mut overlay = String::from("\x1b7");
+        for row in 0..toggles.menu_lines().len() {
+            overlay.push_str(&format!("\x1b[{};1H\x1b[K", row + 1));
+        }
+        overlay.push_str("\x1b8");
+        tty.write_all(overlay.as_bytes())
+    }
+
+
+    /// Guard restoring the terminal when processing ends.
+    pub struct Interactive {
+        pub toggles: Arc<SharedToggles>,
+    }
+
+
+    impl Drop for Interactive {
+        fn drop(&mut self) {
+            term::restore();
+        }
+    }
+
+
+    pub fn start(toggles: RuleToggles, targets: Vec<usize>) -> io::Result<Interactive> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        enter_cbreak_mode(&tty)?;
+        let shared = Arc::new(SharedToggles {
+            enabled: std::sync::Mutex::new(toggles.enabled().to_vec()),
+            generation: std::sync::atomic::AtomicUsize::new(0),
+            targets,
+        });
+
+        let thread_shared = Arc::clone(&shared);
+        let mut toggles = toggles;
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            while let Ok(n) = tty.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                for key in parse_keys(&buffer[..n]) {
+                    let result = match toggles.handle_key(key) {
+                        Action::ShowMenu => draw_menu(&mut tty, &toggles),
+                        Action::HideMenu => clear_menu(&mut tty, &toggles),
+                        Action::Toggled => {
+                            thread_shared.update(toggles.enabled());
+                            if toggles.menu_open {
+                                draw_menu(&mut tty, &toggles)
+                            } else {
+                                Ok(())
+                            }
+                        }
+                        Action::None => Ok(()),
+                    };
+                    if result.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Interactive { toggles: shared })
+    }
+}
+
+
+#[cfg(unix)]
+pub use tty::Interactive;
+
+
+/// Start reading keys from the terminal, the returned guard restores the
+/// terminal once it's dropped.
+#[cfg(unix)]
+pub fn start(config_list: &[ColourConfig]) -> std::io::Result<Interactive> {
+    let targets: Vec<usize> = config_list.iter().enumerate()
+        .filter(|(_, config)| !config.regexp().is_empty())
+        .map(|(index, _)| index)
+        .collect();
+    let names = targets.iter().map(|index| config_list[*index].label().to_string()).collect();
+    tty::start(RuleToggles::new(names), targets)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"\x14"), vec![Key::CtrlT]);
+        assert_eq!(parse_keys(b"\x1bOP\x1b[15~\x1b[24~"), vec![Key::Function(1), Key::Function(5), Key::Function(12)]);
+        assert_eq!(parse_keys(b"1q\x1b"), vec![Key::Char('1'), Key::Char('q'), Key::Escape]);
+        assert_eq!(parse_keys(b"\x1b[A\x1b[99~x"), vec![Key::Other, Key::Other, Key::Char('x')]);
+    }
+
+    #[test]
+    fn test_rule_toggles() {
+        let mut toggles = RuleToggles::new(vec!["ip".to_string(), "time".to_string()]);
+        // Digits do nothing while the menu is closed.
+        assert_eq!(toggles.handle_key(Key::Char('1')), Action::None);
+        assert_eq!(toggles.handle_key(Key::Function(2)), Action::Toggled);
+        assert_eq!(toggles.enabled(), &[true, false]);
+        assert_eq!(toggles.handle_key(Key::Function(3)), Action::None);
+
+        assert_eq!(toggles.handle_key(Key::CtrlT), Action::ShowMenu);
+        assert_eq!(toggles.handle_key(Key::Char('1')), Action::Toggled);
+        assert_eq!(toggles.enabled(), &[false, false]);
+        assert_eq!(toggles.menu_lines()[1], "1 [off] ip");
+        assert_eq!(toggles.handle_key(Key::Char('q')), Action::HideMenu);
+        assert_eq!(toggles.handle_key(Key::Char('2')), Action::None);
+        assert_eq!(toggles.enabled(), &[false, false]);
+    }
+}
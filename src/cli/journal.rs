@@ -0,0 +1,291 @@
+//! `rgrcat --journal` reads the systemd journal through `journalctl
+//! --output=json` and colours the entries, formatted with a template of
+//! journal fields.
+
+// Only reading journalctl's JSON needs serde, formatting is always built.
+#![cfg_attr(not(feature = "serde"), allow(dead_code))]
+
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use std::io::{BufRead, BufReader};
+use std::io;
+#[cfg(feature = "serde")]
+use std::process::{Command, Stdio};
+
+use rgrcat::{format_timestamp, render_ansi, Attributes, Colour, Colourizer, NamedColour, ProcessedLine, Style, StyledSpan};
+
+use super::error::Error;
+use super::output::Sink;
+
+
+/// `{TIME}` stands for `__REALTIME_TIMESTAMP` turned into a UTC date.
+pub const DEFAULT_TEMPLATE: &str = "{TIME} {_HOSTNAME} {SYSLOG_IDENTIFIER}[{_PID}]: {MESSAGE}";
+
+
+/// A journal entry, its fields by name.
+pub type Entry = HashMap<String, String>;
+
+
+pub struct JournalOptions {
+    pub unit: Option<String>,
+    pub follow: bool,
+    /// Where to keep the cursor of the last entry, to resume after it.
+    pub cursor_file: Option<String>,
+    pub template: String,
+    /// Colour lines by their `PRIORITY` beneath what the rules colour.
+    pub priority: bool,
+    /// Flush the output after every entry.
+    pub line_buffered: bool,
+}
+
+
+/// Turn microseconds since the epoch into `YYYY-MM-DD HH:MM:SS`.
+fn get_time(timestamp: &str) -> Option<String> {
+    Some(format_timestamp(timestamp.parse::<i64>().ok()? / 1_000_000))
+}
+
+
+/// Fill the `{FIELD}`s of `template` from `entry`, missing fields left
+/// empty. `{{` and `}}` are literal braces.
+fn format_entry(template: &str, entry: &Entry) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        result.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if rest.starts_with(brace) || brace == "}" {
+            result.push_str(brace);
+            rest = rest.strip_prefix(brace).unwrap_or(rest);
+            continue;
+        }
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => {
+                result.push('{');
+                continue;
+            }
+        };
+        let field = &rest[..end];
+        let value = if field == "TIME" {
+            entry.get("__REALTIME_TIMESTAMP").and_then(|timestamp| get_time(timestamp))
+        } else {
+            entry.get(field).cloned()
+        };
+        result.push_str(&value.unwrap_or_default());
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+
+/// The style of a syslog priority, like journalctl colours them.
+fn get_priority_style(priority: &str) -> Option<Style> {
+    let (fg, attributes) = match priority {
+        "0" | "1" | "2" => (Some(NamedColour::Red), Attributes::BOLD),
+        "3" => (Some(NamedColour::Red), Attributes::empty()),
+        "4" => (Some(NamedColour::Yellow), Attributes::empty()),
+        "5" => (None, Attributes::BOLD),
+        "7" => (None, Attributes::DIM),
+        _ => return None
+    };
+    Some(Style { fg: fg.map(Colour::Named), bg: None, attributes })
+}
+
+
+/// Colour `line` with the rules, `base` filling in what they leave plain.
+fn colourize_entry_line(colourizer: &Colourizer, line: &str, base: Option<Style>) -> String {
+    let ProcessedLine { text: line, spans, .. } = colourizer.process_line(line);
+    let base = match base {
+        Some(base) => base,
+        None => return render_ansi(&line, &spans)
+    };
+    let mut filled = vec![];
+    let mut end = 0;
+    for span in spans {
+        if end < span.range.start {
+            filled.push(StyledSpan { range: end..span.range.start, style: base });
+        }
+        end = span.range.end;
+        filled.push(span);
+    }
+    if end < line.len() {
+        filled.push(StyledSpan { range: end..line.len(), style: base });
+    }
+    render_ansi(&line, &filled)
+}
+
+
+/// Format and colour `entries` into `sinks`, saving the cursor after every
+/// entry when asked to.
+pub fn process(entries: impl IntoIterator<Item = io::Result<Entry>>, colourizer: &Colourizer,
+               sinks: &mut [Sink], options: &JournalOptions) -> Result<(), Error> {
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io("journalctl", err))?;
+        let base = if options.priority { entry.get("PRIORITY").and_then(|priority| get_priority_style(priority)) } else { None };
+        let text = format_entry(&options.template, &entry);
+        // A multi-line message becomes several output lines.
+        for line in text.lines() {
+            if colourizer.is_skipped(line) {
+                continue;
+            }
+            let result = if sinks.iter().any(|sink| sink.coloured) {
+                colourize_entry_line(colourizer, line, base)
+            } else {
+                String::new()
+            };
+            for sink in sinks.iter_mut() {
+                let line = if sink.coloured { &result[..] } else { line };
+                writeln!(sink.writer, "{}", line).map_err(|err| Error::io("<output>", err))?;
+            }
+        }
+        if options.line_buffered {
+            super::flush_sinks(sinks)?;
+        }
+        if let (Some(path), Some(cursor)) = (&options.cursor_file, entry.get("__CURSOR")) {
+            std::fs::write(path, cursor).map_err(|err| Error::io(path, err))?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Reads the entries of `journalctl --output=json`, one JSON object a line.
+#[cfg(feature = "serde")]
+pub struct JournalEntries<R> {
+    reader: R,
+    buffer: String,
+}
+
+
+#[cfg(feature = "serde")]
+impl<R: BufRead> JournalEntries<R> {
+    pub fn new(reader: R) -> JournalEntries<R> {
+        JournalEntries { reader, buffer: String::new() }
+    }
+}
+
+
+/// A field value as text. Binary values come as arrays of bytes, fields
+/// given several times as arrays of values, of which the first is used.
+#[cfg(feature = "serde")]
+fn get_field_value(value: serde_json::Value) -> Option<String> {
+    use serde_json::Value;
+    match value {
+        Value::String(value) => Some(value),
+        Value::Array(values) if values.iter().all(Value::is_u64) => {
+            let bytes: Vec<u8> = values.iter().filter_map(Value::as_u64).map(|byte| byte as u8).collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        Value::Array(values) => values.into_iter().find_map(get_field_value),
+        _ => None
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<R: BufRead> Iterator for JournalEntries<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) if self.buffer.trim().is_empty() => continue,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err))
+            }
+            let fields: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(&self.buffer) {
+                Ok(fields) => fields,
+                Err(err) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err)))
+            };
+            return Some(Ok(fields.into_iter()
+                .filter_map(|(name, value)| get_field_value(value).map(|value| (name, value)))
+                .collect()));
+        }
+    }
+}
+
+
+/// Start journalctl, resuming after the saved cursor if there is one.
+#[cfg(feature = "serde")]
+fn get_journalctl(options: &JournalOptions) -> Command {
+    let mut command = Command::new("journalctl");
+    command.args(["--output=json", "--all", "--no-pager"]);
+    if options.follow {
+        command.arg("--follow");
+    }
+    if let Some(unit) = &options.unit {
+        command.arg(format!("--unit={}", unit));
+    }
+    let cursor = options.cursor_file.as_ref().and_then(|path| std::fs::read_to_string(path).ok());
+    if let Some(cursor) = cursor.as_deref().map(str::trim).filter(|cursor| !cursor.is_empty()) {
+        command.arg(format!("--after-cursor={}", cursor));
+    }
+    command
+}
+
+
+#[cfg(feature = "serde")]
+pub fn run(options: &JournalOptions, colourizer: &Colourizer, sinks: &mut [Sink]) -> Result<i32, Error> {
+    let mut child = get_journalctl(options).stdout(Stdio::piped()).spawn().map_err(|err| Error::io("journalctl", err))?;
+    let stdout = child.stdout.take().expect("journalctl stdout is piped");
+    process(JournalEntries::new(BufReader::new(stdout)), colourizer, sinks, options)?;
+    let status = child.wait().map_err(|err| Error::io("journalctl", err))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+
+#[cfg(not(feature = "serde"))]
+pub fn run(_options: &JournalOptions, _colourizer: &Colourizer, _sinks: &mut [Sink]) -> Result<i32, Error> {
+    Err(Error::Usage(format!("{} (--journal needs the serde feature)", super::USAGE)))
+}
+
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use rgrcat::Config;
+
+    #[test]
+    fn test_format_entry() {
+        let entry: Entry = [("MESSAGE", "hi"), ("__REALTIME_TIMESTAMP", "1700000000123456")].iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        assert_eq!(format_entry("{TIME} {_PID}: {MESSAGE} {{x}}", &entry), "2023-11-14 22:13:20 : hi {x}");
+        assert_eq!(get_time("0").unwrap(), "1970-01-01 00:00:00");
+        assert_eq!(get_time("951782400000000").unwrap(), "2000-02-29 00:00:00");
+    }
+
+    #[test]
+    fn test_process_journal_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cursor_file = dir.path().join("cursor");
+        let input = concat!(
+            r#"{"__CURSOR":"s=1","PRIORITY":"3","SYSLOG_IDENTIFIER":"sshd","MESSAGE":"failed login"}"#, "\n",
+            r#"{"__CURSOR":"s=2","PRIORITY":"6","SYSLOG_IDENTIFIER":"cron","MESSAGE":[108,111,103,105,110,32,111,107]}"#, "\n",
+        );
+        let config: Config = "regexp=login\ncolours=green\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        let options = JournalOptions {
+            unit: None,
+            follow: false,
+            cursor_file: Some(cursor_file.to_str().unwrap().to_string()),
+            template: "{SYSLOG_IDENTIFIER}: {MESSAGE}".to_string(),
+            priority: true,
+            line_buffered: false,
+        };
+        let mut output = vec![];
+        let mut sinks = [Sink { writer: &mut output, coloured: true }];
+        process(JournalEntries::new(input.as_bytes()), &colourizer, &mut sinks, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "\x1b[31msshd: failed \x1b[0;32mlogin\x1b[0m\n",
+            "cron: \x1b[32mlogin\x1b[0m ok\n",
+        ));
+        assert_eq!(std::fs::read_to_string(&cursor_file).unwrap(), "s=2");
+        let args: Vec<_> = get_journalctl(&options).get_args().map(|arg| arg.to_str().unwrap().to_string()).collect();
+        assert_eq!(args.last().unwrap(), "--after-cursor=s=2");
+    }
+}
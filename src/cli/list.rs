@@ -0,0 +1,49 @@
+//! `--list`: the conffiles that can be loaded by name, and where from.
+
+use std::collections::BTreeMap;
+
+use rgrcat::{get_config_list, get_embedded_config, get_embedded_config_names, Config};
+
+
+/// How many rules the conffile at `path` has and how many problems, or
+/// why it can't be loaded.
+fn get_rule_count(path: &str) -> String {
+    let checked = match path.strip_prefix("embedded:").and_then(get_embedded_config) {
+        Some(text) => Config::from_reader_named(text.as_bytes(), path, false).map(|config| (config, vec![])),
+        None => Config::check_path(path),
+    };
+    match checked {
+        Ok((config, problems)) if problems.is_empty() => format!("{} rules", config.rules().len()),
+        Ok((config, problems)) => format!("{} rules, {} problems", config.rules().len(), problems.len()),
+        Err(err) => format!("error: {}", err),
+    }
+}
+
+
+/// One line for each place a conffile is found, sorted by name, with the
+/// name, the path, with `verbose` the number of rules, and `shadowed` for
+/// the copies never loaded since one comes first. The conffiles built in
+/// come last, as `embedded:conf.ping`.
+pub fn get_listing(verbose: bool) -> Vec<String> {
+    let mut configs: BTreeMap<String, Vec<String>> = get_config_list().into_iter()
+        .map(|(name, paths)| (name, paths.iter().map(|path| path.display().to_string()).collect()))
+        .collect();
+    for name in get_embedded_config_names() {
+        configs.entry(name.to_string()).or_default().push(format!("embedded:{}", name));
+    }
+    let mut lines = vec![];
+    for (name, paths) in &configs {
+        for (index, path) in paths.iter().enumerate() {
+            let mut line = format!("{}\t{}", name, path);
+            if verbose {
+                line.push('\t');
+                line.push_str(&get_rule_count(path));
+            }
+            if index > 0 {
+                line.push_str("\tshadowed");
+            }
+            lines.push(line);
+        }
+    }
+    lines
+}
@@ -0,0 +1,21 @@
+//! Tracing for the command line. Setting `RUST_LOG` installs a subscriber
+//! printing events on stderr, such as `RUST_LOG=rgrcat=debug`.
+
+use std::env;
+use std::io;
+
+use tracing_subscriber::EnvFilter;
+
+pub use rgrcat::log::warn;
+
+
+/// Install the stderr subscriber when `RUST_LOG` asks for one.
+pub fn init() {
+    if env::var_os("RUST_LOG").is_some() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::from_default_env())
+            .with_writer(io::stderr)
+            .try_init();
+    }
+}
+
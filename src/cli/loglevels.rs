@@ -0,0 +1,108 @@
+//! `--loglevels`: colour the levels of log lines without a conffile, for
+//! applications nobody wrote one for. Levels are found the way Python's
+//! logging writes them, like `ERROR:root:message`, in brackets like
+//! `[warn]`, and as the `<3>` priorities of kernel and syslog messages.
+
+use rgrcat::{Config, ConfigBuilder, RuleBuilder};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Error,
+    Critical,
+}
+
+
+/// The levels, most severe first, with the names they go by, the syslog
+/// priorities they stand for and their colours.
+const LEVELS: [(Level, &str, &str, &str); 6] = [
+    (Level::Critical, "critical|crit|fatal|alert|emerg|emergency|panic", "0-2", "bold red"),
+    (Level::Error, "error|err", "3", "red"),
+    (Level::Warn, "warning|warn", "4", "yellow"),
+    (Level::Notice, "notice", "5", "cyan"),
+    (Level::Info, "info", "6", "green"),
+    (Level::Debug, "debug|trace", "7", "blue"),
+];
+
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "notice" => Some(Level::Notice),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" | "err" => Some(Level::Error),
+            "critical" | "crit" => Some(Level::Critical),
+            _ => None,
+        }
+    }
+
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Notice => "notice",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Critical => "critical",
+        }
+    }
+}
+
+
+/// The value of `--loglevels=LEVEL+`, the level from which on whole lines
+/// get tinted.
+pub fn get_tint_level(value: &str) -> Option<Level> {
+    Level::from_name(value.strip_suffix('+')?)
+}
+
+
+/// The rules of `--loglevels`, one per level and named after it. They
+/// colour the level of every line, or with `tint_from` the whole lines of
+/// that level and above, leaving the others alone.
+pub fn get_config(tint_from: Option<Level>) -> Config {
+    let mut builder = ConfigBuilder::new();
+    for (level, names, priorities, colours) in LEVELS {
+        let regexp = match tint_from {
+            None => format!(r"^(?i:{names}):|\[(?i:{names})\]|^<[{priorities}]>", names = names, priorities = priorities),
+            Some(tint_from) if level >= tint_from => {
+                format!(r"^(?:(?i:{names}):|<[{priorities}]>).*|^.*\[(?i:{names})\].*", names = names, priorities = priorities)
+            }
+            Some(_) => continue,
+        };
+        builder = builder.rule(RuleBuilder::new(&regexp).name(level.name()).colour_name(colours));
+    }
+    builder.build().expect("the --loglevels rules are valid")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::Colourizer;
+
+    #[test]
+    fn test_levels() {
+        let colourizer = Colourizer::new(get_config(None));
+        assert_eq!(colourizer.colourize_line("ERROR:root:boom"), "\x1b[31mERROR:\x1b[0mroot:boom");
+        assert_eq!(colourizer.colourize_line("<4>disk almost full"), "\x1b[33m<4>\x1b[0mdisk almost full");
+        assert_eq!(colourizer.colourize_line("11:00 [Debug] poll"), "11:00 \x1b[34m[Debug]\x1b[0m poll");
+        assert_eq!(colourizer.colourize_line("no error here"), "no error here");
+    }
+
+    #[test]
+    fn test_tint_from_level() {
+        assert_eq!(get_tint_level("warn+"), Some(Level::Warn));
+        assert_eq!(get_tint_level("warn"), None);
+        let colourizer = Colourizer::new(get_config(get_tint_level("warn+")));
+        assert_eq!(colourizer.colourize_line("WARNING:app:slow"), "\x1b[33mWARNING:app:slow\x1b[0m");
+        assert_eq!(colourizer.colourize_line("11:00 [error] down"), "\x1b[31m11:00 [error] down\x1b[0m");
+        assert_eq!(colourizer.colourize_line("INFO:app:fine"), "INFO:app:fine");
+    }
+}
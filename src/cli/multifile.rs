@@ -0,0 +1,141 @@
+//! Several input files, each coloured with a config of its own. With
+//! `--map PATTERN=CONFFILE` the file name picks the config by a glob, with
+//! `--auto` the entries of grc.conf are tried on it as well. Files nothing
+//! matches get `--default-config`, or pass through uncoloured.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use regex::Regex;
+use rgrcat::{find_config, get_grc_conf_entries, Colourizer, Config, StreamState};
+
+use super::decompress::{self, ReadError};
+use super::error::Error;
+use super::output::{SideOutputs, Sink};
+use super::{LineOptions, Progress};
+
+
+pub struct FileMapping {
+    /// Glob patterns of file names with the conffile for them, tried in order.
+    pub maps: Vec<(String, String)>,
+    /// Also look the file names up in grc.conf.
+    pub auto: bool,
+    pub default_config: Option<String>,
+    pub files: Vec<String>,
+    /// Go on counting lines from one file to the next instead of from 1.
+    pub number_across_files: bool,
+}
+
+
+/// Split a `--map` value like `*.log=conf.log` at its last `=`.
+pub fn get_map(value: &str) -> Option<(String, String)> {
+    let (pattern, config_name) = value.rsplit_once('=')?;
+    if pattern.is_empty() || config_name.is_empty() {
+        return None;
+    }
+    Some((pattern.to_string(), config_name.to_string()))
+}
+
+
+/// Whether `name` matches `pattern`, where `*` stands for any text and `?`
+/// for one character.
+fn is_glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // matches[j] tells whether the pattern so far matches name[..j].
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for c in pattern {
+        if c == '*' {
+            for j in 1..=name.len() {
+                matches[j] |= matches[j - 1];
+            }
+        } else {
+            for j in (1..=name.len()).rev() {
+                matches[j] = matches[j - 1] && (c == '?' || c == name[j - 1]);
+            }
+            matches[0] = false;
+        }
+    }
+    matches[name.len()]
+}
+
+
+/// The conffile for the file at `path`, `None` to pass it through.
+fn get_config_name<'a>(mapping: &'a FileMapping, grc_conf: &'a [(Regex, String)], path: &str) -> Option<&'a str> {
+    let file_name = Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+    let mapped = mapping.maps.iter()
+        .find(|(pattern, _)| is_glob_match(pattern, file_name))
+        .map(|(_, config_name)| &config_name[..]);
+    mapped
+        .or_else(|| grc_conf.iter().find(|(re, _)| re.is_match(path)).map(|(_, config_name)| &config_name[..]))
+        .or(mapping.default_config.as_deref())
+}
+
+
+/// Colour every file with its config into `sinks`. Each config is loaded
+/// once, but every file gets a colourizer and stream of its own, unless
+/// `number_across_files` carries the stream over. The rules apply to the
+/// output of `command`, see `Config::for_command`.
+pub fn process_files(mapping: &FileMapping, sinks: &mut [Sink], side_outputs: &mut SideOutputs, command: Option<&str>,
+                     line_options: LineOptions) -> Result<(), Error> {
+    let grc_conf: Vec<(Regex, String)> = if mapping.auto {
+        get_grc_conf_entries()?.into_iter()
+            .filter_map(|(regexp, config_name)| Regex::new(&regexp).ok().map(|re| (re, config_name)))
+            .collect()
+    } else {
+        vec![]
+    };
+    let mut configs: HashMap<&str, Config> = HashMap::new();
+    let mut progress = Progress::default();
+    for (index, path) in mapping.files.iter().enumerate() {
+        if line_options.headers {
+            super::write_header(sinks, path, index == 0, line_options)?;
+        }
+        if mapping.number_across_files {
+            progress.state.set_file(path);
+        } else {
+            progress.state = StreamState::for_file(path);
+        }
+        let config = match get_config_name(mapping, &grc_conf, path) {
+            Some(config_name) => {
+                if !configs.contains_key(config_name) {
+                    let config = find_config(config_name, false)?.1;
+                    configs.insert(config_name, config);
+                }
+                configs[config_name].for_command(command)
+            }
+            None => Config::from_rules(vec![])
+        };
+        let config = match line_options.colour_depth {
+            Some(depth) => config.for_colour_depth(depth),
+            None => config,
+        };
+        let error = ReadError::default();
+        let file = File::open(path).and_then(|file| decompress::open(file, &error)).map_err(|err| Error::io(path, err))?;
+        super::process_input(BufReader::new(file), &Colourizer::new(config), sinks, side_outputs, None, &mut progress,
+                             line_options)?;
+        if let Some(err) = error.take() {
+            return Err(Error::io(path, err));
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_glob_match() {
+        assert!(is_glob_match("*.access.log", "site.access.log"));
+        assert!(is_glob_match("dmesg*", "dmesg.txt"));
+        assert!(is_glob_match("build-?.log", "build-1.log"));
+        assert!(!is_glob_match("build-?.log", "build-12.log"));
+        assert!(!is_glob_match("*.log", "log.txt"));
+        assert!(is_glob_match("*", ""));
+    }
+}
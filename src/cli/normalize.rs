@@ -0,0 +1,46 @@
+//! `--normalize nfc|nfd` brings input lines into one Unicode normalization
+//! form before the rules see them, so a rule written in NFC also matches
+//! text decomposed into NFD, as macOS writes file names.
+
+use std::borrow::Cow;
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+    Nfd,
+}
+
+
+pub fn get_normalization(name: &str) -> Option<Normalization> {
+    match name {
+        "none" => Some(Normalization::None),
+        "nfc" => Some(Normalization::Nfc),
+        "nfd" => Some(Normalization::Nfd),
+        _ => None
+    }
+}
+
+
+/// `line` in the normalization form, borrowed when it already is.
+#[cfg(feature = "normalize")]
+pub fn normalize(line: &str, normalization: Normalization) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc_quick, is_nfd_quick, IsNormalized, UnicodeNormalization};
+
+    match normalization {
+        Normalization::None => Cow::Borrowed(line),
+        Normalization::Nfc if is_nfc_quick(line.chars()) == IsNormalized::Yes => Cow::Borrowed(line),
+        Normalization::Nfc => Cow::Owned(line.nfc().collect()),
+        Normalization::Nfd if is_nfd_quick(line.chars()) == IsNormalized::Yes => Cow::Borrowed(line),
+        Normalization::Nfd => Cow::Owned(line.nfd().collect()),
+    }
+}
+
+
+/// Without the feature only `none` gets past the options.
+#[cfg(not(feature = "normalize"))]
+pub fn normalize(line: &str, _normalization: Normalization) -> Cow<'_, str> {
+    Cow::Borrowed(line)
+}
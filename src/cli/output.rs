@@ -0,0 +1,166 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// A secondary output file written alongside stdout. A failing side output
+/// only warns once and stops writing, it never interrupts the main stream.
+pub struct SideOutput {
+    path: String,
+    writer: Option<BufWriter<File>>,
+    /// Flush after every line, as stdout is on a terminal or with
+    /// `--line-buffered`. Otherwise it's flushed along with stdout.
+    line_buffered: bool,
+}
+
+
+impl SideOutput {
+    pub fn open(path: &str, append: bool, line_buffered: bool) -> Result<SideOutput, io::Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(SideOutput { path: path.to_string(), writer: Some(BufWriter::new(file)), line_buffered })
+    }
+
+
+    /// Reopen the file in append mode, so that a rotated file gets recreated.
+    pub fn reopen(&mut self) {
+        tracing::debug!(path = %self.path, "reopening");
+        match SideOutput::open(&self.path, true, self.line_buffered) {
+            Ok(side_output) => self.writer = side_output.writer,
+            Err(err) => {
+                super::log::warn(&format!("error reopening {}: {}, stop writing to it", self.path, err));
+                self.writer = None;
+            }
+        }
+    }
+
+
+    pub fn write_line(&mut self, line: &[u8]) {
+        if let Some(writer) = &mut self.writer {
+            // Line by line a whole line is flushed in one write, so that
+            // processes tailing the file never see half lines.
+            let line_buffered = self.line_buffered;
+            let written = writer.write_all(line).and_then(|_| if line_buffered { writer.flush() } else { Ok(()) });
+            if let Err(err) = written {
+                super::log::warn(&format!("error writing to {}: {}, stop writing to it", self.path, err));
+                self.writer = None;
+            }
+        }
+    }
+
+
+    /// Write out what's buffered, when stdout is flushed.
+    pub fn flush(&mut self) {
+        if let Some(writer) = &mut self.writer {
+            if let Err(err) = writer.flush() {
+                super::log::warn(&format!("error writing to {}: {}, stop writing to it", self.path, err));
+                self.writer = None;
+            }
+        }
+    }
+}
+
+
+/// Side outputs written while processing, besides coloured stdout.
+#[derive(Default)]
+pub struct SideOutputs {
+    pub record: Option<SideOutput>,
+    pub matched: Option<SideOutput>,
+    pub matched_tags: Vec<String>,
+    /// The lines as written, uncoloured, for `--plain-copy`.
+    pub plain: Option<SideOutput>,
+    pub reopen: Arc<AtomicBool>,
+}
+
+
+impl SideOutputs {
+    pub fn reopen_if_requested(&mut self) {
+        if self.reopen.swap(false, Ordering::Relaxed) {
+            for side_output in self.matched.iter_mut().chain(self.plain.iter_mut()) {
+                side_output.reopen();
+            }
+        }
+    }
+
+
+    /// Flush every side output, as stdout just was.
+    pub fn flush(&mut self) {
+        for side_output in self.record.iter_mut().chain(self.matched.iter_mut()).chain(self.plain.iter_mut()) {
+            side_output.flush();
+        }
+    }
+}
+
+
+/// A destination of the processed lines, either coloured or plain.
+pub struct Sink<'a> {
+    pub writer: &'a mut dyn Write,
+    pub coloured: bool,
+}
+
+
+/// A file that only appears under its final name once it was completely
+/// written: everything goes to a temporary file next to it, which gets
+/// renamed on `commit`, or removed if it is dropped before.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+
+impl AtomicFile {
+    pub fn create(path: &str) -> Result<AtomicFile, io::Error> {
+        let path = PathBuf::from(path);
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let tmp_name = format!(".{}.{}.tmp", file_name, process::id());
+        let tmp_path = match path.parent() {
+            Some(parent) => parent.join(tmp_name),
+            None => Path::new(&tmp_name).to_path_buf()
+        };
+        let file = File::create(&tmp_path)?;
+        Ok(AtomicFile { path, tmp_path, writer: Some(BufWriter::new(file)) })
+    }
+
+
+    pub fn commit(mut self) -> Result<(), io::Error> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        fs::rename(&self.tmp_path, &self.path)
+    }
+}
+
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.writer {
+            Some(writer) => writer.write(buf),
+            None => Err(io::Error::other("file already committed"))
+        }
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(())
+        }
+    }
+}
+
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
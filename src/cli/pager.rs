@@ -0,0 +1,49 @@
+//! `--pager`: the coloured output goes to a pager, `less -R` unless
+//! `GRC_PAGER` or `PAGER` name another, which renders the colours.
+
+use std::env;
+use std::io;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+#[cfg(unix)]
+use super::log::warn;
+
+
+/// The pager writing to the terminal. Dropping it waits for it to exit,
+/// once its input is closed, so rgrcat ends with it.
+pub struct Pager {
+    child: Child,
+}
+
+
+/// The command line of the pager: `GRC_PAGER`, else `PAGER`, else `less -R`.
+pub fn get_command() -> String {
+    ["GRC_PAGER", "PAGER"].iter()
+        .find_map(|name| env::var(name).ok().filter(|command| !command.trim().is_empty()))
+        .unwrap_or_else(|| "less -R".to_string())
+}
+
+
+impl Pager {
+    /// Start `command`, split at spaces without any quoting, returning the
+    /// pager and where its input goes. From then on Ctrl-C is left to the
+    /// pager, as less takes it to stop waiting, and no longer ends rgrcat.
+    pub fn spawn(command: &str) -> io::Result<(Pager, ChildStdin)> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no pager command"))?;
+        let mut child = Command::new(program).args(words).stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("the pager's stdin is piped");
+        #[cfg(unix)]
+        if let Err(err) = signal_hook::flag::register(signal_hook::consts::SIGINT, Default::default()) {
+            warn(&format!("Can not install SIGINT handler: {}", err));
+        }
+        Ok((Pager { child }, stdin))
+    }
+}
+
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.child.wait();
+    }
+}
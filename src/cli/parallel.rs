@@ -0,0 +1,195 @@
+//! `--jobs N`: colour the lines of a big input on several threads, for
+//! batch jobs where running the regexps is what takes the time. The lines
+//! go out in the order they came in, byte for byte as on one thread.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+use rgrcat::{escape_bytes, unescape_bytes, Colourizer, LineReader, StreamState};
+
+use super::error::Error;
+use super::log::warn;
+use super::output::Sink;
+use super::LineOptions;
+
+
+/// How many lines a thread colours at a time.
+const CHUNK_LINES: usize = 1024;
+
+
+/// How many chunks per thread are read ahead of the output.
+const CHUNKS_AHEAD: usize = 4;
+
+
+/// A line as it was read, or a piece of one too long for the rules, which
+/// goes out as it is.
+enum Item {
+    Line { raw: Vec<u8>, ending: usize },
+    Piece(Vec<u8>),
+}
+
+
+/// The bytes a chunk of lines comes out as, coloured for the sinks that
+/// take colours and plain for the others, each only if some sink wants it.
+#[derive(Default)]
+struct Output {
+    coloured: Vec<u8>,
+    plain: Vec<u8>,
+}
+
+
+/// The output of `items` coloured with `colourizer`, the same bytes the
+/// line loop would write for them.
+fn colour_chunk(colourizer: &Colourizer, items: &[Item], line_options: LineOptions, coloured: bool, plain: bool) -> Output {
+    let mut output = Output::default();
+    let mut result = String::new();
+    for item in items {
+        let (raw, ending) = match item {
+            Item::Line { raw, ending } => (raw, *ending),
+            Item::Piece(piece) => {
+                if coloured {
+                    output.coloured.extend_from_slice(piece);
+                }
+                if plain {
+                    output.plain.extend_from_slice(piece);
+                }
+                continue;
+            }
+        };
+        let (content, ending) = raw.split_at(raw.len() - ending);
+        let ending = line_options.output_ending(ending);
+        let input = escape_bytes(content);
+        // The rules don't look at any line but this one.
+        let processed = match colourizer.process_next_line(&input, &mut StreamState::new(), None) {
+            Some(processed) => processed,
+            None => continue
+        };
+        let escaped = matches!(input, Cow::Owned(_));
+        let push = |out: &mut Vec<u8>, text: &str| {
+            if escaped {
+                out.extend_from_slice(&unescape_bytes(text));
+            } else {
+                out.extend_from_slice(text.as_bytes());
+            }
+            out.extend_from_slice(ending);
+        };
+        if coloured {
+            result.clear();
+            processed.render_into(true, &mut result);
+            push(&mut output.coloured, &result);
+        }
+        if plain {
+            push(&mut output.plain, &processed.text);
+        }
+    }
+    output
+}
+
+
+/// Up to `CHUNK_LINES` lines from `reader`, the pieces of overlong lines
+/// counting as lines, and `true` for the last chunk. `line` counts the
+/// lines read, and `overlong_warned` tells whether one was already warned
+/// about.
+fn read_chunk(reader: &mut LineReader<BufReader<impl Read>>, line: &mut u64, overlong_warned: &mut bool,
+              line_options: LineOptions) -> Result<(Vec<Item>, bool), Error> {
+    let mut items = Vec::with_capacity(CHUNK_LINES);
+    while items.len() < CHUNK_LINES {
+        if !reader.read().map_err(|err| Error::io("<stdin>", err))? {
+            return Ok((items, true));
+        }
+        if reader.is_overlong() {
+            if !*overlong_warned {
+                warn(&format!("line {} is longer than {} bytes, passed through uncoloured", *line + 1,
+                              line_options.max_line_bytes.unwrap_or_default()));
+                *overlong_warned = true;
+            }
+            let ending = if reader.is_continued() { &[][..] } else { line_options.output_ending(reader.ending()) };
+            let mut piece = reader.raw()[..reader.raw().len() - reader.ending().len()].to_vec();
+            piece.extend_from_slice(ending);
+            items.push(Item::Piece(piece));
+            if !reader.is_continued() {
+                *line += 1;
+            }
+            continue;
+        }
+        items.push(Item::Line { raw: reader.raw().to_vec(), ending: reader.ending().len() });
+        *line += 1;
+    }
+    Ok((items, false))
+}
+
+
+/// Send the chunks `reader` reads to the threads, a few ahead of the
+/// output, and write what comes back to `sinks` in order.
+fn write_in_order(reader: &mut LineReader<BufReader<impl Read>>, chunk_sender: &mpsc::Sender<(u64, Vec<Item>)>,
+                  output_receiver: &mpsc::Receiver<(u64, Output)>, sinks: &mut [Sink], jobs: usize, line: &mut u64,
+                  line_options: LineOptions) -> Result<(), Error> {
+    // Chunks done out of order wait here for those before them.
+    let mut done = BTreeMap::new();
+    let (mut read, mut written) = (0, 0);
+    let mut overlong_warned = false;
+    let mut at_end = false;
+    loop {
+        while !at_end && read - written < (jobs * CHUNKS_AHEAD) as u64 {
+            let (items, last) = read_chunk(reader, line, &mut overlong_warned, line_options)?;
+            at_end = last;
+            if !items.is_empty() {
+                chunk_sender.send((read, items)).expect("the threads take chunks until the sender is dropped");
+                read += 1;
+            }
+        }
+        if written == read {
+            return Ok(());
+        }
+        let (number, output) = output_receiver.recv().expect("a thread colours every chunk sent");
+        done.insert(number, output);
+        while let Some(output) = done.remove(&written) {
+            for sink in sinks.iter_mut() {
+                let bytes = if sink.coloured { &output.coloured } else { &output.plain };
+                sink.writer.write_all(bytes).map_err(|err| Error::io("<output>", err))?;
+            }
+            written += 1;
+        }
+    }
+}
+
+
+/// Colour what `reader` reads on `jobs` threads and write it to `sinks` in
+/// order. The config of `colourizer` must colour every line on its own,
+/// see `Config::is_line_independent`. Returns the number of lines read.
+pub fn process(reader: &mut LineReader<BufReader<impl Read>>, colourizer: &Colourizer, sinks: &mut [Sink], jobs: usize,
+               line_options: LineOptions) -> Result<u64, Error> {
+    let coloured = sinks.iter().any(|sink| sink.coloured);
+    let plain = sinks.iter().any(|sink| !sink.coloured);
+    let (chunk_sender, chunk_receiver) = mpsc::channel::<(u64, Vec<Item>)>();
+    let (output_sender, output_receiver) = mpsc::channel::<(u64, Output)>();
+    let chunk_receiver = Mutex::new(chunk_receiver);
+    let mut line = 0;
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let output_sender = output_sender.clone();
+            let chunk_receiver = &chunk_receiver;
+            scope.spawn(move || loop {
+                // The lock is only held while waiting for the next chunk.
+                let next = chunk_receiver.lock().map(|receiver| receiver.recv());
+                let (number, items) = match next {
+                    Ok(Ok(chunk)) => chunk,
+                    _ => break
+                };
+                if output_sender.send((number, colour_chunk(colourizer, &items, line_options, coloured, plain))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(output_sender);
+        let result = write_in_order(reader, &chunk_sender, &output_receiver, sinks, jobs, &mut line, line_options);
+        // Without more chunks coming the threads finish.
+        drop(chunk_sender);
+        result
+    })?;
+    Ok(line)
+}
@@ -0,0 +1,232 @@
+//! Finding the command grc.conf should match for `rgrcat run`, when the
+//! command run is a shell with a script like `sh -c "dmesg | tail"` or a
+//! wrapper like `env FOO=bar dmesg`.
+
+use std::path::Path;
+
+
+const SHELLS: [&str; 6] = ["sh", "bash", "dash", "zsh", "ksh", "fish"];
+
+/// Commands which run the command after them, which is the one that counts.
+const WRAPPERS: [&str; 5] = ["env", "exec", "command", "nohup", "time"];
+
+
+/// Which of the simple commands in a pipeline or chain picks the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pick {
+    First,
+    Last,
+}
+
+
+impl Pick {
+    pub fn from_name(name: &str) -> Option<Pick> {
+        match name {
+            "first" => Some(Pick::First),
+            "last" => Some(Pick::Last),
+            _ => None,
+        }
+    }
+}
+
+
+/// Split a shell script into its simple commands, each a list of words with
+/// the quoting removed. Commands are separated by `|`, `||`, `&&`, `;`, `&`
+/// and newlines, parentheses and braces are dropped. `None` if a quote or
+/// escape is unterminated.
+fn get_simple_commands(script: &str) -> Option<Vec<Vec<String>>> {
+    let mut commands = vec![];
+    let mut words = vec![];
+    let mut word = String::new();
+    // Whether `word` holds a word, which may be empty like `''`.
+    let mut in_word = false;
+    let mut chars = script.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => {
+                            let c = chars.next()?;
+                            if !matches!(c, '"' | '\\' | '$' | '`') {
+                                word.push('\\');
+                            }
+                            word.push(c);
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next()?);
+            }
+            // `2>&1`, `>&2` and `<&3` duplicate descriptors.
+            '&' if word.ends_with(['>', '<']) => word.push(c),
+            '|' | '&' | ';' | '\n' | '(' | ')' | ' ' | '\t' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                if !matches!(c, ' ' | '\t' | '(' | ')') && !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    for command in &mut commands {
+        *command = strip_redirections(command);
+        // Braces of a command group are words of their own.
+        command.retain(|word| word != "{" && word != "}");
+    }
+    commands.retain(|command| !command.is_empty());
+    Some(commands)
+}
+
+
+/// `words` without redirections like `2>/dev/null`, `> out` or `2>&1`.
+fn strip_redirections(words: &[String]) -> Vec<String> {
+    let mut kept = vec![];
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let operator = word.trim_start_matches(|c: char| c.is_ascii_digit());
+        let operator = operator.strip_prefix('&').unwrap_or(operator);
+        if !operator.starts_with(['<', '>']) {
+            kept.push(word.clone());
+            continue;
+        }
+        let target = operator.trim_start_matches(['<', '>', '&']);
+        if target.is_empty() && !operator.ends_with('&') {
+            // The file is the next word.
+            words.next();
+        }
+    }
+    kept
+}
+
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+
+/// `words` without the variable assignments and wrappers in front of the
+/// command, along with the options the wrappers are given.
+fn strip_prefixes(words: &[String]) -> &[String] {
+    let mut start = 0;
+    while let Some(word) = words.get(start) {
+        let is_wrapper = WRAPPERS.contains(&word.as_str()) && start + 1 < words.len();
+        let is_wrapper_option = start > 0 && word.starts_with('-')
+            && WRAPPERS.contains(&words[start - 1].as_str());
+        if is_assignment(word) || is_wrapper || is_wrapper_option {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    &words[start..]
+}
+
+
+/// The script of a shell invocation like `sh -c SCRIPT`, `None` if `command`
+/// isn't one.
+fn get_shell_script(command: &[String]) -> Option<&str> {
+    let command = strip_prefixes(command);
+    let name = Path::new(command.first()?).file_name()?.to_str()?;
+    if !SHELLS.contains(&name) {
+        return None;
+    }
+    let position = command.iter().skip(1)
+        .position(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains('c'))?;
+    command.get(position + 2).map(|script| &script[..])
+}
+
+
+/// The command line grc.conf gets matched against for `command`: the first
+/// or last simple command of a shell script, or `command` itself, in both
+/// cases without variable assignments and wrappers in front. A script which
+/// can't be parsed falls back to the whole command line.
+pub fn get_command_line(command: &[String], pick: Pick) -> String {
+    let script = match get_shell_script(command) {
+        Some(script) => script,
+        None => return strip_prefixes(command).join(" "),
+    };
+    let commands = match get_simple_commands(script) {
+        Some(commands) => commands,
+        None => return command.join(" "),
+    };
+    let words = match pick {
+        Pick::First => commands.iter().map(|words| strip_prefixes(words)).find(|words| !words.is_empty()),
+        Pick::Last => commands.iter().map(|words| strip_prefixes(words)).rfind(|words| !words.is_empty()),
+    };
+    match words {
+        Some(words) => words.join(" "),
+        None => command.join(" "),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(command: &[&str], pick: Pick) -> String {
+        let command: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+        get_command_line(&command, pick)
+    }
+
+    #[test]
+    fn test_pipelines_and_chains() {
+        assert_eq!(get(&["sh", "-c", "dmesg | tail -n 50"], Pick::First), "dmesg");
+        assert_eq!(get(&["sh", "-c", "dmesg | tail -n 50"], Pick::Last), "tail -n 50");
+        assert_eq!(get(&["/bin/bash", "-ec", "cd /tmp && make -j4 || true"], Pick::First), "cd /tmp");
+        assert_eq!(get(&["bash", "-c", "cd /tmp && make -j4; echo done"], Pick::Last), "echo done");
+        assert_eq!(get(&["sh", "-c", "(ping -c 3 host) 2>&1"], Pick::First), "ping -c 3 host");
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(get(&["sh", "-c", "grep 'a | b' file | sort"], Pick::First), "grep a | b file");
+        assert_eq!(get(&["sh", "-c", r#"echo "say \"hi\"; ok" \; x"#], Pick::First), r#"echo say "hi"; ok ; x"#);
+        // An unterminated quote falls back to the whole command line.
+        assert_eq!(get(&["sh", "-c", "echo 'oops"], Pick::First), "sh -c echo 'oops");
+    }
+
+    #[test]
+    fn test_prefixes() {
+        assert_eq!(get(&["sh", "-c", "LC_ALL=C FOO=bar df -h"], Pick::First), "df -h");
+        assert_eq!(get(&["env", "-i", "FOO=bar", "sh", "-c", "exec nohup ping host"], Pick::First), "ping host");
+        assert_eq!(get(&["FOO=bar", "dmesg"], Pick::First), "dmesg");
+        assert_eq!(get(&["time"], Pick::First), "time");
+        // Not a shell, so the script-looking argument is left alone.
+        assert_eq!(get(&["ssh", "-c", "aes", "host"], Pick::First), "ssh -c aes host");
+    }
+}
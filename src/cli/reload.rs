@@ -0,0 +1,68 @@
+//! Loading the config again on SIGHUP, for a pipe left running for days
+//! while its conffile is worked on.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rgrcat::Colourizer;
+
+use super::error::Error;
+use super::log::warn;
+
+
+/// The config as last loaded on SIGHUP, and how to load it.
+pub struct Reload<'a> {
+    requested: Arc<AtomicBool>,
+    load: &'a dyn Fn() -> Result<Colourizer, Error>,
+    /// `None` until the first reload.
+    colourizer: Option<Colourizer>,
+}
+
+
+impl<'a> Reload<'a> {
+    /// Load the config with `load` whenever SIGHUP comes. `None` where
+    /// there's no SIGHUP, or its handler can't be installed.
+    pub fn new(load: &'a dyn Fn() -> Result<Colourizer, Error>) -> Option<Reload<'a>> {
+        let requested = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        match signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&requested)) {
+            Ok(_) => Some(Reload { requested, load, colourizer: None }),
+            Err(err) => {
+                warn(&format!("Can not install SIGHUP handler: {}", err));
+                None
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (requested, load);
+            None
+        }
+    }
+
+
+    /// The colourizer of the config last reloaded, `None` if it never was.
+    pub fn colourizer(&self) -> Option<&Colourizer> {
+        self.colourizer.as_ref()
+    }
+
+
+    /// Load the config if SIGHUP came since the last time, returning
+    /// whether the rules changed. A config that can't be loaded, or has
+    /// problems, is reported and the rules stay as they were.
+    pub fn reload_if_requested(&mut self) -> bool {
+        if !self.requested.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        match (self.load)() {
+            Ok(colourizer) => {
+                warn(&format!("config reloaded, {} rules", colourizer.config().rules().len()));
+                self.colourizer = Some(colourizer);
+                true
+            }
+            Err(err) => {
+                warn(&format!("config not reloaded, keeping the rules as they were: {}", err));
+                false
+            }
+        }
+    }
+}
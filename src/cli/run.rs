@@ -0,0 +1,271 @@
+//! `rgrcat run -- CMD...` runs a command and colours its stdout and stderr
+//! with the conffile grc.conf picks for it, or the one given with
+//! `--config`. `--plain-stderr` leaves stderr as it is.
+//! `--dry-run` only reports how that choice was made, without running
+//! anything. Further `--` separate commands run one after the other while
+//! they succeed. When several grc.conf entries match, their conffiles are
+//! chained, the rules of each following those of the one before.
+
+use std::io::{self, BufReader, Write};
+use std::process::{Command, Stdio};
+
+use regex::Regex;
+use rgrcat::{find_config, get_config_path, get_grc_conf_entries, Colourizer, Config, ConfigError};
+
+use super::error::Error;
+use super::print_text;
+use super::pipeline::{self, Pick};
+
+
+pub const RUN_USAGE: &str = "rgrcat run [--config conffile | --config-for command] [--pick first|last] [--plain-stderr] [--dry-run | --dump-config] [--] command [args...] [-- command [args...]]...";
+
+
+struct RunOptions {
+    config_name: Option<String>,
+    /// The command grc.conf is matched against instead of the one run.
+    config_for: Option<String>,
+    pick: Pick,
+    /// Pass the command's stderr through uncoloured.
+    plain_stderr: bool,
+    dry_run: bool,
+    dump_config: bool,
+    commands: Vec<Vec<String>>,
+}
+
+
+fn get_run_options(args: impl IntoIterator<Item = String>) -> Result<RunOptions, Error> {
+    let usage = || Error::Usage(RUN_USAGE.to_string());
+    let mut args = args.into_iter();
+    let mut options = RunOptions { config_name: None, config_for: None, pick: Pick::First, plain_stderr: false, dry_run: false, dump_config: false,
+                               commands: vec![] };
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            options.config_name = Some(args.next().ok_or_else(usage)?);
+        } else if arg == "--config-for" {
+            options.config_for = Some(args.next().ok_or_else(usage)?);
+        } else if arg == "--pick" {
+            options.pick = args.next().as_deref().and_then(Pick::from_name).ok_or_else(usage)?;
+        } else if arg == "--plain-stderr" {
+            options.plain_stderr = true;
+        } else if arg == "--dry-run" {
+            options.dry_run = true;
+        } else if arg == "--dump-config" {
+            options.dump_config = true;
+        } else if arg == "--" {
+            // Only after a leading `--` does a later one start another command.
+            let mut command = vec![];
+            for arg in args.by_ref() {
+                if arg == "--" {
+                    options.commands.push(std::mem::take(&mut command));
+                } else {
+                    command.push(arg);
+                }
+            }
+            options.commands.push(command);
+        } else {
+            options.commands.push(std::iter::once(arg).chain(args.by_ref()).collect());
+        }
+    }
+    if options.commands.is_empty() || options.commands.iter().any(|command| command.is_empty())
+        || (options.config_name.is_some() && options.config_for.is_some()) || (options.dry_run && options.dump_config) {
+        return Err(usage());
+    }
+    Ok(options)
+}
+
+
+/// How the conffile for a command was found.
+struct Resolution {
+    /// What grc.conf is matched against.
+    command_line: String,
+    /// Where grc.conf is, `None` without one.
+    grc_conf: Option<String>,
+    /// The grc.conf entries matching the command in order, each its regexp
+    /// and conffile.
+    entries: Vec<(String, String)>,
+    /// The conffiles chained with their paths, each path once, empty when
+    /// the output isn't coloured.
+    chain: Vec<(String, String, Config)>,
+}
+
+
+impl Resolution {
+    /// The rules of the whole chain, `None` without any conffile.
+    fn get_config(&self) -> Option<Config> {
+        if self.chain.is_empty() {
+            return None;
+        }
+        Some(Config::from_rules(self.chain.iter().flat_map(|(_, _, config)| config.rules().to_vec()).collect()))
+    }
+}
+
+
+/// The command line grc.conf is matched against for `options`.
+fn get_command_line(options: &RunOptions) -> String {
+    if let Some(config_for) = &options.config_for {
+        return config_for.clone();
+    }
+    let command = match options.pick {
+        Pick::First => &options.commands[0],
+        Pick::Last => &options.commands[options.commands.len() - 1],
+    };
+    pipeline::get_command_line(command, options.pick)
+}
+
+
+fn get_full_command_line(options: &RunOptions) -> String {
+    options.commands.iter().map(|command| command.join(" ")).collect::<Vec<_>>().join(" -- ")
+}
+
+
+fn resolve(options: &RunOptions) -> Result<Resolution, Error> {
+    let command_line = get_command_line(options);
+    let grc_conf = match get_config_path("grc.conf") {
+        Ok(path) => Some(path),
+        Err(ConfigError::NotFound { .. }) => None,
+        Err(err) => return Err(err.into())
+    };
+    let entries: Vec<(String, String)> = if options.config_name.is_some() {
+        vec![]
+    } else {
+        get_grc_conf_entries()?.into_iter()
+            .filter(|(regexp, _)| Regex::new(regexp).is_ok_and(|re| re.is_match(&command_line)))
+            .collect()
+    };
+    let config_names = match &options.config_name {
+        Some(config_name) => vec![config_name],
+        None => entries.iter().map(|(_, config_name)| config_name).collect(),
+    };
+    let mut chain: Vec<(String, String, Config)> = vec![];
+    for config_name in config_names {
+        let (path, config) = find_config(config_name, false)?;
+        if !chain.iter().any(|(_, chained, _)| *chained == path) {
+            chain.push((config_name.clone(), path, config));
+        }
+    }
+    Ok(Resolution { command_line, grc_conf, entries, chain })
+}
+
+
+/// The dry-run report of `resolution` for `options`.
+fn get_report(options: &RunOptions, resolution: &Resolution) -> String {
+    let full_command_line = get_full_command_line(options);
+    let mut report = format!("command: {}\n", full_command_line);
+    if options.config_for.is_some() {
+        report.push_str(&format!("matched as: {}, from --config-for\n", resolution.command_line));
+    } else if resolution.command_line != full_command_line {
+        report.push_str(&format!("matched as: {}\n", resolution.command_line));
+    }
+    match (&options.config_name, &resolution.grc_conf) {
+        (Some(_), _) => report.push_str("grc.conf: not consulted, --config given\n"),
+        (None, Some(path)) => {
+            report.push_str(&format!("grc.conf: {}\n", path));
+            for (regexp, config_name) in &resolution.entries {
+                report.push_str(&format!("matched entry: {} -> {}\n", regexp, config_name));
+            }
+            if resolution.entries.is_empty() {
+                report.push_str("matched entry: none\n");
+            }
+        }
+        (None, None) => report.push_str("grc.conf: not found\n"),
+    }
+    for (config_name, path, config) in &resolution.chain {
+        let enabled = config.rules().iter().filter(|rule| rule.enabled()).count();
+        report.push_str(&format!("conffile: {} at {}\nrules: {} ({} enabled)\n", config_name, path, config.rules().len(), enabled));
+    }
+    match resolution.get_config() {
+        Some(config) if resolution.chain.len() > 1 => {
+            report.push_str(&format!("chained: {} conffiles, {} rules in all\n", resolution.chain.len(), config.rules().len()));
+        }
+        Some(_) => {}
+        None => report.push_str("conffile: none, the output passes through uncoloured\n"),
+    }
+    report.push_str("pty: no, the command writes to a pipe\n");
+    report.push_str("stderr: passed through uncoloured\n");
+    report
+}
+
+
+/// Print the rules of the chain as JSON, along with the conffiles they
+/// came from, like `rgrcat --dump-config` does for a single one.
+#[cfg(feature = "serde")]
+fn dump_config(resolution: &Resolution) -> Result<(), Error> {
+    let to_io_error = |err: serde_json::Error| Error::io("<stdout>", err.into());
+    let config = resolution.get_config().unwrap_or_else(|| Config::from_rules(vec![]));
+    let mut json = serde_json::to_value(&config).map_err(to_io_error)?;
+    let chain: Vec<_> = resolution.chain.iter()
+        .map(|(config_name, path, _)| {
+            let entry = resolution.entries.iter().find(|(_, entry_name)| entry_name == config_name).map(|(regexp, _)| regexp);
+            serde_json::json!({ "conffile": config_name, "path": path, "entry": entry })
+        })
+        .collect();
+    json["provenance"] = serde_json::json!({
+        "command_line": resolution.command_line,
+        "grc_conf": resolution.grc_conf,
+        "selected_by": match (resolution.entries.is_empty(), resolution.chain.is_empty()) {
+            (_, true) => None,
+            (true, false) => Some("--config"),
+            (false, false) => Some("grc.conf"),
+        },
+        "chain": chain,
+    });
+    super::print_line(&serde_json::to_string_pretty(&json).map_err(to_io_error)?)
+}
+
+
+#[cfg(not(feature = "serde"))]
+fn dump_config(_resolution: &Resolution) -> Result<(), Error> {
+    Err(Error::Usage(format!("{} (--dump-config needs the serde feature)", RUN_USAGE)))
+}
+
+
+/// Run `rgrcat run`, returning the exit code of the command.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<i32, Error> {
+    let options = get_run_options(args)?;
+    let resolution = resolve(&options)?;
+    if options.dry_run {
+        print_text(&get_report(&options, &resolution))?;
+        return Ok(0);
+    }
+    if options.dump_config {
+        dump_config(&resolution)?;
+        return Ok(0);
+    }
+
+    let config = resolution.get_config().unwrap_or_else(|| Config::from_rules(vec![]));
+    let colourizer = Colourizer::new(config.for_command(Some(&resolution.command_line)));
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut exit_code = 0;
+    for command in &options.commands {
+        let mut child = Command::new(&command[0]).args(&command[1..])
+            .stdout(Stdio::piped())
+            .stderr(if options.plain_stderr { Stdio::inherit() } else { Stdio::piped() })
+            .spawn()
+            .map_err(|err| Error::io(&command[0], err))?;
+        let output = BufReader::new(child.stdout.take().expect("the command's stdout is piped"));
+        let errors = child.stderr.take().map(BufReader::new);
+        // Each stream gets a thread, so neither fills up its pipe while the
+        // other one is read, and its lines stay in order on our side.
+        let coloured = std::thread::scope(|scope| {
+            let errors = errors.map(|errors| scope.spawn(|| colourizer.process(errors, io::stderr().lock())));
+            let coloured = colourizer.process(output, &mut stdout).and_then(|_| stdout.flush());
+            let errors_coloured = errors.map_or(Ok(()), |thread| thread.join().expect("colouring stderr doesn't panic"));
+            coloured.map_err(|err| Error::io("<output>", err)).and(errors_coloured.map_err(|err| Error::io("<stderr>", err)))
+        });
+        // The command's output is closed by now, so it ends even when the colouring failed.
+        let status = child.wait().map_err(|err| Error::io(&command[0], err))?;
+        exit_code = super::watch::get_exit_code(status);
+        if let Err(err) = coloured {
+            // Our own exit code tells of the failure, the command's still gets told.
+            if !err.is_broken_pipe() {
+                eprintln!("{} exited with {}", command[0], exit_code);
+            }
+            return Err(err);
+        }
+        if exit_code != 0 {
+            break;
+        }
+    }
+    Ok(exit_code)
+}
@@ -0,0 +1,93 @@
+//! `--sample N`: colour only the first of every N lines of a stream too
+//! big to look at all of it, the others pass through as they came in.
+//! With `--sample-matched` the lines matched by a rule tagged `important`
+//! are always coloured.
+
+use regex::RegexSet;
+use rgrcat::ColourConfig;
+
+
+/// The tag of the rules whose lines `--sample-matched` never skips.
+const IMPORTANT_TAG: &str = "important";
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampling {
+    /// Colour one line in this many, at least 1.
+    pub every: u64,
+    pub matched: bool,
+}
+
+
+/// Decides line by line what gets coloured.
+pub struct Sampler {
+    sampling: Sampling,
+    /// The regexps of the important rules, checked in one pass.
+    important: Option<RegexSet>,
+    line: u64,
+    /// The number of lines passed through uncoloured.
+    passed: u64,
+}
+
+
+impl Sampler {
+    pub fn new(sampling: Sampling, rules: &[ColourConfig]) -> Sampler {
+        let important = if sampling.matched {
+            let regexps = rules.iter()
+                .filter(|rule| rule.enabled() && rule.tags().iter().any(|tag| tag == IMPORTANT_TAG))
+                .filter(|rule| !rule.regexp().is_empty() && rule.compile_regexp().is_ok())
+                .map(|rule| rule.flagged_regexp());
+            RegexSet::new(regexps).ok()
+        } else {
+            None
+        };
+        Sampler { sampling, important, line: 0, passed: 0 }
+    }
+
+
+    /// Whether the next line, `line`, is to be coloured.
+    pub fn is_sampled(&mut self, line: &[u8]) -> bool {
+        let phase = self.line % self.sampling.every;
+        self.line += 1;
+        let sampled = phase == 0 || self.important.as_ref()
+            .is_some_and(|important| important.is_match(&String::from_utf8_lossy(line)));
+        if !sampled {
+            self.passed += 1;
+        }
+        sampled
+    }
+
+
+    /// A line for the reports on how much was left out.
+    pub fn report(&self) -> String {
+        format!("sampling: 1 in {} lines, {} of {} lines passed through uncoloured\n",
+                self.sampling.every, self.passed, self.line)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::Config;
+
+    #[test]
+    fn test_sampler() {
+        let config: Config = "regexp=ERROR\ntags=important\n-\nregexp=INFO\n".parse().unwrap();
+        let lines: [&[u8]; 7] = [b"INFO a", b"INFO b", b"ERROR c", b"INFO d", b"INFO e", b"INFO f", b"INFO g"];
+        let mut sampler = Sampler::new(Sampling { every: 3, matched: false }, config.rules());
+        let sampled: Vec<bool> = lines.iter().map(|line| sampler.is_sampled(line)).collect();
+        assert_eq!(sampled, vec![true, false, false, true, false, false, true]);
+        assert_eq!(sampler.report(), "sampling: 1 in 3 lines, 4 of 7 lines passed through uncoloured\n");
+
+        let mut sampler = Sampler::new(Sampling { every: 3, matched: true }, config.rules());
+        let sampled: Vec<bool> = lines.iter().map(|line| sampler.is_sampled(line)).collect();
+        assert_eq!(sampled, vec![true, false, true, true, false, false, true]);
+
+        // The flags of the important rules hold in the set as well.
+        let config: Config = "regexp=error\nignorecase=yes\ntags=important\n".parse().unwrap();
+        let mut sampler = Sampler::new(Sampling { every: 3, matched: true }, config.rules());
+        let sampled: Vec<bool> = lines.iter().map(|line| sampler.is_sampled(line)).collect();
+        assert_eq!(sampled, vec![true, false, true, true, false, false, true]);
+    }
+}
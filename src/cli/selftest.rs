@@ -0,0 +1,312 @@
+//! `rgrcat self-test DIR` checks conffiles against golden files. Every
+//! `NAME.conf` in the directory comes with a `NAME.input` to colour and a
+//! `NAME.expected` holding the output it should give. `--bless` writes the
+//! actual output to the `.expected` files instead of comparing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rgrcat::{Colourizer, Config, Style};
+
+use super::error::Error;
+use super::{print_line, print_text};
+
+
+pub const SELF_TEST_USAGE: &str = "rgrcat self-test [--bless] DIR";
+
+
+/// The name of an SGR parameter, `None` for ones rgrcat never writes.
+fn get_sgr_name(code: u32) -> Option<String> {
+    const COLOURS: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+    const ATTRIBUTES: [&str; 10] = ["reset", "bold", "dim", "italic", "underline", "blink", "rapid_blink", "reverse", "concealed", "strikethrough"];
+    let name = match code {
+        0..=9 => ATTRIBUTES[code as usize].to_string(),
+        30..=37 => COLOURS[code as usize - 30].to_string(),
+        40..=47 => format!("on_{}", COLOURS[code as usize - 40]),
+        90..=97 => format!("bright_{}", COLOURS[code as usize - 90]),
+        100..=107 => format!("on_bright_{}", COLOURS[code as usize - 100]),
+        _ => return None
+    };
+    Some(name)
+}
+
+
+/// Describe the parameters of an SGR sequence, such as `red bold` for
+/// `31;1`. Extended colours become `colour(n)` or `#rrggbb`.
+fn get_sgr_description(params: &str) -> Option<String> {
+    let codes = params.split(';')
+        .map(|code| if code.is_empty() { Some(0) } else { code.parse::<u32>().ok() })
+        .collect::<Option<Vec<u32>>>()?;
+    let mut names = vec![];
+    let mut codes = codes.into_iter();
+    while let Some(code) = codes.next() {
+        let name = match code {
+            38 | 48 => {
+                let prefix = if code == 48 { "on_" } else { "" };
+                match codes.next()? {
+                    5 => format!("{}colour({})", prefix, codes.next()?),
+                    2 => {
+                        let (r, g, b) = (codes.next()?, codes.next()?, codes.next()?);
+                        format!("{}#{:02x}{:02x}{:02x}", prefix, r, g, b)
+                    }
+                    _ => return None
+                }
+            }
+            _ => get_sgr_name(code)?
+        };
+        names.push(name);
+    }
+    Some(names.join(" "))
+}
+
+
+/// Describe a style like `get_sgr_description` does, `default` for a
+/// plain one.
+pub fn get_style_description(style: &Style) -> String {
+    let sequence = style.to_ansi();
+    match sequence.strip_prefix("\x1b[").and_then(|codes| codes.strip_suffix('m')) {
+        Some(codes) => get_sgr_description(codes).unwrap_or_else(|| codes.to_string()),
+        None => "default".to_string()
+    }
+}
+
+
+/// Make the escape sequences and control characters of `line` readable:
+/// SGR sequences become `{red bold}`, other control characters `\xNN`.
+fn get_visible(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(sequence) = rest.strip_prefix("\x1b[") {
+            let end = sequence.find(|c: char| !(c.is_ascii_digit() || c == ';'));
+            if let Some(end) = end.filter(|&end| sequence[end..].starts_with('m')) {
+                if let Some(description) = get_sgr_description(&sequence[..end]) {
+                    result.push('{');
+                    result.push_str(&description);
+                    result.push('}');
+                    rest = &sequence[end + 1..];
+                    continue;
+                }
+            }
+        }
+        match c {
+            '\t' => result.push_str("\\t"),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => result.push_str(&format!("\\x{:02x}", c as u32)),
+            c => result.push(c)
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Expected(usize, &'a str),
+    Actual(usize, &'a str),
+}
+
+
+/// Diff two texts line by line through their longest common subsequence.
+fn get_diff<'a>(expected: &'a str, actual: &'a str) -> Vec<DiffLine<'a>> {
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+    // lengths[i][j] is the LCS length of expected[i..] and actual[j..].
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push(DiffLine::Same(expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff.push(DiffLine::Expected(i + 1, expected[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Actual(j + 1, actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+
+/// A line of the report. A line missing its final newline is marked, since
+/// that's a difference too.
+fn get_report_line(marker: char, line_number: Option<usize>, line: &str) -> String {
+    let (text, newline) = match line.strip_suffix('\n') {
+        Some(text) => (text, ""),
+        None => (line, " (no newline at end)")
+    };
+    let line_number = line_number.map(|number| format!("{:>4}", number)).unwrap_or_else(|| "    ".to_string());
+    format!("{} {} | {}{}\n", marker, line_number, get_visible(text), newline)
+}
+
+
+/// A readable diff of `expected` and `actual`, changed lines with one line
+/// of context around them.
+fn get_diff_report(expected: &str, actual: &str) -> String {
+    let diff = get_diff(expected, actual);
+    let is_changed = |index: usize| diff.get(index).is_some_and(|line| !matches!(line, DiffLine::Same(_)));
+    let mut report = String::new();
+    let mut skipped = false;
+    for (index, line) in diff.iter().enumerate() {
+        match line {
+            DiffLine::Same(text) if is_changed(index + 1) || (index > 0 && is_changed(index - 1)) => {
+                report.push_str(&get_report_line(' ', None, text));
+                skipped = false;
+            }
+            DiffLine::Same(_) => {
+                if !skipped {
+                    report.push_str("  ...\n");
+                }
+                skipped = true;
+            }
+            DiffLine::Expected(number, text) => {
+                report.push_str(&get_report_line('-', Some(*number), text));
+                skipped = false;
+            }
+            DiffLine::Actual(number, text) => {
+                report.push_str(&get_report_line('+', Some(*number), text));
+                skipped = false;
+            }
+        }
+    }
+    report
+}
+
+
+/// The names of the triples in `dir`, taken from the `.conf` files.
+fn get_test_names(dir: &Path) -> Result<Vec<String>, Error> {
+    let entries = fs::read_dir(dir).map_err(|err| Error::io(&dir.display().to_string(), err))?;
+    let mut names = vec![];
+    for entry in entries {
+        let path = entry.map_err(|err| Error::io(&dir.display().to_string(), err))?.path();
+        if path.extension().is_some_and(|extension| extension == "conf") {
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+
+/// Colour the input of the triple `name`, a message on failure.
+fn get_actual(dir: &Path, name: &str) -> Result<Vec<u8>, String> {
+    let config = Config::from_path(dir.join(format!("{}.conf", name))).map_err(|err| err.to_string())?;
+    let input_path = dir.join(format!("{}.input", name));
+    let input = fs::read(&input_path).map_err(|err| format!("{}: {}", input_path.display(), err))?;
+    let mut output = vec![];
+    Colourizer::new(config).process(&input[..], &mut output).map_err(|err| format!("{}: {}", input_path.display(), err))?;
+    Ok(output)
+}
+
+
+/// Check or bless the triple `name`, returning whether it passed.
+fn run_test(dir: &Path, name: &str, bless: bool) -> Result<bool, Error> {
+    let actual = match get_actual(dir, name) {
+        Ok(actual) => actual,
+        Err(message) => {
+            print_line(&format!("FAIL {}: {}", name, message))?;
+            return Ok(false);
+        }
+    };
+    let expected_path: PathBuf = dir.join(format!("{}.expected", name));
+    if bless {
+        fs::write(&expected_path, &actual).map_err(|err| Error::io(&expected_path.display().to_string(), err))?;
+        print_line(&format!("blessed {}", name))?;
+        return Ok(true);
+    }
+    let expected = match fs::read(&expected_path) {
+        Ok(expected) => expected,
+        Err(err) => {
+            print_line(&format!("FAIL {}: {}: {}", name, expected_path.display(), err))?;
+            return Ok(false);
+        }
+    };
+    if expected == actual {
+        print_line(&format!("ok {}", name))?;
+        return Ok(true);
+    }
+    print_line(&format!("FAIL {}: output differs from {}", name, expected_path.display()))?;
+    print_text(&get_diff_report(&String::from_utf8_lossy(&expected), &String::from_utf8_lossy(&actual)))?;
+    Ok(false)
+}
+
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<i32, Error> {
+    let mut bless = false;
+    let mut dir = None;
+    for arg in args {
+        if arg == "--bless" {
+            bless = true;
+        } else if dir.is_none() {
+            dir = Some(PathBuf::from(arg));
+        } else {
+            return Err(Error::Usage(SELF_TEST_USAGE.to_string()));
+        }
+    }
+    let dir = dir.ok_or_else(|| Error::Usage(SELF_TEST_USAGE.to_string()))?;
+
+    let names = get_test_names(&dir)?;
+    let mut failed = 0;
+    for name in &names {
+        if !run_test(&dir, name, bless)? {
+            failed += 1;
+        }
+    }
+    print_line(&format!("{} passed, {} failed", names.len() - failed, failed))?;
+    Ok(if failed == 0 { 0 } else { 1 })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_visible() {
+        assert_eq!(get_visible("an \x1b[31;1mERROR\x1b[0m\there"), "an {red bold}ERROR{reset}\\there");
+        assert_eq!(get_visible("\x1b[38;5;196mx\x1b[48;2;0;128;255my"), "{colour(196)}x{on_#0080ff}y");
+        // Sequences it can't name stay visible as they are.
+        assert_eq!(get_visible("\x1b[2Jz\x07"), "\\x1b[2Jz\\x07");
+    }
+
+    #[test]
+    fn test_get_diff_report() {
+        let expected = "one\ntwo\n\x1b[31mthree\x1b[0m\nfour\nfive\nsix\n";
+        let actual = "one\ntwo\n\x1b[32mthree\x1b[0m\nfour\nfive\nsix";
+        assert_eq!(get_diff_report("same\n".repeat(4).as_str(), "same\nsame\nother\nsame\n"), concat!(
+            "  ...\n",
+            "       | same\n",
+            "-    3 | same\n",
+            "+    3 | other\n",
+            "       | same\n",
+        ));
+        assert_eq!(get_diff_report(expected, actual), concat!(
+            "  ...\n",
+            "       | two\n",
+            "-    3 | {red}three{reset}\n",
+            "+    3 | {green}three{reset}\n",
+            "       | four\n",
+            "       | five\n",
+            "-    6 | six\n",
+            "+    6 | six (no newline at end)\n",
+        ));
+    }
+}
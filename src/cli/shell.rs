@@ -0,0 +1,214 @@
+//! `rgrcat shell-init bash|zsh|fish` prints wrapper functions that pipe the
+//! commands with a known conffile through rgrcat, like grc.bashrc does.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rgrcat::{get_config_dirs, get_grc_conf_entries};
+
+use super::error::Error;
+use super::print_text;
+
+
+pub const SHELL_USAGE: &str = "rgrcat shell-init bash|zsh|fish";
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+
+/// Whether `name` can be used as a function name in all the shells.
+fn is_command_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(['-', '.'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "_.-+".contains(c))
+}
+
+
+/// The command a grc.conf regexp matches, when it starts with a plain word
+/// such as `(^|[/\w\.]+/)ping\s?`.
+fn get_regexp_command(regexp: &str) -> Option<&str> {
+    let rest = regexp.strip_prefix(r"(^|[/\w\.]+/)")
+        .or_else(|| regexp.strip_prefix('^'))
+        .unwrap_or(regexp);
+    let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(rest.len());
+    let (name, after) = rest.split_at(end);
+    // A quantifier or group right after means the word isn't literal.
+    let literal = after.is_empty() || after.starts_with(['\\', '$', ' ']);
+    if literal && is_command_name(name) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+
+/// The commands to wrap with their conffile names. A `conf.<cmd>` in the
+/// search path comes first, then grc.conf fills in the rest.
+fn get_wrapped_commands(dirs: &[PathBuf], grc_conf_entries: &[(String, String)], exclude: &[&str]) -> BTreeMap<String, String> {
+    let mut commands = BTreeMap::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let command = match file_name.to_str().and_then(|name| name.strip_prefix("conf.")) {
+                Some(command) if is_command_name(command) && !entry.path().is_dir() => command,
+                _ => continue
+            };
+            commands.entry(command.to_string()).or_insert_with(|| format!("conf.{}", command));
+        }
+    }
+    for (regexp, config_name) in grc_conf_entries {
+        if let Some(command) = get_regexp_command(regexp) {
+            commands.entry(command.to_string()).or_insert_with(|| config_name.clone());
+        }
+    }
+    commands.retain(|command, _| !exclude.contains(&command.as_str()));
+    commands
+}
+
+
+/// Quote `value` as a single word for `shell`.
+fn quote(value: &str, shell: Shell) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+    }
+}
+
+
+/// The wrapper function of `command`. Output that doesn't go to a terminal is
+/// left alone, and the exit status is the command's, not rgrcat's.
+fn get_wrapper(command: &str, config_name: &str, shell: Shell) -> String {
+    let config_name = quote(config_name, shell);
+    match shell {
+        Shell::Bash => format!(
+            "{command}() {{\n    if [ -t 1 ]; then\n        command {command} \"$@\" | rgrcat {config_name}\n        return \"${{PIPESTATUS[0]}}\"\n    fi\n    command {command} \"$@\"\n}}\n",
+            command = command, config_name = config_name),
+        Shell::Zsh => format!(
+            "{command}() {{\n    if [ -t 1 ]; then\n        command {command} \"$@\" | rgrcat {config_name}\n        return \"${{pipestatus[1]}}\"\n    fi\n    command {command} \"$@\"\n}}\n",
+            command = command, config_name = config_name),
+        Shell::Fish => format!(
+            "function {command} --wraps {command}\n    if isatty stdout\n        command {command} $argv | rgrcat {config_name}\n        return $pipestatus[1]\n    end\n    command {command} $argv\nend\n",
+            command = command, config_name = config_name),
+    }
+}
+
+
+fn get_shell_init(commands: &BTreeMap<String, String>, shell: Shell) -> String {
+    let mut result = String::from("# Generated by `rgrcat shell-init`.\n");
+    for (command, config_name) in commands {
+        result.push('\n');
+        result.push_str(&get_wrapper(command, config_name, shell));
+    }
+    result
+}
+
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<i32, Error> {
+    let mut args = args.into_iter();
+    let shell = match args.next().as_deref() {
+        Some("bash") => Shell::Bash,
+        Some("zsh") => Shell::Zsh,
+        Some("fish") => Shell::Fish,
+        _ => return Err(Error::Usage(SHELL_USAGE.to_string()))
+    };
+    if args.next().is_some() {
+        return Err(Error::Usage(SHELL_USAGE.to_string()));
+    }
+    let exclude = env::var("GRC_SHELL_EXCLUDE").unwrap_or_default();
+    let exclude: Vec<&str> = exclude.split(|c: char| c == ',' || c.is_whitespace()).filter(|name| !name.is_empty()).collect();
+    let commands = get_wrapped_commands(&get_config_dirs(), &get_grc_conf_entries()?, &exclude);
+    print_text(&get_shell_init(&commands, shell))?;
+    Ok(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_regexp_command() {
+        assert_eq!(get_regexp_command(r"(^|[/\w\.]+/)ping\s?"), Some("ping"));
+        assert_eq!(get_regexp_command(r"^docker-compose\s"), Some("docker-compose"));
+        assert_eq!(get_regexp_command(r"(^|[/\w\.]+/)g?cc\s"), None);
+        assert_eq!(get_regexp_command(r"(^|[/\w\.]+/)(ls|dir)\s"), None);
+    }
+
+    #[test]
+    fn test_shell_init_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("conf.ping"), "regexp=ms\ncolours=green\n").unwrap();
+        fs::write(dir.path().join("conf.df"), "regexp=%\ncolours=red\n").unwrap();
+        fs::write(dir.path().join("grc.conf"), "").unwrap();
+        let entries = [(r"(^|[/\w\.]+/)ping\s?".to_string(), "conf.other".to_string()),
+                       (r"^mount\s".to_string(), "conf.mount".to_string())];
+        let commands = get_wrapped_commands(&[dir.path().to_path_buf()], &entries, &["mount"]);
+
+        assert_eq!(get_shell_init(&commands, Shell::Bash), r#"# Generated by `rgrcat shell-init`.
+
+df() {
+    if [ -t 1 ]; then
+        command df "$@" | rgrcat 'conf.df'
+        return "${PIPESTATUS[0]}"
+    fi
+    command df "$@"
+}
+
+ping() {
+    if [ -t 1 ]; then
+        command ping "$@" | rgrcat 'conf.ping'
+        return "${PIPESTATUS[0]}"
+    fi
+    command ping "$@"
+}
+"#);
+        assert_eq!(get_shell_init(&commands, Shell::Zsh), r#"# Generated by `rgrcat shell-init`.
+
+df() {
+    if [ -t 1 ]; then
+        command df "$@" | rgrcat 'conf.df'
+        return "${pipestatus[1]}"
+    fi
+    command df "$@"
+}
+
+ping() {
+    if [ -t 1 ]; then
+        command ping "$@" | rgrcat 'conf.ping'
+        return "${pipestatus[1]}"
+    fi
+    command ping "$@"
+}
+"#);
+        assert_eq!(get_shell_init(&commands, Shell::Fish), r#"# Generated by `rgrcat shell-init`.
+
+function df --wraps df
+    if isatty stdout
+        command df $argv | rgrcat 'conf.df'
+        return $pipestatus[1]
+    end
+    command df $argv
+end
+
+function ping --wraps ping
+    if isatty stdout
+        command ping $argv | rgrcat 'conf.ping'
+        return $pipestatus[1]
+    end
+    command ping $argv
+end
+"#);
+        assert_eq!(quote("it's", Shell::Bash), r"'it'\''s'");
+        assert_eq!(quote(r"it's \", Shell::Fish), r"'it\'s \\'");
+    }
+}
@@ -0,0 +1,107 @@
+//! `--stats`: after the input ends, a table of what every rule did, in
+//! conffile order, and how the whole run went.
+
+use std::time::Instant;
+
+use rgrcat::{ColourConfig, Profile};
+
+
+/// Regexps longer than this many characters are cut in the table.
+const REGEXP_WIDTH: usize = 40;
+
+
+/// How many lines went through and how many of them the rules matched or
+/// skipped, the rules themselves counted by the `Profile`.
+pub struct Stats {
+    started: Instant,
+    lines: u64,
+    matched: u64,
+    skipped: u64,
+}
+
+
+/// `regexp` cut to `REGEXP_WIDTH` characters, with `…` where it was cut.
+fn get_short_regexp(regexp: &str) -> String {
+    match regexp.char_indices().nth(REGEXP_WIDTH) {
+        Some((end, _)) => format!("{}…", &regexp[..end]),
+        None => regexp.to_string()
+    }
+}
+
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats { started: Instant::now(), lines: 0, matched: 0, skipped: 0 }
+    }
+
+
+    /// Count a line read, whichever way it goes.
+    pub fn record_line(&mut self) {
+        self.lines += 1;
+    }
+
+
+    /// Count a line the rules ran on by whether a `skip=yes` rule dropped
+    /// it and whether any regexp matched, which it did if `profile` found
+    /// more than `found_before` matches.
+    pub fn record_processed(&mut self, skipped: bool, profile: &Profile, found_before: u64) {
+        if skipped {
+            self.skipped += 1;
+        }
+        if profile.get_found() > found_before {
+            self.matched += 1;
+        }
+    }
+
+
+    /// The table of `rules` as `profile` counted them, and the totals.
+    pub fn report(&self, rules: &[ColourConfig], profile: &Profile) -> String {
+        let elapsed = self.started.elapsed();
+        let mut report = "rule  source              lines  matches  time_ms     regexp\n".to_string();
+        for rule in rules {
+            let timing = profile.get_timing(rule.index()).cloned().unwrap_or_default();
+            let source = if rule.source().is_empty() {
+                "-".to_string()
+            } else {
+                format!("{}:{}", rule.source().rsplit('/').next().unwrap_or_default(), rule.line())
+            };
+            report.push_str(&format!("{:<5} {:<19} {:<6} {:<8} {:<11.3} {}\n", rule.index(), source, timing.matched_lines, timing.found,
+                                     timing.total.as_secs_f64() * 1000.0, get_short_regexp(rule.regexp())));
+        }
+        let rate = if elapsed.is_zero() { 0.0 } else { self.lines as f64 / elapsed.as_secs_f64() };
+        report.push_str(&format!("{} lines read, {} with a match, {} skipped, in {:.3}ms, {:.0} lines/sec\n",
+                                 self.lines, self.matched, self.skipped, elapsed.as_secs_f64() * 1000.0, rate));
+        report
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgrcat::{Colourizer, Config, StreamState};
+
+    #[test]
+    fn test_report() {
+        let config: Config = "regexp=\\d\ncolours=green\n-\nregexp=^debug\nskip=yes\n-\nregexp=never matches, and is quite a bit longer than forty\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        let mut profile = Profile::new(colourizer.config().rules());
+        let mut stats = Stats::new();
+        for line in ["a 1 2 3", "debug 4", "none"] {
+            stats.record_line();
+            let found = profile.get_found();
+            let processed = colourizer.process_next_line(line, &mut StreamState::new(), Some(&mut profile));
+            stats.record_processed(processed.is_none(), &profile, found);
+        }
+
+        let report = stats.report(colourizer.config().rules(), &profile);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5);
+        // The skipped line never got to the first rule.
+        assert!(lines[1].starts_with("0     <string>:1          1      3 "), "{}", lines[1]);
+        assert!(lines[1].ends_with(" \\d"));
+        assert!(lines[2].starts_with("1     <string>:4          1      1 "), "{}", lines[2]);
+        assert!(lines[3].ends_with(" never matches, and is quite a bit longer…"), "{}", lines[3]);
+        assert!(lines[4].starts_with("3 lines read, 2 with a match, 1 skipped, in "), "{}", lines[4]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Restoring the terminal after the modes which change its state: the saved
+//! termios settings of `--interactive`, and the alternate screen of watch mode.
+//! `restore` may run from a panic hook or a signal handler, so it only reads
+//! atomics and writes fixed byte strings. On Windows the console is also
+//! made to interpret escape sequences in the first place.
+
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+
+/// Reset the style, the scroll region, show the cursor and leave the alternate screen.
+pub const RESET_SEQUENCE: &[u8] = b"\x1b[0m\x1b[r\x1b[?25h\x1b[?1049l";
+
+static SCREEN_TAKEN: AtomicBool = AtomicBool::new(false);
+static TERMIOS_FD: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static SAVED_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+static PANIC_HOOK: Once = Once::new();
+
+
+/// Have the Windows console interpret the escape sequences written to
+/// stdout, which it only does with virtual terminal processing on. Nothing
+/// changes when stdout is no console.
+#[cfg(windows)]
+pub fn enable_escape_sequences() {
+    type Handle = *mut std::ffi::c_void;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> Handle;
+        fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+    }
+    unsafe {
+        let console = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(console, &mut mode) != 0 {
+            SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+        }
+    }
+}
+
+
+/// Terminals elsewhere interpret escape sequences as they are.
+#[cfg(not(windows))]
+pub fn enable_escape_sequences() {}
+
+
+/// Remember that stdout shows our screen, which must be reset on the way out.
+pub fn take_screen() {
+    SCREEN_TAKEN.store(true, Ordering::SeqCst);
+}
+
+
+/// Save the termios settings of `fd`, to be put back by `restore`.
+#[cfg(unix)]
+pub fn save_termios(fd: i32) -> std::io::Result<libc::termios> {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let _ = SAVED_TERMIOS.set(termios);
+    TERMIOS_FD.store(fd, Ordering::SeqCst);
+    Ok(termios)
+}
+
+
+/// Put the terminal back the way we found it. Only the first call after a
+/// change does anything, so it's fine to call it from everywhere.
+pub fn restore() {
+    #[cfg(unix)]
+    {
+        let fd = TERMIOS_FD.swap(-1, Ordering::SeqCst);
+        if let (true, Some(termios)) = (fd >= 0, SAVED_TERMIOS.get()) {
+            unsafe {
+                libc::tcsetattr(fd, libc::TCSANOW, termios);
+            }
+        }
+        if SCREEN_TAKEN.swap(false, Ordering::SeqCst) {
+            unsafe {
+                libc::write(libc::STDOUT_FILENO, RESET_SEQUENCE.as_ptr() as *const libc::c_void, RESET_SEQUENCE.len());
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    if SCREEN_TAKEN.swap(false, Ordering::SeqCst) {
+        use std::io::Write;
+        let _ = std::io::stdout().write_all(RESET_SEQUENCE).and_then(|_| std::io::stdout().flush());
+    }
+}
+
+
+/// Restore the terminal before a panic message gets printed, and before the
+/// given signals kill us.
+pub fn install_cleanup(signals: &[i32]) -> std::io::Result<()> {
+    PANIC_HOOK.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            previous_hook(info);
+        }));
+    });
+    #[cfg(unix)]
+    for &signal in signals {
+        unsafe {
+            signal_hook::low_level::register(signal, move || {
+                restore();
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+            })?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signals;
+    Ok(())
+}
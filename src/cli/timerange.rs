@@ -0,0 +1,82 @@
+//! `--since` and `--until`: only the lines whose timestamp, as found by the
+//! `timestamp=yes` rules, lies within the range get through.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rgrcat::parse_timestamp;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    /// Both ends in seconds since the epoch, included.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Drop the lines without a timestamp instead of keeping them.
+    pub strict: bool,
+    /// When the run started, the date of timestamps without one.
+    pub now: i64,
+}
+
+
+pub fn get_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+
+/// The time `spec` stands for: `now`, an offset back from now like `-15m`,
+/// `-2h`, `-30s` or `-1d`, or a timestamp like those of the log lines.
+pub fn get_time(spec: &str, now: i64) -> Option<i64> {
+    if spec == "now" {
+        return Some(now);
+    }
+    if let Some(offset) = spec.strip_prefix('-') {
+        let unit = match offset.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        let count: i64 = offset[..offset.len() - 1].parse().ok().filter(|count| *count >= 0)?;
+        return Some(now - count * unit);
+    }
+    parse_timestamp(spec, now)
+}
+
+
+impl TimeRange {
+    /// Whether a line with `timestamp` gets through.
+    pub fn contains(&self, timestamp: Option<i64>) -> bool {
+        match timestamp {
+            Some(timestamp) => self.since.is_none_or(|since| timestamp >= since) && self.until.is_none_or(|until| timestamp <= until),
+            None => !self.strict,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_time() {
+        let now = 1_714_564_800;
+        assert_eq!(get_time("now", now), Some(now));
+        assert_eq!(get_time("-15m", now), Some(now - 900));
+        assert_eq!(get_time("-1d", now), Some(now - 86400));
+        assert_eq!(get_time("10:30", now), Some(1_714_559_400));
+        assert_eq!(get_time("2024-05-01T11:00Z", now), Some(1_714_561_200));
+        assert_eq!(get_time("-15", now), None);
+        assert_eq!(get_time("-m", now), None);
+        assert_eq!(get_time("--5m", now), None);
+    }
+
+    #[test]
+    fn test_contains() {
+        let range = TimeRange { since: Some(10), until: Some(20), strict: false, now: 0 };
+        assert!(range.contains(Some(10)) && range.contains(Some(20)) && range.contains(None));
+        assert!(!range.contains(Some(9)) && !range.contains(Some(21)));
+        assert!(!TimeRange { strict: true, ..range }.contains(None));
+    }
+}
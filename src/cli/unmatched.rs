@@ -0,0 +1,74 @@
+//! `--unmatched-report`: after the input ends, list the rules which never
+//! matched a line, where they come from and whether they ever got to look
+//! at a line at all.
+
+use rgrcat::{ColourConfig, Colourizer};
+
+
+/// How many lines every rule looked at and matched, by rule index.
+pub struct RuleActivity {
+    lines: u64,
+    evaluated: Vec<u64>,
+    matched: Vec<u64>,
+}
+
+
+impl RuleActivity {
+    pub fn new(rules: &[ColourConfig]) -> RuleActivity {
+        RuleActivity { lines: 0, evaluated: vec![0; rules.len()], matched: vec![0; rules.len()] }
+    }
+
+
+    /// Count which rules of `colourizer` looked at `line` and which matched it.
+    pub fn record(&mut self, colourizer: &Colourizer, line: &str) {
+        self.lines += 1;
+        for rule in colourizer.config().rules() {
+            if !rule.enabled() || rule.regexp().is_empty() || !rule.is_applied_to(line) {
+                continue;
+            }
+            if let Some(evaluated) = self.evaluated.get_mut(rule.index()) {
+                *evaluated += 1;
+            }
+        }
+        for rule in colourizer.matching_rules(line) {
+            if let Some(matched) = self.matched.get_mut(rule.index()) {
+                *matched += 1;
+            }
+        }
+    }
+
+
+    /// The rules of `rules` which never matched, empty when all did.
+    pub fn report(&self, rules: &[ColourConfig]) -> String {
+        let mut report = String::new();
+        for rule in rules {
+            let (evaluated, matched) = match (self.evaluated.get(rule.index()), self.matched.get(rule.index())) {
+                (Some(&evaluated), Some(&matched)) => (evaluated, matched),
+                _ => continue
+            };
+            if matched > 0 || rule.regexp().is_empty() {
+                continue;
+            }
+            let origin = if rule.source().is_empty() {
+                "built in code".to_string()
+            } else {
+                format!("{} line {}", rule.source(), rule.line())
+            };
+            let reason = if !rule.enabled() {
+                "never evaluated, it is disabled".to_string()
+            } else if self.lines == 0 {
+                "never evaluated, there was no input".to_string()
+            } else if evaluated == 0 {
+                "never evaluated, every line was over its max_line_length".to_string()
+            } else {
+                format!("evaluated on {} lines, never matched", evaluated)
+            };
+            report.push_str(&format!("  rule {} {} ({}): {}\n", rule.index(), rule.label(), origin, reason));
+        }
+        if report.is_empty() {
+            report
+        } else {
+            format!("rules which never matched:\n{}", report)
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::{self, Write};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rgrcat::colour::get_colour;
+use rgrcat::{find_config, get_config_name_for_command, Colourizer, Config};
+
+use super::error::Error;
+use super::term;
+
+
+pub const WATCH_USAGE: &str = "rgrcat watch [-n SECONDS] [--config conffile] [--watch-diff STYLE] [--] command [args...]";
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h";
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+
+struct WatchOptions {
+    interval: Duration,
+    config_name: Option<String>,
+    diff_style: Option<String>,
+    command: Vec<String>,
+}
+
+
+fn get_watch_options(args: impl IntoIterator<Item = String>) -> Result<WatchOptions, Error> {
+    let usage = || Error::Usage(WATCH_USAGE.to_string());
+    let mut args = args.into_iter();
+    let mut options = WatchOptions {
+        interval: Duration::from_secs(2),
+        config_name: None,
+        diff_style: None,
+        command: vec![],
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "-n" || arg == "--interval" {
+            let seconds: f64 = args.next().and_then(|value| value.parse().ok()).ok_or_else(usage)?;
+            if !seconds.is_finite() || seconds <= 0.0 {
+                return Err(usage());
+            }
+            options.interval = Duration::from_secs_f64(seconds);
+        } else if arg == "--config" {
+            options.config_name = Some(args.next().ok_or_else(usage)?);
+        } else if arg == "--watch-diff" {
+            options.diff_style = Some(args.next().ok_or_else(usage)?);
+        } else if arg == "--" {
+            options.command.extend(args.by_ref());
+        } else {
+            options.command.push(arg);
+            options.command.extend(args.by_ref());
+        }
+    }
+
+    if options.command.is_empty() {
+        return Err(usage());
+    }
+    Ok(options)
+}
+
+
+/// Compare `line` with the line at the same position of the previous run,
+/// returning for every char whether it differs from the char in the same cell.
+fn get_changed_cells(line: &str, previous: Option<&str>) -> Vec<bool> {
+    match previous {
+        Some(previous) => {
+            let mut previous_chars = previous.chars();
+            line.chars().map(|c| previous_chars.next() != Some(c)).collect()
+        }
+        None => line.chars().map(|_| true).collect()
+    }
+}
+
+
+/// The SGR sequence that turns off `style` without resetting other colours.
+fn get_style_off(style: &str) -> &'static str {
+    match style {
+        "bold" | "dark" => "\x1b[22m",
+        "italic" => "\x1b[23m",
+        "underline" => "\x1b[24m",
+        "blink" | "rapidblink" => "\x1b[25m",
+        "reverse" => "\x1b[27m",
+        "concealed" => "\x1b[28m",
+        "strikethrough" => "\x1b[29m",
+        _ if style.starts_with("on_") => "\x1b[49m",
+        _ => "\x1b[39m",
+    }
+}
+
+
+/// Overlay `style_on`/`style_off` on the cells marked in `changed`, walking the
+/// coloured line so that escape sequences do not count as cells.
+fn highlight_changed_cells(coloured: &str, changed: &[bool], style_on: &str, style_off: &str) -> String {
+    let mut result = String::with_capacity(coloured.len());
+    let mut chars = coloured.chars().peekable();
+    let mut cell = 0;
+    let mut highlighting = false;
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Copy the whole escape sequence, and re-apply the highlight after
+            // it in case it was a reset.
+            result.push(c);
+            if chars.peek() == Some(&'[') {
+                for c in chars.by_ref() {
+                    result.push(c);
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            if highlighting {
+                result.push_str(style_on);
+            }
+            continue;
+        }
+        let is_changed = changed.get(cell).copied().unwrap_or(false);
+        if is_changed && !highlighting {
+            result.push_str(style_on);
+        } else if !is_changed && highlighting {
+            result.push_str(style_off);
+        }
+        highlighting = is_changed;
+        result.push(c);
+        cell += 1;
+    }
+    if highlighting {
+        result.push_str(style_off);
+    }
+    result
+}
+
+
+fn render(output: &str, previous: Option<&[String]>, colourizer: &Colourizer, diff_style: Option<&str>) -> String {
+    let mut result = String::new();
+    for (index, line) in output.lines().enumerate() {
+        let coloured = colourizer.colourize_line(line);
+        match (diff_style, previous) {
+            (Some(style), Some(previous)) => {
+                let changed = get_changed_cells(line, previous.get(index).map(|line| &line[..]));
+                result.push_str(&highlight_changed_cells(&coloured, &changed, &get_colour(style), get_style_off(style)));
+            }
+            _ => result.push_str(&coloured),
+        }
+        result.push('\n');
+    }
+    result
+}
+
+
+fn get_killing_signal(status: std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+
+pub(super) fn get_exit_code(status: std::process::ExitStatus) -> i32 {
+    match get_killing_signal(status) {
+        Some(signal) => 128 + signal,
+        None => status.code().unwrap_or(1)
+    }
+}
+
+
+/// Run `rgrcat watch`, returning the exit code of the last command run.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<i32, Error> {
+    let options = get_watch_options(args)?;
+    let command_line = options.command.join(" ");
+    let config_name = match options.config_name {
+        Some(config_name) => Some(config_name),
+        None => get_config_name_for_command(&command_line)?
+    };
+    let config = match config_name {
+        Some(config_name) => find_config(&config_name, false)?.1,
+        None => Config::from_rules(vec![])
+    };
+    let colourizer = Colourizer::new(config.for_command(Some(&command_line)));
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+        // Ctrl-C ends watching gracefully, other fatal signals still restore the screen.
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, Arc::clone(&interrupted)).map_err(|err| Error::io("signal handler", err))?;
+        }
+        term::install_cleanup(&[SIGHUP, SIGQUIT]).map_err(|err| Error::io("signal handler", err))?;
+    }
+    #[cfg(not(unix))]
+    term::install_cleanup(&[]).map_err(|err| Error::io("signal handler", err))?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let write_error = |err| Error::io("<stdout>", err);
+    term::take_screen();
+    write!(stdout, "{}", ENTER_ALTERNATE_SCREEN).and_then(|_| stdout.flush()).map_err(write_error)?;
+
+    let mut previous: Option<Vec<String>> = None;
+    let mut exit_code = 0;
+    let result = loop {
+        let started = Instant::now();
+        let output = match Command::new(&options.command[0]).args(&options.command[1..]).output() {
+            Ok(output) => output,
+            Err(err) => break Err(Error::io(&options.command[0], err))
+        };
+        exit_code = get_exit_code(output.status);
+        if interrupted.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        // A command killed by a signal most likely crashed, and no further run
+        // will make it any better: give the terminal back.
+        if get_killing_signal(output.status).is_some() {
+            break Ok(());
+        }
+
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let rendered = render(&text, previous.as_deref(), &colourizer, options.diff_style.as_deref());
+        let header = format!("Every {:.1}s: {}\n\n", options.interval.as_secs_f64(), command_line);
+        if let Err(err) = write!(stdout, "{}{}{}", CLEAR_SCREEN, header, rendered).and_then(|_| stdout.flush()) {
+            break Err(write_error(err));
+        }
+        previous = Some(text.lines().map(|line| line.to_string()).collect());
+
+        while started.elapsed() < options.interval && !interrupted.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if interrupted.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+    };
+
+    let _ = stdout.flush();
+    term::restore();
+    result.map(|_| exit_code)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_changed_cells() {
+        assert_eq!(get_changed_cells("/dev/sda1  42%", Some("/dev/sda1  41%")),
+                   vec![false, false, false, false, false, false, false, false, false, false, false, false, true, false]);
+        assert_eq!(get_changed_cells("abc", Some("a")), vec![false, true, true]);
+        assert_eq!(get_changed_cells("ab", None), vec![true, true]);
+    }
+
+    #[test]
+    fn test_highlight_changed_cells() {
+        let coloured = "\x1b[32m42\x1b[0m%";
+        let changed = get_changed_cells("42%", Some("41%"));
+        assert_eq!(highlight_changed_cells(coloured, &changed, "\x1b[7m", "\x1b[27m"),
+                   "\x1b[32m4\x1b[7m2\x1b[0m\x1b[7m\x1b[27m%");
+    }
+}
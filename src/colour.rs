@@ -0,0 +1,455 @@
+//! grcat colour names and their escape sequences.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::log::warn;
+use crate::style::{Attributes, Colour, NamedColour, Style, RESET};
+
+
+/// Get the escape sequence of a grcat colour name like `bold` or `on_red`.
+/// A raw sequence like `\033[38;5;208m` is passed through as it is.
+/// Unknown names give the reset sequence. Names `GRC_COLOURS` remaps give
+/// the sequence of their substitute.
+pub fn get_colour(colour_name: &str) -> String {
+    if let Some(parameters) = get_raw_parameters(colour_name) {
+        return format!("\x1b[{}m", parameters);
+    }
+    if let Some(style) = get_overrides().get(colour_name) {
+        return style.to_ansi();
+    }
+    match colour_name {
+        "none" => String::new(),
+        "default" => RESET.to_string(),
+        "beep" => "\x07".to_string(),
+        "previous" => "prev".to_string(),
+        "unchanged" => "unchanged".to_string(),
+        // We don't raise Exception like original grc, instead of return default value.
+        _ => colour_name.parse::<Style>().map_or_else(|_| RESET.to_string(), |style| style.to_ansi())
+    }
+}
+
+
+/// Get the escape sequences of a `colours=` value, one for each group. As
+/// in grcat commas separate the groups and the names within a group,
+/// separated by spaces, go together, so `bold red, underline yellow` gives
+/// `\x1b[1;31m` and `\x1b[4;33m`.
+pub fn get_colour_list(raw_colour: &str) -> Vec<String> {
+    get_colour_groups(raw_colour).iter()
+        .map(|group| match &group[..] {
+            [name] => get_colour(name),
+            names => get_combined_style(&names.join(" ")).to_ansi(),
+        })
+        .collect()
+}
+
+
+/// Get the names of a `colours=` value by group, leaving out empty ones.
+pub(crate) fn get_colour_groups(raw_colour: &str) -> Vec<Vec<String>> {
+    raw_colour.split(',')
+        .map(|colours| colours.split_whitespace().map(|colour| colour.to_string()).collect::<Vec<_>>())
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+
+/// Get the names of a `colours=` value, of all groups.
+pub(crate) fn get_colour_name_list(raw_colour: &str) -> Vec<String> {
+    get_colour_groups(raw_colour).into_iter().flatten().collect()
+}
+
+
+fn get_named_colour(colour_name: &str) -> Option<NamedColour> {
+    let colour = match colour_name {
+        "black" => NamedColour::Black,
+        "red" => NamedColour::Red,
+        "green" => NamedColour::Green,
+        "yellow" => NamedColour::Yellow,
+        "blue" => NamedColour::Blue,
+        "magenta" => NamedColour::Magenta,
+        "cyan" => NamedColour::Cyan,
+        "white" => NamedColour::White,
+        "bright_black" => NamedColour::BrightBlack,
+        "bright_red" => NamedColour::BrightRed,
+        "bright_green" => NamedColour::BrightGreen,
+        "bright_yellow" => NamedColour::BrightYellow,
+        "bright_blue" => NamedColour::BrightBlue,
+        "bright_magenta" => NamedColour::BrightMagenta,
+        "bright_cyan" => NamedColour::BrightCyan,
+        "bright_white" => NamedColour::BrightWhite,
+        _ => return None
+    };
+    Some(colour)
+}
+
+
+/// The number of a colour of the 256-colour palette, `colour(N)`, `colourN`
+/// or `colorN`, which may be out of the palette.
+fn get_palette_number(colour_name: &str) -> Option<u32> {
+    let number = match colour_name.strip_prefix("colour(").and_then(|rest| rest.strip_suffix(')')) {
+        Some(number) => number,
+        None => colour_name.strip_prefix("colour").or_else(|| colour_name.strip_prefix("color"))?,
+    };
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some(number.parse().unwrap_or(u32::MAX))
+}
+
+
+/// A colour beyond the named ones: one of the 256-colour palette or
+/// `#rrggbb` and `#rgb`, in either case.
+fn get_extended_colour(colour_name: &str) -> Option<Colour> {
+    if let Some(number) = get_palette_number(colour_name) {
+        return u8::try_from(number).ok().map(Colour::Indexed);
+    }
+    let hex = colour_name.strip_prefix('#').filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))?;
+    // Each digit of `#rgb` stands for two, so `#f80` is `#ff8800`.
+    let (width, scale) = match hex.len() {
+        3 => (1, 17),
+        6 => (2, 1),
+        _ => return None
+    };
+    let channel = |index: usize| u8::from_str_radix(&hex[index * width..(index + 1) * width], 16).ok().map(|value| value * scale);
+    Some(Colour::Rgb(channel(0)?, channel(1)?, channel(2)?))
+}
+
+
+/// The escapes a raw sequence may start with, written out in a conffile or
+/// the escape itself.
+const RAW_PREFIXES: [&str; 4] = ["\\033[", "\\x1b[", "\\e[", "\x1b["];
+
+
+/// Whether `colour_name` is bare SGR codes like `01;34`, as grcat takes
+/// them, only digits and semicolons.
+fn is_numeric(colour_name: &str) -> bool {
+    colour_name.bytes().any(|byte| byte.is_ascii_digit()) && colour_name.bytes().all(|byte| byte.is_ascii_digit() || byte == b';')
+}
+
+
+/// The SGR parameters of a raw escape sequence like `\033[1;31m`, as in
+/// `1;31`, or of bare codes like `1;31`. The sequence may be in quotes, as
+/// grcat conffiles have it.
+fn get_raw_parameters(colour_name: &str) -> Option<&str> {
+    let unquoted = ['"', '\''].iter()
+        .find_map(|quote| colour_name.strip_prefix(*quote).and_then(|rest| rest.strip_suffix(*quote)))
+        .unwrap_or(colour_name);
+    if is_numeric(unquoted) {
+        return Some(unquoted);
+    }
+    RAW_PREFIXES.iter().find_map(|prefix| unquoted.strip_prefix(prefix))?.strip_suffix('m')
+}
+
+
+/// Whether `colour_name` is written like a raw escape sequence or bare
+/// codes, whether or not it is one of colours.
+fn is_raw(colour_name: &str) -> bool {
+    let unquoted = colour_name.trim_matches(|c| c == '"' || c == '\'');
+    is_numeric(unquoted) || RAW_PREFIXES.iter().any(|prefix| unquoted.starts_with(prefix))
+}
+
+
+const SGR_COLOURS: [NamedColour; 16] = [
+    NamedColour::Black, NamedColour::Red, NamedColour::Green, NamedColour::Yellow,
+    NamedColour::Blue, NamedColour::Magenta, NamedColour::Cyan, NamedColour::White,
+    NamedColour::BrightBlack, NamedColour::BrightRed, NamedColour::BrightGreen, NamedColour::BrightYellow,
+    NamedColour::BrightBlue, NamedColour::BrightMagenta, NamedColour::BrightCyan, NamedColour::BrightWhite,
+];
+
+
+/// The colour of the extended SGR codes after a 38 or 48, `5;N` for the
+/// palette and `2;R;G;B` for 24 bits.
+fn get_sgr_extended_colour(codes: &mut impl Iterator<Item = Option<u8>>) -> Option<Colour> {
+    match codes.next()?? {
+        5 => Some(Colour::Indexed(codes.next()??)),
+        2 => Some(Colour::Rgb(codes.next()??, codes.next()??, codes.next()??)),
+        _ => None
+    }
+}
+
+
+/// The style the SGR sequence with `parameters` switches to from the
+/// default one, `None` if it holds codes that are neither colours nor
+/// attributes.
+fn get_sgr_style(parameters: &str) -> Option<Style> {
+    let mut style = Style::default();
+    // An empty code is a 0, as in `\033[m`.
+    let mut codes = parameters.split(';').map(|code| if code.is_empty() { Some(0) } else { code.parse::<u8>().ok() });
+    while let Some(code) = codes.next() {
+        match code? {
+            0 => style = Style::default(),
+            code @ 1..=9 => {
+                let (attribute, _, _) = Attributes::CODES.iter().find(|(_, attribute_code, _)| *attribute_code == code)?;
+                style.attributes |= *attribute;
+            }
+            code @ 30..=37 => style.fg = Some(Colour::Named(SGR_COLOURS[usize::from(code - 30)])),
+            code @ 90..=97 => style.fg = Some(Colour::Named(SGR_COLOURS[usize::from(code - 90 + 8)])),
+            code @ 40..=47 => style.bg = Some(Colour::Named(SGR_COLOURS[usize::from(code - 40)])),
+            code @ 100..=107 => style.bg = Some(Colour::Named(SGR_COLOURS[usize::from(code - 100 + 8)])),
+            38 => style.fg = Some(get_sgr_extended_colour(&mut codes)?),
+            48 => style.bg = Some(get_sgr_extended_colour(&mut codes)?),
+            39 => style.fg = None,
+            49 => style.bg = None,
+            _ => return None
+        }
+    }
+    Some(style)
+}
+
+
+/// Get the style of a grcat colour name, or of `colour(N)`, `colourN` or
+/// `colorN` and `#rrggbb` or `#rgb` with `on_` in front for the background. Names which don't change how
+/// text looks, like `none`, `default` or unknown ones, give the plain style.
+/// Names `GRC_COLOURS` remaps give the style of their substitute.
+pub fn get_style(colour_name: &str) -> Style {
+    match get_overrides().get(colour_name) {
+        Some(style) => *style,
+        None => get_own_style(colour_name)
+    }
+}
+
+
+/// The style of a colour name like `get_style`, but never remapped.
+fn get_own_style(colour_name: &str) -> Style {
+    if let Some(parameters) = get_raw_parameters(colour_name) {
+        return get_sgr_style(parameters).unwrap_or_default();
+    }
+    let mut style = Style::default();
+    let attributes = match colour_name {
+        "bold" => Attributes::BOLD,
+        "dark" => Attributes::DIM,
+        "italic" => Attributes::ITALIC,
+        "underline" => Attributes::UNDERLINE,
+        "blink" => Attributes::BLINK,
+        "rapidblink" => Attributes::RAPID_BLINK,
+        "reverse" => Attributes::REVERSE,
+        "concealed" => Attributes::CONCEALED,
+        "strikethrough" => Attributes::STRIKETHROUGH,
+        _ => Attributes::empty()
+    };
+    style.attributes = attributes;
+    let get_colour = |name| get_named_colour(name).map(Colour::Named).or_else(|| get_extended_colour(name));
+    if let Some(colour) = colour_name.strip_prefix("on_").and_then(get_colour) {
+        style.bg = Some(colour);
+    } else if let Some(colour) = get_colour(colour_name) {
+        style.fg = Some(colour);
+    }
+    style
+}
+
+
+/// Whether `colour_name` is one of grcat, of the palette or a 24-bit one.
+fn is_colour_name(colour_name: &str) -> bool {
+    const NAMES: [&str; 14] = ["none", "default", "bold", "underline", "blink", "reverse", "concealed", "beep", "previous",
+                               "unchanged", "dark", "italic", "rapidblink", "strikethrough"];
+    let colour = colour_name.strip_prefix("on_").unwrap_or(colour_name);
+    NAMES.contains(&colour_name) || get_named_colour(colour).is_some() || get_extended_colour(colour).is_some()
+}
+
+
+/// The warning about a colour name that can't be used: an unknown one, a
+/// raw sequence of other things than colours, or one that looks like one
+/// of the palette but lies beyond it, like `color999`.
+pub(crate) fn get_colour_warning(colour_name: &str) -> Option<String> {
+    if let Some(number) = get_palette_number(colour_name.strip_prefix("on_").unwrap_or(colour_name)) {
+        if number <= 255 {
+            return None;
+        }
+        return Some(format!("{} is no colour of the 256-colour palette, which goes from 0 to 255", colour_name));
+    }
+    if is_raw(colour_name) {
+        return match get_raw_parameters(colour_name).and_then(get_sgr_style) {
+            Some(_) => None,
+            None => Some(format!("{} is no escape sequence of colours and attributes, it leaves the text as it is", colour_name))
+        };
+    }
+    if is_colour_name(colour_name) {
+        return None;
+    }
+    Some(format!("{} is no colour name, it leaves the text as it is", colour_name))
+}
+
+
+/// Parse colour names like `bold red on_yellow` into the style they make
+/// together, as a group of `colours=` does. Names that mean nothing, and
+/// `previous` and `unchanged` which are no style, give why.
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(colour_names: &str) -> Result<Style, String> {
+        let names: Vec<&str> = colour_names.split_whitespace().collect();
+        if names.is_empty() {
+            return Err("no colour names".to_string());
+        }
+        for name in &names {
+            if let Some(warning) = get_colour_warning(name) {
+                return Err(warning);
+            }
+            if ["previous", "unchanged"].contains(name) {
+                return Err(format!("{} is no style", name));
+            }
+        }
+        Ok(combine_styles(names.iter().map(|name| get_style(name))))
+    }
+}
+
+
+/// Put `styles` together, a later colour replacing an earlier one.
+fn combine_styles(styles: impl Iterator<Item = Style>) -> Style {
+    let mut combined = Style::default();
+    for style in styles {
+        combined.fg = style.fg.or(combined.fg);
+        combined.bg = style.bg.or(combined.bg);
+        combined.attributes |= style.attributes;
+    }
+    combined
+}
+
+
+/// The style of several colour names like `bold red on_yellow` together,
+/// a later colour replacing an earlier one.
+pub(crate) fn get_combined_style(colour_names: &str) -> Style {
+    combine_styles(get_colour_name_list(colour_names).iter().map(|name| get_style(name)))
+}
+
+
+/// Parse colour overrides like `yellow=blue,white=bold black` into the
+/// style every name stands for instead, along with warnings about the
+/// entries left out. The substitutes are never remapped in turn.
+fn parse_overrides(value: &str) -> (HashMap<String, Style>, Vec<String>) {
+    let mut overrides = HashMap::new();
+    let mut warnings = vec![];
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (name, colours) = match entry.split_once('=') {
+            Some((name, colours)) => (name.trim(), colours),
+            None => {
+                warnings.push(format!("{} is no NAME=COLOURS", entry));
+                continue;
+            }
+        };
+        if !is_colour_name(name) || ["previous", "unchanged"].contains(&name) {
+            warnings.push(format!("{} is no colour name to remap", name));
+            continue;
+        }
+        let names: Vec<&str> = colours.split_whitespace().collect();
+        let name_warnings: Vec<String> = names.iter().filter_map(|name| get_colour_warning(name)).collect();
+        if names.is_empty() || !name_warnings.is_empty() {
+            warnings.push(format!("{} can't be remapped to {}", name, colours.trim()));
+            continue;
+        }
+        overrides.insert(name.to_string(), combine_styles(names.iter().map(|name| get_own_style(name))));
+    }
+    (overrides, warnings)
+}
+
+
+/// The colour names remapped by `GRC_COLOURS`, read once with the first
+/// name looked up.
+fn get_overrides() -> &'static HashMap<String, Style> {
+    static OVERRIDES: OnceLock<HashMap<String, Style>> = OnceLock::new();
+    OVERRIDES.get_or_init(|| {
+        let (overrides, warnings) = parse_overrides(&env::var("GRC_COLOURS").unwrap_or_default());
+        for warning in warnings {
+            warn(&format!("GRC_COLOURS: {}", warning));
+        }
+        overrides
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_colour_list() {
+        assert_eq!(get_colour_list("default,blink ,yellow"), vec!["\u{1b}[0m", "\u{1b}[5m", "\u{1b}[33m"]);
+        assert_eq!(get_colour_list(" bold  red,underline yellow on_blue ,, cyan,"),
+                   vec!["\u{1b}[1;31m", "\u{1b}[4;33;44m", "\u{1b}[36m"]);
+        assert_eq!(get_colour_groups("bold red,underline"), vec![vec!["bold", "red"], vec!["underline"]]);
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let (overrides, warnings) = parse_overrides("yellow=bold blue, white = black,,flavour=red,bright_white=bolt,cyan");
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides["yellow"], Style { fg: Some(Colour::Named(NamedColour::Blue)), attributes: Attributes::BOLD, ..Style::default() });
+        assert_eq!(overrides["white"].fg, Some(Colour::Named(NamedColour::Black)));
+        assert_eq!(warnings, ["flavour is no colour name to remap", "bright_white can't be remapped to bolt", "cyan is no NAME=COLOURS"]);
+        // A substitute that is remapped itself is taken as it is.
+        let (overrides, _) = parse_overrides("red=blue,blue=red");
+        assert_eq!(overrides["red"].fg, Some(Colour::Named(NamedColour::Blue)));
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!("bold red".parse::<Style>(), Ok(get_combined_style("bold red")));
+        assert_eq!(" on_#202030 ".parse::<Style>().unwrap().bg, Some(Colour::Rgb(32, 32, 48)));
+        assert_eq!("bold bolt".parse::<Style>(), Err("bolt is no colour name, it leaves the text as it is".to_string()));
+        assert_eq!("previous".parse::<Style>(), Err("previous is no style".to_string()));
+        assert!("".parse::<Style>().is_err());
+        // The names of grcat's table keep their sequences.
+        for (name, colour) in [("none", ""), ("default", "\x1b[0m"), ("dark", "\x1b[2m"), ("concealed", "\x1b[8m"), ("on_white", "\x1b[47m"),
+                               ("bright_black", "\x1b[30;90m"), ("on_bright_magenta", "\x1b[45;105m"), ("unchanged", "unchanged"), ("bolt", "\x1b[0m")] {
+            assert_eq!(get_colour(name), colour, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_beep_is_the_bell() {
+        assert_eq!(get_colour("beep"), "\u{7}");
+        assert!(get_style("beep").is_plain());
+    }
+
+    #[test]
+    fn test_raw_sequences() {
+        assert_eq!(get_colour("\"\\033[38;5;208m\""), "\x1b[38;5;208m");
+        assert_eq!(get_colour("\\e[1;31m"), "\x1b[1;31m");
+        let style = get_style("\\x1b[1;38;2;1;2;3;104m");
+        assert_eq!(style, Style { fg: Some(Colour::Rgb(1, 2, 3)), bg: Some(Colour::Named(NamedColour::BrightBlue)), attributes: Attributes::BOLD });
+        assert_eq!(get_style("\\033[38;5;208m").to_ansi(), "\x1b[38;5;208m");
+        assert_eq!(get_colour_warning("\\033[1m"), None);
+        assert!(get_colour_warning("\\033[2J").is_some());
+        assert!(get_colour_warning("\\033[38;5m").is_some());
+        assert_eq!(get_colour_warning("on_bright_red"), None);
+        assert_eq!(get_colour_warning("bold"), None);
+        assert!(get_colour_warning("bolt").unwrap().contains("bolt is no colour name"));
+    }
+
+    #[test]
+    fn test_numeric_codes() {
+        assert_eq!(get_colour_list("01;34"), vec!["\x1b[01;34m"]);
+        assert_eq!(get_colour_list("4 33, bold 34,01;31 on_blue"), vec!["\x1b[4;33m", "\x1b[1;34m", "\x1b[1;31;44m"]);
+        assert_eq!(get_style("1;34"), get_combined_style("bold blue"));
+        assert_eq!(get_colour_warning("01;34"), None);
+        assert!(get_colour_warning("3x").unwrap().contains("is no colour name"));
+        assert!(get_colour_warning(";;").unwrap().contains("is no colour name"));
+        assert!(get_colour_warning("34;999").unwrap().contains("is no escape sequence"));
+    }
+
+    #[test]
+    fn test_get_style_matches_get_colour() {
+        for name in ["bold", "dark", "red", "bright_cyan", "on_blue", "on_bright_white", "strikethrough"] {
+            assert_eq!(get_style(name).to_ansi(), get_colour(name));
+        }
+        assert_eq!(get_style("colour(208)").fg, Some(Colour::Indexed(208)));
+        assert_eq!(get_style("color208").fg, Some(Colour::Indexed(208)));
+        assert_eq!(get_style("on_colour236").bg, Some(Colour::Indexed(236)));
+        assert_eq!(get_colour("on_color236"), "\x1b[48;5;236m");
+        assert!(get_style("color256").is_plain() && get_style("color").is_plain() && get_style("color-1").is_plain());
+        assert_eq!(get_colour_warning("on_color999").unwrap(), "on_color999 is no colour of the 256-colour palette, which goes from 0 to 255");
+        assert_eq!(get_colour_warning("color255"), None);
+        assert_eq!(get_colour_warning("red"), None);
+        assert_eq!(get_style("on_#ff8700").bg, Some(Colour::Rgb(255, 135, 0)));
+        assert!(get_style("#ff87").is_plain());
+        assert_eq!(get_style("#F80").fg, Some(Colour::Rgb(255, 136, 0)));
+        assert_eq!(get_style("on_#202030").bg, Some(Colour::Rgb(32, 32, 48)));
+        assert_eq!(get_colour("#FF8800"), "\x1b[38;2;255;136;0m");
+        assert!(get_style("#ggg").is_plain() && get_style("#+12").is_plain() && get_style("#").is_plain());
+        assert!(get_style("none").is_plain());
+        assert!(get_style("no_such_colour").is_plain());
+    }
+}
@@ -0,0 +1,879 @@
+//! Applying the rules of a config to lines of text.
+
+use std::borrow::Cow;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::time::Instant;
+
+use regex::{RegexSet, SetMatches};
+
+use crate::html::render_html;
+use crate::config::{get_escaped_char, ColourConfig, Config, CountMode, EscalateWindow, RuleColour};
+use crate::lines::{ColourLines, LineReader, SpanLines};
+use crate::profile::Profile;
+use crate::pattern::CompiledPattern;
+use crate::state::StreamState;
+use crate::style::{push_rendered_ansi, LineEnd, Style, StyledSpan};
+use crate::template::Template;
+use crate::timestamp::parse_timestamp;
+use crate::width::strip_escapes;
+
+
+/// Colours lines of text with the rules of a config.
+///
+/// ```
+/// use rgrcat::{Colourizer, Config};
+///
+/// let config: Config = "regexp=ERROR\ncolours=red\n".parse().unwrap();
+/// let colourizer = Colourizer::new(config);
+/// assert_eq!(colourizer.colourize_line("an ERROR"), "an \x1b[31mERROR\x1b[0m");
+/// ```
+pub struct Colourizer {
+    config: Config,
+    /// The regexp of every rule of `config`, compiled once up front, `None`
+    /// for an empty or invalid one.
+    regexes: Vec<Option<CompiledPattern>>,
+    /// All regexps in one, to find the rules that can match a line in a
+    /// single pass, but for those of fancy-regex. `None` if the set couldn't
+    /// be built.
+    set: Option<RegexSet>,
+    /// Whether a rule acts on lines its regexp doesn't match: `escalate=`
+    /// counts them, `window=` keeps them and `prefix=` without a regexp goes
+    /// on every line. A fancy-regex regexp, which the prefilter can't run,
+    /// counts as well.
+    has_line_rules: bool,
+}
+
+
+/// The style of a byte along with the index of the rule that gave it.
+type ByteStyle = Option<(Style, usize)>;
+
+
+/// A styled part of a line along with the rule that styled it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleSpan {
+    /// The byte range of the part.
+    pub range: Range<usize>,
+    /// How the part is styled.
+    pub style: Style,
+    /// The index of the rule in its config, see `ColourConfig::index`.
+    pub rule: usize,
+}
+
+
+/// A group of a match of a rule, see `RuleStep::matches`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupMatch {
+    /// The number of the group, 0 for the whole match.
+    pub group: usize,
+    /// The byte range of the group in the text the rule ran on.
+    pub range: Range<usize>,
+    /// The style the group was painted in, `None` if it was left alone.
+    pub style: Option<Style>,
+}
+
+
+/// What one rule did to a line, see `Colourizer::explain_line`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleStep {
+    /// The index of the rule in its config, see `ColourConfig::index`.
+    pub rule: usize,
+    /// The text the rule ran on, as the rules before rewrote it.
+    pub text: String,
+    /// Every match the rule took, each with the groups that took part in
+    /// it, the whole match first. Empty if the rule didn't match.
+    pub matches: Vec<Vec<GroupMatch>>,
+    /// The text after a `replace=` of the rule rewrote it.
+    pub replaced: Option<String>,
+    /// Whether a `count=stop` of the rule left the line to no later rule.
+    pub stopped: bool,
+}
+
+
+/// How the rules went about a line, rule by rule, see
+/// `Colourizer::explain_line`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineExplanation {
+    /// The enabled rules in the order they ran, up to a `count=stop`.
+    pub steps: Vec<RuleStep>,
+    /// The index of the `skip=yes` rule that drops the line, if one does.
+    pub skipped_by: Option<usize>,
+    /// The line as it comes out, `None` if it's skipped.
+    pub processed: Option<ProcessedLine>,
+}
+
+
+/// A line the rules ran on: its text, as rewritten by the rules, with the
+/// styled parts of that text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessedLine {
+    /// The text of the line.
+    pub text: String,
+    /// The styled parts of `text`.
+    pub spans: Vec<StyledSpan>,
+    /// What comes after the last span where it ends the line, as the
+    /// `eol=` of its rule says.
+    pub eol: LineEnd,
+}
+
+
+impl ProcessedLine {
+    /// The line with escape sequences, or without `coloured` the text
+    /// alone, where every style is rendered as nothing at all.
+    pub fn render(&self, coloured: bool) -> String {
+        let mut rendered = String::with_capacity(self.text.len());
+        self.render_into(coloured, &mut rendered);
+        rendered
+    }
+
+
+    /// Like `render`, but appends to `out`, so one buffer can take line
+    /// after line.
+    pub fn render_into(&self, coloured: bool, out: &mut String) {
+        if coloured {
+            push_rendered_ansi(out, &self.text, &self.spans, self.eol);
+        } else {
+            out.push_str(&self.text);
+        }
+    }
+}
+
+
+/// Paint `range` of `styles` in `style` for the rule `index`, returning
+/// whether that changed anything. Where matches overlap the first rule
+/// wins, as in grcat: a byte another rule styled keeps its style, while the
+/// groups of a rule still paint over its whole match.
+fn paint(styles: &mut [ByteStyle], range: Range<usize>, style: Style, index: usize) -> bool {
+    let style = if style.is_plain() { None } else { Some(style) };
+    let mut changed = false;
+    for slot in styles[range].iter_mut().filter(|slot| slot.is_none_or(|(_, rule)| rule == index)) {
+        changed |= slot.map(|(style, _)| style) != style;
+        *slot = style.map(|style| (style, index));
+    }
+    changed
+}
+
+
+/// Whether the compiled regexp of a rule matches `line`, which an empty or
+/// invalid one never does.
+fn is_matched(re: Option<&CompiledPattern>, line: &str) -> bool {
+    re.is_some_and(|re| re.is_match(line))
+}
+
+
+/// How often `re` of `config` matches `line`, once at most for a rule
+/// that only takes one match or makes a block of the line.
+fn count_found(config: &ColourConfig, re: &CompiledPattern, line: &str) -> u64 {
+    match config.count {
+        CountMode::Once | CountMode::Block | CountMode::Unblock => re.is_match(line) as u64,
+        _ => re.count(line) as u64
+    }
+}
+
+
+/// The regexp of `config` for the prefilter, with its flags inline, or one
+/// that never matches for a rule that doesn't compile or needs fancy-regex.
+fn get_set_pattern<'a>(config: &'a ColourConfig, re: Option<&CompiledPattern>) -> Cow<'a, str> {
+    match re {
+        Some(re) if re.is_fast() => config.flagged_regexp(),
+        _ => Cow::Borrowed(r"[^\s\S]"),
+    }
+}
+
+
+/// `re` of the rule at `position`, or `None` if the prefilter found that it
+/// doesn't match. The prefilter ran on the line as it came in, so once a
+/// rule rewrote it every regexp has to run. Those of fancy-regex aren't in
+/// the prefilter and always run.
+fn get_candidate<'a>(re: Option<&'a CompiledPattern>, position: usize, candidates: Option<&SetMatches>, rewritten: bool) -> Option<&'a CompiledPattern> {
+    match candidates {
+        Some(candidates) if !rewritten && re.is_some_and(CompiledPattern::is_fast) && !candidates.matched(position) => None,
+        _ => re,
+    }
+}
+
+
+/// The style of the whole match of `config`, `escalated` if it is.
+fn get_rule_style(config: &ColourConfig, escalated: Option<Style>) -> Style {
+    // An empty `colours=` leaves no colour at all, which paints plain.
+    let rule_style = match config.colours.first() {
+        Some(RuleColour::Style(style)) => *style,
+        Some(RuleColour::Unchanged) | Some(RuleColour::Previous) | None => Style::default()
+    };
+    escalated.unwrap_or(rule_style)
+}
+
+
+/// Colour the bytes of `line` covered by `config` into `styles`, which holds
+/// one entry per byte, with `escalated` replacing the colour of the rule.
+/// Returns whether the rule changed anything. Every match goes into
+/// `matches` if given. With `window`, the lines before `line` of a
+/// `window=` rule, the regexp runs on them followed by `line`, and the parts
+/// of the matches on `line` get coloured.
+///
+/// As in grcat the first colour is that of the whole match and the Nth one
+/// that of the Nth group, the last colour going on for any further groups.
+fn apply_config(line: &str, window: &str, config: &ColourConfig, re: Option<&CompiledPattern>, styles: &mut [ByteStyle], escalated: Option<Style>,
+                mut matches: Option<&mut Vec<Vec<GroupMatch>>>) -> bool {
+    let rule_style = get_rule_style(config, escalated);
+
+    if config.count == CountMode::Block || config.count == CountMode::Unblock {
+        if !is_matched(re, line) {
+            return false;
+        }
+        let style = if config.count == CountMode::Block { rule_style } else { Style::default() };
+        if let Some(matches) = matches {
+            matches.push(vec![GroupMatch { group: 0, range: 0..line.len(), style: Some(style) }]);
+        }
+        if config.count == CountMode::Block {
+            tracing::trace!(rule = config.index, "block");
+        } else {
+            tracing::trace!(rule = config.index, "unblock");
+        }
+        paint(styles, 0..line.len(), style, config.index)
+    } else {
+        let re = match re {
+            Some(re) => re,
+            None => return false
+        };
+        // `count=once` leaves the matches after the first alone.
+        let taken = if config.count == CountMode::Once { 1 } else { usize::MAX };
+        let joined;
+        let text = if window.is_empty() {
+            line
+        } else {
+            joined = format!("{}{}", window, line);
+            &joined
+        };
+        let start = window.len();
+        // Of the text before the line, what is left is to the lines written already.
+        let is_on_line = |found: &Range<usize>| found.end > start || found.start >= start;
+        let mut changed = false;
+        for captures in re.captures_iter(text).filter(|captures| captures.get(0).is_some_and(|found| is_on_line(&found))).take(taken) {
+            let mut groups = vec![];
+            for (group, found) in captures.ranges().into_iter().enumerate() {
+                // Groups which aren't part of the match are left alone.
+                let range = match found {
+                    Some(found) if is_on_line(&found) => found.start.max(start) - start..found.end - start,
+                    _ => continue
+                };
+                let style = match config.colours.get(group).or(config.colours.last()) {
+                    _ if group == 0 && escalated.is_some() => Some(rule_style),
+                    Some(RuleColour::Style(style)) => Some(*style),
+                    Some(RuleColour::Unchanged) => None,
+                    Some(RuleColour::Previous) => {
+                        let before = range.start.checked_sub(1).and_then(|before| styles[before]);
+                        Some(before.map_or_else(Style::default, |(style, _)| style))
+                    }
+                    None => Some(Style::default())
+                };
+                if matches.is_some() {
+                    groups.push(GroupMatch { group, range: range.clone(), style });
+                }
+                if let Some(style) = style {
+                    changed |= paint(styles, range, style, config.index);
+                }
+            }
+            if let Some(matches) = matches.as_mut() {
+                matches.push(groups);
+            }
+        }
+        changed
+    }
+}
+
+
+/// The `replace=` of grcat, with Python's `\1` and `\g<name>` for groups and
+/// `\\` for a backslash, in the syntax of `Captures::expand`, where `${1}`
+/// and `$name` work as they are. `\n`, `\t`, `\r`, `\e`, `\033` and
+/// `\xNN` are the characters they stand for, and any other escape is left as
+/// it is.
+fn get_expansion(replace: &str) -> String {
+    let mut expansion = String::new();
+    let mut chars = replace.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            expansion.push(c);
+            continue;
+        }
+        let rest: String = chars.clone().collect();
+        if let Some((escaped, length)) = get_escaped_char(&rest) {
+            if escaped == '$' {
+                expansion.push('$');
+            }
+            expansion.push(escaped);
+            chars.nth(length - 1);
+            continue;
+        }
+        match chars.peek() {
+            Some(digit) if digit.is_ascii_digit() => {
+                let mut group = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    group.push(digit);
+                }
+                expansion.push_str(&format!("${{{}}}", group));
+            }
+            Some('g') => match rest[1..].strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+                Some((name, _)) => {
+                    expansion.push_str(&format!("${{{}}}", name));
+                    chars.nth(name.chars().count() + 2);
+                }
+                None => expansion.push('\\'),
+            },
+            _ => expansion.push('\\'),
+        }
+    }
+    expansion
+}
+
+
+/// Rewrite the matches of `config` in `line` with its `replace=`, `None`
+/// without one or without a match. The styles of the text around the
+/// matches stay, while the replacements get the colour of the rule. With
+/// `concat=no` the replacements are all that is left of the line.
+fn replace_config(line: &str, config: &ColourConfig, re: Option<&CompiledPattern>, styles: &[ByteStyle], escalated: Option<Style>,
+                  mut matches: Option<&mut Vec<Vec<GroupMatch>>>) -> Option<(String, Vec<ByteStyle>)> {
+    if config.replace.is_empty() || config.count == CountMode::Block || config.count == CountMode::Unblock {
+        return None;
+    }
+    let re = re.filter(|re| re.is_match(line))?;
+    let expansion = get_expansion(&config.replace);
+    let rule_style = get_rule_style(config, escalated);
+    let style = if rule_style.is_plain() { None } else { Some((rule_style, config.index)) };
+    let taken = if config.count == CountMode::Once { 1 } else { usize::MAX };
+    let mut text = String::new();
+    let mut text_styles = vec![];
+    let mut end = 0;
+    let concat = config.concat();
+    for captures in re.captures_iter(line).take(taken) {
+        let found = captures.get(0)?;
+        // The replacement takes the colour of the rule, its groups none.
+        if let Some(matches) = matches.as_mut() {
+            matches.push(captures.ranges().into_iter().enumerate()
+                .filter_map(|(group, found)| found.map(|range| GroupMatch { group, range, style: Some(rule_style).filter(|_| group == 0) }))
+                .collect());
+        }
+        if concat {
+            text.push_str(&line[end..found.start]);
+            text_styles.extend_from_slice(&styles[end..found.start]);
+        }
+        captures.expand(&expansion, &mut text);
+        text_styles.resize(text.len(), style);
+        end = found.end;
+    }
+    if concat {
+        text.push_str(&line[end..]);
+        text_styles.extend_from_slice(&styles[end..]);
+    }
+    tracing::trace!(rule = config.index, "replace");
+    Some((text, text_styles))
+}
+
+
+/// Collapse per byte styles into spans of equal style.
+fn get_spans(styles: &[ByteStyle]) -> Vec<StyledSpan> {
+    let mut spans: Vec<StyledSpan> = vec![];
+    for (index, style) in styles.iter().enumerate() {
+        if let Some((style, _)) = style {
+            match spans.last_mut() {
+                Some(span) if span.range.end == index && span.style == *style => span.range.end += 1,
+                _ => spans.push(StyledSpan { range: index..index + 1, style: *style })
+            }
+        }
+    }
+    spans
+}
+
+
+/// Collapse per byte styles into spans of equal style and rule.
+fn get_rule_spans(styles: &[ByteStyle]) -> Vec<RuleSpan> {
+    let mut spans: Vec<RuleSpan> = vec![];
+    for (index, style) in styles.iter().enumerate() {
+        if let Some((style, rule)) = style {
+            match spans.last_mut() {
+                Some(span) if span.range.end == index && span.style == *style && span.rule == *rule => span.range.end += 1,
+                _ => spans.push(RuleSpan { range: index..index + 1, style: *style, rule: *rule })
+            }
+        }
+    }
+    spans
+}
+
+
+impl Colourizer {
+    /// Colour with the rules of `config`.
+    pub fn new(config: Config) -> Colourizer {
+        let regexes: Vec<Option<CompiledPattern>> = config.rules().iter()
+            .map(|config| if config.regexp.is_empty() { None } else { config.compile_pattern().ok() })
+            .collect();
+        let set = RegexSet::new(config.rules().iter().zip(&regexes).map(|(config, re)| get_set_pattern(config, re.as_ref()))).ok();
+        let has_line_rules = config.rules().iter()
+            .zip(&regexes)
+            .any(|(config, re)| config.enabled && (!config.escalate.is_empty() || config.window > 1 || (config.regexp.is_empty() && !config.prefix.is_empty())
+                || re.as_ref().is_some_and(|re| !re.is_fast())));
+        Colourizer { config, regexes, set, has_line_rules }
+    }
+
+
+    /// Like `new`, only every rule runs on every line, without the prefilter
+    /// that finds the rules that can match. For comparing the two.
+    #[doc(hidden)]
+    pub fn without_prefilter(config: Config) -> Colourizer {
+        Colourizer { set: None, ..Colourizer::new(config) }
+    }
+
+
+    /// Whether the rules are sure to leave `line`, the next line of the
+    /// stream of `state`, as it is: no regexp matches it, no block colours
+    /// it and no rule acts on every line. Such a line can go out without
+    /// running the rules. Only the prefilter knows, without it this is false.
+    pub fn is_untouched(&self, line: &str, state: &StreamState) -> bool {
+        !self.has_line_rules && state.block().is_none() && self.set.as_ref().is_some_and(|set| !set.is_match(line))
+    }
+
+
+    /// The config the colourizer was built with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+
+    /// The enabled rules, each with its compiled regexp.
+    fn get_enabled_rules(&self) -> impl Iterator<Item = (&ColourConfig, Option<&CompiledPattern>)> {
+        self.config.rules().iter().zip(&self.regexes)
+            .filter(|(config, _)| config.enabled)
+            .map(|(config, re)| (config, re.as_ref()))
+    }
+
+
+    /// Colour a single line, which should not end with a newline.
+    pub fn colourize_line(&self, line: &str) -> String {
+        self.colourize_line_profiled(line, None)
+    }
+
+
+    /// Colour a single line as HTML, see `render_html`.
+    pub fn colourize_line_html(&self, line: &str) -> String {
+        let (text, styles) = self.get_styled_line(line, None, None, None);
+        render_html(&text, &get_spans(&styles))
+    }
+
+
+    /// Like `colourize_line`, but adds the time spent on each rule to
+    /// `profile`. Without a profile no clock is read at all.
+    pub fn colourize_line_profiled(&self, line: &str, profile: Option<&mut Profile>) -> String {
+        let (text, styles) = self.get_styled_line(line, profile, None, None);
+        self.get_processed(text.into_owned(), &styles).render(true)
+    }
+
+
+    /// Run the rules on a single line, giving its text as rewritten by the
+    /// `replace=` rules along with the styled parts of that text.
+    pub fn process_line(&self, line: &str) -> ProcessedLine {
+        let (text, styles) = self.get_styled_line(line, None, None, None);
+        self.get_processed(text.into_owned(), &styles)
+    }
+
+
+    /// The line of `text` styled by `styles`, ending as the `eol=` of the
+    /// rule that styled its last byte says.
+    fn get_processed(&self, text: String, styles: &[ByteStyle]) -> ProcessedLine {
+        let eol = styles.last().copied().flatten()
+            .and_then(|(_, index)| self.config.rules().iter().find(|rule| rule.index == index))
+            .map_or(LineEnd::Reset, |rule| rule.eol);
+        ProcessedLine { text, spans: get_spans(styles), eol }
+    }
+
+
+    /// The styled parts of `line`. The spans are ordered, don't overlap and
+    /// leave out unstyled text, where an earlier rule wins over a later one.
+    /// With `replace=` rules they are those of the rewritten text, see
+    /// `process_line`.
+    pub fn spans(&self, line: &str) -> Vec<StyledSpan> {
+        self.spans_profiled(line, None)
+    }
+
+
+    /// Like `spans`, but adds the time spent on each rule to `profile`.
+    pub fn spans_profiled(&self, line: &str, profile: Option<&mut Profile>) -> Vec<StyledSpan> {
+        get_spans(&self.get_styled_line(line, profile, None, None).1)
+    }
+
+
+    /// Colour the next line of a stream, where rules such as `escalate=`
+    /// depend on the lines before, as kept in `state`. Adds the time spent
+    /// on each rule to `profile` if given.
+    pub fn colourize_next_line(&self, line: &str, state: &mut StreamState, profile: Option<&mut Profile>) -> String {
+        let (text, styles) = self.get_styled_line(line, profile, Some(state), None);
+        self.get_processed(text.into_owned(), &styles).render(true)
+    }
+
+
+    /// Like `colourize_next_line`, but returns the styled parts of the line.
+    pub fn next_spans(&self, line: &str, state: &mut StreamState, profile: Option<&mut Profile>) -> Vec<StyledSpan> {
+        get_spans(&self.get_styled_line(line, profile, Some(state), None).1)
+    }
+
+
+    /// Run the rules on the next line of a stream like `next_spans` does,
+    /// `None` if they drop the line. The result can be rendered with or
+    /// without colour, the rules run the same either way.
+    pub fn process_next_line(&self, line: &str, state: &mut StreamState, profile: Option<&mut Profile>) -> Option<ProcessedLine> {
+        self.process_next(line, state, profile, false).map(|(processed, _)| processed)
+    }
+
+
+    /// Like `process_next_line`, along with the spans of every rule as
+    /// `rule_spans` gives them, their offsets into the text before any
+    /// `prefix=`.
+    pub fn trace_next_line(&self, line: &str, state: &mut StreamState, profile: Option<&mut Profile>) -> Option<(ProcessedLine, Vec<RuleSpan>)> {
+        self.process_next(line, state, profile, true)
+    }
+
+
+    /// Run the rules on the next line of a stream, with the spans of every
+    /// rule only if `traced`.
+    fn process_next(&self, line: &str, state: &mut StreamState, profile: Option<&mut Profile>, traced: bool) -> Option<(ProcessedLine, Vec<RuleSpan>)> {
+        if profile.is_none() && self.is_untouched(line, state) {
+            state.next_line();
+            return Some((ProcessedLine { text: line.to_string(), spans: vec![], eol: LineEnd::Reset }, vec![]));
+        }
+        if let Some(config) = self.get_skipping_rule(line) {
+            if let Some(profile) = profile {
+                profile.record_found(config.index, 1);
+            }
+            state.next_line();
+            return None;
+        }
+        let (text, styles) = self.get_styled_line(line, profile, Some(state), None);
+        let processed = self.get_processed(text.into_owned(), &styles);
+        let rule_spans = if traced { get_rule_spans(&styles) } else { vec![] };
+        let (prefix, prefix_spans) = self.get_prefix(line, state);
+        if prefix.is_empty() {
+            return Some((processed, rule_spans));
+        }
+        let shift = prefix.len();
+        let shifted = processed.spans.into_iter()
+            .map(|span| StyledSpan { range: span.range.start + shift..span.range.end + shift, style: span.style });
+        let spans = prefix_spans.into_iter().chain(shifted).collect();
+        Some((ProcessedLine { text: prefix + &processed.text, spans, eol: processed.eol }, rule_spans))
+    }
+
+
+    /// The expanded `prefix=` templates of the rules matching `line`, each
+    /// styled with the colour of its rule.
+    fn get_prefix(&self, line: &str, state: &StreamState) -> (String, Vec<StyledSpan>) {
+        let mut prefix = String::new();
+        let mut spans = vec![];
+        let rules = self.get_enabled_rules()
+            .filter(|(config, _)| !config.prefix.is_empty() && config.is_applied_to(line))
+            .filter(|(config, re)| config.regexp.is_empty() || is_matched(*re, line));
+        for (config, _) in rules {
+            // The templates were checked when the config was parsed.
+            let template = match Template::parse(&config.prefix) {
+                Ok(template) => template,
+                Err(_) => continue
+            };
+            let start = prefix.len();
+            prefix.push_str(&template.expand(|field, _| match field {
+                "line" => state.line().to_string(),
+                _ => state.file().to_string(),
+            }));
+            if let Some(RuleColour::Style(style)) = config.colours.first() {
+                if !style.is_plain() && prefix.len() > start {
+                    spans.push(StyledSpan { range: start..prefix.len(), style: *style });
+                }
+            }
+        }
+        (prefix, spans)
+    }
+
+
+    /// What every rule does to `line` on its own, as the first line of a
+    /// stream: the spans it matched, the colours of their groups and how it
+    /// rewrote the text, along with the line as it comes out. For writing
+    /// and checking rules, the regexps run more than once.
+    pub fn explain_line(&self, line: &str) -> LineExplanation {
+        let mut steps = vec![];
+        self.get_styled_line(line, None, Some(&mut StreamState::new()), Some(&mut steps));
+        let skipped_by = self.get_skipping_rule(line).map(ColourConfig::index);
+        let processed = self.process_next_line(line, &mut StreamState::new(), None);
+        LineExplanation { steps, skipped_by, processed }
+    }
+
+
+    /// Like `spans`, but split wherever another rule takes over, with each
+    /// span telling which rule styled it.
+    pub fn rule_spans(&self, line: &str) -> Vec<RuleSpan> {
+        get_rule_spans(&self.get_styled_line(line, None, None, None).1)
+    }
+
+
+    /// The style an `escalate=` rule has reached on a line, counting it in
+    /// `state` if `matched`.
+    fn get_escalated_style(config: &ColourConfig, matched: bool, state: &mut StreamState) -> Option<Style> {
+        // Only a window of time needs the clock.
+        let now = match config.escalate_window {
+            EscalateWindow::Duration(_) => Some(Instant::now()),
+            EscalateWindow::Lines(_) => None,
+        };
+        state.get_escalated_style(config, matched, now)
+    }
+
+
+    /// The text of `line` as rewritten by the `replace=` rules, along with
+    /// the style of each of its bytes.
+    fn get_styled_line<'a>(&self, line: &'a str, mut profile: Option<&mut Profile>, mut state: Option<&mut StreamState>,
+                           mut steps: Option<&mut Vec<RuleStep>>) -> (Cow<'a, str>, Vec<ByteStyle>) {
+        let _span = tracing::trace_span!("colourize_line").entered();
+        // The profile times what every rule costs on its own, so with one all
+        // the regexps run.
+        let candidates = self.set.as_ref().filter(|_| profile.is_none()).map(|set| set.matches(line));
+        let block = state.as_mut().and_then(|state| {
+            state.next_line();
+            state.block()
+        });
+        // A line no regexp matches is left as it is, unless rules act on such
+        // lines as well.
+        if candidates.as_ref().is_some_and(|candidates| !candidates.matched_any()) && block.is_none() && !self.has_line_rules && steps.is_none() {
+            return (Cow::Borrowed(line), vec![]);
+        }
+        let mut block = block;
+        let mut text = Cow::Borrowed(line);
+        let mut styles = vec![None; line.len()];
+        let rules = self.config.rules().iter().zip(&self.regexes).enumerate().filter(|(_, (config, _))| config.enabled);
+        for (position, (config, re)) in rules {
+            if !config.is_applied_to(&text) {
+                continue;
+            }
+            // The prefilter only saw the line, not the lines before it in a window.
+            let re = get_candidate(re.as_ref(), position, candidates.as_ref(), matches!(text, Cow::Owned(_)) || config.window > 1);
+            // The matches are counted once the clock stopped, on the line as
+            // the rule got it.
+            let before = profile.as_ref().filter(|_| !config.replace.is_empty()).map(|_| text.clone());
+            let started = profile.as_ref().map(|_| Instant::now());
+            // Most rules don't need to know, which saves running the regexp twice.
+            let is_match_needed = !config.concat() || !config.escalate.is_empty()
+                || matches!(config.count, CountMode::Block | CountMode::Unblock | CountMode::Stop);
+            let matched = is_match_needed && is_matched(re, &text);
+            let escalated = match state.as_mut() {
+                Some(state) if !config.escalate.is_empty() => Colourizer::get_escalated_style(config, matched, state),
+                _ => None
+            };
+            // A matching `concat=no` rule throws away what the rules before it
+            // did to the line, while later rules go on from its result, so of
+            // several such rules the last one to match wins.
+            if matched && !config.concat() {
+                styles.fill(None);
+                block = None;
+            }
+            let mut step = steps.as_ref().map(|_| RuleStep { rule: config.index, text: text.to_string(), matches: vec![], replaced: None, stopped: false });
+            let changed = match replace_config(&text, config, re, &styles, escalated, step.as_mut().map(|step| &mut step.matches)) {
+                Some((replaced, replaced_styles)) => {
+                    if let Some(step) = step.as_mut() {
+                        step.replaced = Some(replaced.clone());
+                    }
+                    text = Cow::Owned(replaced);
+                    styles = replaced_styles;
+                    true
+                }
+                None => {
+                    let window = match state.as_mut() {
+                        Some(state) if config.window > 1 => {
+                            let window = state.get_window(config);
+                            state.push_window(config, &text);
+                            window
+                        }
+                        _ => String::new()
+                    };
+                    apply_config(&text, &window, config, re, &mut styles, escalated, step.as_mut().map(|step| &mut step.matches))
+                }
+            };
+            if let (Some(steps), Some(mut step)) = (steps.as_mut(), step) {
+                step.stopped = config.count == CountMode::Stop && matched;
+                steps.push(step);
+            }
+            if let (Some(profile), Some(started)) = (profile.as_mut(), started) {
+                profile.record(config.index, started.elapsed(), changed);
+                profile.record_found(config.index, re.map_or(0, |re| count_found(config, re, before.as_ref().unwrap_or(&text))));
+            }
+            match (config.count, state.as_mut()) {
+                (CountMode::Block, Some(state)) if matched => {
+                    state.set_block(Some((get_rule_style(config, escalated), config.index)));
+                }
+                (CountMode::Unblock, Some(state)) if matched => {
+                    state.set_block(None);
+                    block = None;
+                }
+                // `count=stop` leaves a line it matches to no later rule.
+                (CountMode::Stop, _) if matched => {
+                    tracing::trace!(rule = config.index, "stop");
+                    break;
+                }
+                _ => {}
+            }
+        }
+        // The lines of a block take its colour where no rule styled them.
+        if let Some((style, index)) = block {
+            paint(&mut styles, 0..text.len(), style, index);
+        }
+        (text, styles)
+    }
+
+
+    /// Whether a `skip=yes` rule matches `line`, which is then dropped
+    /// instead of shown.
+    pub fn is_skipped(&self, line: &str) -> bool {
+        self.get_skipping_rule(line).is_some()
+    }
+
+
+    /// The first `skip=yes` rule matching `line`.
+    fn get_skipping_rule(&self, line: &str) -> Option<&ColourConfig> {
+        self.get_enabled_rules()
+            .filter(|(config, _)| config.skip && config.is_applied_to(line))
+            .find(|(_, re)| is_matched(*re, line))
+            .map(|(config, _)| config)
+    }
+
+
+    /// Whether the output should be flushed after writing `line`, because a
+    /// `flush=yes` rule matches it. Lines that get skipped are never written,
+    /// so they don't flush either.
+    pub fn is_flushed(&self, line: &str) -> bool {
+        if self.is_skipped(line) {
+            return false;
+        }
+        self.get_enabled_rules()
+            .filter(|(config, _)| config.flush && config.is_applied_to(line))
+            .any(|(_, re)| is_matched(re, line))
+    }
+
+
+    /// Whether `line` rings the bell: a rule with `beep` among its colours
+    /// or an `alert_after_quiet=` matches it, the latter only after a quiet
+    /// period. `now` is when the line came in, noted in `state`.
+    pub fn is_alerted(&self, line: &str, state: &mut StreamState, now: Instant) -> bool {
+        let mut alerted = false;
+        for (config, re) in self.get_enabled_rules() {
+            if !(config.beep || config.alert_after_quiet.is_some()) || !config.is_applied_to(line) {
+                continue;
+            }
+            // Every match counts, so a burst keeps the rule from going quiet.
+            if is_matched(re, line) {
+                alerted |= state.is_alert_due(config, now);
+            }
+        }
+        alerted
+    }
+
+
+    /// Whether a rule carrying one of `tags` matches `line`. Without any tag
+    /// given, every rule counts.
+    pub fn is_matched_by_tags(&self, line: &str, tags: &[String]) -> bool {
+        self.get_enabled_rules()
+            .filter(|(config, _)| tags.is_empty() || config.tags.iter().any(|tag| tags.contains(tag)))
+            .filter(|(config, _)| config.is_applied_to(line))
+            .any(|(_, re)| is_matched(re, line))
+    }
+
+
+    /// The enabled rules whose regexp matches `line`, in evaluation order.
+    pub fn matching_rules<'a>(&'a self, line: &'a str) -> impl Iterator<Item = &'a ColourConfig> {
+        self.get_enabled_rules()
+            .filter(move |(config, re)| config.is_applied_to(line) && is_matched(*re, line))
+            .map(|(config, _)| config)
+    }
+
+
+    /// The timestamp of `line` in seconds since the epoch, from the first
+    /// `timestamp=yes` rule whose match, or its first group, is one that
+    /// [`parse_timestamp`] knows. `now` supplies the date of bare times.
+    pub fn get_timestamp(&self, line: &str, now: i64) -> Option<i64> {
+        self.get_enabled_rules()
+            .filter(|(config, _)| config.timestamp && config.is_applied_to(line))
+            .filter_map(|(_, re)| re)
+            .find_map(|re| {
+                let captures = re.captures(line)?;
+                parse_timestamp(&line[captures.get(1).or_else(|| captures.get(0))?], now)
+            })
+    }
+
+
+    /// Iterate over the coloured lines of `input`.
+    ///
+    /// ```
+    /// # let colourizer = rgrcat::Colourizer::new("regexp=up\ncolours=green\n".parse().unwrap());
+    /// for line in colourizer.lines("eth0 up\n".as_bytes()) {
+    ///     println!("{}", line?);
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn lines<R: BufRead>(&self, input: R) -> ColourLines<'_, R> {
+        ColourLines::new(self, input)
+    }
+
+
+    /// Iterate over the lines of `input` along with their styled spans.
+    pub fn span_lines<R: BufRead>(&self, input: R) -> SpanLines<'_, R> {
+        SpanLines::new(self, input)
+    }
+
+
+    /// Colour every line of `input` into `output`, each ending as it came
+    /// in, and ring the bell after the lines that do so. Lines ending in a
+    /// lone `\r`, as progress bars draw theirs, get flushed right away.
+    /// With `Config::strips_ansi` the escape sequences of the input are
+    /// left out.
+    pub fn process<R: BufRead, W: Write>(&self, input: R, mut output: W) -> io::Result<()> {
+        let mut reader = LineReader::new(input);
+        let mut state = StreamState::new();
+        let mut rendered = String::new();
+        let strip_ansi = self.config.strips_ansi();
+        while reader.read()? {
+            let line = if strip_ansi { strip_escapes(reader.line()?) } else { Cow::Borrowed(reader.line()?) };
+            if let Some(processed) = self.process_next_line(&line, &mut state, None) {
+                rendered.clear();
+                processed.render_into(true, &mut rendered);
+                output.write_all(rendered.as_bytes())?;
+                match reader.ending() {
+                    b"" => output.write_all(b"\n")?,
+                    ending => output.write_all(ending)?,
+                }
+                if self.is_alerted(&line, &mut state, Instant::now()) {
+                    output.write_all(b"\x07")?;
+                }
+                if reader.ending() == b"\r" {
+                    output.flush()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefilter_keeps_flags() {
+        let config: Config = "regexp=(?x) b a z # spaced\ncolours=blue\nignorecase=yes\n-\nregexp=a(b\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        assert!(colourizer.set.is_some());
+        assert!(colourizer.is_untouched("bar", &StreamState::new()));
+        assert!(!colourizer.is_untouched("BAZ", &StreamState::new()));
+        assert_eq!(colourizer.colourize_line("BAZ"), "\x1b[34mBAZ\x1b[0m");
+    }
+
+    #[cfg(feature = "fancy")]
+    #[test]
+    fn test_prefilter_runs_fancy_regexps() {
+        let config: Config = "regexp=baz\ncolours=blue\n-\nregexp=a(?=r)\ncolours=red\n".parse().unwrap();
+        let colourizer = Colourizer::new(config);
+        assert!(colourizer.set.is_some());
+        assert!(!colourizer.is_untouched("bar", &StreamState::new()));
+        assert_eq!(colourizer.colourize_line("bar baz"), "b\x1b[31ma\x1b[0mr \x1b[34mbaz\x1b[0m");
+    }
+}
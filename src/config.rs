@@ -0,0 +1,1272 @@
+//! grcat conffiles: rules split by non-alphabetic lines, each made of
+//! `keyword=value` lines.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::colour::{get_colour_groups, get_colour_name_list, get_colour_warning, get_combined_style, get_style};
+use crate::embedded::get_embedded_config;
+use crate::error::ConfigError;
+use crate::log::warn;
+use crate::pattern::CompiledPattern;
+use crate::style::{ColourDepth, LineEnd, Style};
+use crate::template::Template;
+
+
+/// What `count=` says about how often a rule applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum CountMode {
+    /// Colour every match, the default.
+    More,
+    /// Colour only the first match.
+    Once,
+    /// Stop processing further rules after a match.
+    Stop,
+    /// Behave like the previous rule. Conffiles never leave it in a rule,
+    /// it's replaced by the count of the rule before once parsed.
+    Previous,
+    /// Colour the whole line and the following ones.
+    Block,
+    /// End a block.
+    Unblock,
+}
+
+
+impl CountMode {
+    /// The mode of a `count=` value, unknown values count as `more`.
+    pub fn from_name(name: &str) -> CountMode {
+        match name {
+            "once" => CountMode::Once,
+            "stop" => CountMode::Stop,
+            "previous" => CountMode::Previous,
+            "block" => CountMode::Block,
+            "unblock" => CountMode::Unblock,
+            _ => CountMode::More
+        }
+    }
+
+
+    /// The `count=` value of the mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            CountMode::More => "more",
+            CountMode::Once => "once",
+            CountMode::Stop => "stop",
+            CountMode::Previous => "previous",
+            CountMode::Block => "block",
+            CountMode::Unblock => "unblock",
+        }
+    }
+}
+
+
+/// Over what the matches of an `escalate=` rule are counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum EscalateWindow {
+    /// The last so many lines.
+    Lines(u64),
+    /// The time since so long ago.
+    Duration(Duration),
+}
+
+
+impl Default for EscalateWindow {
+    fn default() -> EscalateWindow {
+        EscalateWindow::Duration(Duration::from_secs(60))
+    }
+}
+
+
+impl EscalateWindow {
+    /// The window of an `escalate_window=` value like `60s`, `500ms`, `5m`
+    /// or `1h`, or a number of lines like `100`.
+    pub fn from_value(value: &str) -> Option<EscalateWindow> {
+        let value = value.trim();
+        let units = [("ms", 1), ("s", 1000), ("m", 60_000), ("h", 3_600_000)];
+        for (suffix, millis) in units.iter() {
+            if let Some(amount) = value.strip_suffix(suffix).and_then(|amount| amount.parse::<u64>().ok()) {
+                return Some(EscalateWindow::Duration(Duration::from_millis(amount * millis)));
+            }
+        }
+        value.parse().ok().filter(|&lines| lines > 0).map(EscalateWindow::Lines)
+    }
+}
+
+
+/// The colours of a `colours=` value, one per group, noting names beyond
+/// the palette as problems.
+fn get_rule_colours(value: &str, problems: &mut Vec<String>) -> Vec<RuleColour> {
+    let groups = get_colour_groups(value);
+    problems.extend(groups.iter().flatten().filter_map(|name| get_colour_warning(name)));
+    groups.iter().map(|names| RuleColour::from_names(names)).collect()
+}
+
+
+/// Parse an `escalate=` value like `10:yellow,50:bold red` into its steps,
+/// ordered by their number of matches.
+fn get_escalate_steps(value: &str) -> Option<Vec<(usize, Style)>> {
+    let mut steps = value.split(',')
+        .filter(|step| !step.trim().is_empty())
+        .map(|step| {
+            let (count, colours) = step.split_once(':')?;
+            Some((count.trim().parse().ok()?, colours.parse().ok()?))
+        })
+        .collect::<Option<Vec<(usize, Style)>>>()?;
+    steps.sort_by_key(|(count, _)| *count);
+    Some(steps)
+}
+
+
+/// A colour of `colours=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum RuleColour {
+    /// Style the text.
+    Style(Style),
+    /// Keep the text as it is.
+    Unchanged,
+    /// Use the colour of the text before.
+    Previous,
+}
+
+
+impl RuleColour {
+    /// The colour of a grcat colour name like `bold`, `unchanged` or `previous`.
+    pub fn from_name(name: &str) -> RuleColour {
+        match name {
+            "unchanged" => RuleColour::Unchanged,
+            "previous" => RuleColour::Previous,
+            _ => RuleColour::Style(get_style(name))
+        }
+    }
+
+
+    /// The colour of a group of names of `colours=`, like `bold red`, whose
+    /// styles go together.
+    pub fn from_names(names: &[String]) -> RuleColour {
+        match names {
+            [name] => RuleColour::from_name(name),
+            names => RuleColour::Style(get_combined_style(&names.join(" ")))
+        }
+    }
+}
+
+
+/// A single rule of a conffile.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct ColourConfig {
+    /// Position of the rule in evaluation order, starting from 0.
+    pub(crate) index: usize,
+    /// Where the rule was read from, and the line its block starts on.
+    pub(crate) source: String,
+    pub(crate) line: usize,
+    /// Lower priorities run earlier, equal ones in conffile order.
+    pub(crate) priority: i32,
+    pub(crate) name: String,
+    pub(crate) regexp: String,
+    /// Flags the regexp is compiled with, besides those inline in it.
+    pub(crate) ignorecase: bool,
+    pub(crate) multiline: bool,
+    pub(crate) dotall: bool,
+    /// With `window=`, the regexp runs on this many lines, the line being
+    /// coloured and those before it, 0 for the line alone.
+    pub(crate) window: usize,
+    pub(crate) colours: Vec<RuleColour>,
+    /// The colours to use on a light background instead, empty for none.
+    pub(crate) colours_light: Vec<RuleColour>,
+    /// What the colour of the rule does where it ends the line.
+    pub(crate) eol: LineEnd,
+    pub(crate) count: CountMode,
+    pub(crate) command: String,
+    pub(crate) skip: bool,
+    /// Flush the output after every line the rule matches.
+    pub(crate) flush: bool,
+    /// A disabled rule is parsed and dumped, but never applied.
+    pub(crate) enabled: bool,
+    /// Lines longer than this many bytes are left to the other rules.
+    pub(crate) max_line_length: Option<usize>,
+    /// The name of the rule whose colours this one takes over.
+    pub(crate) same_as: String,
+    /// The style to use once the rule matched so often within the window.
+    pub(crate) escalate: Vec<(usize, Style)>,
+    pub(crate) escalate_window: EscalateWindow,
+    pub(crate) replace: String,
+    pub(crate) concat: String,
+    pub(crate) tags: Vec<String>,
+    /// A template printed at the end of the stream, for a footer block.
+    pub(crate) footer: String,
+    /// A template put in front of the lines the rule matches.
+    pub(crate) prefix: String,
+    /// The match, or its first group, is the time of the line.
+    pub(crate) timestamp: bool,
+    /// `beep` is one of the colours, so matches ring the bell.
+    pub(crate) beep: bool,
+    /// Only ring the bell when the rule didn't match for this long.
+    pub(crate) alert_after_quiet: Option<Duration>,
+    /// `stripansi=yes`, the escape sequences of the input go before any rule
+    /// sees the lines.
+    pub(crate) strip_ansi: bool,
+}
+
+
+/// The lines of `window=yes`.
+const DEFAULT_WINDOW: usize = 16;
+
+
+/// A `yes`/`no` value of `key`, or `None` with a problem in `problems` for
+/// any other.
+fn get_bool(key: &str, value: &str, problems: &mut Vec<String>) -> Option<bool> {
+    match value.trim() {
+        "yes" | "1" | "true" => Some(true),
+        "no" | "0" | "false" => Some(false),
+        _ => {
+            problems.push(format!("{} must be yes or no, not {}", key, value));
+            None
+        }
+    }
+}
+
+
+/// The character that the escape at the start of `escape`, the text after
+/// a backslash in `replace=`, stands for and how many bytes it takes.
+/// `None` for a group, `\g<name>` or an unknown escape.
+pub(crate) fn get_escaped_char(escape: &str) -> Option<(char, usize)> {
+    if escape.starts_with("033") {
+        return Some(('\x1b', 3));
+    }
+    match escape.chars().next()? {
+        '\\' => Some(('\\', 1)),
+        'n' => Some(('\n', 1)),
+        't' => Some(('\t', 1)),
+        'r' => Some(('\r', 1)),
+        'e' => Some(('\x1b', 1)),
+        'x' => {
+            let hex = escape.get(1..3).filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))?;
+            Some((u8::from_str_radix(hex, 16).ok()? as char, 3))
+        }
+        _ => None,
+    }
+}
+
+
+/// A problem for every escape in the `replace=` value that stands for
+/// nothing, which is left in as it is.
+fn get_escape_problems(value: &str, problems: &mut Vec<String>) {
+    let mut rest = value;
+    while let Some(at) = rest.find('\\') {
+        let escape = &rest[at + 1..];
+        let length = match (get_escaped_char(escape), escape.chars().next()) {
+            (Some((_, length)), _) => length,
+            (None, Some(c)) if c.is_ascii_digit() || c == 'g' => 1,
+            (None, Some(c)) => {
+                problems.push(format!("unknown escape \\{} in replace, left as it is", c));
+                c.len_utf8()
+            }
+            (None, None) => {
+                problems.push("replace ends with a lone \\, left as it is".to_string());
+                0
+            }
+        };
+        rest = &escape[length..];
+    }
+}
+
+
+/// Split a `tags=` value like `error, net` into its tags.
+pub fn get_tag_list(raw_tags: &str) -> Vec<String> {
+    raw_tags.split(',')
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+
+impl ColourConfig {
+    pub(crate) fn new() -> ColourConfig {
+        ColourConfig {
+            index: 0,
+            source: String::new(),
+            line: 0,
+            priority: 0,
+            name: String::new(),
+            regexp: String::new(),
+            ignorecase: false,
+            multiline: false,
+            dotall: false,
+            window: 0,
+            eol: LineEnd::Reset,
+            colours: vec![RuleColour::Style(Style::default())],
+            colours_light: vec![],
+            count: CountMode::More,
+            command: String::new(),
+            skip: false,
+            flush: false,
+            enabled: true,
+            max_line_length: None,
+            same_as: String::new(),
+            escalate: vec![],
+            escalate_window: EscalateWindow::default(),
+            replace: String::new(),
+            concat: String::new(),
+            tags: vec![],
+            footer: String::new(),
+            prefix: String::new(),
+            timestamp: false,
+            beep: false,
+            strip_ansi: false,
+            alert_after_quiet: None,
+        }
+    }
+
+
+    /// Position of the rule in evaluation order, starting from 0. Without
+    /// any `priority=` that's its position in the conffile.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+
+    /// The conffile the rule was read from, empty for rules built in code.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+
+    /// The line of its conffile on which the rule starts, from 1 on, or 0
+    /// for rules built in code.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+
+    /// The `priority=` of the rule, 0 without one.
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+
+    /// The `name=` of the rule, empty without one.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+
+    /// The `regexp=` of the rule, empty without one.
+    pub fn regexp(&self) -> &str {
+        &self.regexp
+    }
+
+
+    /// Whether the regexp ignores case, with `ignorecase=yes`.
+    pub fn ignorecase(&self) -> bool {
+        self.ignorecase
+    }
+
+
+    /// Whether `^` and `$` of the regexp match at every line of the text,
+    /// with `multiline=yes`.
+    pub fn multiline(&self) -> bool {
+        self.multiline
+    }
+
+
+    /// Whether `.` of the regexp matches a newline too, with `dotall=yes`.
+    pub fn dotall(&self) -> bool {
+        self.dotall
+    }
+
+
+    /// The regexp with the flags of the rule inline in front, like
+    /// `(?i)error`, for matching it along with others in a `RegexSet`.
+    pub fn flagged_regexp(&self) -> Cow<'_, str> {
+        let flags: String = [(self.ignorecase, 'i'), (self.multiline, 'm'), (self.dotall, 's')].iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| *flag)
+            .collect();
+        if flags.is_empty() {
+            Cow::Borrowed(&self.regexp)
+        } else {
+            // Flags set at the start hold up to the end, so the regexp needs
+            // no group around it, which a trailing `(?x)` comment would eat.
+            Cow::Owned(format!("(?{}){}", flags, self.regexp))
+        }
+    }
+
+
+    /// Compile the regexp of the rule with its flags. Flag groups like
+    /// `(?i)` in the regexp itself work as well.
+    pub fn compile_regexp(&self) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(&self.regexp)
+            .case_insensitive(self.ignorecase)
+            .multi_line(self.multiline)
+            .dot_matches_new_line(self.dotall)
+            .build()
+    }
+
+
+    /// Compile the regexp like `compile_regexp`, falling back to fancy-regex
+    /// for the look-around and backreferences the regex crate lacks.
+    pub(crate) fn compile_pattern(&self) -> Result<CompiledPattern, regex::Error> {
+        CompiledPattern::new(self.compile_regexp(), &self.flagged_regexp())
+    }
+
+
+    /// The `colours=` of the rule.
+    pub fn colours(&self) -> &[RuleColour] {
+        &self.colours
+    }
+
+
+    /// The `colours_light=` of the rule, empty without one.
+    pub fn colours_light(&self) -> &[RuleColour] {
+        &self.colours_light
+    }
+
+
+    /// The `count=` of the rule.
+    pub fn count(&self) -> CountMode {
+        self.count
+    }
+
+
+    /// Whether the rule drops the input instead of showing it.
+    pub fn skip(&self) -> bool {
+        self.skip
+    }
+
+
+    /// Whether the output gets flushed after every line the rule matches.
+    pub fn flush(&self) -> bool {
+        self.flush
+    }
+
+
+    /// Whether the rule gets applied, `enabled=no` keeps it in the conffile
+    /// without any effect.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+
+    /// The `max_line_length=` of the rule, the longest line in bytes it
+    /// runs on.
+    pub fn max_line_length(&self) -> Option<usize> {
+        self.max_line_length
+    }
+
+
+    /// The `same_as=` of the rule, naming the rule its colours come from.
+    pub fn same_as(&self) -> &str {
+        &self.same_as
+    }
+
+
+    /// The steps of `escalate=`, the number of matches within the window
+    /// from which each style applies.
+    pub fn escalate(&self) -> &[(usize, Style)] {
+        &self.escalate
+    }
+
+
+    /// What `eol=` has the colour of the rule do where it ends the line.
+    pub fn eol(&self) -> LineEnd {
+        self.eol
+    }
+
+
+    /// The number of lines the regexp runs on with `window=`, 0 without.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+
+    /// The `escalate_window=` of the rule, 60 seconds without one.
+    pub fn escalate_window(&self) -> EscalateWindow {
+        self.escalate_window
+    }
+
+
+    /// Switch the rule on or off, like `enabled=` does.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+
+    /// The `command=` of the rule, a regexp for the commands it applies to,
+    /// empty for all.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+
+    /// Whether the rule runs on `line` at all.
+    pub fn is_applied_to(&self, line: &str) -> bool {
+        self.max_line_length.is_none_or(|limit| line.len() <= limit)
+    }
+
+
+    /// Whether `selector` picks the rule, by name or by index.
+    fn is_selected_by(&self, selector: &str) -> bool {
+        (!self.name.is_empty() && self.name == selector) || selector.parse() == Ok(self.index)
+    }
+
+
+    /// The `replace=` of the rule, empty without one.
+    pub fn replace(&self) -> &str {
+        &self.replace
+    }
+
+
+    /// Whether the rule adds to what the rules before it did to a line,
+    /// rather than `concat=no` making its result stand alone.
+    pub fn concat(&self) -> bool {
+        !(self.concat.eq("no") || self.concat.eq("0") || self.concat.eq("false"))
+    }
+
+
+    /// The `footer=` template of a footer block, empty for other rules.
+    pub fn footer(&self) -> &str {
+        &self.footer
+    }
+
+
+    /// The `prefix=` template of the rule, empty without one.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+
+    /// Whether the rule finds the timestamp of the lines it matches, for
+    /// `timestamp=yes`.
+    pub fn timestamp(&self) -> bool {
+        self.timestamp
+    }
+
+
+    /// Whether `beep` is one of the `colours=`, which rings the bell on a
+    /// match instead of styling the text.
+    pub fn beep(&self) -> bool {
+        self.beep
+    }
+
+
+    /// Whether the rule says `stripansi=yes`, see `Config::strips_ansi`.
+    pub fn strip_ansi(&self) -> bool {
+        self.strip_ansi
+    }
+
+
+    /// The `alert_after_quiet=` of the rule, how long it mustn't have
+    /// matched before a match rings the bell again.
+    pub fn alert_after_quiet(&self) -> Option<Duration> {
+        self.alert_after_quiet
+    }
+
+
+    /// The `tags=` of the rule.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+
+    /// How to call the rule in reports: its name, or its regexp without one.
+    pub fn label(&self) -> &str {
+        if self.name.is_empty() {
+            &self.regexp
+        } else {
+            &self.name
+        }
+    }
+
+
+    /// Take the `keyword=value` pairs of the rule, each with its line, and
+    /// add every value that can't be used to `problems`.
+    fn insert_content(&mut self, content: &[(usize, String, String)], problems: &mut Vec<(usize, String)>) {
+        for (line, key, value) in content {
+            let mut line_problems = vec![];
+            if key.eq("name") {
+                self.name = value.clone();
+            } else if key.eq("regexp") {
+                // workaround for dig conf line 2
+                self.regexp = value.clone().replace("\\:", "\\\\:");
+            } else if key.eq("ignorecase") {
+                self.ignorecase = get_bool(key, value, &mut line_problems).unwrap_or(self.ignorecase);
+            } else if key.eq("multiline") {
+                self.multiline = get_bool(key, value, &mut line_problems).unwrap_or(self.multiline);
+            } else if key.eq("dotall") {
+                self.dotall = get_bool(key, value, &mut line_problems).unwrap_or(self.dotall);
+            } else if key.eq("eol") {
+                match LineEnd::from_name(value.trim()) {
+                    Some(eol) => self.eol = eol,
+                    None => line_problems.push(format!("eol must be reset, extend or persist, not {}", value))
+                }
+            } else if key.eq("window") {
+                match value.trim() {
+                    "yes" | "true" => self.window = DEFAULT_WINDOW,
+                    "no" | "false" => self.window = 0,
+                    lines => match lines.parse() {
+                        Ok(lines) => self.window = lines,
+                        Err(_) => line_problems.push(format!("window must be a number of lines or yes, not {}", value))
+                    }
+                }
+            } else if key.eq("colours") {
+                self.colours = get_rule_colours(value, &mut line_problems);
+                self.beep = get_colour_name_list(value).iter().any(|name| name == "beep");
+            } else if key.eq("colours_light") {
+                self.colours_light = get_rule_colours(value, &mut line_problems);
+            } else if key.eq("count") {
+                self.count = CountMode::from_name(value);
+            } else if key.eq("command") {
+                self.command = value.clone();
+            } else if key.eq("skip") {
+                self.skip = value.eq("yes") || value.eq("1") || value.eq("true");
+            } else if key.eq("enabled") {
+                self.enabled = !(value.eq("no") || value.eq("0") || value.eq("false"));
+            } else if key.eq("priority") {
+                match value.trim().parse() {
+                    Ok(priority) => self.priority = priority,
+                    Err(_) => line_problems.push(format!("priority must be a whole number, not {}", value))
+                }
+            } else if key.eq("max_line_length") {
+                match value.trim().parse() {
+                    Ok(limit) => self.max_line_length = Some(limit),
+                    Err(_) => line_problems.push(format!("max_line_length must be a number of bytes, not {}", value))
+                }
+            } else if key.eq("same_as") {
+                self.same_as = value.trim().to_string();
+            } else if key.eq("escalate") {
+                match get_escalate_steps(value) {
+                    Some(steps) => self.escalate = steps,
+                    None => line_problems.push(format!("escalate must be a list of count:colours, not {}", value))
+                }
+            } else if key.eq("escalate_window") {
+                match EscalateWindow::from_value(value) {
+                    Some(window) => self.escalate_window = window,
+                    None => line_problems.push(format!("escalate_window must be a duration like 60s or a number of lines, not {}", value))
+                }
+            } else if key.eq("alert_after_quiet") {
+                match EscalateWindow::from_value(value) {
+                    Some(EscalateWindow::Duration(quiet)) => self.alert_after_quiet = Some(quiet),
+                    _ => line_problems.push(format!("alert_after_quiet must be a duration like 300s or 5m, not {}", value))
+                }
+            } else if key.eq("flush") {
+                self.flush = value.eq("yes") || value.eq("1") || value.eq("true");
+            } else if key.eq("replace") {
+                get_escape_problems(value, &mut line_problems);
+                self.replace = value.clone();
+            } else if key.eq("concat") {
+                self.concat = value.clone();
+            } else if key.eq("tags") {
+                self.tags = get_tag_list(value);
+            } else if key.eq("footer") {
+                self.footer = value.clone();
+            } else if key.eq("prefix") {
+                self.prefix = value.clone();
+            } else if key.eq("timestamp") {
+                self.timestamp = value.eq("yes") || value.eq("1") || value.eq("true");
+            } else if key.eq("stripansi") {
+                self.strip_ansi = get_bool(key, value, &mut line_problems).unwrap_or(self.strip_ansi);
+            } else {
+                line_problems.push(format!("{} is not key", key));
+            }
+            problems.extend(line_problems.into_iter().map(|problem| (*line, problem)));
+        }
+    }
+}
+
+
+impl Default for ColourConfig {
+    fn default() -> ColourConfig {
+        ColourConfig::new()
+    }
+}
+
+
+fn is_config_split_line(line: &str) -> bool {
+    // It's a comment line or a blank line.
+    if line.starts_with('#') || line.is_empty() {
+        false
+        // First char not in ascii alphabet, so it's a split line. Spaces in
+        // front of a key don't count, but a line of spaces alone is a split.
+    } else {
+        !line.trim_start().starts_with(|c: char| c.is_ascii_alphabetic())
+    }
+}
+
+
+/// `value` without quotes around it, and for keys other than `regexp=` and
+/// `replace=` without a trailing ` # comment`. Only quotes that close the
+/// value are taken off, so `regexp="[^"]*"` is left as it is. As grcat
+/// matches the quotes of `regexp="\w+"`, those of `regexp=` and `replace=`
+/// only go when they hold spaces at either end, the reason to write them.
+fn get_config_value(key: &str, value: &str) -> String {
+    let verbatim = key == "regexp" || key == "replace";
+    if let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        if let Some(end) = value[1..].find(quote) {
+            let (inner, rest) = (&value[1..end + 1], value[end + 2..].trim_start());
+            let padded = inner.starts_with(char::is_whitespace) || inner.ends_with(char::is_whitespace);
+            if (rest.is_empty() || rest.starts_with('#')) && (padded || !verbatim) {
+                return inner.to_string();
+            }
+        }
+    }
+    if verbatim {
+        return value.to_string();
+    }
+    match value.find(" #").or_else(|| value.find("\t#")) {
+        Some(start) => value[..start].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+
+fn parse_config_line(line: &str) -> Result<Option<(String, String)>, String> {
+    if line.starts_with('#') || line.is_empty() {
+        Ok(None)
+    } else {
+        // Only the first `=` splits, the others belong to the value, which
+        // keeps spaces in front only in quotes.
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim_start()),
+            None => return Err(format!("expected keyword=value, got {}", line)),
+        };
+
+        let key = if key.starts_with("colo") && key.ends_with("_light") {
+            "colours_light"
+        } else if key.starts_with("colo") {
+            "colours"
+        } else {
+            key
+        };
+
+        Ok(Some((key.to_string(), get_config_value(key, value))))
+    }
+}
+
+
+/// Give every `same_as=` rule the colours of the rule it names, following
+/// chains of references to their end.
+fn resolve_same_as(rules: &mut [ColourConfig]) -> Result<(), ConfigError> {
+    for index in 0..rules.len() {
+        let mut chain = vec![index];
+        let mut current = index;
+        while !rules[current].same_as.is_empty() {
+            let target = &rules[current].same_as;
+            let found = rules.iter().position(|rule| rule.name == *target).ok_or_else(|| ConfigError::InvalidRule {
+                rule: rules[index].label().to_string(),
+                message: format!("same_as={} names no rule", target),
+            })?;
+            if chain.contains(&found) {
+                let names: Vec<&str> = chain.iter().chain(Some(&found)).map(|&rule| rules[rule].label()).collect();
+                return Err(ConfigError::InvalidRule {
+                    rule: rules[index].label().to_string(),
+                    message: format!("same_as is circular: {}", names.join(" -> ")),
+                });
+            }
+            chain.push(found);
+            current = found;
+        }
+        if current != index {
+            rules[index].colours = rules[current].colours.clone();
+            rules[index].colours_light = rules[current].colours_light.clone();
+        }
+    }
+    Ok(())
+}
+
+
+/// Give every `count=previous` rule the count of the rule before it in the
+/// conffile, which the first rule lacks, so it counts as `more` there.
+fn resolve_count_previous(rules: &mut [ColourConfig], problems: &mut Vec<(usize, String)>) {
+    // Blocks without any keyword aren't rules anyone wrote.
+    let mut previous = None;
+    for rule in rules.iter_mut().filter(|rule| rule.line != 0) {
+        if rule.count == CountMode::Previous {
+            rule.count = previous.unwrap_or_else(|| {
+                problems.push((rule.line, "count=previous on the first rule counts as more".to_string()));
+                CountMode::More
+            });
+        }
+        previous = Some(rule.count);
+    }
+}
+
+
+/// Check the templates of the rules. Footers can use `lines`, `elapsed`
+/// and `count:NAME` of a rule with that name, prefixes `line` and `file`.
+fn check_templates(rules: &[ColourConfig]) -> Result<(), ConfigError> {
+    for rule in rules {
+        let invalid = |message: String| ConfigError::InvalidRule { rule: rule.label().to_string(), message };
+        for (key, text) in [("footer", &rule.footer), ("prefix", &rule.prefix)] {
+            if text.is_empty() {
+                continue;
+            }
+            let template = Template::parse(text).map_err(|message| invalid(format!("{}: {}", key, message)))?;
+            for field in template.fields() {
+                match (key, field) {
+                    ("footer", ("lines", None)) | ("footer", ("elapsed", None)) => {}
+                    ("footer", ("count", Some(name))) if rules.iter().any(|rule| rule.name == name) => {}
+                    ("footer", ("count", Some(name))) => {
+                        return Err(invalid(format!("footer counts {}, which names no rule", name)));
+                    }
+                    ("prefix", ("line", None)) | ("prefix", ("file", None)) => {}
+                    (key, (name, _)) => return Err(invalid(format!("{} has no field {}", key, name))),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// The message of a regexp that doesn't compile, the last line of the
+/// error, which names what's wrong without the pattern drawn above it.
+fn get_regexp_error(err: &regex::Error) -> String {
+    let text = err.to_string();
+    let last = text.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("");
+    last.trim().trim_start_matches("error: ").to_string()
+}
+
+
+/// The rules of a conffile along with its problems by line.
+type ParsedConfig = (Vec<ColourConfig>, Vec<(usize, String)>);
+
+
+/// How deep `include=` nests, a conffile including one that includes
+/// another being two deep.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+
+/// The rules of a conffile with those of its includes spliced in, each
+/// with the line of the `include=` it came from, 0 for its own, and the
+/// problems by line.
+struct Rules {
+    config_list: Vec<(ColourConfig, usize)>,
+    problems: Vec<(usize, String)>,
+}
+
+
+/// The path and text of the conffile `include=NAME` in the conffile at
+/// `path` names: NAME next to that conffile, else in the grc search path,
+/// else built in.
+fn read_include(name: &str, path: &str) -> Result<(String, String), ConfigError> {
+    let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+    #[cfg(not(target_arch = "wasm32"))]
+    let found = match dir.map(|dir| dir.join(name)).filter(|beside| beside.is_file()) {
+        Some(beside) => Ok(beside.to_string_lossy().into_owned()),
+        None => crate::search::get_config_path(name).map_err(|err| match err {
+            ConfigError::NotFound { name: config_name, mut searched } => {
+                searched.splice(0..0, dir.map(|dir| dir.join(name)));
+                ConfigError::NotFound { name: config_name, searched }
+            }
+            err => err
+        }),
+    };
+    #[cfg(target_arch = "wasm32")]
+    let found: Result<String, _> = Err(ConfigError::NotFound { name: name.to_string(), searched: dir.map(|dir| dir.join(name)).into_iter().collect() });
+    match (found, get_embedded_config(name)) {
+        (Ok(found), _) => fs::read_to_string(&found).map(|text| (found.clone(), text)).map_err(|err| ConfigError::io(&found, err)),
+        (Err(ConfigError::NotFound { .. }), Some(text)) => Ok((format!("embedded:{}", name), text.to_string())),
+        (Err(err), _) => Err(err)
+    }
+}
+
+
+/// What tells conffiles apart in an include chain, their canonical path
+/// where there's one.
+fn get_include_key(path: &str) -> String {
+    fs::canonicalize(path).map_or_else(|_| path.to_string(), |path| path.to_string_lossy().into_owned())
+}
+
+
+/// Add the block `key_val_list` to `rules` as a rule read from `path`, or
+/// when it's an `include=` of its own, the rules of that conffile.
+/// `chain` holds each conffile being read, by key and path, the last
+/// being `path`.
+fn add_block(rules: &mut Rules, key_val_list: &mut Vec<(usize, String, String)>, path: &str,
+             chain: &mut Vec<(String, String)>) -> Result<(), ConfigError> {
+    let include = key_val_list.iter().position(|(_, key, _)| key == "include");
+    if let (Some(index), 1) = (include, key_val_list.len()) {
+        let (line, _, name) = key_val_list.remove(index);
+        let error = |message: String| ConfigError::Parse { path: path.to_string(), line, message };
+        if chain.len() > MAX_INCLUDE_DEPTH {
+            return Err(error(format!("include={} nests more than {} deep", name, MAX_INCLUDE_DEPTH)));
+        }
+        let (found, text) = read_include(&name, path).map_err(|err| error(format!("include={}: {}", name, err)))?;
+        let key = get_include_key(&found);
+        if chain.iter().any(|(included, _)| *included == key) {
+            let paths: Vec<&str> = chain.iter().map(|(_, path)| path.as_str()).chain(Some(found.as_str())).collect();
+            return Err(error(format!("include={} goes round in a cycle: {}", name, paths.join(" -> "))));
+        }
+        chain.push((key, found.clone()));
+        let included = parse_rules(text.as_bytes(), &found, chain)?;
+        chain.pop();
+        rules.config_list.extend(included.config_list.into_iter().map(|(config, at)| (config, if at == 0 { line } else { at })));
+        rules.problems.extend(included.problems.into_iter().map(|(at, problem)| (line, format!("{} line {}: {}", found, at, problem))));
+        return Ok(());
+    }
+    if let Some(index) = include {
+        let (line, _, _) = key_val_list.remove(index);
+        rules.problems.push((line, "include= only works in a block of its own, left out".to_string()));
+    }
+    let mut config = ColourConfig::new();
+    config.source = path.to_string();
+    // The line of the first keyword of the block.
+    config.line = key_val_list.first().map_or(0, |(line, _, _)| *line);
+    config.insert_content(key_val_list, &mut rules.problems);
+    rules.config_list.push((config, 0));
+    key_val_list.clear();
+    Ok(())
+}
+
+
+/// The rules read from `reader`, with the conffiles it includes read in
+/// turn, and the lines that can't be parsed.
+fn parse_rules(reader: impl BufRead, path: &str, chain: &mut Vec<(String, String)>) -> Result<Rules, ConfigError> {
+    let mut key_val_list: Vec<(usize, String, String)> = vec![];
+    let mut rules = Rules { config_list: vec![], problems: vec![] };
+    for (index, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                return Err(ConfigError::Parse { path: path.to_string(), line: index + 1, message: err.to_string() });
+            }
+            Err(err) => return Err(ConfigError::io(path, err))
+        };
+        // Conffiles saved on Windows come with a BOM and a \r on every line.
+        let line = if index == 0 { line.strip_prefix('\u{feff}').unwrap_or(&line) } else { &line };
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if is_config_split_line(line) {
+            add_block(&mut rules, &mut key_val_list, path, chain)?;
+        } else {
+            match parse_config_line(line) {
+                Ok(Some((key, value))) => key_val_list.push((index + 1, key, value)),
+                Ok(None) => {}
+                Err(problem) => rules.problems.push((index + 1, problem)),
+            }
+        }
+    }
+    add_block(&mut rules, &mut key_val_list, path, chain)?;
+    Ok(rules)
+}
+
+
+/// Parse the rules read from `reader`, `path` only names it in errors,
+/// along with the malformed lines, values that can't be used and rules
+/// that do nothing, by their line. Problems in included conffiles are put
+/// at the line of their `include=`.
+fn parse_config_problems(reader: impl BufRead, path: &str) -> Result<ParsedConfig, ConfigError> {
+    let mut chain = vec![(get_include_key(path), path.to_string())];
+    let Rules { config_list, mut problems } = parse_rules(reader, path, &mut chain)?;
+    let (mut config_list, included_at): (Vec<ColourConfig>, Vec<usize>) = config_list.into_iter().unzip();
+    for (index, config) in config_list.iter_mut().enumerate() {
+        config.index = index;
+    }
+
+    resolve_same_as(&mut config_list)?;
+    resolve_count_previous(&mut config_list, &mut problems);
+    check_templates(&config_list)?;
+    for (config, at) in config_list.iter().zip(included_at) {
+        let mut locate = |problem: String| match at {
+            0 => problems.push((config.line, problem)),
+            at => problems.push((at, format!("{} line {}: {}", config.source, config.line, problem)))
+        };
+        // grcat's regexps are Python's, so refusing the conffile over one the
+        // regex crate lacks, such as a lookahead, would lose all the others.
+        if let (false, Err(err)) = (config.regexp.is_empty(), config.compile_pattern()) {
+            locate(format!("{} is no valid regexp, rule {} never matches: {}", config.regexp, config.index, get_regexp_error(&err)));
+        } else if config.regexp.is_empty() && config.line != 0 && config.prefix.is_empty() && config.footer.is_empty() {
+            locate("the rule has no regexp, so it never matches".to_string());
+        }
+        let alone = if !config.replace.is_empty() {
+            Some("replace=".to_string())
+        } else if matches!(config.count, CountMode::Block | CountMode::Unblock) {
+            Some(format!("count={}", config.count.name()))
+        } else {
+            None
+        };
+        if let (true, Some(alone)) = (config.window > 1, alone) {
+            locate(format!("window= doesn't go with {}, the rule runs on the line alone", alone));
+        }
+    }
+    problems.sort_by_key(|(line, _)| *line);
+    Ok((config_list, problems))
+}
+
+
+/// Parse the rules read from `reader` like `parse_config_problems`, the
+/// problems skipped with a warning each, or with `strict` refusing the
+/// conffile.
+fn parse_config(reader: impl BufRead, path: &str, strict: bool) -> Result<Vec<ColourConfig>, ConfigError> {
+    let _span = tracing::debug_span!("parse_config", path).entered();
+    let (config_list, problems) = parse_config_problems(reader, path)?;
+    if strict && !problems.is_empty() {
+        return Err(ConfigError::Invalid { path: path.to_string(), problems });
+    }
+    for (line, problem) in &problems {
+        warn(&format!("{} line {}: {}", path, line, problem));
+    }
+    tracing::debug!(rules = config_list.len(), "parsed config");
+    Ok(config_list)
+}
+
+
+/// A parsed conffile.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    rules: Vec<ColourConfig>,
+}
+
+
+impl Config {
+    /// Put `rules` into evaluation order, a stable sort by priority, and
+    /// number them in that order.
+    fn new(mut rules: Vec<ColourConfig>) -> Config {
+        rules.sort_by_key(|rule| rule.priority);
+        for (index, rule) in rules.iter_mut().enumerate() {
+            rule.index = index;
+        }
+        Config { rules }
+    }
+
+
+    /// Read and parse the conffile at `path`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Config, ConfigError> {
+        // Ref: https://riptutorial.com/rust/example/4275/read-a-file-line-by-line
+        let path = path.as_ref().display().to_string();
+        let file = std::fs::File::open(&path).map_err(|err| ConfigError::io(&path, err))?;
+        Ok(Config::new(parse_config(io::BufReader::new(file), &path, false)?))
+    }
+
+
+    /// Read and parse the conffile at `path` like `from_path`, only refuse
+    /// it with every problem that would only be warned about.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path_strict(path: impl AsRef<std::path::Path>) -> Result<Config, ConfigError> {
+        let path = path.as_ref().display().to_string();
+        let file = std::fs::File::open(&path).map_err(|err| ConfigError::io(&path, err))?;
+        Ok(Config::new(parse_config(io::BufReader::new(file), &path, true)?))
+    }
+
+
+    /// Read and parse the conffile at `path` like `from_path`, returning
+    /// the problems by their line instead of warning about them.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn check_path(path: impl AsRef<std::path::Path>) -> Result<(Config, Vec<(usize, String)>), ConfigError> {
+        let path = path.as_ref().display().to_string();
+        let file = std::fs::File::open(&path).map_err(|err| ConfigError::io(&path, err))?;
+        let (rules, problems) = parse_config_problems(io::BufReader::new(file), &path)?;
+        Ok((Config::new(rules), problems))
+    }
+
+
+    /// Parse a conffile from any reader, such as bytes in memory. Invalid
+    /// UTF-8 is a parse error.
+    pub fn from_reader(reader: impl BufRead) -> Result<Config, ConfigError> {
+        Ok(Config::new(parse_config(reader, "<reader>", false)?))
+    }
+
+
+    /// Parse a conffile from any reader like `from_reader`, with `name`
+    /// naming it in warnings and errors, and with `strict` refusing it over
+    /// every problem like `from_path_strict`.
+    pub fn from_reader_named(reader: impl BufRead, name: &str, strict: bool) -> Result<Config, ConfigError> {
+        Ok(Config::new(parse_config(reader, name, strict)?))
+    }
+
+
+    /// Build a config from rules taken out of other configs, or made with a
+    /// `RuleBuilder`. The rules are sorted by priority and renumbered.
+    pub fn from_rules(rules: Vec<ColourConfig>) -> Config {
+        Config::new(rules)
+    }
+
+
+    /// The rules in evaluation order.
+    pub fn rules(&self) -> &[ColourConfig] {
+        &self.rules
+    }
+
+
+    /// Whether the escape sequences already in the input are taken out of
+    /// every line before the rules run on it, as any rule with
+    /// `stripansi=yes` asks for the whole conffile.
+    pub fn strips_ansi(&self) -> bool {
+        self.rules.iter().any(|rule| rule.strip_ansi)
+    }
+
+
+    /// Whether every line comes out the same whatever lines came before it,
+    /// so lines can be coloured in any order: no rule makes blocks of lines,
+    /// escalates, alerts, looks at the lines before with `window=` or puts
+    /// a prefix in front, which may number lines.
+    pub fn is_line_independent(&self) -> bool {
+        self.rules.iter().filter(|rule| rule.enabled).all(|rule| {
+            !matches!(rule.count, CountMode::Block | CountMode::Unblock) && rule.escalate.is_empty()
+                && rule.alert_after_quiet.is_none() && rule.prefix.is_empty() && rule.window < 2
+        })
+    }
+
+
+    /// Whether any rule has colours for a light background.
+    pub fn has_light_colours(&self) -> bool {
+        self.rules.iter().any(|rule| !rule.colours_light.is_empty())
+    }
+
+
+    /// The config for a light background, where every rule with a
+    /// `colours_light=` uses those colours.
+    pub fn for_light_background(&self) -> Config {
+        let rules = self.rules.iter()
+            .map(|rule| if rule.colours_light.is_empty() {
+                rule.clone()
+            } else {
+                ColourConfig { colours: rule.colours_light.clone(), ..rule.clone() }
+            })
+            .collect();
+        Config { rules }
+    }
+
+
+    /// The config with every colour brought within `depth`, for terminals
+    /// that show fewer colours than the rules use.
+    pub fn for_colour_depth(&self, depth: ColourDepth) -> Config {
+        let convert = |colours: &[RuleColour]| -> Vec<RuleColour> {
+            colours.iter()
+                .map(|colour| match colour {
+                    RuleColour::Style(style) => RuleColour::Style(style.for_depth(depth)),
+                    colour => *colour,
+                })
+                .collect()
+        };
+        let rules = self.rules.iter()
+            .map(|rule| ColourConfig {
+                colours: convert(&rule.colours),
+                colours_light: convert(&rule.colours_light),
+                escalate: rule.escalate.iter().map(|(count, style)| (*count, style.for_depth(depth))).collect(),
+                ..rule.clone()
+            })
+            .collect();
+        Config { rules }
+    }
+
+
+    /// The config for output of `command`, with the rules whose `command=`
+    /// regexp doesn't match it disabled, as are all rules with a `command=`
+    /// when there's no command. Rules without one always apply.
+    pub fn for_command(&self, command: Option<&str>) -> Config {
+        let rules = self.rules.iter()
+            .map(|rule| {
+                let applies = rule.command.is_empty()
+                    || command.is_some_and(|command| Regex::new(&rule.command).is_ok_and(|re| re.is_match(command)));
+                ColourConfig { enabled: rule.enabled && applies, ..rule.clone() }
+            })
+            .collect();
+        Config { rules }
+    }
+
+
+    /// Pick rules by name or index. With `only` non-empty just those rules
+    /// are kept, and enabled even if the conffile disables them; the rules
+    /// in `except` are dropped.
+    pub fn select(&self, only: &[String], except: &[String]) -> Config {
+        let rules = self.rules.iter()
+            .filter(|rule| only.is_empty() || only.iter().any(|selector| rule.is_selected_by(selector)))
+            .filter(|rule| !except.iter().any(|selector| rule.is_selected_by(selector)))
+            .map(|rule| ColourConfig { enabled: rule.enabled || !only.is_empty(), ..rule.clone() })
+            .collect();
+        Config::new(rules)
+    }
+}
+
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    /// Parse conffile text.
+    fn from_str(content: &str) -> Result<Config, ConfigError> {
+        Ok(Config::new(parse_config(content.as_bytes(), "<string>", false)?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = "name=first\nregexp=a\ncolours=red\n-\nregexp=b\ntags=x, y\n".parse().unwrap();
+        let rules = config.rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].label(), "first");
+        assert_eq!(rules[1].index(), 1);
+        assert_eq!(rules[1].label(), "b");
+        assert_eq!(rules[1].tags(), ["x", "y"]);
+    }
+
+    #[test]
+    fn test_for_command() {
+        let config: Config = "regexp=a\ncommand=^mount\\b\n-\nregexp=b\n".parse().unwrap();
+        let enabled = |command| -> Vec<bool> { config.for_command(command).rules().iter().map(|rule| rule.enabled()).collect() };
+        assert_eq!(enabled(Some("mount -l")), [true, true]);
+        assert_eq!(enabled(Some("findmnt")), [false, true]);
+        assert_eq!(enabled(None), [false, true]);
+    }
+
+    #[test]
+    fn test_parse_config_line() {
+        let parse = |line| parse_config_line(line).unwrap().unwrap();
+        let pair = |key: &str, value: &str| (key.to_string(), value.to_string());
+        assert_eq!(parse(" regexp =foo=bar"), pair("regexp", "foo=bar"));
+        assert_eq!(parse("regexp = foo "), pair("regexp", "foo "));
+        assert_eq!(parse("regexp= \" foo\""), pair("regexp", " foo"));
+        assert_eq!(parse("regexp=\"  foo  \""), pair("regexp", "  foo  "));
+        assert_eq!(parse("regexp=' a' # quoted"), pair("regexp", " a"));
+        assert_eq!(parse("regexp=\"\\w+\""), pair("regexp", "\"\\w+\""));
+        assert_eq!(parse("regexp=\"[^\"]*\""), pair("regexp", "\"[^\"]*\""));
+        assert_eq!(parse("regexp=a # b"), pair("regexp", "a # b"));
+        assert_eq!(parse("colours=bold red # errors"), pair("colours", "bold red"));
+        assert_eq!(parse("color=\"green\" # quoted"), pair("colours", "green"));
+        assert_eq!(parse("count=more\t# as before"), pair("count", "more"));
+        assert!(parse_config_line("regexp").is_err());
+    }
+
+    #[test]
+    fn test_indented_keys() {
+        let config: Config = "  regexp=foo\n\tcolours=red\n \nregexp=bar\n".parse().unwrap();
+        let rules = config.rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].regexp(), "foo");
+        assert_eq!(rules[1].regexp(), "bar");
+        assert_eq!(crate::Colourizer::new(config).colourize_line("foo"), "\x1b[31mfoo\x1b[0m");
+    }
+
+    #[test]
+    fn test_parse_stock_conffiles() {
+        // Lines the way the conffiles that come with grc have them.
+        let conffiles = [
+            ("# ping\nregexp=\\d+\\.\\d+ ms\ncolours=yellow\ncount=more\n", vec![("regexp", r"\d+\.\d+ ms"), ("colours", "yellow"), ("count", "more")]),
+            ("regexp=^(\\+\\+\\+|---)\\s.*$\ncolours=bold blue\n", vec![("regexp", r"^(\+\+\+|---)\s.*$"), ("colours", "bold blue")]),
+            ("regexp=(?<=\\s)#\\d+\ncolours=default,bold cyan\nskip=no\n", vec![("regexp", r"(?<=\s)#\d+"), ("colours", "default,bold cyan"), ("skip", "no")]),
+            ("regexp=\\s(\\d+)=(\\w+)\ncolour=green\nconcat=no\n", vec![("regexp", r"\s(\d+)=(\w+)"), ("colours", "green"), ("concat", "no")]),
+        ];
+        for (conffile, expected) in &conffiles {
+            let pairs: Vec<(String, String)> = conffile.lines().filter_map(|line| parse_config_line(line).unwrap()).collect();
+            let expected: Vec<(String, String)> = expected.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+            assert_eq!(pairs, expected);
+        }
+    }
+}
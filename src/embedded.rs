@@ -0,0 +1,39 @@
+//! Conffiles built into rgrcat, for machines without grc's.
+
+/// The embedded conffiles by name, in the order they're listed.
+const EMBEDDED_CONFIGS: &[(&str, &str)] = &[
+    ("conf.df", include_str!("../conf/conf.df")),
+    ("conf.gcc", include_str!("../conf/conf.gcc")),
+    ("conf.log", include_str!("../conf/conf.log")),
+    ("conf.ping", include_str!("../conf/conf.ping")),
+    ("conf.ps", include_str!("../conf/conf.ps")),
+];
+
+
+/// The text of the conffile `config_name` built into rgrcat, which is only
+/// used when no file of that name is in the search path.
+pub fn get_embedded_config(config_name: &str) -> Option<&'static str> {
+    EMBEDDED_CONFIGS.iter().find(|(name, _)| *name == config_name).map(|(_, text)| *text)
+}
+
+
+/// The names of the conffiles built into rgrcat, sorted.
+pub fn get_embedded_config_names() -> Vec<&'static str> {
+    EMBEDDED_CONFIGS.iter().map(|(name, _)| *name).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_embedded_configs_are_valid() {
+        for name in get_embedded_config_names() {
+            let config = Config::from_reader_named(get_embedded_config(name).unwrap().as_bytes(), name, true).unwrap();
+            assert!(!config.rules().is_empty(), "{} has no rules", name);
+        }
+        assert!(get_embedded_config("conf.missing").is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+
+/// Why a config could not be loaded.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The config name was not found in any of the searched directories.
+    #[error("config file [{name}] not found after searching {}, tried: {}", display_dir_count(.searched), display_paths(.searched))]
+    NotFound {
+        /// The config name looked for.
+        name: String,
+        /// Every path which was tried, in order.
+        searched: Vec<PathBuf>,
+    },
+    /// The config text is malformed.
+    #[error("error in configuration {path} line {line}: {message}")]
+    Parse {
+        /// Where the config came from.
+        path: String,
+        /// The line number, starting from 1.
+        line: usize,
+        /// What's wrong with the line.
+        message: String,
+    },
+    /// The config has lines that can't be used, refused when parsing
+    /// strictly.
+    #[error("error in configuration {path}:{}", display_problems(.problems))]
+    Invalid {
+        /// Where the config came from.
+        path: String,
+        /// The line numbers, starting from 1, with what's wrong there.
+        problems: Vec<(usize, String)>,
+    },
+    /// A rule built in code is invalid.
+    #[error("invalid rule {rule}: {message}")]
+    InvalidRule {
+        /// The label of the rule.
+        rule: String,
+        /// What's wrong with the rule.
+        message: String,
+    },
+    /// The config could not be read.
+    #[error("{path}: {source}")]
+    Io {
+        /// The file which could not be read.
+        path: String,
+        /// The underlying error.
+        source: io::Error,
+    },
+}
+
+
+impl ConfigError {
+    pub(crate) fn io(path: &str, source: io::Error) -> ConfigError {
+        ConfigError::Io { path: path.to_string(), source }
+    }
+}
+
+
+fn display_problems(problems: &[(usize, String)]) -> String {
+    problems.iter().map(|(line, problem)| format!("\n  line {}: {}", line, problem)).collect()
+}
+
+
+/// How many directories `paths` are in, as in `3 directories`.
+fn display_dir_count(paths: &[PathBuf]) -> String {
+    let mut dirs: Vec<_> = paths.iter().map(|path| path.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+    match dirs.len() {
+        1 => "1 directory".to_string(),
+        count => format!("{} directories", count)
+    }
+}
+
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    let paths: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+    paths.join(", ")
+}
@@ -0,0 +1,185 @@
+//! A C interface to the colourizer, for embedding rgrcat in C programs or
+//! calling it through Lua's FFI. See `include/rgrcat.h`.
+//!
+//! Functions returning a pointer return NULL on failure, after which
+//! `rgrcat_last_error` tells what went wrong. No panic crosses the boundary.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::colourizer::Colourizer;
+use crate::config::Config;
+
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+
+/// An opaque colourizer handle.
+pub struct RgrcatColourizer {
+    colourizer: Colourizer,
+}
+
+
+fn set_last_error(message: &str) {
+    // Interior NULs can't be represented, so cut the message there.
+    let message = message.split('\0').next().unwrap_or_default();
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+
+fn get_panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => format!("panic: {}", message),
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => format!("panic: {}", message),
+            None => "panic".to_string()
+        }
+    }
+}
+
+
+/// Run `body` and turn its errors and panics into NULL plus the last error.
+fn guard<T>(body: impl FnOnce() -> Result<*mut T, String>) -> *mut T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(result)) => result,
+        Ok(Err(message)) => {
+            set_last_error(&message);
+            ptr::null_mut()
+        }
+        Err(panic) => {
+            set_last_error(&get_panic_message(panic));
+            ptr::null_mut()
+        }
+    }
+}
+
+
+/// Read a NUL-terminated UTF-8 argument.
+unsafe fn get_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+    if value.is_null() {
+        return Err(format!("{} is NULL", name));
+    }
+    CStr::from_ptr(value).to_str().map_err(|err| format!("{} is not UTF-8: {}", name, err))
+}
+
+
+fn new_handle(config: Config) -> *mut RgrcatColourizer {
+    Box::into_raw(Box::new(RgrcatColourizer { colourizer: Colourizer::new(config) }))
+}
+
+
+/// Create a colourizer from the conffile at `path`.
+///
+/// # Safety
+/// `path` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rgrcat_colourizer_from_path(path: *const c_char) -> *mut RgrcatColourizer {
+    guard(|| {
+        let path = get_str(path, "path")?;
+        Config::from_path(path).map(new_handle).map_err(|err| err.to_string())
+    })
+}
+
+
+/// Create a colourizer from conffile text.
+///
+/// # Safety
+/// `config` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rgrcat_colourizer_from_string(config: *const c_char) -> *mut RgrcatColourizer {
+    guard(|| {
+        get_str(config, "config")?.parse::<Config>().map(new_handle).map_err(|err| err.to_string())
+    })
+}
+
+
+/// Colour `line`, returning a new string to release with `rgrcat_string_free`.
+///
+/// # Safety
+/// `colourizer` must come from one of the constructors and not be freed yet,
+/// `line` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rgrcat_colourize(colourizer: *const RgrcatColourizer, line: *const c_char) -> *mut c_char {
+    guard(|| {
+        let colourizer = colourizer.as_ref().ok_or("colourizer is NULL")?;
+        let line = get_str(line, "line")?;
+        let result = colourizer.colourizer.colourize_line(line);
+        // The line had no NUL and rules can't add one.
+        Ok(CString::new(result).map_err(|err| err.to_string())?.into_raw())
+    })
+}
+
+
+/// Release a string returned by `rgrcat_colourize`. NULL is ignored.
+///
+/// # Safety
+/// `value` must be NULL or come from `rgrcat_colourize`, and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rgrcat_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+
+/// Release a colourizer. NULL is ignored.
+///
+/// # Safety
+/// `colourizer` must be NULL or come from one of the constructors, and not be
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rgrcat_colourizer_free(colourizer: *mut RgrcatColourizer) {
+    if !colourizer.is_null() {
+        drop(Box::from_raw(colourizer));
+    }
+}
+
+
+/// The last error of the calling thread, NULL without one. The string stays
+/// valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn rgrcat_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null()
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colourize_through_ffi() {
+        let config = CString::new("regexp=ERROR\ncolours=red\n").unwrap();
+        let line = CString::new("an ERROR").unwrap();
+        unsafe {
+            let colourizer = rgrcat_colourizer_from_string(config.as_ptr());
+            assert!(!colourizer.is_null());
+            let result = rgrcat_colourize(colourizer, line.as_ptr());
+            assert_eq!(CStr::from_ptr(result).to_str().unwrap(), "an \x1b[31mERROR\x1b[0m");
+            rgrcat_string_free(result);
+            rgrcat_colourizer_free(colourizer);
+        }
+    }
+
+    #[test]
+    fn test_errors_through_ffi() {
+        let path = CString::new("/no/such/conffile").unwrap();
+        unsafe {
+            assert!(rgrcat_colourizer_from_path(path.as_ptr()).is_null());
+            let error = CStr::from_ptr(rgrcat_last_error()).to_str().unwrap();
+            assert!(error.starts_with("/no/such/conffile: "));
+            assert!(rgrcat_colourize(ptr::null(), path.as_ptr()).is_null());
+            assert_eq!(CStr::from_ptr(rgrcat_last_error()).to_str().unwrap(), "colourizer is NULL");
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Rendering styled lines as HTML with inline CSS.
+
+use crate::style::{Attributes, Colour, Style, StyledSpan};
+
+
+fn get_css_colour(colour: Colour) -> String {
+    let (r, g, b) = colour.to_rgb();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+
+/// The inline CSS of `style`.
+pub fn get_css(style: &Style) -> String {
+    let mut fg = style.fg.map(get_css_colour);
+    let mut bg = style.bg.map(get_css_colour);
+    if style.attributes.contains(Attributes::REVERSE) {
+        // CSS system colours stand in for the terminal defaults.
+        let reversed_fg = bg.take().unwrap_or_else(|| "Canvas".to_string());
+        bg = Some(fg.take().unwrap_or_else(|| "CanvasText".to_string()));
+        fg = Some(reversed_fg);
+    }
+
+    let mut css = vec![];
+    if let Some(fg) = fg {
+        css.push(format!("color:{}", fg));
+    }
+    if let Some(bg) = bg {
+        css.push(format!("background-color:{}", bg));
+    }
+    if style.attributes.contains(Attributes::BOLD) {
+        css.push("font-weight:bold".to_string());
+    }
+    if style.attributes.contains(Attributes::DIM) {
+        css.push("opacity:0.5".to_string());
+    }
+    if style.attributes.contains(Attributes::ITALIC) {
+        css.push("font-style:italic".to_string());
+    }
+    let mut decorations = vec![];
+    if style.attributes.contains(Attributes::UNDERLINE) {
+        decorations.push("underline");
+    }
+    if style.attributes.contains(Attributes::STRIKETHROUGH) {
+        decorations.push("line-through");
+    }
+    if style.attributes.contains(Attributes::BLINK) || style.attributes.contains(Attributes::RAPID_BLINK) {
+        decorations.push("blink");
+    }
+    if !decorations.is_empty() {
+        css.push(format!("text-decoration:{}", decorations.join(" ")));
+    }
+    if style.attributes.contains(Attributes::CONCEALED) {
+        css.push("visibility:hidden".to_string());
+    }
+    css.join(";")
+}
+
+
+fn push_escaped(result: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c)
+        }
+    }
+}
+
+
+/// Render `line` with its `spans` as HTML, every span becoming a `<span>`
+/// with inline CSS. The text is escaped, but not wrapped in any element.
+pub fn render_html(line: &str, spans: &[StyledSpan]) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut end = 0;
+    for span in spans {
+        push_escaped(&mut result, &line[end..span.range.start]);
+        result.push_str("<span style=\"");
+        result.push_str(&get_css(&span.style));
+        result.push_str("\">");
+        push_escaped(&mut result, &line[span.range.clone()]);
+        result.push_str("</span>");
+        end = span.range.end;
+    }
+    push_escaped(&mut result, &line[end..]);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::NamedColour;
+
+    #[test]
+    fn test_render_html() {
+        let style = Style { fg: Some(Colour::Named(NamedColour::Red)), bg: None, attributes: Attributes::BOLD | Attributes::UNDERLINE };
+        let spans = [StyledSpan { range: 4..9, style }];
+        assert_eq!(render_html("<a> ERROR & more", &spans),
+                   "&lt;a&gt; <span style=\"color:#cd0000;font-weight:bold;text-decoration:underline\">ERROR</span> &amp; more");
+        assert_eq!(get_css_colour(Colour::Indexed(196)), "#ff0000");
+        assert_eq!(get_css_colour(Colour::Indexed(244)), "#808080");
+    }
+}
@@ -0,0 +1,60 @@
+//! Colour text with grcat conffiles.
+//!
+//! Parse a conffile into a [`Config`], then hand it to a [`Colourizer`] to
+//! colour single lines or whole streams. The `rgrcat` binary is built on
+//! this API.
+//!
+//! ```
+//! use rgrcat::{Colourizer, Config};
+//!
+//! let config = Config::from_reader("regexp=ERROR\ncolours=red\n".as_bytes()).unwrap();
+//! let colourizer = Colourizer::new(config);
+//! assert_eq!(colourizer.colourize_line("an ERROR"), "an \x1b[31mERROR\x1b[0m");
+//!
+//! let mut output = vec![];
+//! colourizer.process("ERROR\nok\n".as_bytes(), &mut output).unwrap();
+//! assert_eq!(output, b"\x1b[31mERROR\x1b[0m\nok\n");
+//! ```
+
+#![warn(missing_docs)]
+
+mod builder;
+pub mod colour;
+mod colourizer;
+mod config;
+mod embedded;
+mod error;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+mod html;
+mod lines;
+#[doc(hidden)]
+pub mod log;
+mod pattern;
+pub mod profile;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(not(target_arch = "wasm32"))]
+mod search;
+mod state;
+mod style;
+mod template;
+mod timestamp;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod width;
+
+pub use builder::{ConfigBuilder, RuleBuilder};
+pub use colourizer::{Colourizer, GroupMatch, LineExplanation, ProcessedLine, RuleSpan, RuleStep};
+pub use config::{get_tag_list, ColourConfig, Config, CountMode, EscalateWindow, RuleColour};
+pub use embedded::{get_embedded_config, get_embedded_config_names};
+pub use error::ConfigError;
+pub use html::{get_css, render_html};
+pub use lines::{escape_bytes, unescape_bytes, ColourLines, LineReader, SpanLines};
+pub use profile::Profile;
+#[cfg(not(target_arch = "wasm32"))]
+pub use search::{find_config, get_config_dirs, get_config_list, get_config_name_for_command, get_config_path, get_grc_conf_entries};
+pub use state::StreamState;
+pub use style::{render_ansi, Attributes, Colour, ColourDepth, LineEnd, NamedColour, Style, StyledSpan};
+pub use template::Template;
+pub use timestamp::{format_timestamp, parse_timestamp};
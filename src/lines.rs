@@ -0,0 +1,312 @@
+//! Reading input line by line and colouring it as it streams past.
+
+use std::borrow::Cow;
+use std::io::{self, BufRead};
+
+use crate::colourizer::{Colourizer, ProcessedLine};
+use crate::state::StreamState;
+use crate::style::StyledSpan;
+use crate::width::strip_escapes;
+
+
+/// The first of the 256 characters standing in for bytes that aren't
+/// UTF-8, at the end of the last private use plane.
+const ESCAPE_BASE: u32 = 0x10FF00;
+
+
+/// Reads lines the way rgrcat does: split at `\n`, `\r\n` or a lone `\r`
+/// such as those of progress bars, and decoded as UTF-8. Only the line
+/// ending is cut off, so padding and tabs at the end stay. The raw bytes of
+/// the line stay available. With a maximum length, lines longer than that
+/// come in pieces, so they never take more memory than the maximum and a
+/// chunk.
+pub struct LineReader<R> {
+    reader: R,
+    /// The byte ending every line.
+    terminator: u8,
+    raw: Vec<u8>,
+    max_length: Option<usize>,
+    /// Whether the last read is a piece of a line over the maximum length.
+    overlong: bool,
+    /// Whether the rest of that line is still to be read.
+    continued: bool,
+}
+
+
+impl<R: BufRead> LineReader<R> {
+    /// Read lines from `reader`.
+    pub fn new(reader: R) -> LineReader<R> {
+        LineReader::with_terminator(reader, b'\n')
+    }
+
+
+    /// Read records ending with `terminator` from `reader`, such as the
+    /// NUL-terminated ones of `find -print0`.
+    pub fn with_terminator(reader: R, terminator: u8) -> LineReader<R> {
+        LineReader { reader, terminator, raw: vec![], max_length: None, overlong: false, continued: false }
+    }
+
+
+    /// Read lines longer than `max_length` bytes in pieces, the first one
+    /// just over it and the others as they come in, see `is_overlong`.
+    /// `None`, the default, reads every line whole.
+    pub fn set_max_length(&mut self, max_length: Option<usize>) {
+        self.max_length = max_length;
+    }
+
+
+    /// Whether the last line read is a piece of one longer than the maximum
+    /// length, to be passed on as it is.
+    pub fn is_overlong(&self) -> bool {
+        self.overlong
+    }
+
+
+    /// Whether the last piece read of a line longer than the maximum length
+    /// is followed by more of it. The last piece has the line ending.
+    pub fn is_continued(&self) -> bool {
+        self.continued
+    }
+
+
+    /// Whether what's read so far of the line is to be returned as a piece
+    /// without waiting for the rest: once it's over the maximum length, and
+    /// as soon as there's anything of the rest.
+    fn is_cut(&mut self) -> bool {
+        let limit = if self.overlong { 0 } else { self.max_length.unwrap_or(usize::MAX) };
+        if self.raw.len() > limit {
+            self.overlong = true;
+            self.continued = true;
+        }
+        self.continued
+    }
+
+
+    /// Read the next line, returning false at the end of the input.
+    pub fn read(&mut self) -> io::Result<bool> {
+        // Ref: https://doc.rust-lang.org/std/io/struct.Stdin.html#method.read_line
+        self.raw.clear();
+        self.overlong = self.continued;
+        self.continued = false;
+        if self.terminator != b'\n' {
+            let terminator = self.terminator;
+            loop {
+                let buffer = self.reader.fill_buf()?;
+                if buffer.is_empty() {
+                    return Ok(!self.raw.is_empty());
+                }
+                match buffer.iter().position(|byte| *byte == terminator) {
+                    Some(end) => {
+                        self.raw.extend_from_slice(&buffer[..=end]);
+                        self.reader.consume(end + 1);
+                        return Ok(true);
+                    }
+                    None => {
+                        let len = buffer.len();
+                        self.raw.extend_from_slice(buffer);
+                        self.reader.consume(len);
+                    }
+                }
+                if self.is_cut() {
+                    return Ok(true);
+                }
+            }
+        }
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(!self.raw.is_empty());
+            }
+            // Only the byte after a `\r` tells whether it's part of `\r\n`.
+            if self.raw.last() == Some(&b'\r') {
+                if buffer[0] == b'\n' {
+                    self.raw.push(b'\n');
+                    self.reader.consume(1);
+                }
+                return Ok(true);
+            }
+            match buffer.iter().position(|byte| *byte == b'\n' || *byte == b'\r') {
+                Some(end) => {
+                    self.raw.extend_from_slice(&buffer[..=end]);
+                    self.reader.consume(end + 1);
+                    if self.raw.last() == Some(&b'\n') {
+                        return Ok(true);
+                    }
+                }
+                None => {
+                    let len = buffer.len();
+                    self.raw.extend_from_slice(buffer);
+                    self.reader.consume(len);
+                    if self.is_cut() {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+    }
+
+
+    /// The reader the lines come from.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+
+    /// The bytes of the last line read, including its terminator.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+
+    /// How the last line read ends: with the terminator, `\r\n` or `\r`
+    /// when that's `\n`, or with nothing at the end of the input.
+    pub fn ending(&self) -> &[u8] {
+        let len = if self.terminator != b'\n' {
+            usize::from(self.raw.last() == Some(&self.terminator))
+        } else if self.raw.ends_with(b"\r\n") {
+            2
+        } else {
+            usize::from(matches!(self.raw.last(), Some(b'\n' | b'\r')))
+        };
+        &self.raw[self.raw.len() - len..]
+    }
+
+
+    fn content(&self) -> &[u8] {
+        &self.raw[..self.raw.len() - self.ending().len()]
+    }
+
+
+    /// The text of the last line read, without its line ending.
+    pub fn line(&self) -> io::Result<&str> {
+        std::str::from_utf8(self.content()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+
+    /// The text of the last line like `line`, only when it isn't UTF-8 the
+    /// bytes that aren't stand in as characters, see `escape_bytes`.
+    pub fn escaped_line(&self) -> Cow<'_, str> {
+        escape_bytes(self.content())
+    }
+}
+
+
+/// `bytes` as text, with every byte that isn't part of valid UTF-8 standing
+/// in as one of the characters U+10FF00 to U+10FFFF, so the rules can run
+/// on the rest of the line and `unescape_bytes` gives the bytes back.
+///
+/// ```
+/// use rgrcat::{escape_bytes, unescape_bytes};
+///
+/// let escaped = escape_bytes(b"caf\xe9 ok");
+/// assert_eq!(escaped, "caf\u{10ffe9} ok");
+/// assert_eq!(unescape_bytes(&escaped), b"caf\xe9 ok");
+/// ```
+pub fn escape_bytes(bytes: &[u8]) -> Cow<'_, str> {
+    let mut rest = match std::str::from_utf8(bytes) {
+        Ok(text) => return Cow::Borrowed(text),
+        Err(_) => bytes,
+    };
+    let mut text = String::with_capacity(bytes.len() + 8);
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("checked to be UTF-8"));
+                let invalid_len = err.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..invalid_len] {
+                    text.push(char::from_u32(ESCAPE_BASE + u32::from(*byte)).expect("within the private use plane"));
+                }
+                rest = &invalid[invalid_len..];
+            }
+        }
+    }
+    Cow::Owned(text)
+}
+
+
+/// The bytes of text from `escape_bytes`, with the characters standing in
+/// for bytes turned back into them.
+pub fn unescape_bytes(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut buffer = [0; 4];
+    for c in text.chars() {
+        match u32::from(c).checked_sub(ESCAPE_BASE) {
+            Some(byte) => bytes.push(byte as u8),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes()),
+        }
+    }
+    bytes
+}
+
+
+/// An iterator over the coloured lines of a reader, see `Colourizer::lines`.
+pub struct ColourLines<'a, R> {
+    colourizer: &'a Colourizer,
+    reader: LineReader<R>,
+    state: StreamState,
+}
+
+
+impl<'a, R: BufRead> ColourLines<'a, R> {
+    pub(crate) fn new(colourizer: &'a Colourizer, reader: R) -> ColourLines<'a, R> {
+        ColourLines { colourizer, reader: LineReader::new(reader), state: StreamState::new() }
+    }
+
+
+    /// Read up to the next line which isn't skipped and run the rules on it.
+    fn next_line(&mut self) -> Option<io::Result<ProcessedLine>> {
+        loop {
+            match self.reader.read() {
+                Ok(true) => {
+                    let line = match self.reader.line() {
+                        Ok(line) if self.colourizer.config().strips_ansi() => strip_escapes(line),
+                        Ok(line) => Cow::Borrowed(line),
+                        Err(err) => return Some(Err(err))
+                    };
+                    if let Some(processed) = self.colourizer.process_next_line(&line, &mut self.state, None) {
+                        return Some(Ok(processed));
+                    }
+                }
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err))
+            }
+        }
+    }
+}
+
+
+impl<'a, R: BufRead> Iterator for ColourLines<'a, R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        self.next_line().map(|line| line.map(|processed| processed.render(true)))
+    }
+}
+
+
+/// An iterator over the lines of a reader with their styled spans, see
+/// `Colourizer::span_lines`.
+pub struct SpanLines<'a, R> {
+    lines: ColourLines<'a, R>,
+}
+
+
+impl<'a, R: BufRead> SpanLines<'a, R> {
+    pub(crate) fn new(colourizer: &'a Colourizer, reader: R) -> SpanLines<'a, R> {
+        SpanLines { lines: ColourLines::new(colourizer, reader) }
+    }
+}
+
+
+impl<'a, R: BufRead> Iterator for SpanLines<'a, R> {
+    type Item = io::Result<(String, Vec<StyledSpan>)>;
+
+    fn next(&mut self) -> Option<io::Result<(String, Vec<StyledSpan>)>> {
+        self.lines.next_line().map(|line| line.map(|processed| (processed.text, processed.spans)))
+    }
+}
@@ -0,0 +1,12 @@
+//! Warnings about conffiles, sent through tracing. The binary warns with
+//! this too.
+
+
+/// Emit `message` as a tracing warning. Without a global subscriber it's
+/// printed on stderr as well, so it reads the same as it always did.
+pub fn warn(message: &str) {
+    tracing::warn!("{}", message);
+    if !tracing::dispatcher::has_been_set() {
+        eprintln!("{}", message);
+    }
+}
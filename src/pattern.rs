@@ -0,0 +1,152 @@
+//! The compiled regexps of the rules: those of the regex crate where it
+//! takes them, and with the `fancy` feature those of fancy-regex for the
+//! look-around and backreferences of Python's regexps, which it lacks.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+
+/// The regexp of a rule, compiled by whichever crate takes it. Only the
+/// rules that need fancy-regex pay for its backtracking.
+#[derive(Clone, Debug)]
+pub(crate) enum CompiledPattern {
+    Fast(Regex),
+    #[cfg(feature = "fancy")]
+    Slow(fancy_regex::Regex),
+}
+
+
+/// The groups of a match of a `CompiledPattern`.
+pub(crate) enum Captures<'t> {
+    Fast(regex::Captures<'t>),
+    #[cfg(feature = "fancy")]
+    Slow(fancy_regex::Captures<'t>),
+}
+
+
+impl CompiledPattern {
+    /// Compile `pattern` with the regex crate, or with fancy-regex if the
+    /// regex crate doesn't support it. `flagged` is the pattern with the
+    /// flags of the rule inline, which fancy-regex takes them as.
+    pub(crate) fn new(regex: Result<Regex, regex::Error>, flagged: &str) -> Result<CompiledPattern, regex::Error> {
+        match regex {
+            Ok(re) => Ok(CompiledPattern::Fast(re)),
+            #[cfg(feature = "fancy")]
+            Err(err) => fancy_regex::Regex::new(flagged).map(CompiledPattern::Slow).map_err(|_| err),
+            #[cfg(not(feature = "fancy"))]
+            Err(err) => {
+                let _ = flagged;
+                Err(err)
+            }
+        }
+    }
+
+
+    /// Whether the regex crate compiled it, so it can join a `RegexSet`.
+    pub(crate) fn is_fast(&self) -> bool {
+        matches!(self, CompiledPattern::Fast(_))
+    }
+
+
+    /// Whether it matches somewhere in `text`. A fancy-regex pattern that
+    /// gives up backtracking doesn't match.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        match self {
+            CompiledPattern::Fast(re) => re.is_match(text),
+            #[cfg(feature = "fancy")]
+            CompiledPattern::Slow(re) => re.is_match(text).unwrap_or(false),
+        }
+    }
+
+
+    /// The first match in `text` with its groups.
+    pub(crate) fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        match self {
+            CompiledPattern::Fast(re) => re.captures(text).map(Captures::Fast),
+            #[cfg(feature = "fancy")]
+            CompiledPattern::Slow(re) => re.captures(text).ok().flatten().map(Captures::Slow),
+        }
+    }
+
+
+    /// Every match in `text` with its groups, from left to right.
+    pub(crate) fn captures_iter<'r, 't>(&'r self, text: &'t str) -> Box<dyn Iterator<Item = Captures<'t>> + 'r>
+    where 't: 'r {
+        match self {
+            CompiledPattern::Fast(re) => Box::new(re.captures_iter(text).map(Captures::Fast)),
+            #[cfg(feature = "fancy")]
+            CompiledPattern::Slow(re) => Box::new(re.captures_iter(text).map_while(Result::ok).map(Captures::Slow)),
+        }
+    }
+
+
+    /// How many times it matches in `text`.
+    pub(crate) fn count(&self, text: &str) -> usize {
+        match self {
+            CompiledPattern::Fast(re) => re.find_iter(text).count(),
+            #[cfg(feature = "fancy")]
+            CompiledPattern::Slow(re) => re.find_iter(text).map_while(Result::ok).count(),
+        }
+    }
+}
+
+
+impl Captures<'_> {
+    /// The byte range of group `group` in the text, 0 for the whole match,
+    /// `None` for a group that didn't take part.
+    pub(crate) fn get(&self, group: usize) -> Option<Range<usize>> {
+        match self {
+            Captures::Fast(captures) => captures.get(group).map(|found| found.range()),
+            #[cfg(feature = "fancy")]
+            Captures::Slow(captures) => captures.get(group).map(|found| found.range()),
+        }
+    }
+
+
+    /// The range of every group, the whole match first.
+    pub(crate) fn ranges(&self) -> Vec<Option<Range<usize>>> {
+        match self {
+            Captures::Fast(captures) => captures.iter().map(|found| found.map(|found| found.range())).collect(),
+            #[cfg(feature = "fancy")]
+            Captures::Slow(captures) => captures.iter().map(|found| found.map(|found| found.range())).collect(),
+        }
+    }
+
+
+    /// Append `replacement` to `dst` with `${1}` and `$name` expanded.
+    pub(crate) fn expand(&self, replacement: &str, dst: &mut String) {
+        match self {
+            Captures::Fast(captures) => captures.expand(replacement, dst),
+            #[cfg(feature = "fancy")]
+            Captures::Slow(captures) => captures.expand(replacement, dst),
+        }
+    }
+}
+
+
+#[cfg(all(test, feature = "fancy"))]
+mod tests {
+    use super::*;
+
+    fn compile(pattern: &str) -> CompiledPattern {
+        CompiledPattern::new(Regex::new(pattern), pattern).unwrap()
+    }
+
+    #[test]
+    fn test_fancy_fallback() {
+        assert!(compile(r"\d+").is_fast());
+        let lookahead = compile(r"\w+(?=\s)");
+        assert!(!lookahead.is_fast());
+        let ranges: Vec<_> = lookahead.captures_iter("ab cd ef").map(|captures| captures.get(0).unwrap()).collect();
+        assert_eq!(ranges, [0..2, 3..5]);
+        let backreference = compile(r"\b(\w+) \1\b");
+        assert!(backreference.is_match("it is is so"));
+        assert_eq!(backreference.count("a a b b c"), 2);
+        let mut expanded = String::new();
+        backreference.captures("it is is").unwrap().expand("${1}!", &mut expanded);
+        assert_eq!(expanded, "is!");
+        let unclosed = "a(b";
+        assert!(CompiledPattern::new(Regex::new(unclosed), unclosed).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Time spent on each rule, for `--profile`.
+
+use std::time::Duration;
+
+use crate::ColourConfig;
+
+
+/// A rule taking at least this share of the total time gets flagged.
+const DISPROPORTIONATE_SHARE: f64 = 0.5;
+
+
+/// What a rule did over the input.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleTiming {
+    /// The time it took, over all lines.
+    pub total: Duration,
+    /// The lines it looked at.
+    pub lines: u64,
+    /// The lines it changed.
+    pub matches: u64,
+    /// The lines its regexp matched.
+    pub matched_lines: u64,
+    /// The matches of its regexp, each of several on a line counting.
+    pub found: u64,
+}
+
+
+/// Cumulative time spent on every rule, for `--profile`.
+pub struct Profile {
+    labels: Vec<String>,
+    timings: Vec<RuleTiming>,
+}
+
+
+impl Profile {
+    /// Start an empty profile of the rules in `config_list`.
+    pub fn new(config_list: &[ColourConfig]) -> Profile {
+        let labels = config_list.iter().map(|config| config.label().to_string()).collect();
+        Profile { labels, timings: vec![RuleTiming::default(); config_list.len()] }
+    }
+
+
+    /// Add `elapsed` to the rule at `index`, counting a match if it changed the line.
+    pub fn record(&mut self, index: usize, elapsed: Duration, matched: bool) {
+        if let Some(timing) = self.timings.get_mut(index) {
+            timing.total += elapsed;
+            timing.lines += 1;
+            if matched {
+                timing.matches += 1;
+            }
+        }
+    }
+
+
+    /// Count the `found` matches of the rule at `index` on a line. They're
+    /// counted apart from `record`, so counting adds nothing to its time.
+    pub fn record_found(&mut self, index: usize, found: u64) {
+        if let Some(timing) = self.timings.get_mut(index) {
+            timing.found += found;
+            if found > 0 {
+                timing.matched_lines += 1;
+            }
+        }
+    }
+
+
+    /// What the rule at `index` did so far.
+    pub fn get_timing(&self, index: usize) -> Option<&RuleTiming> {
+        self.timings.get(index)
+    }
+
+
+    /// The matches of all rules so far.
+    pub fn get_found(&self) -> u64 {
+        self.timings.iter().map(|timing| timing.found).sum()
+    }
+
+
+    /// The rule indexes sorted by total time, most expensive first.
+    fn get_ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..self.timings.len()).collect();
+        ranking.sort_by(|a, b| self.timings[*b].total.cmp(&self.timings[*a].total));
+        ranking
+    }
+
+
+    /// A table of the rules, most expensive first.
+    pub fn report(&self) -> String {
+        let total: Duration = self.timings.iter().map(|timing| timing.total).sum();
+        let mut report = format!("rule profile, {:.3}ms in total:\n", total.as_secs_f64() * 1000.0);
+        report.push_str("rank  rule  total_ms    share  lines  matches  avg_us/match  regexp\n");
+        for (rank, index) in self.get_ranking().into_iter().enumerate() {
+            let timing = &self.timings[index];
+            let share = if total.is_zero() { 0.0 } else { timing.total.as_secs_f64() / total.as_secs_f64() };
+            let average = if timing.matches == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.3}", timing.total.as_secs_f64() * 1_000_000.0 / timing.matches as f64)
+            };
+            let flag = if self.timings.len() > 1 && share >= DISPROPORTIONATE_SHARE { "  <- disproportionate" } else { "" };
+            report.push_str(&format!("{:<5} {:<5} {:<11.3} {:>5.1}%  {:<6} {:<8} {:<13} {}{}\n",
+                                     rank + 1, index, timing.total.as_secs_f64() * 1000.0, share * 100.0,
+                                     timing.lines, timing.matches, average, self.labels[index], flag));
+        }
+        report
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut first = ColourConfig::new();
+        first.regexp = "cheap".to_string();
+        let mut second = ColourConfig::new();
+        second.regexp = "expensive".to_string();
+        second.index = 1;
+        let mut profile = Profile::new(&[first, second]);
+        profile.record(0, Duration::from_millis(1), true);
+        profile.record(1, Duration::from_millis(8), false);
+        profile.record(1, Duration::from_millis(1), true);
+
+        let report = profile.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("rule profile, 10.000ms"));
+        assert!(lines[2].starts_with("1     1     9.000"));
+        assert!(lines[2].ends_with("expensive  <- disproportionate"));
+        assert!(lines[3].starts_with("2     0     1.000"));
+        assert!(lines[3].ends_with("cheap"));
+    }
+}
@@ -0,0 +1,146 @@
+//! The `rgrcat` Python module, built with PyO3 and maturin.
+//!
+//! ```python
+//! import rgrcat
+//!
+//! colourizer = rgrcat.Colourizer("/usr/share/grc/conf.ping")
+//! with open("ping.log") as log:
+//!     for line in colourizer.lines(log):
+//!         print(line)
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyFileNotFoundError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::colourizer::Colourizer;
+use crate::config::Config;
+use crate::error::ConfigError;
+
+
+create_exception!(rgrcat, ConfigParseError, PyValueError, "A conffile or rule is malformed.");
+
+
+fn get_py_err(err: ConfigError) -> PyErr {
+    let message = err.to_string();
+    match err {
+        ConfigError::NotFound { .. } => PyFileNotFoundError::new_err(message),
+        ConfigError::Parse { .. } | ConfigError::Invalid { .. } | ConfigError::InvalidRule { .. } => ConfigParseError::new_err(message),
+        ConfigError::Io { .. } => PyOSError::new_err(message),
+    }
+}
+
+
+/// Colours lines of text with the rules of a conffile.
+#[pyclass(name = "Colourizer", module = "rgrcat", frozen)]
+struct PyColourizer {
+    colourizer: Colourizer,
+}
+
+
+#[pymethods]
+impl PyColourizer {
+    /// Read the conffile at `config_path`.
+    #[new]
+    fn new(config_path: &str) -> PyResult<PyColourizer> {
+        let config = Config::from_path(config_path).map_err(get_py_err)?;
+        Ok(PyColourizer { colourizer: Colourizer::new(config) })
+    }
+
+
+    /// Parse conffile text instead of reading a file.
+    #[staticmethod]
+    fn from_string(config_text: &str) -> PyResult<PyColourizer> {
+        let config: Config = config_text.parse().map_err(get_py_err)?;
+        Ok(PyColourizer { colourizer: Colourizer::new(config) })
+    }
+
+
+    /// Colour a single line.
+    fn colourize(&self, line: &str) -> String {
+        self.colourizer.colourize_line(line)
+    }
+
+
+    /// Colour a whole text, lines and all. The GIL is released meanwhile, so
+    /// other threads keep running on large inputs.
+    fn colourize_text(&self, py: Python<'_>, text: &str) -> PyResult<String> {
+        let mut output = vec![];
+        py.allow_threads(|| self.colourizer.process(text.as_bytes(), &mut output))?;
+        String::from_utf8(output).map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+
+    /// Iterate over the coloured lines of a text file object.
+    fn lines(slf: Py<PyColourizer>, file: PyObject) -> ColourFileLines {
+        ColourFileLines { colourizer: slf, file }
+    }
+}
+
+
+/// The iterator of `Colourizer.lines`, reading the file a line at a time.
+#[pyclass(module = "rgrcat")]
+struct ColourFileLines {
+    colourizer: Py<PyColourizer>,
+    file: PyObject,
+}
+
+
+#[pymethods]
+impl ColourFileLines {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        let colourizer = &self.colourizer.get().colourizer;
+        loop {
+            let line: String = self.file.call_method0(py, "readline")?.extract(py)?;
+            if line.is_empty() {
+                return Ok(None);
+            }
+            let line = line.strip_suffix('\n').map_or(&line[..], |line| line.strip_suffix('\r').unwrap_or(line));
+            if !colourizer.is_skipped(line) {
+                return Ok(Some(colourizer.colourize_line(line)));
+            }
+        }
+    }
+}
+
+
+/// Colour text with grcat conffiles.
+#[pymodule]
+fn rgrcat(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyColourizer>()?;
+    m.add("ConfigParseError", m.py().get_type::<ConfigParseError>())?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_python_glue() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "rgrcat").unwrap();
+            rgrcat(&module).unwrap();
+            let colourizer = module.getattr("Colourizer").unwrap()
+                .call_method1("from_string", ("regexp=ERROR\ncolours=red\n",)).unwrap();
+            let line: String = colourizer.call_method1("colourize", ("an ERROR",)).unwrap().extract().unwrap();
+            assert_eq!(line, "an \x1b[31mERROR\x1b[0m");
+
+            let io = py.import("io").unwrap();
+            let file = io.call_method1("StringIO", ("ERROR one\nfine\n",)).unwrap();
+            let lines: Vec<String> = colourizer.call_method1("lines", (file,)).unwrap()
+                .try_iter().unwrap().map(|line| line.unwrap().extract().unwrap()).collect();
+            assert_eq!(lines, ["\x1b[31mERROR\x1b[0m one", "fine"]);
+
+            let err = module.getattr("Colourizer").unwrap().call1(("/no/such/conffile",)).unwrap_err();
+            assert!(err.is_instance_of::<PyOSError>(py));
+        });
+    }
+}
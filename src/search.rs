@@ -0,0 +1,299 @@
+//! Finding conffiles in the grc search path.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::config::Config;
+use crate::embedded::get_embedded_config;
+use crate::error::ConfigError;
+
+
+/// The value of the variable `key`, `None` when it's unset or empty as the
+/// XDG base directory spec has it.
+fn get_env_path(key: &str) -> Option<PathBuf> {
+    env::var_os(key).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+
+/// The existing directories of `GRC_CONFIG_PATH`, a list like `PATH`, with
+/// relative ones taken from the current directory.
+fn get_extra_config_dirs() -> Vec<PathBuf> {
+    let paths = match env::var_os("GRC_CONFIG_PATH") {
+        Some(paths) => paths,
+        None => return vec![]
+    };
+    let current_dir = env::current_dir().unwrap_or_default();
+    env::split_paths(&paths)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(|dir| current_dir.join(dir))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+
+/// The home directory from `var`: `HOME`, or `USERPROFILE` where Windows
+/// has no `HOME`.
+fn get_home(var: &dyn Fn(&str) -> Option<PathBuf>) -> Option<PathBuf> {
+    var("HOME").or_else(|| var("USERPROFILE"))
+}
+
+
+/// The grc search path after `GRC_CONFIG_PATH`, the variables read with
+/// `var`: `grc` in the XDG config and data homes, and on `windows` in
+/// `%APPDATA%`, then `~/.grc` and `grc` in each of the XDG data dirs. The
+/// data dirs default to `/usr/local/share:/usr/share`, which Windows lacks.
+/// Without a home directory only the places set by a variable are left of
+/// those in it, as in a service with a scrubbed environment.
+fn get_standard_config_dirs(var: &dyn Fn(&str) -> Option<PathBuf>, windows: bool) -> Vec<PathBuf> {
+    let home = get_home(var);
+    let mut config_dir = vec![];
+    config_dir.extend(var("XDG_CONFIG_HOME").or_else(|| home.as_ref().map(|home| home.join(".config"))).map(|dir| dir.join("grc")));
+    config_dir.extend(var("XDG_DATA_HOME").or_else(|| home.as_ref().map(|home| home.join(".local").join("share"))).map(|dir| dir.join("grc")));
+    if windows {
+        config_dir.extend(var("APPDATA").map(|dir| dir.join("grc")));
+    }
+    config_dir.extend(home.map(|home| home.join(".grc")));
+    let data_dirs = match var("XDG_DATA_DIRS") {
+        Some(dirs) => dirs.into_os_string(),
+        None if windows => return config_dir,
+        None => "/usr/local/share:/usr/share".into()
+    };
+    config_dir.extend(env::split_paths(&data_dirs).filter(|dir| !dir.as_os_str().is_empty()).map(|dir| dir.join("grc")));
+    config_dir
+}
+
+
+/// The grc search path, in the order conffiles are looked up: the
+/// directories of `GRC_CONFIG_PATH` first, then `grc` in the XDG config
+/// and data homes, `~/.grc` and `grc` in each of the XDG data dirs.
+pub fn get_config_dirs() -> Vec<PathBuf> {
+    let mut config_dir = get_extra_config_dirs();
+    config_dir.extend(get_standard_config_dirs(&get_env_path, cfg!(windows)));
+    config_dir
+}
+
+
+/// The first file `name` in `dirs`, each path looked at added to `tried`.
+fn find_file(dirs: &[PathBuf], name: &str, tried: &mut Vec<PathBuf>) -> Option<PathBuf> {
+    for dir in dirs {
+        let path = dir.join(name);
+        if path.exists() && !path.is_dir() {
+            return Some(path);
+        }
+        tried.push(path);
+    }
+    None
+}
+
+
+/// Where the conffile `config_name` is in `dirs`: the name as it is, else
+/// with `conf.` in front, since grc users call conffiles by their command,
+/// else the conffile the grc.conf in `dirs` has for the name as a command
+/// line. Every path looked at is added to `tried`.
+fn resolve_config_path(config_name: &str, dirs: &[PathBuf], tried: &mut Vec<PathBuf>) -> Result<Option<PathBuf>, ConfigError> {
+    if let Some(path) = find_file(dirs, config_name, tried) {
+        return Ok(Some(path));
+    }
+    if is_short_name(config_name) {
+        if let Some(path) = find_file(dirs, &format!("conf.{}", config_name), tried) {
+            return Ok(Some(path));
+        }
+    }
+    if config_name == "grc.conf" {
+        return Ok(None);
+    }
+    match get_matching_config(&get_grc_conf_entries_in(dirs)?, config_name) {
+        Some(mapped) if mapped != config_name => Ok(find_file(dirs, &mapped, tried)),
+        _ => Ok(None)
+    }
+}
+
+
+/// Whether `config_name` may be a command name `conf.` goes in front of,
+/// rather than a conffile name or a path.
+fn is_short_name(config_name: &str) -> bool {
+    !config_name.starts_with("conf.") && !config_name.contains(std::path::is_separator)
+}
+
+
+/// Find the conffile `config_name` in the grc search path, returning its
+/// path. A command name like `ping` finds `conf.ping`, or the conffile
+/// grc.conf has for the command, when there's no conffile of that name.
+/// An absolute path is only looked for where it points.
+pub fn get_config_path(config_name: &str) -> Result<String, ConfigError> {
+    let mut tried = vec![];
+    let found = if Path::new(config_name).is_absolute() {
+        find_file(&[PathBuf::new()], config_name, &mut tried)
+    } else {
+        resolve_config_path(config_name, &get_config_dirs(), &mut tried)?
+    };
+    match found {
+        Some(path) => match path.to_str() {
+            Some(path) => Ok(path.to_string()),
+            None => Err(ConfigError::io(&path.to_string_lossy(), io::Error::new(io::ErrorKind::InvalidData, "path is not UTF-8")))
+        },
+        None => Err(ConfigError::NotFound { name: config_name.to_string(), searched: tried })
+    }
+}
+
+
+/// The conffiles in the grc search path by name, sorted, each with every
+/// path it's found at in search order, so the first is the one loaded and
+/// the others are shadowed. Only files named like `conf.ping` count, and
+/// directories that can't be read are skipped.
+pub fn get_config_list() -> Vec<(String, Vec<PathBuf>)> {
+    let mut configs: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for dir in get_config_dirs() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let name = match entry.file_name().into_string() {
+                Ok(name) if name.starts_with("conf.") && path.is_file() => name,
+                _ => continue
+            };
+            // A directory can be in the search path twice.
+            let paths = configs.entry(name).or_default();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    configs.into_iter().collect()
+}
+
+
+/// Find and load the conffile `config_name`, returning where it came from.
+/// A conffile in the search path always wins over the copy built into
+/// rgrcat, which is named like `embedded:conf.ping` and found by the command
+/// name too. `strict` refuses it
+/// over every problem like `Config::from_path_strict`.
+pub fn find_config(config_name: &str, strict: bool) -> Result<(String, Config), ConfigError> {
+    let short_name = Some(format!("conf.{}", config_name)).filter(|_| is_short_name(config_name));
+    let embedded = Some(config_name.to_string()).into_iter().chain(short_name)
+        .find_map(|name| get_embedded_config(&name).map(|text| (name, text)));
+    let path = match (get_config_path(config_name), embedded) {
+        (Ok(path), _) => path,
+        (Err(ConfigError::NotFound { .. }), Some((config_name, text))) => {
+            let name = format!("embedded:{}", config_name);
+            let config = Config::from_reader_named(text.as_bytes(), &name, strict)?;
+            return Ok((name, config));
+        }
+        (Err(err), _) => return Err(err)
+    };
+    let config = if strict { Config::from_path_strict(&path)? } else { Config::from_path(&path)? };
+    Ok((path, config))
+}
+
+
+/// The entries of the grc.conf in `dirs` as pairs of a command regexp and
+/// a conffile name, none without a grc.conf. The file holds the two on
+/// alternating lines.
+fn get_grc_conf_entries_in(dirs: &[PathBuf]) -> Result<Vec<(String, String)>, ConfigError> {
+    let grc_conf_path = match find_file(dirs, "grc.conf", &mut vec![]) {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => return Ok(vec![])
+    };
+    let file = File::open(&grc_conf_path).map_err(|err| ConfigError::io(&grc_conf_path, err))?;
+    let mut lines = BufReader::new(file).lines();
+    let mut entries = vec![];
+
+    while let Some(line) = lines.next() {
+        let line = line.map_err(|err| ConfigError::io(&grc_conf_path, err))?;
+        let regexp = line.trim();
+        if regexp.is_empty() || regexp.starts_with('#') {
+            continue;
+        }
+        let config_name = match lines.next() {
+            Some(line) => line.map_err(|err| ConfigError::io(&grc_conf_path, err))?,
+            None => break
+        };
+        entries.push((regexp.to_string(), config_name.trim().to_string()));
+    }
+    Ok(entries)
+}
+
+
+/// The entries of grc.conf as pairs of a command regexp and a conffile name,
+/// none without a grc.conf. The file holds the two on alternating lines.
+pub fn get_grc_conf_entries() -> Result<Vec<(String, String)>, ConfigError> {
+    get_grc_conf_entries_in(&get_config_dirs())
+}
+
+
+/// The conffile of the first of `entries` matching `command_line`.
+fn get_matching_config(entries: &[(String, String)], command_line: &str) -> Option<String> {
+    entries.iter()
+        .find(|(regexp, _)| Regex::new(regexp).is_ok_and(|re| re.is_match(command_line)))
+        .map(|(_, config_name)| config_name.clone())
+}
+
+
+/// Find the conffile for `command_line` in grc.conf.
+pub fn get_config_name_for_command(command_line: &str) -> Result<Option<String>, ConfigError> {
+    Ok(get_matching_config(&get_grc_conf_entries()?, command_line))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_dirs(vars: &[(&str, &str)], windows: bool) -> Vec<PathBuf> {
+        let var = |key: &str| vars.iter().find(|(name, _)| *name == key).map(|(_, value)| PathBuf::from(value));
+        get_standard_config_dirs(&var, windows)
+    }
+
+    #[test]
+    fn test_standard_config_dirs() {
+        assert_eq!(get_dirs(&[("HOME", "/home/me")], false), [
+            "/home/me/.config/grc", "/home/me/.local/share/grc", "/home/me/.grc", "/usr/local/share/grc", "/usr/share/grc",
+        ].iter().map(PathBuf::from).collect::<Vec<_>>());
+        // Without HOME, as on Windows, the profile is the home.
+        let home = PathBuf::from("C:/Users/me");
+        assert_eq!(get_dirs(&[("USERPROFILE", "C:/Users/me"), ("APPDATA", "C:/Users/me/AppData/Roaming")], true), [
+            home.join(".config").join("grc"), home.join(".local").join("share").join("grc"),
+            home.join("AppData/Roaming").join("grc"), home.join(".grc"),
+        ]);
+        // Without any home only the system directories are left.
+        assert_eq!(get_dirs(&[], false), [PathBuf::from("/usr/local/share/grc"), PathBuf::from("/usr/share/grc")]);
+        assert_eq!(get_dirs(&[("XDG_CONFIG_HOME", "/etc/xdg"), ("XDG_DATA_DIRS", "/opt/share")], false),
+                   [PathBuf::from("/etc/xdg/grc"), PathBuf::from("/opt/share/grc")]);
+    }
+
+    #[test]
+    fn test_resolve_config_path() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = [root.path().join("user"), root.path().join("system")];
+        for dir in &dirs {
+            fs::create_dir(dir).unwrap();
+        }
+        fs::write(dirs[1].join("conf.ping"), "regexp=ms\n").unwrap();
+        fs::write(dirs[1].join("netstat"), "regexp=tcp\n").unwrap();
+        fs::write(dirs[0].join("grc.conf"), "# ping's rules suit mtr\n(^|/)mtr\\b\nconf.ping\n").unwrap();
+        let resolve = |name: &str| {
+            let mut tried = vec![];
+            (resolve_config_path(name, &dirs, &mut tried).unwrap(), tried)
+        };
+
+        // A conffile name and an exact file are found as they are.
+        assert_eq!(resolve("conf.ping"), (Some(dirs[1].join("conf.ping")), vec![dirs[0].join("conf.ping")]));
+        assert_eq!(resolve("netstat").0, Some(dirs[1].join("netstat")));
+        // A command name gets conf. in front once it isn't found as it is.
+        assert_eq!(resolve("ping"), (Some(dirs[1].join("conf.ping")), vec![dirs[0].join("ping"), dirs[1].join("ping"), dirs[0].join("conf.ping")]));
+        // Then grc.conf picks the conffile for the command.
+        assert_eq!(resolve("mtr").0, Some(dirs[1].join("conf.ping")));
+        assert_eq!(resolve("nothing"), (None, vec![
+            dirs[0].join("nothing"), dirs[1].join("nothing"), dirs[0].join("conf.nothing"), dirs[1].join("conf.nothing"),
+        ]));
+        // Paths never get conf. in front.
+        assert_eq!(resolve("sub/ping").1, [dirs[0].join("sub/ping"), dirs[1].join("sub/ping")]);
+    }
+}
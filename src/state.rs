@@ -0,0 +1,193 @@
+//! What a stream of lines carries over from one line to the next, for the
+//! rules whose colour depends on the lines before.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use crate::config::{ColourConfig, EscalateWindow};
+use crate::style::Style;
+
+
+/// The state of a stream being coloured line by line, see
+/// `Colourizer::colourize_next_line`. Every stream needs its own.
+#[derive(Clone, Debug, Default)]
+pub struct StreamState {
+    /// The number of the line being coloured, from 1 on.
+    line: u64,
+    /// The name of the input, `None` for stdin.
+    file: Option<String>,
+    /// The lines and times at which the `escalate=` rules matched within
+    /// their windows, by rule index.
+    matches: HashMap<usize, VecDeque<(u64, Option<Instant>)>>,
+    /// When the `alert_after_quiet=` rules last matched, by rule index.
+    last_matches: HashMap<usize, Instant>,
+    /// The style and rule index of the `count=block` rule whose block the
+    /// stream is in, until a `count=unblock` rule matches.
+    block: Option<(Style, usize)>,
+    /// The lines before the current one as the `window=` rules saw them,
+    /// each with its newline, as many as fit in their windows, by rule index.
+    windows: HashMap<usize, VecDeque<String>>,
+}
+
+
+impl StreamState {
+    /// The state of a stream before its first line.
+    pub fn new() -> StreamState {
+        StreamState::default()
+    }
+
+
+    /// The state of a stream read from the file `name`.
+    pub fn for_file(name: &str) -> StreamState {
+        StreamState { file: Some(name.to_string()), ..StreamState::default() }
+    }
+
+
+    /// Go on with the stream in the file `name`, keeping the line numbers
+    /// and everything else.
+    pub fn set_file(&mut self, name: &str) {
+        self.file = Some(name.to_string());
+    }
+
+
+    /// The number of the current line, from 1 on, 0 before the first.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+
+    /// The name of the input file, `-` for stdin.
+    pub fn file(&self) -> &str {
+        self.file.as_deref().unwrap_or("-")
+    }
+
+
+    /// Count a line, which the colourizer does for every line it gets.
+    /// Lines that pass by it are counted with this, so the line numbers
+    /// stay those of the input.
+    pub fn next_line(&mut self) {
+        self.line += 1;
+    }
+
+
+    /// Forget what the rules carried over, the block the stream is in and
+    /// the matches counted, for rules loaded anew. The line numbers and the
+    /// file stay.
+    pub fn reset_rules(&mut self) {
+        self.matches.clear();
+        self.last_matches.clear();
+        self.block = None;
+        self.windows.clear();
+    }
+
+
+    pub(crate) fn block(&self) -> Option<(Style, usize)> {
+        self.block
+    }
+
+
+    pub(crate) fn set_block(&mut self, block: Option<(Style, usize)>) {
+        self.block = block;
+    }
+
+
+    /// The lines before the current one in the window of `config`, joined
+    /// into one text ending with a newline, empty before the first.
+    pub(crate) fn get_window(&self, config: &ColourConfig) -> String {
+        self.windows.get(&config.index).map_or_else(String::new, |lines| lines.iter().map(String::as_str).collect())
+    }
+
+
+    /// Keep `line`, as `config` saw it, for the windows of the lines after,
+    /// forgetting the lines that fall out of its window.
+    pub(crate) fn push_window(&mut self, config: &ColourConfig, line: &str) {
+        let lines = self.windows.entry(config.index).or_default();
+        lines.push_back(format!("{}\n", line));
+        while lines.len() >= config.window {
+            lines.pop_front();
+        }
+    }
+
+
+    /// Count a match of `config` on the current line if `matched`, forget
+    /// the matches which fell out of its window and return the style of the
+    /// escalation step reached, `None` below the first. `now` is only needed
+    /// for a window of time.
+    pub(crate) fn get_escalated_style(&mut self, config: &ColourConfig, matched: bool, now: Option<Instant>) -> Option<Style> {
+        let line = self.line;
+        let matches = self.matches.entry(config.index).or_default();
+        if matched {
+            matches.push_back((line, now));
+        }
+        while let Some(&(match_line, match_time)) = matches.front() {
+            let expired = match config.escalate_window {
+                EscalateWindow::Lines(lines) => match_line + lines <= line,
+                EscalateWindow::Duration(duration) => match (match_time, now) {
+                    (Some(match_time), Some(now)) => now.duration_since(match_time) >= duration,
+                    _ => false
+                },
+            };
+            if !expired {
+                break;
+            }
+            matches.pop_front();
+        }
+        let count = matches.len();
+        config.escalate.iter().rev().find(|(threshold, _)| count >= *threshold).map(|(_, style)| *style)
+    }
+
+
+    /// Note a match of the alerting `config` at `now`, returning whether it
+    /// rings the bell: always without `alert_after_quiet=`, otherwise only
+    /// if the rule hadn't matched for longer than that before.
+    pub(crate) fn is_alert_due(&mut self, config: &ColourConfig, now: Instant) -> bool {
+        let quiet = match config.alert_after_quiet {
+            Some(quiet) => quiet,
+            None => return true
+        };
+        let last = self.last_matches.insert(config.index, now);
+        last.is_none_or(|last| now.duration_since(last) > quiet)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_escalation_within_time_window() {
+        let config: crate::Config = "regexp=ERROR\nescalate=2:yellow,3:red\nescalate_window=10s\n".parse().unwrap();
+        let config = &config.rules()[0];
+        let (yellow, red) = (config.escalate()[0].1, config.escalate()[1].1);
+        let start = Instant::now();
+        let at = |seconds| Some(start + Duration::from_secs(seconds));
+        let mut state = StreamState::new();
+        let mut next = |matched, seconds| {
+            state.next_line();
+            state.get_escalated_style(config, matched, at(seconds))
+        };
+        assert_eq!(next(true, 0), None);
+        assert_eq!(next(true, 1), Some(yellow));
+        assert_eq!(next(true, 2), Some(red));
+        assert_eq!(next(false, 10), Some(yellow));
+        // A quiet period drains the window.
+        assert_eq!(next(false, 12), None);
+        assert_eq!(next(true, 13), None);
+    }
+
+    #[test]
+    fn test_alerts_after_quiet_periods() {
+        let config: crate::Config = "regexp=ERROR\ncolours=red\nalert_after_quiet=300s\n".parse().unwrap();
+        let config = &config.rules()[0];
+        let start = Instant::now();
+        let mut state = StreamState::new();
+        // A burst, five quiet minutes and another burst.
+        let alerts = [0, 2, 5, 120, 421, 422, 500]
+            .iter()
+            .filter(|seconds| state.is_alert_due(config, start + Duration::from_secs(**seconds)))
+            .count();
+        assert_eq!(alerts, 2);
+    }
+}
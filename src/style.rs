@@ -0,0 +1,507 @@
+//! Styles as data, for embedding rgrcat in TUIs and editors where escape
+//! sequences are the wrong currency.
+
+use std::fmt::Write;
+use std::ops::{BitOr, BitOrAssign, Range};
+
+
+/// The sequence that ends every styled span, back to the default style.
+pub(crate) const RESET: &str = "\x1b[0m";
+
+
+/// Erases from the cursor to the end of the line, in the background colour.
+const ERASE_LINE: &str = "\x1b[K";
+
+
+/// The 16 standard terminal colours.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum NamedColour {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+
+/// A foreground or background colour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Colour {
+    /// One of the 16 standard colours.
+    Named(NamedColour),
+    /// An entry of the 256-colour palette.
+    Indexed(u8),
+    /// A 24-bit colour.
+    Rgb(u8, u8, u8),
+}
+
+
+/// The named colours in palette order.
+const NAMED_COLOURS: [NamedColour; 16] = [
+    NamedColour::Black, NamedColour::Red, NamedColour::Green, NamedColour::Yellow,
+    NamedColour::Blue, NamedColour::Magenta, NamedColour::Cyan, NamedColour::White,
+    NamedColour::BrightBlack, NamedColour::BrightRed, NamedColour::BrightGreen, NamedColour::BrightYellow,
+    NamedColour::BrightBlue, NamedColour::BrightMagenta, NamedColour::BrightCyan, NamedColour::BrightWhite,
+];
+
+
+/// The xterm palette of the 16 standard colours.
+const NAMED_RGB: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0xcd, 0x00, 0x00), (0x00, 0xcd, 0x00), (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee), (0xcd, 0x00, 0xcd), (0x00, 0xcd, 0xcd), (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f), (0xff, 0x00, 0x00), (0x00, 0xff, 0x00), (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff), (0xff, 0x00, 0xff), (0x00, 0xff, 0xff), (0xff, 0xff, 0xff),
+];
+
+
+/// How many colours the escape sequences may use, for terminals that
+/// can't show them all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColourDepth {
+    /// The 16 standard colours.
+    Ansi16,
+    /// The 256-colour palette.
+    Ansi256,
+    /// 24-bit colours.
+    TrueColour,
+}
+
+
+impl ColourDepth {
+    /// The depth named `16`, `256` or `truecolor`.
+    pub fn from_name(name: &str) -> Option<ColourDepth> {
+        match name {
+            "16" => Some(ColourDepth::Ansi16),
+            "256" => Some(ColourDepth::Ansi256),
+            "truecolor" | "truecolour" | "24bit" => Some(ColourDepth::TrueColour),
+            _ => None,
+        }
+    }
+
+
+    /// The name `from_name` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColourDepth::Ansi16 => "16",
+            ColourDepth::Ansi256 => "256",
+            ColourDepth::TrueColour => "truecolor",
+        }
+    }
+
+
+    /// Guess the depth of a terminal from its `TERM` and `COLORTERM`,
+    /// `None` when `TERM` is empty and there's nothing to go by.
+    ///
+    /// ```
+    /// use rgrcat::ColourDepth;
+    ///
+    /// assert_eq!(ColourDepth::detect("xterm-256color", "truecolor"), Some(ColourDepth::TrueColour));
+    /// assert_eq!(ColourDepth::detect("screen-256color", ""), Some(ColourDepth::Ansi256));
+    /// assert_eq!(ColourDepth::detect("linux", ""), Some(ColourDepth::Ansi16));
+    /// ```
+    pub fn detect(term: &str, colorterm: &str) -> Option<ColourDepth> {
+        if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+            Some(ColourDepth::TrueColour)
+        } else if term.contains("256col") {
+            Some(ColourDepth::Ansi256)
+        } else if term.is_empty() {
+            None
+        } else {
+            Some(ColourDepth::Ansi16)
+        }
+    }
+}
+
+
+fn get_distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> i32 {
+    let square = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+    square(r1, r2) + square(g1, g2) + square(b1, b2)
+}
+
+
+impl Colour {
+    /// The colour in red, green and blue, for the palette colours as xterm
+    /// shows them by default.
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Colour::Named(named) => NAMED_RGB[named as usize],
+            Colour::Indexed(index) if index < 16 => NAMED_RGB[index as usize],
+            Colour::Indexed(index) if index < 232 => {
+                // The 6x6x6 colour cube.
+                let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+                let index = index - 16;
+                (level(index / 36), level(index / 6 % 6), level(index % 6))
+            }
+            Colour::Indexed(index) => {
+                let grey = 8 + (index - 232) * 10;
+                (grey, grey, grey)
+            }
+            Colour::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+
+    /// The nearest colour within `depth`. Colours within it already stay as
+    /// they are, there's no upscaling.
+    pub fn for_depth(self, depth: ColourDepth) -> Colour {
+        match (self, depth) {
+            (Colour::Rgb(r, g, b), ColourDepth::Ansi256) => {
+                // The nearest of the cube and the grey ramp.
+                let step = |value: u8| if value < 48 { 0 } else if value < 115 { 1 } else { (value - 35) / 40 };
+                let cube = Colour::Indexed(16 + 36 * step(r) + 6 * step(g) + step(b));
+                let average = (r as u16 + g as u16 + b as u16) / 3;
+                let grey = Colour::Indexed(232 + ((average.clamp(8, 238) - 8 + 5) / 10).min(23) as u8);
+                if get_distance(cube.to_rgb(), (r, g, b)) <= get_distance(grey.to_rgb(), (r, g, b)) { cube } else { grey }
+            }
+            (Colour::Indexed(index), ColourDepth::Ansi16) if index < 16 => Colour::Named(NAMED_COLOURS[index as usize]),
+            (Colour::Indexed(_), ColourDepth::Ansi16) | (Colour::Rgb(..), ColourDepth::Ansi16) => {
+                let rgb = self.to_rgb();
+                let nearest = (0..16).min_by_key(|index| get_distance(NAMED_RGB[*index], rgb)).unwrap_or_default();
+                Colour::Named(NAMED_COLOURS[nearest])
+            }
+            _ => self,
+        }
+    }
+}
+
+
+/// A set of text attributes like bold or underline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Attributes(u16);
+
+
+#[allow(missing_docs)]
+impl Attributes {
+    pub const BOLD: Attributes = Attributes(1);
+    pub const DIM: Attributes = Attributes(1 << 1);
+    pub const ITALIC: Attributes = Attributes(1 << 2);
+    pub const UNDERLINE: Attributes = Attributes(1 << 3);
+    pub const BLINK: Attributes = Attributes(1 << 4);
+    pub const RAPID_BLINK: Attributes = Attributes(1 << 5);
+    pub const REVERSE: Attributes = Attributes(1 << 6);
+    pub const CONCEALED: Attributes = Attributes(1 << 7);
+    pub const STRIKETHROUGH: Attributes = Attributes(1 << 8);
+}
+
+
+impl Attributes {
+    /// The attributes with their SGR codes and names, in code order.
+    pub(crate) const CODES: [(Attributes, u8, &'static str); 9] = [
+        (Attributes::BOLD, 1, "bold"),
+        (Attributes::DIM, 2, "dim"),
+        (Attributes::ITALIC, 3, "italic"),
+        (Attributes::UNDERLINE, 4, "underline"),
+        (Attributes::BLINK, 5, "blink"),
+        (Attributes::RAPID_BLINK, 6, "rapid_blink"),
+        (Attributes::REVERSE, 7, "reverse"),
+        (Attributes::CONCEALED, 8, "concealed"),
+        (Attributes::STRIKETHROUGH, 9, "strikethrough"),
+    ];
+
+
+    /// No attribute at all.
+    pub const fn empty() -> Attributes {
+        Attributes(0)
+    }
+
+
+    /// The raw bits, in the order of the constants above.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+
+    /// Whether no attribute is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+
+    /// Whether every attribute of `other` is set.
+    pub const fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+
+    fn bitor(self, other: Attributes) -> Attributes {
+        Attributes(self.0 | other.0)
+    }
+}
+
+
+impl BitOrAssign for Attributes {
+    fn bitor_assign(&mut self, other: Attributes) {
+        self.0 |= other.0;
+    }
+}
+
+
+/// Attributes serialize as a list of names like `["bold", "underline"]`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Attributes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names: Vec<&str> = Attributes::CODES.iter()
+            .filter(|(attribute, _, _)| self.contains(*attribute))
+            .map(|(_, _, name)| *name)
+            .collect();
+        names.serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Attributes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Attributes, D::Error> {
+        let names: Vec<String> = serde::Deserialize::deserialize(deserializer)?;
+        let mut attributes = Attributes::empty();
+        for name in names {
+            match Attributes::CODES.iter().find(|(_, _, known)| *known == name) {
+                Some((attribute, _, _)) => attributes |= *attribute,
+                None => return Err(serde::de::Error::custom(format!("unknown attribute {}", name)))
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+
+/// How a piece of text should look.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Style {
+    /// The foreground colour, the terminal default without one.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub fg: Option<Colour>,
+    /// The background colour, the terminal default without one.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub bg: Option<Colour>,
+    /// The text attributes.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Attributes::is_empty"))]
+    pub attributes: Attributes,
+}
+
+
+/// Append the SGR code `code` to the codes being written into `out`, after
+/// a `;` unless it's the first.
+fn push_code(out: &mut String, code: std::fmt::Arguments) {
+    if !out.ends_with('[') {
+        out.push(';');
+    }
+    // Writing into a String never fails.
+    let _ = out.write_fmt(code);
+}
+
+
+fn push_colour_codes(out: &mut String, colour: Colour, base: u8) {
+    match colour {
+        Colour::Named(named) => {
+            let index = named as u8;
+            if index < 8 {
+                push_code(out, format_args!("{}", base + index));
+            } else {
+                // Prefixed with the standard code for graceful failure on
+                // terminals without aixterm bright colours.
+                push_code(out, format_args!("{}", base + index - 8));
+                push_code(out, format_args!("{}", base + 60 + index - 8));
+            }
+        }
+        Colour::Indexed(index) => push_code(out, format_args!("{};5;{}", base + 8, index)),
+        Colour::Rgb(r, g, b) => push_code(out, format_args!("{};2;{};{};{}", base + 8, r, g, b)),
+    }
+}
+
+
+impl Style {
+    /// Whether the style leaves the text as the terminal shows it by default.
+    pub fn is_plain(&self) -> bool {
+        self.fg.is_none() && self.bg.is_none() && self.attributes.is_empty()
+    }
+
+
+    /// The SGR escape sequence switching to the style, empty for a plain one.
+    pub fn to_ansi(&self) -> String {
+        let mut sequence = String::new();
+        self.push_ansi(&mut sequence);
+        sequence
+    }
+
+
+    /// Append the sequence of `to_ansi` to `out`, which saves allocating it.
+    pub fn push_ansi(&self, out: &mut String) {
+        if self.is_plain() {
+            return;
+        }
+        out.push_str("\x1b[");
+        self.push_codes(out);
+        out.push('m');
+    }
+
+
+    /// Append the codes of the sequence to `out`, which ends in the `[` of
+    /// the sequence or in a code before them.
+    fn push_codes(&self, out: &mut String) {
+        for (attribute, code, _) in Attributes::CODES.iter() {
+            if self.attributes.contains(*attribute) {
+                push_code(out, format_args!("{}", code));
+            }
+        }
+        if let Some(fg) = self.fg {
+            push_colour_codes(out, fg, 30);
+        }
+        if let Some(bg) = self.bg {
+            push_colour_codes(out, bg, 40);
+        }
+    }
+
+
+    /// The style with its colours brought within `depth`.
+    pub fn for_depth(&self, depth: ColourDepth) -> Style {
+        Style {
+            fg: self.fg.map(|colour| colour.for_depth(depth)),
+            bg: self.bg.map(|colour| colour.for_depth(depth)),
+            attributes: self.attributes,
+        }
+    }
+}
+
+
+/// What `eol=` has the colour of a rule do at the end of a line, where its
+/// span ends the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum LineEnd {
+    /// Reset the colours right after the text, the default.
+    #[default]
+    Reset,
+    /// Erase to the end of the line first, which fills the rest of the row
+    /// with the background colour.
+    Extend,
+    /// Leave the colours on, for the lines after to go on in them.
+    Persist,
+}
+
+
+impl LineEnd {
+    /// The line end of an `eol=` value, `None` for an unknown one.
+    pub fn from_name(name: &str) -> Option<LineEnd> {
+        match name {
+            "reset" => Some(LineEnd::Reset),
+            "extend" => Some(LineEnd::Extend),
+            "persist" => Some(LineEnd::Persist),
+            _ => None
+        }
+    }
+
+
+    /// The `eol=` value of the line end.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnd::Reset => "reset",
+            LineEnd::Extend => "extend",
+            LineEnd::Persist => "persist",
+        }
+    }
+}
+
+
+/// A styled byte range of a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StyledSpan {
+    /// The byte range into the line, always on char boundaries.
+    pub range: Range<usize>,
+    /// The style of the range, never plain.
+    pub style: Style,
+}
+
+
+/// Render `line` with its `spans` as escape sequences. Where a span ends
+/// right where the next one starts, such as at the end of a match within
+/// another one, the colour switches over in a single sequence, so the text
+/// never passes through the default colour in between.
+pub fn render_ansi(line: &str, spans: &[StyledSpan]) -> String {
+    let mut result = String::with_capacity(line.len());
+    push_rendered_ansi(&mut result, line, spans, LineEnd::Reset);
+    result
+}
+
+
+/// Append what `render_ansi` gives for `line` to `out`, with `end_of_line`
+/// in place of the reset after a span that ends the line.
+pub(crate) fn push_rendered_ansi(out: &mut String, line: &str, spans: &[StyledSpan], end_of_line: LineEnd) {
+    let mut end = 0;
+    let mut is_open = false;
+    for span in spans {
+        if is_open && end == span.range.start && !span.style.is_plain() {
+            // The reset is folded into the sequence of the next span.
+            out.truncate(out.len() - "m".len());
+            span.style.push_codes(out);
+            out.push('m');
+        } else {
+            out.push_str(&line[end..span.range.start]);
+            span.style.push_ansi(out);
+        }
+        out.push_str(&line[span.range.clone()]);
+        match end_of_line {
+            _ if span.range.end < line.len() => out.push_str(RESET),
+            LineEnd::Reset => out.push_str(RESET),
+            LineEnd::Extend => {
+                out.push_str(ERASE_LINE);
+                out.push_str(RESET);
+            }
+            LineEnd::Persist => {}
+        }
+        is_open = true;
+        end = span.range.end;
+    }
+    out.push_str(&line[end..]);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ansi() {
+        assert_eq!(Style::default().to_ansi(), "");
+        let style = Style {
+            fg: Some(Colour::Named(NamedColour::BrightRed)),
+            bg: Some(Colour::Indexed(236)),
+            attributes: Attributes::BOLD | Attributes::UNDERLINE,
+        };
+        assert_eq!(style.to_ansi(), "\x1b[1;4;31;91;48;5;236m");
+        let style = Style { fg: Some(Colour::Rgb(1, 2, 3)), ..Style::default() };
+        assert_eq!(style.to_ansi(), "\x1b[38;2;1;2;3m");
+    }
+
+    #[test]
+    fn test_colour_depth() {
+        let orange = Colour::Rgb(255, 135, 0);
+        assert_eq!(orange.for_depth(ColourDepth::TrueColour), orange);
+        assert_eq!(orange.for_depth(ColourDepth::Ansi256), Colour::Indexed(208));
+        assert_eq!(Colour::Rgb(100, 100, 100).for_depth(ColourDepth::Ansi256), Colour::Indexed(241));
+        assert_eq!(orange.for_depth(ColourDepth::Ansi16), Colour::Named(NamedColour::Yellow));
+        assert_eq!(Colour::Indexed(9).for_depth(ColourDepth::Ansi16), Colour::Named(NamedColour::BrightRed));
+        assert_eq!(Colour::Indexed(196).for_depth(ColourDepth::Ansi16), Colour::Named(NamedColour::BrightRed));
+        assert_eq!(Colour::Indexed(196).for_depth(ColourDepth::TrueColour), Colour::Indexed(196));
+        assert_eq!(Colour::Named(NamedColour::Cyan).for_depth(ColourDepth::Ansi16), Colour::Named(NamedColour::Cyan));
+    }
+}
@@ -0,0 +1,118 @@
+//! Templates such as `{lines} lines, {count:errors} errors`, where the
+//! fields in braces get filled in. `{{` and `}}` stand for literal braces.
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// `{name}` or `{name:argument}`.
+    Field { name: String, argument: Option<String> },
+}
+
+
+/// A parsed template.
+///
+/// ```
+/// use rgrcat::Template;
+///
+/// let template = Template::parse("{lines} lines, {count:errors} errors").unwrap();
+/// let text = template.expand(|name, argument| match (name, argument) {
+///     ("lines", None) => "120".to_string(),
+///     (_, Some(rule)) => format!("<{}>", rule),
+///     _ => String::new(),
+/// });
+/// assert_eq!(text, "120 lines, <errors> errors");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+
+impl Template {
+    /// Parse `text`, failing with a message on a brace that isn't closed or
+    /// a field without a name.
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(format!("unclosed {{ in {}", text)),
+                            Some(c) => field.push(c),
+                        }
+                    }
+                    let (name, argument) = match field.split_once(':') {
+                        Some((name, argument)) => (name.trim(), Some(argument.trim().to_string())),
+                        None => (field.trim(), None),
+                    };
+                    if name.is_empty() {
+                        return Err(format!("a field without a name in {}", text));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field { name: name.to_string(), argument });
+                }
+                '}' => return Err(format!("unmatched }} in {}, write }}}} for a brace", text)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+
+    /// The fields of the template in order, each as its name and argument.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field { name, argument } => Some((&name[..], argument.as_deref())),
+            Part::Text(_) => None,
+        })
+    }
+
+
+    /// Fill in every field with what `get_value` returns for its name and
+    /// argument.
+    pub fn expand(&self, get_value: impl Fn(&str, Option<&str>) -> String) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(literal) => text.push_str(literal),
+                Part::Field { name, argument } => text.push_str(&get_value(name, argument.as_deref())),
+            }
+        }
+        text
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        let template = Template::parse("{{literal}} {lines}, {count: errors}}}").unwrap();
+        assert_eq!(template.fields().collect::<Vec<_>>(), vec![("lines", None), ("count", Some("errors"))]);
+        assert_eq!(template.expand(|name, _| name.to_uppercase()), "{literal} LINES, COUNT}");
+        assert!(Template::parse("{lines").is_err());
+        assert!(Template::parse("lines}").is_err());
+        assert!(Template::parse("{:errors}").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Timestamps of log lines as seconds since the epoch, for the forms logs
+//! mostly come with: `2024-05-01T11:00:00Z`, `2024-05-01 11:00:00,123`,
+//! syslog's `May  1 11:00:00` and a bare `11:00`. Times without a zone are
+//! taken as UTC.
+
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+
+/// Digits only, so signs and spaces don't slip through.
+fn get_number(text: &str) -> Option<i64> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, see
+/// http://howardhinnant.github.io/date_algorithms.html
+fn get_days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+
+/// The year, month and day of a number of days since 1970-01-01.
+fn get_date(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+
+/// `HH:MM[:SS[.fraction]]` at the start of `text` as seconds into the day,
+/// along with what follows it.
+fn parse_clock(text: &str) -> Option<(i64, &str)> {
+    let (hour, rest) = text.split_once(':')?;
+    let minute = get_number(rest.get(..2)?)?;
+    let mut rest = &rest[2..];
+    let mut second = 0;
+    if let Some(after) = rest.strip_prefix(':') {
+        second = get_number(after.get(..2)?)?;
+        rest = &after[2..];
+        if let Some(fraction) = rest.strip_prefix(['.', ',']) {
+            rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+    }
+    let hour = if hour.len() <= 2 { get_number(hour)? } else { return None };
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some((hour * 3600 + minute * 60 + second, rest))
+}
+
+
+/// The offset east of UTC of a zone like `Z`, `+02:00` or `-0500`, of UTC
+/// without any.
+fn parse_zone(text: &str) -> Option<i64> {
+    let zone = text.trim();
+    if matches!(zone, "" | "Z" | "z" | "UTC" | "GMT") {
+        return Some(0);
+    }
+    let (sign, digits) = match (zone.strip_prefix('+'), zone.strip_prefix('-')) {
+        (Some(digits), _) => (1, digits.replacen(':', "", 1)),
+        (_, Some(digits)) => (-1, digits.replacen(':', "", 1)),
+        _ => return None,
+    };
+    let (hours, minutes) = match digits.len() {
+        2 => (get_number(&digits)?, 0),
+        4 => (get_number(&digits[..2])?, get_number(&digits[2..])?),
+        _ => return None,
+    };
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+
+fn get_seconds(days: i64, clock: i64, zone: &str) -> Option<i64> {
+    Some(days * 86400 + clock - parse_zone(zone)?)
+}
+
+
+/// `text` as seconds since the epoch, `None` if it's none of the forms
+/// above. Forms without a date, or syslog's without a year, take it from
+/// `now`, in seconds since the epoch as well.
+///
+/// ```
+/// use rgrcat::parse_timestamp;
+///
+/// assert_eq!(parse_timestamp("2024-05-01T11:00:00Z", 0), Some(1_714_561_200));
+/// assert_eq!(parse_timestamp("2024-05-01 13:00:00.250 +02:00", 0), Some(1_714_561_200));
+/// assert_eq!(parse_timestamp("May  1 11:00:00", 1_714_000_000), Some(1_714_561_200));
+/// assert_eq!(parse_timestamp("11:00", 1_714_560_000), Some(1_714_561_200));
+/// assert_eq!(parse_timestamp("eleven", 0), None);
+/// ```
+pub fn parse_timestamp(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    let bytes = text.as_bytes();
+    if bytes.len() >= 10 && bytes[4] == b'-' && bytes[7] == b'-' {
+        let (year, month, day) = (get_number(&text[..4])?, get_number(&text[5..7])?, get_number(text.get(8..10)?)?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        let days = get_days(year, month, day);
+        let rest = &text[10..];
+        return match rest.strip_prefix(['T', 't', ' ']) {
+            Some(time) => {
+                let (clock, zone) = parse_clock(time)?;
+                get_seconds(days, clock, zone)
+            }
+            None => get_seconds(days, 0, rest),
+        };
+    }
+    let today = now.div_euclid(86400);
+    if let Some(month) = text.get(..3).and_then(|name| MONTHS.iter().position(|month| name.eq_ignore_ascii_case(month))) {
+        let (day, time) = text[3..].trim_start().split_once(' ')?;
+        let day = get_number(day)?;
+        if !(1..=31).contains(&day) {
+            return None;
+        }
+        let (clock, zone) = parse_clock(time.trim_start())?;
+        let (year, _, _) = get_date(today);
+        return get_seconds(get_days(year, month as i64 + 1, day), clock, zone);
+    }
+    let (clock, zone) = parse_clock(text)?;
+    get_seconds(today, clock, zone)
+}
+
+
+/// Seconds since the epoch as `YYYY-MM-DD HH:MM:SS` in UTC.
+///
+/// ```
+/// assert_eq!(rgrcat::format_timestamp(1_714_561_200), "2024-05-01 11:00:00");
+/// ```
+pub fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = get_date(seconds.div_euclid(86400));
+    let seconds = seconds.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        let noon = 1_714_564_800;
+        assert_eq!(parse_timestamp("2024-05-01", 0), Some(1_714_521_600));
+        assert_eq!(parse_timestamp("2024-05-01T11:00Z", 0), Some(1_714_561_200));
+        assert_eq!(parse_timestamp("2024-05-01T06:00:00-0500", 0), Some(1_714_561_200));
+        assert_eq!(parse_timestamp("2024-05-01 11:00:00,123", 0), Some(1_714_561_200));
+        assert_eq!(parse_timestamp("1999-12-31T23:59:60Z", 0), Some(946_684_800));
+        assert_eq!(parse_timestamp("dec 31 23:59:59", noon), Some(1_735_689_599));
+        assert_eq!(parse_timestamp("9:05", noon), Some(1_714_554_300));
+        assert_eq!(parse_timestamp("2024-13-01", 0), None);
+        assert_eq!(parse_timestamp("25:00", 0), None);
+        assert_eq!(parse_timestamp("11:00 somewhere", 0), None);
+        assert_eq!(parse_timestamp("", 0), None);
+    }
+
+    #[test]
+    fn test_dates_round_trip() {
+        for days in [-719_468, -1, 0, 59, 11_016, 19_844, 2_932_896] {
+            let (year, month, day) = get_date(days);
+            assert_eq!(get_days(year, month, day), days);
+        }
+    }
+}
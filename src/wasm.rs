@@ -0,0 +1,44 @@
+//! A JavaScript wrapper around the colourizer, built with wasm-bindgen.
+//!
+//! ```js
+//! const colourizer = new Colourizer("regexp=ERROR\ncolours=red\n");
+//! element.innerHTML = colourizer.colourizeToHtml(line);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::colourizer::Colourizer;
+use crate::config::Config;
+
+
+/// A colourizer for JavaScript, only taking conffile text as there are no
+/// files to read in the browser.
+#[wasm_bindgen(js_name = Colourizer)]
+pub struct WasmColourizer {
+    colourizer: Colourizer,
+}
+
+
+#[wasm_bindgen(js_class = Colourizer)]
+impl WasmColourizer {
+    /// Parse `config_text`, throwing an `Error` when it's invalid.
+    #[wasm_bindgen(constructor)]
+    pub fn new(config_text: &str) -> Result<WasmColourizer, JsError> {
+        let config: Config = config_text.parse()?;
+        Ok(WasmColourizer { colourizer: Colourizer::new(config) })
+    }
+
+
+    /// Colour `line` as HTML with inline CSS.
+    #[wasm_bindgen(js_name = colourizeToHtml)]
+    pub fn colourize_to_html(&self, line: &str) -> String {
+        self.colourizer.colourize_line_html(line)
+    }
+
+
+    /// Colour `line` with escape sequences, for terminal emulators like xterm.js.
+    #[wasm_bindgen(js_name = colourizeToAnsi)]
+    pub fn colourize_to_ansi(&self, line: &str) -> String {
+        self.colourizer.colourize_line(line)
+    }
+}
@@ -0,0 +1,248 @@
+//! Measuring and cutting styled text by terminal cells.
+//!
+//! CJK characters take two cells, combining marks none and escape sequences
+//! none at all. Text is only ever cut between grapheme clusters, so an emoji
+//! sequence or an accented letter stays whole, and a cut never leaves a style
+//! open behind it.
+
+use std::borrow::Cow;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::style::RESET;
+
+
+/// Where `pad` puts the text within its width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    /// Pad on the right.
+    Left,
+    /// Pad on the left.
+    Right,
+    /// Pad on both sides, the extra cell going to the right.
+    Centre,
+}
+
+
+enum Part<'a> {
+    Escape(&'a str),
+    Grapheme(&'a str),
+}
+
+
+/// The length of the escape sequence at the start of `text`: a CSI sequence
+/// up to its final byte, or ESC with one more character after any
+/// intermediate bytes, like the `(` of `ESC ( B`.
+fn get_escape_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    if bytes.get(1) == Some(&b'[') {
+        match bytes[2..].iter().position(|byte| (0x40..=0x7e).contains(byte)) {
+            Some(end) => end + 3,
+            None => text.len()
+        }
+    } else {
+        let intermediates = bytes[1..].iter().take_while(|byte| (0x20..=0x2f).contains(*byte)).count();
+        1 + intermediates + text[1 + intermediates..].chars().next().map_or(0, char::len_utf8)
+    }
+}
+
+
+/// Split `text` into escape sequences and grapheme clusters.
+fn get_parts(text: &str) -> Vec<Part<'_>> {
+    let mut parts = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        match rest.find('\x1b') {
+            Some(0) => {
+                let len = get_escape_len(rest);
+                parts.push(Part::Escape(&rest[..len]));
+                rest = &rest[len..];
+            }
+            Some(start) => {
+                parts.extend(rest[..start].graphemes(true).map(Part::Grapheme));
+                rest = &rest[start..];
+            }
+            None => {
+                parts.extend(rest.graphemes(true).map(Part::Grapheme));
+                rest = "";
+            }
+        }
+    }
+    parts
+}
+
+
+fn is_reset(escape: &str) -> bool {
+    escape == RESET || escape == "\x1b[m"
+}
+
+
+fn is_sgr(escape: &str) -> bool {
+    escape.starts_with("\x1b[") && escape.ends_with('m')
+}
+
+
+/// The number of cells `text` takes on a terminal.
+pub fn get_width(text: &str) -> usize {
+    get_parts(text).into_iter().map(|part| match part {
+        Part::Escape(_) => 0,
+        Part::Grapheme(grapheme) => grapheme.width(),
+    }).sum()
+}
+
+
+/// `text` without its escape sequences: CSI sequences such as colours and
+/// cursor movements, and the shorter ones like `ESC ( B`.
+pub fn strip_escapes(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + get_escape_len(&rest[start..])..];
+    }
+    stripped.push_str(rest);
+    Cow::Owned(stripped)
+}
+
+
+/// Cut `text` down to at most `width` cells. The escape sequences before the
+/// cut are kept, and a style still open at the cut gets reset.
+pub fn truncate(text: &str, width: usize) -> Cow<'_, str> {
+    if get_width(text) <= width {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut used = 0;
+    let mut styled = false;
+    for part in get_parts(text) {
+        match part {
+            Part::Escape(escape) => {
+                if is_sgr(escape) {
+                    styled = !is_reset(escape);
+                }
+                result.push_str(escape);
+            }
+            Part::Grapheme(grapheme) => {
+                used += grapheme.width();
+                if used > width {
+                    break;
+                }
+                result.push_str(grapheme);
+            }
+        }
+    }
+    if styled {
+        result.push_str(RESET);
+    }
+    Cow::Owned(result)
+}
+
+
+/// Pad `text` with spaces to `width` cells. Wider text is left alone.
+pub fn pad(text: &str, width: usize, align: Align) -> Cow<'_, str> {
+    let missing = width.saturating_sub(get_width(text));
+    if missing == 0 {
+        return Cow::Borrowed(text);
+    }
+    let left = match align {
+        Align::Left => 0,
+        Align::Right => missing,
+        Align::Centre => missing / 2,
+    };
+    Cow::Owned(format!("{}{}{}", " ".repeat(left), text, " ".repeat(missing - left)))
+}
+
+
+/// Break `text` into lines of at most `width` cells. Every line ends with its
+/// styles reset and the next one starts them again, so each can be printed on
+/// its own. A grapheme wider than `width` gets a line of its own.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut used = 0;
+    // The style sequences in effect, to reopen on the next line.
+    let mut active: Vec<&str> = vec![];
+    for part in get_parts(text) {
+        match part {
+            Part::Escape(escape) => {
+                if is_reset(escape) {
+                    active.clear();
+                } else if is_sgr(escape) {
+                    active.push(escape);
+                }
+                line.push_str(escape);
+            }
+            Part::Grapheme(grapheme) => {
+                let grapheme_width = grapheme.width();
+                if used > 0 && used + grapheme_width > width {
+                    if !active.is_empty() {
+                        line.push_str(RESET);
+                    }
+                    lines.push(std::mem::take(&mut line));
+                    line.extend(active.iter().copied());
+                    used = 0;
+                }
+                line.push_str(grapheme);
+                used += grapheme_width;
+            }
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A family emoji joined with ZWJs, one cluster two cells wide.
+    const FAMILY: &str = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+
+    #[test]
+    fn test_get_width() {
+        assert_eq!(get_width("abc"), 3);
+        assert_eq!(get_width("日本語"), 6);
+        assert_eq!(get_width("e\u{301}te\u{301}"), 3);
+        assert_eq!(get_width(FAMILY), 2);
+        assert_eq!(get_width("\x1b[1;31mERROR\x1b[0m \x1b[38;5;196m日本\x1b[0m"), 10);
+    }
+
+    #[test]
+    fn test_strip_escapes() {
+        assert_eq!(strip_escapes("\x1b[1;32m+added\x1b[m \x1b[2K\x1b[1Gdone\x1b(B"), "+added done");
+        assert!(matches!(strip_escapes("plain"), Cow::Borrowed("plain")));
+        // A sequence cut off at the end of the line goes as a whole.
+        assert_eq!(strip_escapes("50%\x1b[3"), "50%");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        // The second CJK character doesn't fit into the last cell.
+        assert_eq!(truncate("a日本", 4), "a日");
+        assert_eq!(truncate("ce\u{301}e\u{301}", 2), "ce\u{301}");
+        assert_eq!(truncate(&format!("{}{}x", FAMILY, FAMILY), 3), FAMILY);
+        assert_eq!(truncate("\x1b[31mERROR\x1b[0m here", 3), "\x1b[31mERR\x1b[0m");
+        assert_eq!(truncate("\x1b[31mERR\x1b[0m here", 4), "\x1b[31mERR\x1b[0m ");
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad("日本", 6, Align::Left), "日本  ");
+        assert_eq!(pad("\x1b[31mab\x1b[0m", 5, Align::Right), "   \x1b[31mab\x1b[0m");
+        assert_eq!(pad("ab", 5, Align::Centre), " ab  ");
+        assert_eq!(pad("toolong", 3, Align::Left), "toolong");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("\x1b[31mabc日本\x1b[0mz", 4), ["\x1b[31mabc\x1b[0m", "\x1b[31m日本\x1b[0m", "z"]);
+        assert_eq!(wrap(&format!("a{}b", FAMILY), 1), ["a", FAMILY, "b"]);
+        assert_eq!(wrap("", 3), [""]);
+    }
+}
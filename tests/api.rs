@@ -0,0 +1,985 @@
+use std::io::{self, Cursor, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rgrcat::{
+    render_ansi, Attributes, Colour, Colourizer, Config, ConfigBuilder, ConfigError, CountMode, EscalateWindow,
+    LineEnd, LineReader, NamedColour, RuleBuilder, StreamState, Style, StyledSpan,
+};
+
+
+#[test]
+fn test_process_through_public_api() {
+    let config: Config = "regexp=ERROR\ncolours=red\n-\nregexp=ok\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("an ERROR\nall ok\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "an \x1b[31mERROR\x1b[0m\nall \x1b[32mok\x1b[0m\n");
+}
+
+
+#[test]
+fn test_trailing_whitespace_is_kept() {
+    let config: Config = "regexp=ERROR.*\ncolours=red\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("an ERROR   \t\nsize   \t\r\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "an \x1b[31mERROR   \t\x1b[0m\nsize   \t\r\n");
+}
+
+#[test]
+fn test_line_endings() {
+    let mut reader = LineReader::new(Cursor::new("crlf\r\n10%\r20%\rdone\nlast"));
+    let mut lines = vec![];
+    while reader.read().unwrap() {
+        lines.push((reader.line().unwrap().to_string(), String::from_utf8(reader.ending().to_vec()).unwrap()));
+    }
+    let expected = [("crlf", "\r\n"), ("10%", "\r"), ("20%", "\r"), ("done", "\n"), ("last", "")];
+    assert_eq!(lines, expected.map(|(line, ending)| (line.to_string(), ending.to_string())));
+
+    // Progress lines get coloured one by one, the reset before their \r.
+    let colourizer = Colourizer::new("regexp=\\d+%\ncolours=green\n".parse().unwrap());
+    let mut output = vec![];
+    colourizer.process(Cursor::new("10%\r20%\r\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "\x1b[32m10%\x1b[0m\r\x1b[32m20%\x1b[0m\r\n");
+}
+
+#[test]
+fn test_overlong_lines_come_in_pieces() {
+    for terminator in [b'\n', b'\0'] {
+        let input = format!("short\n{}{}ok\n", "x".repeat(20), terminator as char).replace('\n', &(terminator as char).to_string());
+        // Read in chunks of 8 bytes, the first piece is all read once over the limit.
+        let mut reader = LineReader::with_terminator(io::BufReader::with_capacity(8, Cursor::new(input)), terminator);
+        reader.set_max_length(Some(10));
+        let mut pieces = vec![];
+        while reader.read().unwrap() {
+            pieces.push((String::from_utf8(reader.raw().to_vec()).unwrap(), reader.is_overlong(), reader.is_continued()));
+        }
+        let end = (terminator as char).to_string();
+        assert_eq!(pieces, [
+            (format!("short{}", end), false, false),
+            ("x".repeat(18), true, true),
+            ("xx".to_string() + &end, true, false),
+            (format!("ok{}", end), false, false),
+        ]);
+    }
+}
+
+#[test]
+fn test_config_from_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "name=level\nregexp=WARN\ncolours=yellow\n").unwrap();
+    let config = Config::from_path(&path).unwrap();
+    assert_eq!(config.rules().len(), 1);
+    assert_eq!(config.rules()[0].label(), "level");
+
+    let missing = Config::from_path(dir.path().join("missing"));
+    assert!(matches!(missing, Err(ConfigError::Io { .. })));
+}
+
+
+/// The conffile `name` of tests/fixtures, loaded strictly so any problem fails.
+fn load_fixture(name: &str) -> Colourizer {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    Colourizer::new(Config::from_path_strict(path).unwrap())
+}
+
+#[test]
+fn test_config_with_bom() {
+    let colourizer = load_fixture("bom.conf");
+    assert_eq!(colourizer.config().rules()[0].regexp(), "ERROR");
+    assert_eq!(colourizer.colourize_line("an ERROR"), "an \x1b[31mERROR\x1b[0m");
+}
+
+#[test]
+fn test_config_with_crlf() {
+    let colourizer = load_fixture("crlf.conf");
+    assert_eq!(colourizer.config().rules().len(), 2);
+    // Neither the `$` of the regexp nor the colour name ends in \r.
+    assert_eq!(colourizer.colourize_line("ERROR 42"), "ERROR \x1b[32m42\x1b[0m");
+    assert_eq!(colourizer.colourize_line("an ERROR"), "an \x1b[1;31mERROR\x1b[0m");
+}
+
+#[test]
+fn test_config_with_spaces_around_equals() {
+    let colourizer = load_fixture("spaced.conf");
+    let rules = colourizer.config().rules();
+    assert_eq!((rules[0].regexp(), rules[1].regexp()), ("ERROR", "  padded"));
+    assert_eq!(colourizer.colourize_line("ERROR  padded"), "\x1b[31mERROR\x1b[0;32m  padded\x1b[0m");
+}
+
+
+/// Turn spans into (text, style) pairs the way a ratatui `Line` is built
+/// from `Span`s, including the unstyled text in between.
+fn get_styled_parts<'a>(line: &'a str, spans: &[StyledSpan]) -> Vec<(&'a str, Style)> {
+    let mut parts = vec![];
+    let mut end = 0;
+    for span in spans {
+        if span.range.start > end {
+            parts.push((&line[end..span.range.start], Style::default()));
+        }
+        parts.push((&line[span.range.clone()], span.style));
+        end = span.range.end;
+    }
+    if end < line.len() {
+        parts.push((&line[end..], Style::default()));
+    }
+    parts
+}
+
+
+#[test]
+fn test_spans_on_multi_rule_line() {
+    let config: Config = "regexp=ERROR \\d\ncolours=bold\n-\nregexp=\\d+\ncolours=red\n-\nregexp=ms\ncolours=on_blue\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let line = "ERROR 42 after 17ms";
+    let spans = colourizer.spans(line);
+
+    let red = Style { fg: Some(Colour::Named(NamedColour::Red)), ..Style::default() };
+    let bold = Style { attributes: Attributes::BOLD, ..Style::default() };
+    let on_blue = Style { bg: Some(Colour::Named(NamedColour::Blue)), ..Style::default() };
+    // The bold rule comes first and keeps "ERROR 4" from the red one.
+    assert_eq!(spans, vec![
+        StyledSpan { range: 0..7, style: bold },
+        StyledSpan { range: 7..8, style: red },
+        StyledSpan { range: 15..17, style: red },
+        StyledSpan { range: 17..19, style: on_blue },
+    ]);
+    assert!(spans.windows(2).all(|pair| pair[0].range.end <= pair[1].range.start));
+    assert_eq!(get_styled_parts(line, &spans), vec![
+        ("ERROR 4", bold),
+        ("2", red),
+        (" after ", Style::default()),
+        ("17", red),
+        ("ms", on_blue),
+    ]);
+    assert_eq!(colourizer.colourize_line(line), render_ansi(line, &spans));
+}
+
+
+// Every rule keeps its colours where no later one paints over them, as with
+// the address and the time of conf.ping.
+#[test]
+fn test_rules_keep_their_colours() {
+    let config: Config = "regexp=\\d+\\.\\d+\\.\\d+\\.\\d+\ncolours=bold\ncount=once\n-\nregexp=time=\\S+\ncolours=green\n-\nregexp=\\d+\\.\\d+\\.\\d+\\.\\d+\ncolours=blue\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(
+        colourizer.colourize_line("64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.045 ms from 10.0.0.2"),
+        "64 bytes from \x1b[1m10.0.0.1\x1b[0m: icmp_seq=1 ttl=64 \x1b[32mtime=0.045\x1b[0m ms from \x1b[34m10.0.0.2\x1b[0m"
+    );
+}
+
+
+// A later rule for digits or brackets doesn't match within the escape
+// sequences of an earlier one.
+#[test]
+fn test_rules_never_match_escape_sequences() {
+    let config: Config = "regexp=\\d\ncolours=green\n-\nregexp=\\d+\\.\\d+\\.\\d+\\.\\d+\ncolours=yellow\n-\nregexp=[\\[m]\ncolours=bold\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let line = "at 10.0.0.1 [m]";
+    let coloured = colourizer.colourize_line(line);
+    assert_eq!(coloured, "at \x1b[32m10\x1b[0;33m.\x1b[0;32m0\x1b[0;33m.\x1b[0;32m0\x1b[0;33m.\x1b[0;32m1\x1b[0m \x1b[1m[m\x1b[0m]");
+    // Taking out the SGR sequences leaves the line as it was.
+    let sgr = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    assert_eq!(sgr.replace_all(&coloured, ""), line);
+}
+
+
+#[test]
+fn test_only_matched_positions_are_coloured() {
+    let config: Config = "regexp=^\\S+ error:\ncolours=red\n-\nregexp=^\\d|x\\d\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("gcc error: something about error: handling"),
+               "\x1b[31mgcc error:\x1b[0m something about error: handling");
+    assert_eq!(colourizer.colourize_line("1 and 1, x1 and x1"), "\x1b[32m1\x1b[0m and 1, \x1b[32mx1\x1b[0m and \x1b[32mx1\x1b[0m");
+}
+
+
+#[test]
+fn test_spans_leave_out_plain_text() {
+    let config: Config = "regexp=é+\ncolours=green\n-\nregexp=x\ncolours=none\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let spans = colourizer.spans("aéé x");
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].range, 1..5);
+}
+
+
+#[test]
+fn test_lines_match_binary_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.lines");
+    std::fs::write(&path, "regexp=\\d+\ncolours=yellow\n-\nregexp=^\\w+\ncolours=bold\n").unwrap();
+    let input = "eth0 mtu 1500  \nlo mtu 65536\r\nno newline 7";
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(input.as_bytes())?;
+            child.wait_with_output()
+        })
+        .unwrap();
+
+    let colourizer = Colourizer::new(Config::from_path(&path).unwrap());
+    let lines: Vec<String> = colourizer.lines(Cursor::new(input)).map(|line| line.unwrap()).collect();
+    // Lines go out with the ending they came in with.
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}\n{}\r\n{}\n", lines[0], lines[1], lines[2]));
+
+    let span_lines: Vec<(String, Vec<StyledSpan>)> = colourizer.span_lines(Cursor::new(input)).map(|line| line.unwrap()).collect();
+    assert_eq!(span_lines.len(), 3);
+    assert_eq!(span_lines[1].0, "lo mtu 65536");
+    assert_eq!(render_ansi(&span_lines[1].0, &span_lines[1].1), lines[1]);
+}
+
+
+#[test]
+fn test_lines_reject_invalid_utf8() {
+    let colourizer = Colourizer::new("regexp=a\ncolours=red\n".parse().unwrap());
+    let mut lines = colourizer.lines(Cursor::new(&b"ok\n\xff\n"[..]));
+    assert_eq!(lines.next().unwrap().unwrap(), "ok");
+    assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+
+#[test]
+fn test_rule_spans_split_between_rules() {
+    let colourizer = Colourizer::new("regexp=ab\ncolours=red\n-\nregexp=cd\ncolours=red\n".parse().unwrap());
+    assert_eq!(colourizer.spans("abcd").len(), 1);
+    let rules: Vec<_> = colourizer.rule_spans("abcd").into_iter().map(|span| (span.range, span.rule)).collect();
+    assert_eq!(rules, [(0..2, 0), (2..4, 1)]);
+}
+
+
+#[test]
+fn test_first_rule_wins_on_overlap() {
+    let colourize = |config: &str, line: &str| Colourizer::new(config.parse().unwrap()).colourize_line(line);
+    // Whichever of the two comes first keeps the bytes both match.
+    assert_eq!(colourize("regexp=abc\ncolours=red\n-\nregexp=bcd\ncolours=blue\n", "abcde"), "\x1b[31mabc\x1b[0;34md\x1b[0me");
+    assert_eq!(colourize("regexp=bcd\ncolours=blue\n-\nregexp=abc\ncolours=red\n", "abcde"), "\x1b[31ma\x1b[0;34mbcd\x1b[0me");
+    // A rule's groups take over from its whole match, not from an earlier rule.
+    assert_eq!(colourize("regexp=c\ncolours=bold\n-\nregexp=a(b)(c)\ncolours=red,green,blue\n", "abc"), "\x1b[31ma\x1b[0;32mb\x1b[0;1mc\x1b[0m");
+
+    let colourizer = Colourizer::new("regexp=abc\ncolours=red\n-\nregexp=bcd\ncolours=blue\n".parse().unwrap());
+    let rules: Vec<_> = colourizer.rule_spans("abcde").into_iter().map(|span| (span.range, span.rule)).collect();
+    assert_eq!(rules, [(0..3, 0), (3..4, 1)]);
+}
+
+
+#[test]
+fn test_explain_line() {
+    let colourizer = Colourizer::new("regexp=(\\w+)=(\\d+)\ncolours=bold,unchanged,red\n-\nregexp=secret\nreplace=***\n-\nregexp=stop\ncount=stop\n-\nregexp=x\ncolours=blue\n-\nregexp=^drop\nskip=yes\n".parse().unwrap());
+    let explanation = colourizer.explain_line("a=1 secret stop");
+    let steps = &explanation.steps;
+    // The rule after count=stop never runs, the skip rule comes after it.
+    assert_eq!(steps.iter().map(|step| step.rule).collect::<Vec<_>>(), [0, 1, 2]);
+    let groups: Vec<_> = steps[0].matches[0].iter().map(|group| (group.group, group.range.clone(), group.style)).collect();
+    let red = Style { fg: Some(Colour::Named(NamedColour::Red)), ..Style::default() };
+    assert_eq!(groups, [(0, 0..3, Some(Style { attributes: Attributes::BOLD, ..Style::default() })), (1, 0..1, None), (2, 2..3, Some(red))]);
+    assert_eq!(steps[1].replaced.as_deref(), Some("a=1 *** stop"));
+    assert_eq!(steps[2].text, "a=1 *** stop");
+    assert!(steps[2].stopped && explanation.skipped_by.is_none());
+    assert_eq!(explanation.processed.unwrap().text, "a=1 *** stop");
+
+    let explanation = colourizer.explain_line("drop x");
+    assert_eq!(explanation.skipped_by, Some(4));
+    assert!(explanation.processed.is_none() && explanation.steps[3].matches.len() == 1);
+}
+
+
+#[test]
+fn test_flush_rules() {
+    let colourizer = Colourizer::new("regexp=ALERT\nflush=yes\n-\nregexp=info\n".parse().unwrap());
+    assert!(colourizer.config().rules()[0].flush());
+    assert!(colourizer.is_flushed("an ALERT"));
+    assert!(!colourizer.is_flushed("some info"));
+    // A skipped line is never written, so there's nothing to flush.
+    let colourizer = Colourizer::new("regexp=ALERT\nflush=yes\nskip=yes\n".parse().unwrap());
+    assert!(!colourizer.is_flushed("an ALERT"));
+}
+
+
+
+
+#[test]
+fn test_footer_templates_are_checked() {
+    let config: Config = "name=errors\nregexp=ERROR\n-\nfooter={count:errors} errors in {elapsed}\n".parse().unwrap();
+    assert_eq!(config.rules()[1].footer(), "{count:errors} errors in {elapsed}");
+    for (footer, message) in [("{count:oops}", "footer counts oops, which names no rule"),
+                              ("{bytes}", "footer has no field bytes"),
+                              ("{lines", "footer: unclosed { in {lines")] {
+        match format!("name=errors\nregexp=ERROR\n-\nfooter={}\n", footer).parse::<Config>() {
+            Err(ConfigError::InvalidRule { message: got, .. }) => assert_eq!(got, message),
+            other => panic!("{} parsed into {:?}", footer, other.map(|config| config.rules().len())),
+        }
+    }
+}
+
+#[test]
+fn test_process_next_line() {
+    let colourizer = Colourizer::new("regexp=\\d+\ncolours=green\n".parse().unwrap());
+    let mut state = StreamState::new();
+    let processed = colourizer.process_next_line("up 42", &mut state, None).unwrap();
+    assert_eq!(processed.render(true), "up \x1b[32m42\x1b[0m");
+    assert_eq!(processed.render(false), "up 42");
+    let mut rendered = "> ".to_string();
+    processed.render_into(true, &mut rendered);
+    assert_eq!(rendered, "> up \x1b[32m42\x1b[0m");
+
+    let colourizer = Colourizer::new("regexp=up\nskip=yes\n".parse().unwrap());
+    assert_eq!(colourizer.process_next_line("up 42", &mut state, None), None);
+}
+
+
+#[test]
+fn test_prefix_template() {
+    let colourizer = Colourizer::new("regexp=ERROR\ncolours=red\nprefix={{{line}}} \n".parse().unwrap());
+    let mut state = StreamState::for_file("app.log");
+    assert_eq!(colourizer.process_next_line("fine", &mut state, None).unwrap().text, "fine");
+    let processed = colourizer.process_next_line("an ERROR", &mut state, None).unwrap();
+    assert_eq!(processed.render(true), "\x1b[31m{2} \x1b[0man \x1b[31mERROR\x1b[0m");
+    assert_eq!(state.file(), "app.log");
+    assert!("prefix={lines}\n".parse::<Config>().is_err());
+}
+
+#[test]
+fn test_timestamp_rules() {
+    let config = ConfigBuilder::new()
+        .rule(RuleBuilder::new(r"at (\d+:\d+)").timestamp(true))
+        .rule(RuleBuilder::new(r"\d{4}-\d\d-\d\dT\S+").timestamp(true))
+        .build()
+        .unwrap();
+    let colourizer = Colourizer::new(config);
+    let now = 1_714_564_800;
+    assert_eq!(colourizer.get_timestamp("started at 11:00", now), Some(1_714_561_200));
+    assert_eq!(colourizer.get_timestamp("at 99:99, 2024-05-01T11:00Z", now), Some(1_714_561_200));
+    assert_eq!(colourizer.get_timestamp("no time here", now), None);
+    assert!(colourizer.config().rules().iter().all(|rule| rule.timestamp()));
+}
+
+#[test]
+fn test_alerts_after_quiet_periods() {
+    let config: Config = "regexp=ERROR\ncolours=red\nalert_after_quiet=5m\n-\nregexp=PANIC\ncolours=beep\n".parse().unwrap();
+    assert_eq!(config.rules()[0].alert_after_quiet(), Some(Duration::from_secs(300)));
+    assert!(config.rules()[1].beep());
+    let colourizer = Colourizer::new(config);
+    let start = Instant::now();
+    let mut state = StreamState::new();
+    let mut alerted = |line, seconds| colourizer.is_alerted(line, &mut state, start + Duration::from_secs(seconds));
+    let burst_quiet_burst = [("ERROR a", 0), ("ERROR b", 1), ("fine", 200), ("ERROR c", 250), ("ERROR d", 551), ("ERROR e", 552)];
+    let alerts: Vec<&str> = burst_quiet_burst.iter().filter(|(line, seconds)| alerted(line, *seconds)).map(|(line, _)| *line).collect();
+    assert_eq!(alerts, vec!["ERROR a", "ERROR d"]);
+    // Without alert_after_quiet= every match rings.
+    assert!(alerted("PANIC", 553) && alerted("PANIC", 554));
+}
+
+// One bell for the line however often the rule matches, the text coloured
+// as without it.
+#[test]
+fn test_beep_rings_once_per_line() {
+    let config: Config = "regexp=PANIC\ncolours=beep,red\n-\nregexp=\\d+\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("PANIC 1 PANIC\nfine 2\n"), &mut output).unwrap();
+    assert_eq!(output.iter().filter(|byte| **byte == 0x07).count(), 1);
+    assert_eq!(String::from_utf8(output).unwrap(), "PANIC \x1b[32m1\x1b[0m PANIC\n\x07fine \x1b[32m2\x1b[0m\n");
+}
+
+
+#[test]
+fn test_disabled_rules_and_selection() {
+    let config: Config = "name=error\nregexp=ERROR\ncolours=red\nenabled=no\n-\nregexp=\\d+\ncolours=green\n".parse().unwrap();
+    assert_eq!(config.rules().len(), 2);
+    assert!(!config.rules()[0].enabled());
+    assert_eq!(Colourizer::new(config.clone()).colourize_line("ERROR 1"), "ERROR \x1b[32m1\x1b[0m");
+
+    let only = config.select(&["error".to_string()], &[]);
+    assert_eq!(only.rules().len(), 1);
+    assert_eq!(Colourizer::new(only).colourize_line("ERROR 1"), "\x1b[31mERROR\x1b[0m 1");
+    let except = config.select(&[], &["1".to_string()]);
+    assert_eq!(Colourizer::new(except).colourize_line("ERROR 1"), "ERROR 1");
+}
+
+
+#[test]
+fn test_priority_orders_rules_across_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let common = dir.path().join("conf.common");
+    let local = dir.path().join("conf.local");
+    std::fs::write(&common, "name=word\nregexp=\\w+\ncolours=blue\n").unwrap();
+    std::fs::write(&local, "name=late\nregexp=ERROR\ncolours=red\n-\nname=early\nregexp=\\d+\ncolours=green\npriority=-1\n").unwrap();
+    let mut rules = Config::from_path(&common).unwrap().rules().to_vec();
+    rules.extend_from_slice(Config::from_path(&local).unwrap().rules());
+    let config = Config::from_rules(rules);
+
+    let names: Vec<_> = config.rules().iter().map(|rule| (rule.index(), rule.name(), rule.priority())).collect();
+    assert_eq!(names, [(0, "early", -1), (1, "word", 0), (2, "late", 0)]);
+    // The number runs first and keeps its colour, and the words rule takes ERROR before the last rule.
+    assert_eq!(Colourizer::new(config).colourize_line("ERROR 42"), "\x1b[34mERROR\x1b[0m \x1b[32m42\x1b[0m");
+}
+
+
+#[test]
+fn test_max_line_length_skips_rule() {
+    let config: Config = "regexp=^INFO\ncolours=green\n-\nregexp=\"\\w+\"\ncolours=yellow\nmax_line_length=64\n".parse().unwrap();
+    assert_eq!(config.rules()[1].max_line_length(), Some(64));
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line(r#"INFO {"a"}"#), "\x1b[32mINFO\x1b[0m {\x1b[33m\"a\"\x1b[0m}");
+
+    let line = format!("INFO {}", r#"{"key":"value"}"#.repeat(1000));
+    let coloured = colourizer.colourize_line(&line);
+    assert_eq!(coloured, format!("\x1b[32mINFO\x1b[0m{}", &line[4..]));
+}
+
+
+#[test]
+fn test_same_as_takes_colours() {
+    let config: Config = "name=ipv6\nregexp=[0-9a-f]+::1\nsame_as=ips\n-\nname=ips\nregexp=\\d+\\.\\d+\\.\\d+\\.\\d+\ncolours=cyan\n".parse().unwrap();
+    assert_eq!(config.rules()[0].same_as(), "ips");
+    assert_eq!(config.rules()[0].colours(), config.rules()[1].colours());
+    assert_eq!(Colourizer::new(config).colourize_line("fe80::1"), "\x1b[36mfe80::1\x1b[0m");
+
+    let err = "name=a\nregexp=a\nsame_as=missing\n".parse::<Config>().err().unwrap();
+    assert_eq!(err.to_string(), "invalid rule a: same_as=missing names no rule");
+    let err = "name=a\nregexp=a\nsame_as=b\n-\nname=b\nregexp=b\nsame_as=a\n".parse::<Config>().err().unwrap();
+    assert_eq!(err.to_string(), "invalid rule a: same_as is circular: a -> b -> a");
+}
+
+
+#[test]
+fn test_colours_light() {
+    let config: Config = "regexp=WARN\ncolours=yellow\ncolors_light=blue\n-\nregexp=ok\ncolours=green\n".parse().unwrap();
+    assert!(config.has_light_colours());
+    assert_eq!(config.rules()[0].colours_light().len(), 1);
+    let light = Colourizer::new(config.for_light_background());
+    assert_eq!(light.colourize_line("WARN ok"), "\x1b[34mWARN\x1b[0m \x1b[32mok\x1b[0m");
+}
+
+
+#[test]
+fn test_escalate_over_burst_and_quiet_lines() {
+    let config: Config = "regexp=ERROR\ncolours=green\nescalate=2:yellow,3:bold red\nescalate_window=3\n".parse().unwrap();
+    assert_eq!(config.rules()[0].escalate_window(), EscalateWindow::Lines(3));
+    let colourizer = Colourizer::new(config);
+    let input = "ERROR\nERROR\nERROR\nok\nERROR\nok\nok\nok\nERROR\n";
+    let lines: Vec<String> = colourizer.lines(input.as_bytes()).map(Result::unwrap).collect();
+    assert_eq!(lines, [
+        "\x1b[32mERROR\x1b[0m",
+        "\x1b[33mERROR\x1b[0m",
+        "\x1b[1;31mERROR\x1b[0m",
+        "ok",
+        // One match fell out of the window, two are left.
+        "\x1b[33mERROR\x1b[0m",
+        "ok",
+        "ok",
+        "ok",
+        "\x1b[32mERROR\x1b[0m",
+    ]);
+    // A single line has no history to escalate on.
+    assert_eq!(colourizer.colourize_line("ERROR"), "\x1b[32mERROR\x1b[0m");
+}
+
+
+#[test]
+fn test_colours_per_group() {
+    let config: Config = "regexp=(\\d+\\.\\d+\\.\\d+\\.\\d+):(\\d+)\ncolours=default,green,yellow\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("to 10.0.0.1:80"), "to \x1b[32m10.0.0.1\x1b[0m:\x1b[33m80\x1b[0m");
+
+    // The last colour goes on, and the group that isn't part of the match is left alone.
+    let config: Config = "regexp=(a)(b)?(c)\ncolours=bold,red\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("ac"), "\x1b[31mac\x1b[0m");
+    assert_eq!(colourizer.colourize_line("abc"), "\x1b[31mabc\x1b[0m");
+
+    let config: Config = "regexp=x(y)\ncolours=blue,unchanged\n".parse().unwrap();
+    assert_eq!(Colourizer::new(config).colourize_line("xy"), "\x1b[34mxy\x1b[0m");
+}
+
+
+#[test]
+fn test_inner_matches_restore_the_enclosing_colour() {
+    let config: Config = "regexp=\\d+\ncolours=red\n-\nregexp=(x)(y)\ncolours=unchanged,previous,blue\n-\nregexp=\\[.*\\]\ncolours=yellow\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("[a 1 b]"), "\x1b[33m[a \x1b[0;31m1\x1b[0;33m b]\x1b[0m");
+    // `previous` takes the colour an earlier rule left before the group,
+    // `unchanged` keeps the one there.
+    assert_eq!(colourizer.colourize_line("[axy] xy"), "\x1b[33m[ax\x1b[0;34my\x1b[0;33m]\x1b[0m x\x1b[34my\x1b[0m");
+    assert_eq!(colourizer.colourize_line("1xy"), "\x1b[31m1x\x1b[0;34my\x1b[0m");
+}
+
+
+#[test]
+fn test_unchanged_groups() {
+    let config: Config = "regexp=^(\\S+) on (\\S+)\ncolours=unchanged,unchanged,green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("/dev/sda1 on /boot type ext4"), "/dev/sda1 on \x1b[32m/boot\x1b[0m type ext4");
+}
+
+
+#[test]
+fn test_count_once() {
+    let config: Config = "regexp=/\ncolours=red\ncount=once\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("/dev/sda1 /mnt"), "\x1b[31m/\x1b[0mdev/sda1 /mnt");
+    assert_eq!(colourizer.colourize_line("no slash"), "no slash");
+}
+
+
+#[test]
+fn test_count_stop() {
+    let config: Config = "regexp=time=\\S+\ncolours=green\ncount=stop\n-\nregexp=\\d+\ncolours=red\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("seq=1 time=2ms"), "seq=1 \x1b[32mtime=2ms\x1b[0m");
+    assert_eq!(colourizer.colourize_line("seq=1"), "seq=\x1b[31m1\x1b[0m");
+}
+
+
+// Spaces combine names within a group, commas separate the groups.
+#[test]
+fn test_colour_groups() {
+    let config: Config = "regexp=(\\w+)=(\\d+)\ncolours=bold red , underline  yellow,\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.config().rules()[0].colours().len(), 2);
+    assert_eq!(colourizer.colourize_line("n=42"), "\x1b[4;33mn\x1b[0;1;31m=\x1b[0;4;33m42\x1b[0m");
+}
+
+
+#[test]
+fn test_blocks_colour_following_lines() {
+    let config: Config = "regexp=^error:\ncolours=red\ncount=block\n-\nregexp=^$\ncount=unblock\n-\nregexp=\\d+\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("make 1\nerror: no\n  in 2\n  here\n\nok 3\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), concat!(
+        "make \x1b[32m1\x1b[0m\n",
+        "\x1b[31merror: no\x1b[0m\n",
+        "\x1b[31m  in \x1b[0;32m2\x1b[0m\n",
+        "\x1b[31m  here\x1b[0m\n",
+        "\n",
+        "ok \x1b[32m3\x1b[0m\n",
+    ));
+
+    // Without a stream a block is only the line that starts it.
+    assert_eq!(colourizer.colourize_line("error: no"), "\x1b[31merror: no\x1b[0m");
+    assert_eq!(colourizer.colourize_line("  here"), "  here");
+}
+
+
+#[test]
+fn test_window_colours_continuation_lines() {
+    let config: Config = "regexp=\\d+\ncolours=green\n-\nregexp=^Caused by:.*(\\n[ \\t]+at .*)*\nmultiline=yes\nwindow=3\ncolours=yellow\n".parse().unwrap();
+    assert_eq!(config.rules()[1].window(), 3);
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("Exception in main\nCaused by: boom\n\tat a.b(A.java:1)\n\tat c.d(C.java:2)\n\tat e.f(E.java:3)\ndone\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), concat!(
+        "Exception in main\n",
+        "\x1b[33mCaused by: boom\x1b[0m\n",
+        "\x1b[33m\tat a.b(A.java:\x1b[0;32m1\x1b[0;33m)\x1b[0m\n",
+        "\x1b[33m\tat c.d(C.java:\x1b[0;32m2\x1b[0;33m)\x1b[0m\n",
+        // The line that caused it is out of the window of three lines by now.
+        "\tat e.f(E.java:\x1b[32m3\x1b[0m)\n",
+        "done\n",
+    ));
+
+    // Without a stream there are no lines before.
+    assert_eq!(colourizer.colourize_line("\tat a.b(A.java)"), "\tat a.b(A.java)");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "regexp=a\nwindow=lots\n-\nregexp=b\nwindow=yes\nreplace=c\n").unwrap();
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => assert_eq!(problems, [
+            (2, "window must be a number of lines or yes, not lots".to_string()),
+            (4, "window= doesn't go with replace=, the rule runs on the line alone".to_string()),
+        ]),
+        _ => panic!("strict parsing accepted the windows"),
+    }
+}
+
+
+#[test]
+fn test_colours_at_end_of_line() {
+    let colourize = |eol: &str, line: &str| {
+        let config: Config = format!("regexp=\\d+\ncolours=bold\n-\nregexp=^ERROR.*$\ncolours=on_red\neol={}\n", eol).parse().unwrap();
+        Colourizer::new(config).colourize_line(line)
+    };
+    assert_eq!(colourize("reset", "ERROR disk"), "\x1b[41mERROR disk\x1b[0m");
+    assert_eq!(colourize("extend", "ERROR disk"), "\x1b[41mERROR disk\x1b[K\x1b[0m");
+    assert_eq!(colourize("persist", "ERROR disk"), "\x1b[41mERROR disk");
+    // Only the rule whose colour ends the line has a say.
+    assert_eq!(colourize("extend", "ERROR 42"), "\x1b[41mERROR \x1b[0;1m42\x1b[0m");
+    assert_eq!(colourize("persist", "ERROR 42 x"), "\x1b[41mERROR \x1b[0;1m42\x1b[0;41m x");
+
+    let config: Config = "regexp=ERROR\ncolours=on_red\neol=extend\n".parse().unwrap();
+    assert_eq!(config.rules()[0].eol(), LineEnd::Extend);
+    // A match short of the end of the line is reset as ever.
+    assert_eq!(Colourizer::new(config).colourize_line("ERROR disk"), "\x1b[41mERROR\x1b[0m disk");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "regexp=x\neol=fill\n").unwrap();
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => assert_eq!(problems, [
+            (2, "eol must be reset, extend or persist, not fill".to_string()),
+        ]),
+        _ => panic!("strict parsing accepted eol=fill"),
+    }
+}
+
+
+#[test]
+fn test_count_previous() {
+    let config: Config = "regexp=^error\ncolours=red\ncount=stop\n-\nregexp=failed\ncolours=bold\ncount=previous\n-\nregexp=\\d+\ncolours=green\n".parse().unwrap();
+    let counts: Vec<CountMode> = config.rules().iter().map(|rule| rule.count()).collect();
+    assert_eq!(counts, [CountMode::Stop, CountMode::Stop, CountMode::More]);
+    let colourizer = Colourizer::new(config);
+    // Once the second rule matches the third is never tried.
+    assert_eq!(colourizer.colourize_line("job 3 failed"), "job 3 \x1b[1mfailed\x1b[0m");
+    assert_eq!(colourizer.colourize_line("job 3 done"), "job \x1b[32m3\x1b[0m done");
+
+    // The first rule has none before it, and counts as more.
+    let problems = match Config::from_reader_named("regexp=a\ncount=previous\n".as_bytes(), "<test>", true) {
+        Err(ConfigError::Invalid { problems, .. }) => problems,
+        _ => panic!("count=previous on the first rule is a problem"),
+    };
+    assert_eq!(problems, [(1, "count=previous on the first rule counts as more".to_string())]);
+    assert_eq!("regexp=a\ncount=previous\n".parse::<Config>().unwrap().rules()[0].count(), CountMode::More);
+}
+
+
+#[test]
+fn test_replace_with_backreferences() {
+    let config: Config = "regexp=(\\d+)ms\nreplace=\\1 ms\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("a 12ms b 3ms"), "a \x1b[32m12 ms\x1b[0m b \x1b[32m3 ms\x1b[0m");
+
+    let config: Config = "regexp=(\\w+)=(\\w+)\nreplace=${2}\\\\\\g<1>\n".parse().unwrap();
+    let processed = Colourizer::new(config).process_line("key=value, \\1");
+    assert_eq!(processed.text, "value\\key, \\1");
+    assert!(processed.spans.is_empty());
+
+    // The colours before a replacement stay, and later rules see the new text.
+    let config: Config = "regexp=a\ncolours=red\n-\nregexp=b\nreplace=bb\ncount=once\n-\nregexp=bb\ncolours=bold\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("a b b"), "\x1b[31ma\x1b[0m \x1b[1mbb\x1b[0m b");
+    assert_eq!(colourizer.spans("a b b")[1].range, 2..4);
+}
+
+
+#[test]
+fn test_replace_with_escapes() {
+    let config: Config = "regexp=(\\w+)$\nreplace=\\t\\1\n".parse().unwrap();
+    assert_eq!(Colourizer::new(config).process_line("key value").text, "key \tvalue");
+
+    let config: Config = "regexp=!\nreplace=\\n\\r\\\\n\\e[1m\\033\\x24\\x4a\\x4\n".parse().unwrap();
+    assert_eq!(Colourizer::new(config).process_line("!").text, "\n\r\\n\x1b[1m\x1b$J\\x4");
+
+    // The regexp keeps its escapes, and unknown ones in replace= are warned about.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "regexp=\\t(\\d)\nreplace=<\\1\\q>\n").unwrap();
+    let colourizer = Colourizer::new(Config::from_path(&path).unwrap());
+    assert_eq!(colourizer.process_line("t1 \t2").text, "t1 <2\\q>");
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => assert_eq!(problems, [
+            (2, "unknown escape \\q in replace, left as it is".to_string()),
+        ]),
+        _ => panic!("strict parsing accepted the unknown escape"),
+    }
+}
+
+
+#[test]
+fn test_concat_no_stands_alone() {
+    let config: Config = concat!(
+        "regexp=\\d+\ncolours=green\n-\n",
+        "regexp=^.*took (\\d+) ms.*$\nreplace=slow: \\1 ms\nconcat=no\n-\n",
+        "regexp=slow\ncolours=red\n",
+    ).parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("request 7 took 1200 ms in total"), "\x1b[31mslow\x1b[0m: 1200 ms");
+    assert_eq!(colourizer.colourize_line("request 7"), "request \x1b[32m7\x1b[0m");
+
+    // Without replace= it only drops the colours of the rules before.
+    let config = ConfigBuilder::new()
+        .rule(RuleBuilder::new("a").colour_name("green"))
+        .rule(RuleBuilder::new("b").colour_name("red").concat(false))
+        .build().unwrap();
+    assert_eq!(Colourizer::new(config).colourize_line("a b"), "a \x1b[31mb\x1b[0m");
+}
+
+
+#[test]
+fn test_regexp_flags() {
+    let config: Config = "regexp=error\ncolours=red\nignorecase=yes\n-\nregexp=warn\ncolours=yellow\n-\nregexp=(?i)info\ncolours=green\n".parse().unwrap();
+    assert!(config.rules()[0].ignorecase() && !config.rules()[1].ignorecase());
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("ERROR error"), "\x1b[31mERROR\x1b[0m \x1b[31merror\x1b[0m");
+    assert_eq!(colourizer.colourize_line("WARN warn"), "WARN \x1b[33mwarn\x1b[0m");
+    assert_eq!(colourizer.colourize_line("INFO"), "\x1b[32mINFO\x1b[0m");
+
+    let config: Config = "regexp=error\nignorecase=sometimes\n".parse().unwrap();
+    assert!(!config.rules()[0].ignorecase());
+    let built = ConfigBuilder::new().rule(RuleBuilder::new("error").colour_name("red").ignorecase(true)).build().unwrap();
+    assert_eq!(Colourizer::new(built).colourize_line("ERROR"), "\x1b[31mERROR\x1b[0m");
+
+    let rule = RuleBuilder::new("^b.c$").multiline(true).dotall(true).build().unwrap();
+    assert!(rule.multiline() && rule.dotall());
+    assert_eq!(rule.flagged_regexp(), "(?ms)^b.c$");
+    assert!(rule.compile_regexp().unwrap().is_match("a\nb\nc"));
+}
+
+
+#[test]
+fn test_prefilter_sees_rewritten_lines() {
+    // The second rule only matches what the first one made of the line.
+    let config: Config = "regexp=foo\nreplace=bar\n-\nregexp=bar\ncolours=red\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert!(!colourizer.is_untouched("foo", &StreamState::new()));
+    assert_eq!(colourizer.colourize_line("foo"), "\x1b[31mbar\x1b[0m");
+    assert!(colourizer.is_untouched("nothing here", &StreamState::new()));
+    assert_eq!(colourizer.colourize_line("nothing here"), "nothing here");
+}
+
+
+#[test]
+fn test_untouched_lines() {
+    let config: Config = "regexp=ERROR\ncount=block\ncolours=red\n-\nregexp=END\ncount=unblock\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    let mut state = StreamState::new();
+    assert!(colourizer.is_untouched("quiet", &state));
+    colourizer.process_next_line("ERROR", &mut state, None);
+    // The block colours the lines after it, which no regexp matches.
+    assert!(!colourizer.is_untouched("quiet", &state));
+
+    let config: Config = "prefix={line}: \n".parse().unwrap();
+    assert!(!Colourizer::new(config).is_untouched("quiet", &StreamState::new()));
+}
+
+
+#[test]
+fn test_invalid_regexp_never_matches() {
+    let config: Config = "regexp=a(b\ncolours=red\n-\nregexp=\\d+\ncolours=green\n".parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("a(b 1"), "a(b \x1b[32m1\x1b[0m");
+    assert_eq!(colourizer.matching_rules("a(b 1").map(|rule| rule.index()).collect::<Vec<_>>(), [1]);
+}
+
+
+#[cfg(feature = "fancy")]
+#[test]
+fn test_look_around_and_backreferences() {
+    let config: Config = concat!(
+        "regexp=\\d+(?=\\s*ms)\ncolours=green\n-\n",
+        "regexp=\\b(\\w+) \\1\\b\ncolours=red,bold\n-\n",
+        "regexp=(?<!-)\\bok\\b\nreplace=OK\ncolours=blue\n",
+    ).parse().unwrap();
+    let colourizer = Colourizer::new(config);
+    assert_eq!(colourizer.colourize_line("took 12 ms, 3 s"), "took \x1b[32m12\x1b[0m ms, 3 s");
+    assert_eq!(colourizer.colourize_line("the the end"), "\x1b[1mthe\x1b[0;31m the\x1b[0m end");
+    assert_eq!(colourizer.process_line("ok not-ok").text, "OK not-ok");
+    assert_eq!(colourizer.matching_rules("took 12 ms").map(|rule| rule.index()).collect::<Vec<_>>(), [0]);
+}
+
+
+#[cfg(not(feature = "fancy"))]
+#[test]
+fn test_look_around_needs_fancy() {
+    let config: Config = "regexp=a(?=b)\ncolours=red\n".parse().unwrap();
+    assert_eq!(Colourizer::new(config).colourize_line("ab"), "ab");
+}
+
+
+#[test]
+fn test_strict_parsing_lists_every_problem() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "regexp=\\d+\ncolours=green\nflavour=mild\n-\ncolours=red\nlevels\n-\nregexp=a(b\npriority=high\n").unwrap();
+    // Leniently the usable rules still load.
+    assert_eq!(Config::from_path(&path).unwrap().rules().len(), 3);
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => assert_eq!(problems, [
+            (3, "flavour is not key".to_string()),
+            (5, "the rule has no regexp, so it never matches".to_string()),
+            (6, "expected keyword=value, got levels".to_string()),
+            (8, "a(b is no valid regexp, rule 2 never matches: unclosed group".to_string()),
+            (9, "priority must be a whole number, not high".to_string()),
+        ]),
+        _ => panic!("strict parsing accepted the conffile"),
+    }
+    std::fs::write(&path, "regexp=\\d+\ncolours=green\n-\nprefix={line}: \n").unwrap();
+    assert_eq!(Config::from_path_strict(&path).unwrap().rules().len(), 2);
+}
+
+#[test]
+fn test_raw_escape_colours() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conf.test");
+    std::fs::write(&path, "regexp=warn\ncolours=\"\\033[38;5;208m\"\n-\nregexp=error\ncolours=\\e[1;31m,redd\n").unwrap();
+    let colourizer = Colourizer::new(Config::from_path(&path).unwrap());
+    assert_eq!(colourizer.colourize_line("warn error"), "\x1b[38;5;208mwarn\x1b[0m \x1b[1;31merror\x1b[0m");
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => assert_eq!(problems, [
+            (5, "redd is no colour name, it leaves the text as it is".to_string()),
+        ]),
+        _ => panic!("strict parsing accepted the unknown colour"),
+    }
+}
+
+
+#[test]
+fn test_strip_ansi_streams() {
+    let config: Config = "regexp=ok\ncolours=green\nstripansi=yes\n".parse().unwrap();
+    assert!(config.strips_ansi());
+    let colourizer = Colourizer::new(config);
+    let mut output = vec![];
+    colourizer.process(Cursor::new("\x1b[33mall o\x1b[0mk\x1b[2K\n"), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "all \x1b[32mok\x1b[0m\n");
+    let lines: Vec<String> = colourizer.lines(Cursor::new("\x1b[1mok\x1b[m\n")).map(Result::unwrap).collect();
+    assert_eq!(lines, ["\x1b[32mok\x1b[0m"]);
+}
+
+
+// Found by the colourize_line fuzz target, see fuzz/regressions.
+#[test]
+fn test_empty_colours_paint_plain() {
+    let colourizer = Colourizer::new("regexp=a\ncolours=\n-\ncount=block\ncolours= ,\n".parse().unwrap());
+    assert_eq!(colourizer.colourize_line("a line"), "a line");
+}
+
+
+#[test]
+fn test_config_from_reader_rejects_invalid_utf8() {
+    let err = Config::from_reader(&b"regexp=\xff\ncolours=red\n"[..]).err().unwrap();
+    assert!(matches!(err, ConfigError::Parse { line: 1, .. }));
+}
+
+
+#[test]
+fn test_built_config_matches_parsed_config() {
+    let built = ConfigBuilder::new()
+        .rule(RuleBuilder::new("\\d+").name("number").colour_name("yellow"))
+        .rule(RuleBuilder::new("^\\w+").style(Style { attributes: Attributes::BOLD, ..Style::default() }).tag("head"))
+        .build()
+        .unwrap();
+    let parsed: Config = "name=number\nregexp=\\d+\ncolours=yellow\n-\nregexp=^\\w+\ncolours=bold\ntags=head\n".parse().unwrap();
+
+    assert_eq!(built.rules()[1].index(), 1);
+    assert_eq!(built.rules()[1].tags(), parsed.rules()[1].tags());
+    assert_eq!(built.rules()[0].count(), CountMode::More);
+    let line = "eth0 mtu 1500 qlen 1000";
+    assert_eq!(Colourizer::new(built).colourize_line(line), Colourizer::new(parsed).colourize_line(line));
+}
+
+
+#[test]
+fn test_rule_builder_rejects_invalid_regexp() {
+    let result = RuleBuilder::new("(unclosed").name("broken").build();
+    assert!(matches!(result, Err(ConfigError::InvalidRule { rule, .. }) if rule == "broken"));
+}
+
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_config_json_round_trip() {
+    let config: Config = "regexp=up\ncolours=bold green\ncount=once\nflush=yes\n-\nregexp=down\ncolours=unchanged\nskip=no\n".parse().unwrap();
+    let json = serde_json::to_string(&config).unwrap();
+    assert!(json.contains("\"flush\":true"));
+    assert!(json.contains("\"same_as\":\"\""));
+    let loaded: Config = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+    assert_eq!(loaded.rules()[0].count(), CountMode::Once);
+    assert!(loaded.rules()[0].flush());
+    assert_eq!(loaded.rules()[1].colours(), config.rules()[1].colours());
+    let line = "eth0 up, eth1 down";
+    assert_eq!(Colourizer::new(loaded).colourize_line(line), Colourizer::new(config).colourize_line(line));
+}
+
+
+/// Records the messages of events and the names of spans.
+#[derive(Clone, Default)]
+struct CapturingLayer {
+    records: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+
+struct MessageVisitor<'a>(&'a mut String);
+
+
+impl tracing::field::Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.push_str(&format!("{:?}", value));
+        }
+    }
+}
+
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturingLayer {
+    fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _id: &tracing::span::Id,
+                   _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        self.records.lock().unwrap().push(format!("span {}", attrs.metadata().name()));
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let mut message = String::new();
+        event.record(&mut MessageVisitor(&mut message));
+        self.records.lock().unwrap().push(format!("{} {}", event.metadata().level(), message));
+    }
+}
+
+
+#[test]
+fn test_tracing_events() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let layer = CapturingLayer::default();
+    let subscriber = tracing_subscriber::registry().with(layer.clone())
+        .with(tracing_subscriber::filter::LevelFilter::TRACE);
+    tracing::subscriber::with_default(subscriber, || {
+        let config: Config = "regexp=a\ncolours=red\nflavour=x\n-\nregexp=b\ncount=block\n".parse().unwrap();
+        Colourizer::new(config).colourize_line("a b");
+    });
+    let records = layer.records.lock().unwrap();
+    assert_eq!(&records[..], [
+        "span parse_config",
+        "WARN <string> line 3: flavour is not key",
+        "DEBUG parsed config",
+        "span colourize_line",
+        "TRACE block",
+    ]);
+}
+
+
+#[test]
+fn test_include() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("common")).unwrap();
+    std::fs::write(dir.path().join("common/conf.base"), "regexp=\\d+\ncolours=green\n-\nregexp=ok\ncolours=redd\n").unwrap();
+    std::fs::write(dir.path().join("conf.net"), "include=common/conf.base\n").unwrap();
+    let path = dir.path().join("conf.app");
+    std::fs::write(&path, "regexp=error\ncolours=red\n-\ninclude=conf.net\n-\nregexp=\\w+\ncolours=bold\n").unwrap();
+    let config = Config::from_path(&path).unwrap();
+    let rules: Vec<(&str, usize)> = config.rules().iter().map(|rule| (rule.regexp(), rule.line())).collect();
+    assert_eq!(rules, [("error", 1), ("\\d+", 1), ("ok", 4), ("\\w+", 6)]);
+    assert!(config.rules()[1].source().ends_with("common/conf.base"));
+    match Config::from_path_strict(&path) {
+        Err(ConfigError::Invalid { problems, .. }) => {
+            assert_eq!(problems.len(), 1);
+            assert_eq!(problems[0].0, 4);
+            assert!(problems[0].1.ends_with("common/conf.base line 5: redd is no colour name, it leaves the text as it is"), "{}", problems[0].1);
+        }
+        _ => panic!("strict parsing accepted the unknown colour of the include"),
+    }
+
+    std::fs::write(dir.path().join("conf.net"), "include=conf.app\n").unwrap();
+    let message = Config::from_path(&path).err().expect("the include cycle is refused").to_string();
+    assert!(message.contains("conf.app -> ") && message.contains("conf.net -> ") && message.ends_with("conf.app"), "{}", message);
+
+    std::fs::write(dir.path().join("conf.net"), "include=conf.nowhere\n").unwrap();
+    assert!(Config::from_path(&path).is_err());
+}
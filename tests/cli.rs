@@ -0,0 +1,1952 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+
+fn write_config(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+
+fn run_rgrcat(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    write_input(&mut child, input);
+    child.wait_with_output().unwrap()
+}
+
+
+/// Write `input` to the stdin of `child`, which may exit on a usage error
+/// before reading any of it.
+fn write_input(child: &mut std::process::Child, input: &[u8]) {
+    match child.stdin.take().unwrap().write_all(input) {
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => panic!("writing stdin: {}", err),
+        _ => {}
+    }
+}
+
+
+#[test]
+fn test_record_raw_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    let record = dir.path().join("session.raw");
+    let input = b"an error here   \nnothing\t\n\nlast line without newline";
+
+    let output = run_rgrcat(&["--record", record.to_str().unwrap(), config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&record).unwrap(), input.to_vec());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\x1b[31merror\x1b[0m"));
+
+    // With --record-append a second run extends the file instead of truncating it.
+    let output = run_rgrcat(&["--record", record.to_str().unwrap(), "--record-append", config.to_str().unwrap()], b"more\n");
+    assert!(output.status.success());
+    let mut expected = input.to_vec();
+    expected.extend_from_slice(b"more\n");
+    assert_eq!(fs::read(&record).unwrap(), expected);
+}
+
+
+#[test]
+fn test_record_flushed_like_stdout() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    let record = dir.path().join("session.raw");
+    let spawn = |buffering: &str| Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args([buffering, "--record", record.to_str().unwrap(), config.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Line by line, the record has the line once stdout shows it.
+    let mut child = spawn("--line-buffered");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"an error\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    assert_eq!(line, "an \x1b[31merror\x1b[0m\n");
+    assert_eq!(fs::read(&record).unwrap(), b"an error\n");
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+
+    // In blocks, neither is written before the input ends.
+    let mut child = spawn("--block-buffered");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"an error\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert_eq!(fs::read(&record).unwrap(), b"");
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+    assert_eq!(fs::read(&record).unwrap(), b"an error\n");
+}
+
+
+#[test]
+fn test_matched_file_with_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "regexp=ERROR\ncolours=red\ntags=errors\n-\nregexp=WARN\ncolours=yellow\ntags=warnings\n");
+    let matched = dir.path().join("errors.txt");
+    let input = b"ERROR one\nWARN two\nINFO three\nERROR four\n";
+
+    let output = run_rgrcat(&["--matched-file", matched.to_str().unwrap(), "--matched-tags", "errors", config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&matched).unwrap(), "ERROR one\nERROR four\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 4);
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_matched_file_reopen_on_sighup() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n");
+    let matched = dir.path().join("errors.txt");
+    let rotated = dir.path().join("errors.txt.1");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--matched-file", matched.to_str().unwrap(), config.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+
+    stdin.write_all(b"ERROR before\n").unwrap();
+    stdout.read_line(&mut line).unwrap();
+    fs::rename(&matched, &rotated).unwrap();
+    let status = Command::new("kill").args(["-HUP", &child.id().to_string()]).status().unwrap();
+    assert!(status.success());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    stdin.write_all(b"ERROR after\n").unwrap();
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+
+    assert_eq!(fs::read_to_string(&rotated).unwrap(), "ERROR before\n");
+    assert_eq!(fs::read_to_string(&matched).unwrap(), "ERROR after\n");
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_reload_on_sighup() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .arg(config.to_str().unwrap())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut send = |input: &[u8]| {
+        stdin.write_all(input).unwrap();
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        line
+    };
+    let hang_up = || {
+        assert!(Command::new("kill").args(["-HUP", &child.id().to_string()]).status().unwrap().success());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    };
+
+    assert_eq!(send(b"ERROR one\n"), "\x1b[31mERROR\x1b[0m one\n");
+    fs::write(&config, "regexp=ERROR\ncolours=green\n").unwrap();
+    hang_up();
+    assert_eq!(send(b"ERROR two\n"), "\x1b[32mERROR\x1b[0m two\n");
+    // Broken rules leave those loaded before in place.
+    fs::write(&config, "regexp=(ERROR\ncolours=blue\n").unwrap();
+    hang_up();
+    assert_eq!(send(b"ERROR three\n"), "\x1b[32mERROR\x1b[0m three\n");
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("config reloaded, 1 rules"), "{}", stderr);
+    assert!(stderr.contains("config not reloaded, keeping the rules as they were: "), "{}", stderr);
+}
+
+
+#[test]
+fn test_help_version_and_pass_through() {
+    let output = run_rgrcat(&["--help"], b"");
+    assert!(output.status.success());
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("conffile|--config-json FILE") && help.contains("rgrcat watch"));
+
+    let output = run_rgrcat(&["-V"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("rgrcat {}\n", env!("CARGO_PKG_VERSION")));
+
+    // As the value of an option, or after the conffile, they are no options.
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    write_config(dir.path(), "-h", "an error\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--command", "-V", config.to_str().unwrap(), "-h"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "an \x1b[31merror\x1b[0m\n");
+
+    // Without a conffile piped input comes out unchanged.
+    let output = run_rgrcat(&["--colour", "always"], b"an error\r\nmore\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"an error\r\nmore\n");
+}
+
+
+#[test]
+fn test_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run_rgrcat(&["--colour", "sometimes"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage"));
+
+    let missing = dir.path().join("conf.missing");
+    let output = run_rgrcat(&[missing.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("conf.missing"));
+
+    let broken = dir.path().join("conf.broken");
+    fs::write(&broken, b"regexp=ok\ncolours=\xff\n").unwrap();
+    let output = run_rgrcat(&[broken.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("{} line 2", broken.display())));
+
+    let config = write_config(dir.path(), "conf.test", "regexp=ok\ncolours=red\n");
+    let output = run_rgrcat(&["--record", dir.path().to_str().unwrap(), config.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(dir.path().to_str().unwrap()));
+}
+
+
+#[test]
+fn test_output_file_and_tee() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    let output_file = dir.path().join("build.log.ans");
+    let output_path = output_file.to_str().unwrap();
+    let input = b"an error here\nfine\n";
+
+    let output = run_rgrcat(&["--output", output_path, config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&output_file).unwrap(), "an \x1b[31merror\x1b[0m here\nfine\n");
+
+    let output = run_rgrcat(&["--output", output_path, "--output-tee", "plain", config.to_str().unwrap()], input);
+    assert_eq!(output.stdout, input.to_vec());
+    assert_eq!(fs::read_to_string(&output_file).unwrap(), "an \x1b[31merror\x1b[0m here\nfine\n");
+
+    let output = run_rgrcat(&["--output", output_path, "--output-tee", "coloured", config.to_str().unwrap()], input);
+    assert_eq!(output.stdout, fs::read(&output_file).unwrap());
+
+    let output = run_rgrcat(&["--output", output_path, "--colour", "never", config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&output_file).unwrap(), input.to_vec());
+    // Only the final file is left behind, no temporary files.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+
+#[test]
+fn test_bench_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    let sample = dir.path().join("sample.log");
+    fs::write(&sample, "an error here\nfine\nanother error\n").unwrap();
+
+    let output = run_rgrcat(&["--bench", sample.to_str().unwrap(), "--bench-json", "--bench-iterations", "2",
+                              config.to_str().unwrap()], b"");
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["file"], sample.to_str().unwrap());
+    assert_eq!(report["config"], config.to_str().unwrap());
+    assert_eq!(report["iterations"], 2);
+    assert_eq!(report["lines"], 3);
+    assert_eq!(report["bytes"], 33);
+    for key in ["total_seconds", "lines_per_second", "megabytes_per_second"] {
+        assert!(report[key].is_number(), "{} is not a number", key);
+    }
+}
+
+
+#[test]
+fn test_profile_ranks_slow_rule_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "name=cheap\nregexp=^E\ncolours=red\n-\nname=slow\nregexp=\\w{10,30}Z\ncolours=green\n");
+    let input = "E this line mentions no capital zed at all, qwertyuiop asdfghjkl\n".repeat(20);
+
+    let output = run_rgrcat(&["--profile", config.to_str().unwrap()], input.as_bytes());
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert!(lines[0].starts_with("rule profile"));
+    assert_eq!(lines[1].split_whitespace().collect::<Vec<_>>(),
+               vec!["rank", "rule", "total_ms", "share", "lines", "matches", "avg_us/match", "regexp"]);
+    assert!(lines[2].starts_with("1     1 "), "{}", stderr);
+    assert!(lines[2].contains("slow"));
+    assert!(lines[3].contains("cheap"));
+
+    // A backreference needs fancy-regex, whose backtracking over the nested
+    // repeats costs more than any rule of the regex crate.
+    if cfg!(feature = "fancy") {
+        let config = write_config(dir.path(), "conf.fancy",
+            "name=cheap\nregexp=^E\ncolours=red\n-\nname=slow\nregexp=\\w{10,30}Z\ncolours=green\n-\nname=backtracking\nregexp=(\\w+ ?){1,4}\\1Z\ncolours=blue\n");
+        let output = run_rgrcat(&["--profile", config.to_str().unwrap()], input.as_bytes());
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        assert!(lines[2].starts_with("1     2 "), "{}", stderr);
+        assert!(lines[2].contains("backtracking"));
+    }
+}
+
+#[test]
+fn test_stats() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\n-\nregexp=^#\nskip=yes\n-\nregexp=never\n");
+    let output = run_rgrcat(&["--stats", config.to_str().unwrap()], b"1 and 2
+# 3
+none
+4
+");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"\x1b[32m1\x1b[0m and \x1b[32m2\x1b[0m\nnone\n\x1b[32m4\x1b[0m\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let rows: Vec<Vec<&str>> = stderr.lines().map(|line| line.split_whitespace().collect()).collect();
+    assert_eq!(rows[0], ["rule", "source", "lines", "matches", "time_ms", "regexp"]);
+    assert_eq!((rows[1][1], rows[1][2], rows[1][3]), ("conf.test:1", "2", "3"));
+    assert_eq!((rows[2][2], rows[2][3]), ("1", "1"));
+    // A dead rule stands out.
+    assert_eq!((rows[3][2], rows[3][3], rows[3][5]), ("0", "0", "never"));
+    assert!(stderr.lines().nth(4).unwrap().starts_with("4 lines read, 3 with a match, 1 skipped, in "), "{}", stderr);
+}
+
+
+
+
+#[test]
+fn test_skip_drops_only_matching_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.dig", "regexp=^;;\nskip=yes\n-\nregexp=\\bIN\\b\ncolours=yellow\n");
+    let input = b";; global options: +cmd\nexample.com. 300 IN A 93.184.216.34\n;; Query time: 3 msec\n\n";
+    let output = run_rgrcat(&[config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "example.com. 300 \x1b[33mIN\x1b[0m A 93.184.216.34\n\n");
+}
+
+
+#[test]
+fn test_rules_run_without_colour() {
+    let dir = tempfile::tempdir().unwrap();
+    let coloured = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\n");
+    let skipping = write_config(dir.path(), "conf.skip", "regexp=noise\nskip=yes\n");
+    let input = b"up 42\n";
+
+    for args in [&["--colour", "never"][..], &["--transforms-only"][..]] {
+        let output = run_rgrcat(&[args, &[coloured.to_str().unwrap()]].concat(), input);
+        assert_eq!(output.stdout, input);
+        let output = run_rgrcat(&[args, &[skipping.to_str().unwrap()]].concat(), b"noise\nup 42\n");
+        assert!(output.status.success());
+        assert_eq!(output.stdout, input);
+    }
+    let output_path = dir.path().join("out.txt");
+    let output = run_rgrcat(&["--transforms-only", "--output", output_path.to_str().unwrap(), coloured.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&output_path).unwrap(), input);
+}
+
+
+#[test]
+fn test_plain_copy() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\n");
+    let copy_path = dir.path().join("cleaned.log");
+    let input = b"up 42\ndown 7\n";
+
+    let output = run_rgrcat(&["--plain-copy", copy_path.to_str().unwrap(), config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up \x1b[32m42\x1b[0m\ndown \x1b[32m7\x1b[0m\n");
+    let transformed = run_rgrcat(&["--transforms-only", config.to_str().unwrap()], input);
+    assert_eq!(fs::read(&copy_path).unwrap(), transformed.stdout);
+
+    let output = run_rgrcat(&["--tee", copy_path.to_str().unwrap(), "--append", config.to_str().unwrap()], b"more 1\n");
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&copy_path).unwrap(), "up 42\ndown 7\nmore 1\n");
+}
+
+
+#[test]
+fn test_footer() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", concat!(
+        "name=errors\nregexp=ERROR\ncolours=red\n-\nname=warnings\nregexp=WARN\ncolours=yellow\n-\n",
+        "footer=Processed {lines} lines, {count:errors} errors, {count:warnings} warnings in {elapsed}\ncolours=bold\n-\n",
+        "footer={{done}}\n",
+    ));
+    let config = config.to_str().unwrap();
+    let input = b"ERROR a\nWARN b\nERROR ERROR c\nfine\n";
+
+    // stdout is no terminal here, so only --footer prints it.
+    let output = run_rgrcat(&["--colour", "never", config], input);
+    assert_eq!(output.stdout, input);
+    let output = run_rgrcat(&["--colour", "never", "--footer", config], input);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let footer: Vec<&str> = stdout.lines().skip(4).collect();
+    assert!(footer[0].starts_with("Processed 4 lines, 2 errors, 1 warnings in 0."), "{}", stdout);
+    assert!(footer[0].ends_with('s'));
+    assert_eq!(footer[1], "{done}");
+    let output = run_rgrcat(&["--footer", config], input);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n\x1b[1mProcessed 4 lines"), "{}", stdout);
+    let output = run_rgrcat(&["--colour", "never", "--footer", "--no-footer", config], input);
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_unmatched_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "name=error\nregexp=ERROR\ncolours=red\n-\nname=warning\nregexp=WARN\ncolours=yellow\n");
+
+    let output = run_rgrcat(&["--unmatched-report", config.to_str().unwrap()], b"ERROR one\nfine\n");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines[0], "rules which never matched:");
+    assert!(lines[1].starts_with("  rule 1 warning ("), "{}", stderr);
+    assert!(lines[1].ends_with("conf.test line 5): evaluated on 2 lines, never matched"), "{}", stderr);
+    assert_eq!(lines.len(), 2);
+}
+
+
+#[test]
+fn test_sample_every_nth_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "name=error\nregexp=ERROR\ncolours=red\ntags=important\n-\nname=number\nregexp=\\d+\ncolours=green\n");
+    let config = config.to_str().unwrap();
+    let input = b"1\n2\n3 ERROR\n4\n5\n6\n7\n";
+    let green = |n| format!("\x1b[32m{}\x1b[0m", n);
+
+    let output = run_rgrcat(&["--sample", "3", config], input);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{}\n2\n3 ERROR\n{}\n5\n6\n{}\n", green(1), green(4), green(7)));
+    let output = run_rgrcat(&["--sample", "3", "--sample-matched", config], input);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{}\n2\n{} \x1b[31mERROR\x1b[0m\n{}\n5\n6\n{}\n", green(1), green(3), green(4), green(7)));
+
+    let output = run_rgrcat(&["--sample", "3", "--unmatched-report", config], input);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("sampling: 1 in 3 lines, 4 of 7 lines passed through uncoloured\n"), "{}", stderr);
+    assert_eq!(run_rgrcat(&["--sample", "0", config], input).status.code(), Some(2));
+    assert_eq!(run_rgrcat(&["--sample-matched", config], input).status.code(), Some(2));
+}
+
+#[test]
+fn test_loglevels_without_conffile() {
+    let input = b"ERROR:root:boom\n<4>disk almost full\n[info] started\nplain line\n";
+    let output = run_rgrcat(&["--loglevels", "--colour", "always"], input);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "\x1b[31mERROR:\x1b[0mroot:boom\n\x1b[33m<4>\x1b[0mdisk almost full\n\x1b[32m[info]\x1b[0m started\nplain line\n");
+    let output = run_rgrcat(&["--loglevels=warn+", "--colour", "always", "--except", "error"], input);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "ERROR:root:boom\n\x1b[33m<4>disk almost full\x1b[0m\n[info] started\nplain line\n");
+
+    // Arguments are input files, there's no conffile to name.
+    let dir = tempfile::tempdir().unwrap();
+    let log = write_config(dir.path(), "app.log", "ERROR:root:boom\n");
+    let output = run_rgrcat(&["--loglevels", log.to_str().unwrap()], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR:\x1b[0mroot:boom\n");
+    assert_eq!(run_rgrcat(&["--loglevels=warn"], input).status.code(), Some(2));
+}
+
+#[test]
+fn test_alert_after_quiet_rings_once_per_burst() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\nalert_after_quiet=300s\n");
+    let output = run_rgrcat(&["--colour", "always", config.to_str().unwrap()], b"ERROR a\nok\nERROR b\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR\x1b[0m a\n\x07ok\n\x1b[31mERROR\x1b[0m b\n");
+    let output = run_rgrcat(&["--colour", "never", config.to_str().unwrap()], b"ERROR a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ERROR a\n");
+}
+
+#[test]
+fn test_html_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n");
+    let output = run_rgrcat(&["--format", "html", "--html-dark", config.to_str().unwrap()], b"a < b ERROR\nfine & dandy\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n<body>\n",
+        "<pre style=\"background-color:#000000;color:#e5e5e5\">\n",
+        "a &lt; b <span style=\"color:#cd0000\">ERROR</span>\n",
+        "fine &amp; dandy\n",
+        "</pre>\n</body>\n</html>\n",
+    ));
+
+    let output = run_rgrcat(&["--format", "html", "--colour", "never", config.to_str().unwrap()], b"a < b ERROR\n");
+    assert!(String::from_utf8(output.stdout).unwrap().contains("<pre>\na &lt; b ERROR\n</pre>"));
+    let output = run_rgrcat(&["--format=ansi", config.to_str().unwrap()], b"ERROR\n");
+    assert_eq!(output.stdout, b"\x1b[31mERROR\x1b[0m\n");
+    for args in [&["--html-dark", "conf.test"][..], &["--format", "html", "--wrap", "conf.test"], &["--format", "pdf", "conf.test"]] {
+        assert_eq!(run_rgrcat(args, b"").status.code(), Some(2));
+    }
+}
+
+
+#[test]
+fn test_forced_colour_depth() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=orange\ncolours=#ff8700\n-\nregexp=red\ncolours=colour(196)\n");
+    let run = |args: &[&str], vars: &[(&str, &str)]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .args(["--colour", "always", config.to_str().unwrap()])
+            .env("TERM", "xterm-256color")
+            .env_remove("COLORTERM")
+            .env_remove("GRC_COLOR_DEPTH")
+            .envs(vars.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"orange red\n");
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let truecolor = "\x1b[38;2;255;135;0morange\x1b[0m \x1b[38;5;196mred\x1b[0m\n";
+    let palette = "\x1b[38;5;208morange\x1b[0m \x1b[38;5;196mred\x1b[0m\n";
+    let basic = "\x1b[33morange\x1b[0m \x1b[31;91mred\x1b[0m\n";
+    assert_eq!(run(&[], &[]), palette);
+    assert_eq!(run(&[], &[("COLORTERM", "truecolor")]), truecolor);
+    assert_eq!(run(&["--color-depth", "truecolor"], &[]), truecolor);
+    assert_eq!(run(&["--color-depth", "256"], &[("COLORTERM", "truecolor")]), palette);
+    assert_eq!(run(&["--color-depth", "16"], &[]), basic);
+    assert_eq!(run(&[], &[("GRC_COLOR_DEPTH", "16")]), basic);
+    assert_eq!(run(&["--color-depth=256"], &[("GRC_COLOR_DEPTH", "16")]), palette);
+
+    if cfg!(feature = "serde") {
+        let dump: serde_json::Value = serde_json::from_str(&run(&["--color-depth", "16", "--dump-config"], &[])).unwrap();
+        assert_eq!(dump["rules"][0]["colours"][0]["style"]["fg"]["rgb"], serde_json::json!([255, 135, 0]));
+        assert_eq!(dump["rules"][0]["effective_colours"][0]["style"]["fg"]["named"], "yellow");
+        assert_eq!((&dump["provenance"]["colour_depth"], &dump["provenance"]["colour_depth_from"]), (&"16".into(), &"--color-depth".into()));
+    }
+}
+
+#[test]
+fn test_since_and_until() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "regexp=^\\[([^]]+)\\]\ntimestamp=yes\n-\nregexp=^\\w+ +\\d+ [\\d:]+\ntimestamp=yes\n");
+    let config = config.to_str().unwrap();
+    let input = b"[2024-05-01T10:00:00Z] a\n[2024-05-01 12:30:00 +02:00] b\ncontinued\n[2024-05-01T11:00:00Z] c\n\
+                  May  1 11:30:00 d\n[2999-01-01T00:00:00Z] e\n";
+    let get = |args: &[&str]| {
+        let output = run_rgrcat(&[args, &["--colour", "never", config]].concat(), input);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(get(&["--since", "2024-05-01T10:30Z", "--until", "2024-05-01T11:00Z"]),
+               "[2024-05-01 12:30:00 +02:00] b\ncontinued\n[2024-05-01T11:00:00Z] c\n");
+    assert_eq!(get(&["--since", "2024-05-01T10:30Z", "--until", "2024-05-01T11:00Z", "--strict-time"]),
+               "[2024-05-01 12:30:00 +02:00] b\n[2024-05-01T11:00:00Z] c\n");
+    assert_eq!(get(&["--until", "2024-05-01"]), "continued\n");
+    // Only the line from the far future is younger than a day.
+    assert_eq!(get(&["--since", "-1d"]), "continued\n[2999-01-01T00:00:00Z] e\n");
+    assert_eq!(run_rgrcat(&["--since", "yesterday", config], input).status.code(), Some(2));
+    assert_eq!(run_rgrcat(&["--strict-time", config], input).status.code(), Some(2));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watch_restores_terminal_when_command_crashes() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=up\ncolours=green\n");
+
+    let output = run_rgrcat(&["watch", "-n", "0.1", "--config", config.to_str().unwrap(), "--",
+                              "sh", "-c", "echo up; kill -SEGV $$"], b"");
+    assert_eq!(output.status.code(), Some(128 + 11));
+    let stdout = output.stdout;
+    assert!(stdout.starts_with(b"\x1b[?1049h"));
+    assert!(stdout.ends_with(b"\x1b[0m\x1b[r\x1b[?25h\x1b[?1049l"));
+    // The reset is emitted exactly once.
+    assert_eq!(stdout.windows(8).filter(|window| window == b"\x1b[?1049l").count(), 1);
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_watch_restores_terminal_on_fatal_signal() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=up\ncolours=green\n");
+    let child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["watch", "-n", "10", "--config", config.to_str().unwrap(), "--", "echo", "up"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(Command::new("kill").args(["-HUP", &child.id().to_string()]).status().unwrap().success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.signal(), Some(1));
+    assert!(output.stdout.ends_with(b"\x1b[0m\x1b[r\x1b[?25h\x1b[?1049l"));
+}
+
+
+#[test]
+fn test_rules_tested_on_samples() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=red\n-\nregexp=^#\nskip=yes\n");
+
+    let output = run_rgrcat(&["--test", config.to_str().unwrap(), "took 42 ms"], b"");
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[..5], ["sample: took 42 ms", &format!("rule 0 ({}:1) \\d+: matched", config.display()), "  took 42 ms", "       ^^", "  [5..7] \"42\" -> red"]);
+    assert_eq!(lines[6..], ["result: took \x1b[31m42\x1b[0m ms", "escaped: took \\e[31m42\\e[0m ms"]);
+
+    // Without samples after the conffile they come from stdin.
+    let output = run_rgrcat(&["--test", config.to_str().unwrap()], b"# a comment\n");
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.ends_with(&format!("result: skipped by rule 1 ({}:4) ^#\n", config.display())), "{}", report);
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_pager() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=red\n");
+    let pager = write_config(dir.path(), "pager", "#!/bin/sh\necho \"paged with $1\"\nexec head -n 1\n");
+    fs::set_permissions(&pager, fs::Permissions::from_mode(0o755)).unwrap();
+    let input = write_config(dir.path(), "input", &"line 1\n".repeat(100_000));
+    let run = |pager: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(["--pager", "--colour", "auto", config.to_str().unwrap(), input.to_str().unwrap()])
+            .env("GRC_PAGER", pager)
+            .env("PAGER", "false")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    // Colours stay on for the pager, and it quitting early is no error worth a word.
+    let output = run(&format!("{} -R", pager.display()));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "paged with -R\nline \x1b[31m1\x1b[0m\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    // Without a pager the output goes to stdout as it is.
+    let output = run(&dir.path().join("missing").display().to_string());
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), 100_000 * 7);
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Can not start the pager "));
+}
+
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_dump_config_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test",
+        "name=number\nregexp=\\d+\ncolours=bold bright_red\n-\nregexp=^\\w+\ncolours=on_blue\ntags=head\n");
+    // --dump-config doesn't read stdin, so it must finish with stdin open.
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--dump-config", "--format", "json", config.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // Space-separated names make up one colour.
+    assert_eq!(json["rules"][0]["colours"].as_array().unwrap().len(), 1);
+    assert_eq!(json["rules"][0]["colours"][0]["style"]["fg"]["named"], "bright_red");
+    assert_eq!(json["rules"][0]["effective_escapes"], serde_json::json!(["\x1b[1;31;91m"]));
+    assert_eq!(json["rules"][0]["line"], 1);
+    assert!(json["rules"][1]["source"].as_str().unwrap().ends_with("conf.test"));
+    // The same conffile dumps the same bytes, for golden files.
+    let again = run_rgrcat(&["--dump-config", config.to_str().unwrap()], b"");
+    assert_eq!(again.stdout, output.stdout);
+
+    let dumped = dir.path().join("conf.json");
+    fs::write(&dumped, &output.stdout).unwrap();
+    let input = b"eth0 mtu 1500\nlo 65536\n";
+    let from_conffile = run_rgrcat(&[config.to_str().unwrap()], input);
+    let from_json = run_rgrcat(&["--config-json", dumped.to_str().unwrap()], input);
+    assert!(from_json.status.success());
+    assert_eq!(from_json.stdout, from_conffile.stdout);
+
+    fs::write(&dumped, "{\"rules\": [\n  {\"count\": \"sometimes\"}\n]}").unwrap();
+    let output = run_rgrcat(&["--config-json", dumped.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(4));
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_client_through_daemon() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error\ncolours=red\n");
+    let socket = dir.path().join("rgrcat.sock");
+    let input = b"an error here\nnothing\n";
+    let expected = run_rgrcat(&[config.to_str().unwrap()], input).stdout;
+
+    // Without a daemon the client falls back to colouring in-process.
+    let output = run_rgrcat(&["--client", "--socket", socket.to_str().unwrap(), config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["daemon", "--socket", socket.to_str().unwrap()])
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if socket.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let output = run_rgrcat(&["--client", "--socket", socket.to_str().unwrap(), config.to_str().unwrap()], input);
+    let missing = run_rgrcat(&["--client", "--socket", socket.to_str().unwrap(), "/no/such/conffile"], input);
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+    assert_eq!(missing.status.code(), Some(3));
+}
+
+
+#[test]
+fn test_self_test_golden_files() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let output = run_rgrcat(&["self-test", golden.to_str().unwrap()], b"");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path(), "log.conf", "regexp=ERROR\ncolours=red\n");
+    write_config(dir.path(), "log.input", "an ERROR\n");
+    write_config(dir.path(), "log.expected", "an \x1b[32mERROR\x1b[0m\n");
+    let output = run_rgrcat(&["self-test", dir.path().to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("-    1 | an {green}ERROR{reset}\n+    1 | an {red}ERROR{reset}\n"), "{}", report);
+
+    let output = run_rgrcat(&["self-test", "--bless", dir.path().to_str().unwrap()], b"");
+    assert!(output.status.success());
+    assert_eq!(fs::read(dir.path().join("log.expected")).unwrap(), b"an \x1b[31mERROR\x1b[0m\n");
+}
+
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_emit_json_spans() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=bold\n-\nregexp=\\d+\ncolours=green\n");
+    let input = "a \"quoted\" ERROR\t\x01 in 42\nplain\n";
+    let output = run_rgrcat(&["--emit", "json-spans", config.to_str().unwrap()], input.as_bytes());
+    assert!(output.status.success());
+    let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout).unwrap().lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["text"], "a \"quoted\" ERROR\t\x01 in 42");
+    assert_eq!(lines[0]["spans"], serde_json::json!([
+        { "start": 11, "end": 16, "rule": 0, "fg": null, "bg": null, "attrs": ["bold"] },
+        { "start": 22, "end": 24, "rule": 1, "fg": { "named": "green" }, "bg": null, "attrs": [] },
+    ]));
+    assert_eq!(lines[1]["spans"], serde_json::json!([]));
+    assert!(lines[0].get("ansi").is_none());
+
+    let output = run_rgrcat(&["--emit", "jsonl-both", config.to_str().unwrap()], b"42\n");
+    let line: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(line["ansi"], "\x1b[32m42\x1b[0m");
+}
+
+
+#[cfg(feature = "normalize")]
+#[test]
+fn test_normalize_nfd_input() {
+    let dir = tempfile::tempdir().unwrap();
+    // The rule is in NFC, the input decomposes the Ü into U and a diaeresis.
+    let config = write_config(dir.path(), "conf.test", "regexp=\u{dc}berwachung\ncolours=red\n");
+    let input = "U\u{308}berwachung l\u{e4}uft\n";
+
+    let output = run_rgrcat(&["--normalize", "none", config.to_str().unwrap()], input.as_bytes());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), input);
+
+    let output = run_rgrcat(&["--normalize", "nfc", config.to_str().unwrap()], input.as_bytes());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31m\u{dc}berwachung\x1b[0m l\u{e4}uft\n");
+}
+
+
+#[test]
+fn test_flush_after_matching_line() {
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ALERT\ncolours=red\nflush=yes\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--colour", "never", config.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdout.lines() {
+            sender.send(line.unwrap()).unwrap();
+        }
+    });
+
+    // Stdin stays open, so only the flush can get the line out.
+    stdin.write_all(b"noise\nALERT disk full\n").unwrap();
+    stdin.flush().unwrap();
+    let timeout = std::time::Duration::from_secs(5);
+    loop {
+        match receiver.recv_timeout(timeout) {
+            Ok(line) if line == "ALERT disk full" => break,
+            Ok(_) => continue,
+            Err(_) => panic!("the matching line didn't arrive before stdin closed")
+        }
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_disabled_rule() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "name=error\nregexp=ERROR\ncolours=red\nenabled=no\n-\nname=number\nregexp=\\d+\ncolours=green\n");
+    let config = config.to_str().unwrap();
+
+    let output = run_rgrcat(&[config], b"ERROR 42\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ERROR \x1b[32m42\x1b[0m\n");
+
+    let output = run_rgrcat(&["--dump-config", config], b"");
+    let dump: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(dump["rules"][0]["name"], "error");
+    assert_eq!(dump["rules"][0]["enabled"], false);
+    assert_eq!(dump["rules"][1]["enabled"], true);
+
+    // Naming the rule with --rules enables it for the run.
+    let output = run_rgrcat(&["--rules", "error", config], b"ERROR 42\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR\x1b[0m 42\n");
+    let output = run_rgrcat(&["--except", "1", config], b"ERROR 42\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ERROR 42\n");
+}
+
+
+
+#[test]
+fn test_command_rules() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.mount", "regexp=/dev/\\w+\ncolours=green\n-\nregexp=\\(\\w+\\)\ncolours=yellow\ncommand=^mount\\b\n");
+    let config = config.to_str().unwrap();
+    let run_with_command = |command: Option<&str>, args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"));
+        child.args(args).env_remove("GRC_COMMAND").env_remove("GRCAT_OPTIONS");
+        if let Some(command) = command {
+            child.env("GRC_COMMAND", command);
+        }
+        let mut child = child.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        write_input(&mut child, b"/dev/sda1 on / (rw)\n");
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+    let all = "\x1b[32m/dev/sda1\x1b[0m on / \x1b[33m(rw)\x1b[0m\n";
+    let unguarded = "\x1b[32m/dev/sda1\x1b[0m on / (rw)\n";
+    assert_eq!(run_with_command(None, &["--command", "mount -l", config]), all);
+    assert_eq!(run_with_command(Some("mount"), &[config]), all);
+    assert_eq!(run_with_command(Some("mount"), &["--command", "findmnt", config]), unguarded);
+    assert_eq!(run_with_command(None, &[config]), unguarded);
+}
+
+#[test]
+fn test_strict_refuses_conffile_with_problems() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\nflavour=mild\n");
+    let config = config.to_str().unwrap();
+    let output = run_rgrcat(&[config], b"42\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[32m42\x1b[0m\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("{} line 3: flavour is not key\n", config));
+    let output = run_rgrcat(&["--strict", config], b"42\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("error in configuration {}:\n  line 3: flavour is not key\n", config));
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn test_debug_traces_rules_on_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=red\n-\nregexp=secret\nskip=yes\n");
+    let config = config.to_str().unwrap();
+    let output = run_rgrcat(&["--debug", config], b"a 42\nnone\nsecret\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a \x1b[31m42\x1b[0m\nnone\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("line 1: rule 0 ({}:1) [2..4] -> red\nline 2: no match\nline 3: skipped\n", config));
+}
+
+#[test]
+fn test_several_conffiles() {
+    let dir = tempfile::tempdir().unwrap();
+    let stock = write_config(dir.path(), "conf.stock", "regexp=ERROR\ncolours=red\ncount=stop\n");
+    let mine = write_config(dir.path(), "conf.mine", "regexp=\\d+\ncolours=green\n");
+    let both = format!("{}:{}", stock.to_str().unwrap(), mine.to_str().unwrap());
+    let output = run_rgrcat(&[&both], b"ERROR 1\nwarning 2\n");
+    // The stop of the first conffile keeps the rules of the second off the line.
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR\x1b[0m 1\nwarning \x1b[32m2\x1b[0m\n");
+
+    let output = run_rgrcat(&[&format!("{}:conf.missing", stock.to_str().unwrap())], b"ERROR 1\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_invalid_regexp_never_panics() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\n-\nregexp=(?P<n>[a-\ncolours=red\n");
+    let config = config.to_str().unwrap();
+    let output = run_rgrcat(&[config], b"abc 42\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "abc \x1b[32m42\x1b[0m\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               format!("{} line 4: (?P<n>[a- is no valid regexp, rule 1 never matches: unclosed character class\n", config));
+    // Strictly it's refused before any input is read.
+    let output = run_rgrcat(&["--strict", config], b"abc 42\n");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_colour_overrides() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=warn\ncolours=yellow\n-\nregexp=\\d+\ncolours=bold white\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .arg(config.to_str().unwrap())
+        .env("GRC_COLOURS", "yellow=bold blue,white=black,flavour=red")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    write_input(&mut child, b"warn 42\n");
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[1;34mwarn\x1b[0m \x1b[1;30m42\x1b[0m\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "GRC_COLOURS: flavour is no colour name to remap\n");
+}
+
+#[test]
+fn test_strip_ansi() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.diff", "regexp=^\\+.*\ncolours=green\n-\nregexp=^-.*\ncolours=red\n");
+    let config = config.to_str().unwrap();
+    // As git diff --color writes it.
+    let diff = b"\x1b[1mdiff --git a/x b/x\x1b[m\n\x1b[36m@@ -1 +1 @@\x1b[m\n\x1b[31m-old\x1b[m\n\x1b[32m+new\x1b[m\x1b[K\n";
+    let expected = "diff --git a/x b/x\n@@ -1 +1 @@\n\x1b[31m-old\x1b[0m\n\x1b[32m+new\x1b[0m\n";
+    assert_eq!(String::from_utf8(run_rgrcat(&["--strip-ansi", config], diff).stdout).unwrap(), expected);
+
+    // The conffile can ask for it as well.
+    let config = write_config(dir.path(), "conf.strip", "stripansi=yes\nregexp=^\\+.*\ncolours=green\n-\nregexp=^-.*\ncolours=red\n");
+    assert_eq!(String::from_utf8(run_rgrcat(&[config.to_str().unwrap()], diff).stdout).unwrap(), expected);
+}
+
+#[test]
+fn test_overlong_line_passes_through() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=x+|ok\ncolours=red\n");
+    // Minified JSON dumped on one line, 64 MB of it.
+    let mut input = b"ok\n".to_vec();
+    input.extend(std::iter::repeat_n(b'x', 64 * 1024 * 1024));
+    input.extend_from_slice(b"\nok\n");
+    let path = dir.path().join("dump.json");
+    fs::write(&path, &input).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--colour", "always", config.to_str().unwrap(), path.to_str().unwrap()])
+        .env_remove("GRCAT_OPTIONS")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let coloured = b"\x1b[31mok\x1b[0m\n";
+    assert_eq!(&output.stdout[..coloured.len()], coloured);
+    assert!(output.stdout[coloured.len()..output.stdout.len() - coloured.len()] == input[3..input.len() - 3], "the long line changed");
+    assert_eq!(&output.stdout[output.stdout.len() - coloured.len()..], coloured);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "line 2 is longer than 4194304 bytes, passed through uncoloured\n");
+}
+
+#[test]
+fn test_rules_without_a_conffile() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.log");
+    fs::write(&input, "ERROR 42\n").unwrap();
+    let input = input.to_str().unwrap();
+    // The conffile from stdin, the input from a file.
+    let output = run_rgrcat(&["-", input], b"regexp=ERROR\ncolours=bold red\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[1;31mERROR\x1b[0m 42\n");
+    assert_eq!(run_rgrcat(&["-"], b"regexp=ERROR\n").status.code(), Some(2));
+    assert_eq!(run_rgrcat(&["-", "-"], b"regexp=ERROR\n").status.code(), Some(2));
+
+    let output = run_rgrcat(&["--inline-rules", "regexp=ERROR\\ncolours=red\n-\nregexp=\\d+\ncolours=cyan"], b"ERROR 42\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR\x1b[0m \x1b[36m42\x1b[0m\n");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .env("GRC_RULES", "regexp=\\d+\\ncolours=cyan")
+        .env_remove("GRC_CONF")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    write_input(&mut child, b"ERROR 42\n");
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ERROR \x1b[36m42\x1b[0m\n");
+}
+
+#[test]
+fn test_check_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let good = write_config(dir.path(), "conf.good", "regexp=\\d+\ncolours=bold red,green\ncount=once\n-\nregexp=secret\nreplace=***\nskip=yes\n");
+    let good = good.to_str().unwrap();
+    let output = run_rgrcat(&["--check-config", good], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}: 2 rules\n\
+        rule 0 (line 1): regexp=\\d+ colours=bold red,green count=once\n\
+        rule 1 (line 5): regexp=secret colours=default count=more skip=yes replace=***\n", good));
+
+    let bad = write_config(dir.path(), "conf.bad", "regexp=(\\d+\ncolours=green\nflavour=mild\n-\ncolours=purplish\n");
+    let bad = bad.to_str().unwrap();
+    let output = run_rgrcat(&["--check-config", bad], b"");
+    assert_eq!(output.status.code(), Some(1));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.ends_with("4 problems:\n\
+        line 1: (\\d+ is no valid regexp, rule 0 never matches: unclosed group\n\
+        line 3: flavour is not key\n\
+        line 5: purplish is no colour name, it leaves the text as it is\n\
+        line 5: the rule has no regexp, so it never matches\n"), "{}", report);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_command_picks_config_from_grc_conf() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path(), "grc.conf", "# ping\n\n(^|[/\\w\\.]+/)ping\\s\nconf.ping\n# ping6 too\n^ping6\\s\nconf.ping\n");
+    write_config(dir.path(), "conf.ping", "regexp=\\d+ ms\ncolours=green\n");
+    let run_with_command = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .env("HOME", dir.path())
+            .env("GRC_CONFIG_PATH", dir.path())
+            .env_remove("GRC_CONF")
+            .env_remove("GRC_COMMAND")
+            .env_remove("GRCAT_OPTIONS")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"time 12 ms\n");
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+    assert_eq!(run_with_command(&["--command", "ping 8.8.8.8"]), "time \x1b[32m12 ms\x1b[0m\n");
+    assert_eq!(run_with_command(&["--command", "ping6 ::1"]), "time \x1b[32m12 ms\x1b[0m\n");
+    // Without an entry for the command the input passes through.
+    assert_eq!(run_with_command(&["--command", "true"]), "time 12 ms\n");
+}
+
+
+#[test]
+fn test_config_from_grc_conf_variable() {
+    let dir = tempfile::tempdir().unwrap();
+    let from_env = write_config(dir.path(), "conf.env", "regexp=\\d+\ncolours=red\n");
+    let from_arg = write_config(dir.path(), "conf.arg", "regexp=\\d+\ncolours=green\n");
+    let run_with_env = |vars: &[(&str, &str)], args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .env_remove("GRC_CONF")
+            .env_remove("GRCAT_OPTIONS")
+            .envs(vars.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"up 42\n");
+        child.wait_with_output().unwrap()
+    };
+    let from_env = from_env.to_str().unwrap();
+    let from_arg = from_arg.to_str().unwrap();
+
+    let output = run_with_env(&[("GRC_CONF", from_env)], &[]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up \x1b[31m42\x1b[0m\n");
+    // An argument wins over the variable.
+    let output = run_with_env(&[("GRC_CONF", from_env)], &[from_arg]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up \x1b[32m42\x1b[0m\n");
+    let output = run_with_env(&[("GRC_CONF", from_env), ("GRCAT_OPTIONS", "--colour never")], &[]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up 42\n");
+    // The command line wins over GRCAT_OPTIONS, which can't name the conffile.
+    let output = run_with_env(&[("GRC_CONF", from_env), ("GRCAT_OPTIONS", "--colour never")], &["--colour", "always"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up \x1b[31m42\x1b[0m\n");
+    let output = run_with_env(&[("GRCAT_OPTIONS", from_arg)], &[]);
+    assert_eq!(output.status.code(), Some(2));
+    // Without either the input passes through as it is.
+    let output = run_with_env(&[], &[]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "up 42\n");
+
+    if cfg!(feature = "serde") {
+        let output = run_with_env(&[("GRC_CONF", from_env)], &["--dump-config"]);
+        let dump: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(dump["provenance"]["selected_by"], "GRC_CONF");
+        assert_eq!(dump["provenance"]["path"], from_env);
+        let output = run_with_env(&[("GRC_CONF", from_env)], &["--dump-config", from_arg]);
+        let dump: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(dump["provenance"]["selected_by"], "argument");
+        assert_eq!(dump["provenance"]["conffile"], from_arg);
+    }
+}
+
+#[test]
+fn test_config_search_path() {
+    let dir = tempfile::tempdir().unwrap();
+    for (sub_dir, colour) in [("home/.grc", "red"), ("a", "green"), ("b", "yellow")] {
+        std::fs::create_dir_all(dir.path().join(sub_dir)).unwrap();
+        write_config(&dir.path().join(sub_dir), "conf.test", &format!("regexp=\\d+\ncolours={}\n", colour));
+    }
+    let run_with_path = |config_path: Option<&str>, args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"));
+        child.args(args).arg("conf.test")
+            .current_dir(dir.path())
+            .env("HOME", dir.path().join("home"))
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("GRC_CONFIG_PATH")
+            .env_remove("GRCAT_OPTIONS");
+        if let Some(config_path) = config_path {
+            child.env("GRC_CONFIG_PATH", config_path);
+        }
+        let mut child = child.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        write_input(&mut child, b"42\n");
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+
+    assert_eq!(run_with_path(None, &[]), "\x1b[31m42\x1b[0m\n");
+    // Missing directories are skipped, relative ones found from the current one.
+    assert_eq!(run_with_path(Some("missing:a:b"), &[]), "\x1b[32m42\x1b[0m\n");
+    assert_eq!(run_with_path(Some(&format!("{}:a", dir.path().join("b").display())), &[]), "\x1b[33m42\x1b[0m\n");
+    assert_eq!(run_with_path(Some("b"), &["--config-dir", "a"]), "\x1b[32m42\x1b[0m\n");
+    assert_eq!(run_with_path(None, &["--config-dir=missing", "--config-dir", "b"]), "\x1b[33m42\x1b[0m\n");
+}
+
+#[test]
+fn test_xdg_base_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let places = [("config/grc", "red"), ("data/grc", "green"), ("home/.grc", "yellow"), ("first/grc", "blue"), ("second/grc", "magenta")];
+    for (place, colour) in places {
+        std::fs::create_dir_all(root.join(place)).unwrap();
+        write_config(&root.join(place), "conf.test", &format!("regexp=\\d+\ncolours={}\n", colour));
+    }
+    let run = || {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .arg("conf.test")
+            .env("HOME", root.join("home"))
+            .env("XDG_CONFIG_HOME", root.join("config"))
+            .env("XDG_DATA_HOME", root.join("data"))
+            .env("XDG_DATA_DIRS", format!("{}:{}", root.join("first").display(), root.join("second").display()))
+            .env_remove("GRC_CONFIG_PATH")
+            .env_remove("GRCAT_OPTIONS")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"42\n");
+        child.wait_with_output().unwrap()
+    };
+
+    // Each place wins over those after it.
+    let expected = ["\x1b[31m42\x1b[0m\n", "\x1b[32m42\x1b[0m\n", "\x1b[33m42\x1b[0m\n", "\x1b[34m42\x1b[0m\n", "\x1b[35m42\x1b[0m\n"];
+    for (index, (place, _)) in places.iter().enumerate() {
+        assert_eq!(String::from_utf8(run().stdout).unwrap(), expected[index], "{}", place);
+        std::fs::remove_file(root.join(place).join("conf.test")).unwrap();
+    }
+    let stderr = String::from_utf8(run().stderr).unwrap();
+    let tried: Vec<String> = places.iter().map(|(place, _)| root.join(place).join("conf.test").display().to_string()).collect();
+    assert!(stderr.contains(&tried.join(", ")), "{}", stderr);
+}
+
+#[test]
+fn test_without_home() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=red\n");
+    let run = |config_name: &str, config_path: &Path| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .arg(config_name)
+            .env_clear()
+            .env("GRC_CONFIG_PATH", config_path)
+            .env("XDG_DATA_DIRS", dir.path().join("nowhere"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"42\n");
+        child.wait_with_output().unwrap()
+    };
+
+    // Without HOME the conffile is still found in GRC_CONFIG_PATH.
+    assert_eq!(String::from_utf8(run("test", dir.path()).stdout).unwrap(), "\x1b[31m42\x1b[0m\n");
+    // An absolute path needs no search path at all.
+    let other = tempfile::tempdir().unwrap();
+    assert_eq!(String::from_utf8(run(config.to_str().unwrap(), other.path()).stdout).unwrap(), "\x1b[31m42\x1b[0m\n");
+    let output = run("conf.missing", dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("config file [conf.missing] not found after searching 2 directories, tried: "), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn test_embedded_conffiles() {
+    let dir = tempfile::tempdir().unwrap();
+    let home = dir.path();
+    let run = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .env("HOME", home)
+            .env("XDG_DATA_DIRS", home.join("share"))
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("GRC_CONFIG_PATH")
+            .env_remove("GRCAT_OPTIONS")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.045 ms\n");
+        child.wait_with_output().unwrap()
+    };
+
+    let listed = String::from_utf8(run(&["--list-embedded"]).stdout).unwrap();
+    assert!(listed.lines().any(|name| name == "conf.ping"), "{}", listed);
+
+    // Without any conffile on disk the built-in one colours.
+    let output = run(&["conf.ping"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("\x1b[32mtime=0.045 ms\x1b[0m"));
+    // The command name finds it as well.
+    assert!(String::from_utf8(run(&["ping"]).stdout).unwrap().contains("\x1b[32mtime=0.045 ms\x1b[0m"));
+
+    // One on disk always wins.
+    fs::create_dir(home.join(".grc")).unwrap();
+    write_config(&home.join(".grc"), "conf.ping", "regexp=ttl\ncolours=red\n");
+    assert_eq!(String::from_utf8(run(&["conf.ping"]).stdout).unwrap(), "64 bytes from 10.0.0.1: icmp_seq=1 \x1b[31mttl\x1b[0m=64 time=0.045 ms\n");
+    assert_eq!(String::from_utf8(run(&["ping"]).stdout).unwrap(), "64 bytes from 10.0.0.1: icmp_seq=1 \x1b[31mttl\x1b[0m=64 time=0.045 ms\n");
+    assert_eq!(run(&["conf.missing"]).status.code(), Some(3));
+}
+
+#[test]
+fn test_follow_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=^BEGIN\ncolours=red\ncount=block\n-\nregexp=^END\ncolours=red\ncount=unblock\n");
+    let log = write_config(dir.path(), "app.log", "BEGIN\n");
+    let child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--colour", "always", "-f", config.to_str().unwrap(), log.to_str().unwrap()])
+        .env_remove("GRCAT_OPTIONS")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let append = |text: &[u8]| {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        fs::OpenOptions::new().append(true).open(&log).unwrap().write_all(text).unwrap();
+    };
+    // The block goes on across the wait, and a partial line waits for the rest.
+    append(b"inside\nEN");
+    append(b"D\nafter\n");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    fs::write(&log, "BEGIN\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap().success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "\x1b[31mBEGIN\x1b[0m\n\x1b[31minside\x1b[0m\nEND\nafter\n\x1b[31mBEGIN\x1b[0m\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("app.log: file truncated"));
+
+    let output = run_rgrcat(&["-f", config.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_list_conffiles() {
+    let dir = tempfile::tempdir().unwrap();
+    let home = dir.path();
+    for place in ["first", "second", ".grc"] {
+        fs::create_dir(home.join(place)).unwrap();
+    }
+    write_config(&home.join("first"), "conf.mine", "regexp=a\ncolours=red\n-\nregexp=(\ncolours=red\n");
+    write_config(&home.join("second"), "conf.mine", "regexp=b\ncolours=red\n");
+    write_config(&home.join("second"), "notes.txt", "not a conffile");
+    write_config(&home.join(".grc"), "conf.ping", "regexp=ttl\ncolours=red\n");
+    let list = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .env("HOME", home)
+            .env("XDG_DATA_DIRS", home.join("missing"))
+            .env("GRC_CONFIG_PATH", format!("{}:{}", home.join("first").display(), home.join("second").display()))
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("GRCAT_OPTIONS")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let listing = list(&["--list"]);
+    let lines: Vec<&str> = listing.lines().collect();
+    let mine = home.join("first/conf.mine").display().to_string();
+    let shadowed = format!("conf.mine\t{}\tshadowed", home.join("second/conf.mine").display());
+    let ping = format!("conf.ping\t{}", home.join(".grc/conf.ping").display());
+    assert_eq!(lines[..4], ["conf.df\tembedded:conf.df", "conf.gcc\tembedded:conf.gcc", "conf.log\tembedded:conf.log", &format!("conf.mine\t{}", mine)]);
+    assert_eq!(lines[4..7], [&shadowed, &ping, "conf.ping\tembedded:conf.ping\tshadowed"]);
+    assert!(!listing.contains("notes.txt"));
+
+    let listing = list(&["--list", "--verbose"]);
+    assert!(listing.contains(&format!("conf.mine\t{}\t2 rules, 1 problems\n", mine)), "{}", listing);
+    assert!(listing.contains(&format!("{}\t1 rules\n", ping)), "{}", listing);
+}
+
+#[test]
+fn test_no_color_and_clicolor_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.env", "regexp=noise\nskip=yes\n-\nregexp=(\\d+)s\nreplace=\\1 seconds\ncolours=red\n");
+    let config = config.to_str().unwrap();
+    let run_with_env = |vars: &[(&str, &str)], args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(args)
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .env_remove("GRCAT_OPTIONS")
+            .envs(vars.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        write_input(&mut child, b"noise\ntook 5s\n");
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+
+    let coloured = "took \x1b[31m5 seconds\x1b[0m\n";
+    assert_eq!(run_with_env(&[], &[config]), coloured);
+    // Without colour lines still get skipped and rewritten.
+    assert_eq!(run_with_env(&[("NO_COLOR", "1")], &[config]), "took 5 seconds\n");
+    assert_eq!(run_with_env(&[("NO_COLOR", "")], &[config]), coloured);
+    assert_eq!(run_with_env(&[("NO_COLOR", "1")], &["--colour", "always", config]), coloured);
+    assert_eq!(run_with_env(&[("CLICOLOR_FORCE", "1")], &["--colour", "auto", config]), "took 5 seconds\n");
+    assert_eq!(run_with_env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")], &[config]), coloured);
+    assert_eq!(run_with_env(&[("CLICOLOR_FORCE", "0"), ("NO_COLOR", "1")], &[config]), "took 5 seconds\n");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_dump_config_in_priority_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "name=first\nregexp=a\n-\nname=second\nregexp=b\npriority=-5\n");
+    let output = run_rgrcat(&["--dump-config", config.to_str().unwrap()], b"");
+    let dump: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(dump["rules"][0]["name"], "second");
+    assert_eq!(dump["rules"][0]["priority"], -5);
+    assert_eq!(dump["rules"][1]["name"], "first");
+    assert_eq!(dump["rules"][1]["index"], 1);
+}
+
+
+#[test]
+fn test_colours_light_by_colorfgbg() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=WARN\ncolours=yellow\ncolours_light=blue\n");
+    let run_with_colorfgbg = |value: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .arg(&config)
+            .env("COLORFGBG", value)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"WARN\n").unwrap();
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+    assert_eq!(run_with_colorfgbg("0;15"), "\x1b[34mWARN\x1b[0m\n");
+    assert_eq!(run_with_colorfgbg("15;0"), "\x1b[33mWARN\x1b[0m\n");
+}
+
+
+#[test]
+fn test_map_files_to_configs() {
+    let dir = tempfile::tempdir().unwrap();
+    let nginx = write_config(dir.path(), "conf.nginx", "regexp=GET\ncolours=green\n");
+    let dmesg = write_config(dir.path(), "conf.dmesg", "regexp=usb\ncolours=red\n");
+    let access = write_config(dir.path(), "site.access.log", "GET /usb\n");
+    let kernel = write_config(dir.path(), "dmesg.txt", "GET usb\n");
+    let other = write_config(dir.path(), "notes.txt", "GET usb\n");
+    let map_nginx = format!("*.access.log={}", nginx.display());
+    let map_dmesg = format!("dmesg*={}", dmesg.display());
+    let files = [access.to_str().unwrap(), kernel.to_str().unwrap(), other.to_str().unwrap()];
+
+    let mut args = vec!["--no-filename", "--map", &map_nginx, "--map", &map_dmesg];
+    args.extend_from_slice(&files);
+    let output = run_rgrcat(&args, b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+        "\x1b[32mGET\x1b[0m /usb\n",
+        "GET \x1b[31musb\x1b[0m\n",
+        "GET usb\n",
+    ));
+
+    let mut args = vec!["--no-filename", "--map", &map_nginx, "--default-config", dmesg.to_str().unwrap()];
+    args.extend_from_slice(&files[1..]);
+    let output = run_rgrcat(&args, b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "GET \x1b[31musb\x1b[0m\nGET \x1b[31musb\x1b[0m\n");
+}
+
+
+
+#[test]
+fn test_bytes_that_are_not_utf8_pass_through() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n-\nregexp=caf.\ncolours=green\n");
+    let input = b"ERROR in caf\xe9 \xff\xfe\nERROR again\n";
+    let output = run_rgrcat(&[config.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"\x1b[31mERROR\x1b[0m in \x1b[32mcaf\xe9\x1b[0m \xff\xfe\n\x1b[31mERROR\x1b[0m again\n");
+    let output = run_rgrcat(&["--colour", "never", config.to_str().unwrap()], input);
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_buffered_output_is_complete() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=7$\ncolours=red\n");
+    let input: String = (0..100_000).map(|line| format!("line {}\n", line)).collect();
+
+    // Stdout is a pipe here, so the lines come in blocks, all of them by the
+    // end. The output outgrows the pipe, so it's read while the input goes in.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--colour", "never", config.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()).map(|_| input));
+    let output = child.wait_with_output().unwrap();
+    let input = writer.join().unwrap().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), input);
+}
+
+
+#[test]
+fn test_line_and_block_buffered() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n");
+
+    // Every line comes out while the input is still open, as under tail -f.
+    for (flag, flush) in [("--line-buffered", None), ("--colour=always", Some("1"))] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args([flag, config.to_str().unwrap()])
+            .envs(flush.map(|flush| ("GRC_FLUSH", flush)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        for (input, expected) in [("ERROR one\n", "\x1b[31mERROR\x1b[0m one\n"), ("fine\n", "fine\n")] {
+            stdin.write_all(input.as_bytes()).unwrap();
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            assert_eq!(line, expected);
+        }
+        drop(stdin);
+        assert!(child.wait().unwrap().success());
+    }
+
+    let output = run_rgrcat(&["--block-buffered", "--colour", "never", config.to_str().unwrap()], b"ERROR one\nfine\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"ERROR one\nfine\n");
+}
+
+
+#[test]
+fn test_closed_output_exits_quietly() {
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=7$\ncolours=red\n");
+    let input = write_config(dir.path(), "input.log", &(0..200_000).map(|line| format!("line {}\n", line)).collect::<String>());
+
+    // Like `| head -n 5`: read a few lines, then close the pipe.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--footer", config.to_str().unwrap()])
+        .stdin(fs::File::open(input).unwrap())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut head = [0; 32];
+    child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(141));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+
+    // The report of self-test, more than the pipe takes.
+    let golden = dir.path().join("golden");
+    fs::create_dir(&golden).unwrap();
+    for index in 0..4000 {
+        write_config(&golden, &format!("triple_{:04}.conf", index), "regexp=x\ncolours=red\n");
+        write_config(&golden, &format!("triple_{:04}.input", index), "a\n");
+        write_config(&golden, &format!("triple_{:04}.expected", index), "a\n");
+    }
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["self-test", golden.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(141));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
+
+
+#[test]
+fn test_trailing_whitespace_is_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+%.*\ncolours=red\n");
+    let input = b"/dev/sda1  42%   \t\n/dev/sda2  plain   \t\n";
+    let output = run_rgrcat(&[config.to_str().unwrap()], input);
+    assert_eq!(output.stdout, b"/dev/sda1  \x1b[31m42%   \t\x1b[0m\n/dev/sda2  plain   \t\n");
+    let output = run_rgrcat(&["--colour", "never", config.to_str().unwrap()], input);
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_line_endings_are_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+%\ncolours=green\n");
+    let output = run_rgrcat(&[config.to_str().unwrap()], b"HTTP/1.1 200 OK\r\n 10%\r 50%\r100%\ndone");
+    assert_eq!(output.stdout, b"HTTP/1.1 200 OK\r\n \x1b[32m10%\x1b[0m\r \x1b[32m50%\x1b[0m\r\x1b[32m100%\x1b[0m\ndone\n");
+    let output = run_rgrcat(&["--null-output", config.to_str().unwrap()], b"OK\r\n");
+    assert_eq!(output.stdout, b"OK\0");
+}
+
+#[test]
+fn test_input_files_after_conffile() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.prefix", "prefix={file}:{line}: \n-\nregexp=two\ncolours=red\n");
+    let first = write_config(dir.path(), "a.log", "one\ntwo\n");
+    let missing = dir.path().join("missing.log");
+    let (config, first, missing) = (config.to_str().unwrap(), first.to_str().unwrap(), missing.to_str().unwrap());
+
+    let output = run_rgrcat(&["--no-filename", config, first, "-", first], b"three\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{0}:1: one\n{0}:2: \x1b[31mtwo\x1b[0m\n-:1: three\n{0}:1: one\n{0}:2: \x1b[31mtwo\x1b[0m\n", first));
+    assert!(output.status.success());
+    // A file that can't be read is reported and the others still get coloured.
+    let output = run_rgrcat(&["--no-filename", config, missing, first], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{0}:1: one\n{0}:2: \x1b[31mtwo\x1b[0m\n", first));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with(&format!("{}: ", missing)));
+    assert_eq!(output.status.code(), Some(5));
+    let output = run_rgrcat(&["--no-filename", config, "--number-across-files", first, first], b"");
+    assert!(String::from_utf8(output.stdout).unwrap().contains(&format!("{}:4: ", first)));
+}
+
+#[test]
+fn test_with_filename_and_headers() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=^\\w+\ncolours=red\n");
+    let first = write_config(dir.path(), "a.log", "one\ntwo\n");
+    let second = write_config(dir.path(), "b.log", "three\n");
+    let (config, first, second) = (config.to_str().unwrap(), first.to_str().unwrap(), second.to_str().unwrap());
+    let prefix = |name: &str| format!("\x1b[2m{}:\x1b[0m", name);
+
+    // Several files get their names in front, after the rules matched at ^.
+    let output = run_rgrcat(&[config, first, second], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{0}\x1b[31mone\x1b[0m\n{0}\x1b[31mtwo\x1b[0m\n{1}\x1b[31mthree\x1b[0m\n",
+                                                                  prefix(first), prefix(second)));
+    let output = run_rgrcat(&["--colour", "never", "-H", config], b"one\n");
+    assert_eq!(output.stdout, b"-:one\n");
+
+    let output = run_rgrcat(&["--colour", "never", "--no-filename", "--headers", config, first, second], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("==> {} <==\none\ntwo\n\n==> {} <==\nthree\n", first, second));
+}
+
+#[test]
+fn test_prefix_with_file_and_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.prefix", "prefix={file}:{line}: \n");
+    let first = write_config(dir.path(), "a.log", "one\ntwo\n");
+    let second = write_config(dir.path(), "b.log", "three\n");
+    let map = format!("*.log={}", config.display());
+    let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+
+    let output = run_rgrcat(&["--no-filename", "--map", &map, first, second], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{0}:1: one\n{0}:2: two\n{1}:1: three\n", first, second));
+    let output = run_rgrcat(&["--no-filename", "--map", &map, "--number-across-files", first, second], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{0}:1: one\n{0}:2: two\n{1}:3: three\n", first, second));
+    // stdin is called -.
+    let output = run_rgrcat(&[config.to_str().unwrap()], b"four\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "-:1: four\n");
+}
+
+
+#[test]
+fn test_nul_terminated_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=b|d\ncolours=red\n");
+    let config = config.to_str().unwrap();
+    let red = |text| format!("\x1b[31m{}\x1b[0m", text);
+
+    let output = run_rgrcat(&["-z", config], b"a\nb\0c\0d");
+    let records: Vec<&[u8]> = output.stdout.split(|&byte| byte == 0).collect();
+    assert_eq!(records, vec![format!("a\n{}", red("b")).as_bytes(), b"c", red("d").as_bytes(), b""]);
+    assert!(!output.stdout.ends_with(b"\n"));
+
+    let output = run_rgrcat(&["--null-output", config], b"b\nc\n");
+    assert_eq!(output.stdout, format!("{}\0c\0", red("b")).as_bytes());
+    let output = run_rgrcat(&["--null-output", "--sample", "2", config], b"b\nd\n");
+    assert_eq!(output.stdout, format!("{}\0d\0", red("b")).as_bytes());
+}
+
+#[test]
+fn test_truncate_and_wrap_to_width() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=ERROR\ncolours=red\n");
+    let config = config.to_str().unwrap();
+
+    let output = run_rgrcat(&["--truncate", "--width", "3", config], b"ERROR here\nok\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERR\x1b[0m\nok\n");
+    let output = run_rgrcat(&["--wrap", "--width=4", config], b"ERROR\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERRO\x1b[0m\n\x1b[31mR\x1b[0m\n");
+    let output = run_rgrcat(&["--wrap", "--width", "0", config], b"ERROR\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mERROR\x1b[0m\n");
+
+    // Without a terminal COLUMNS gives the width.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["--truncate", config])
+        .env("COLUMNS", "2")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all("日本語\n".as_bytes()).unwrap();
+    assert_eq!(String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap(), "日\n");
+}
+
+
+#[test]
+fn test_conffile_in_non_utf8_dir() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config_dir = dir.path().join(std::ffi::OsStr::from_bytes(b"grc\xff"));
+    fs::create_dir(&config_dir).unwrap();
+    write_config(&config_dir, "conf.test", "regexp=a\ncolours=red\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .arg("conf.test")
+        .env("GRC_CONFIG_PATH", &config_dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("path is not UTF-8"));
+}
+
+
+#[test]
+fn test_run_dry_run_report() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path(), "grc.conf", "# ping\n(^|[/\\w\\.]+/)ping\\s\nconf.ping\n");
+    let ping = write_config(dir.path(), "conf.ping", "regexp=ms\ncolours=green\n-\nregexp=ttl\nenabled=no\n");
+    let run_dry = |command: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(["run", "--dry-run", "--", command, "host"])
+            .env("HOME", dir.path())
+            .env("GRC_CONFIG_PATH", dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let grc_conf = dir.path().join("grc.conf");
+    assert_eq!(run_dry("ping"), format!(concat!(
+        "command: ping host\n",
+        "grc.conf: {}\n",
+        "matched entry: (^|[/\\w\\.]+/)ping\\s -> conf.ping\n",
+        "conffile: conf.ping at {}\n",
+        "rules: 2 (1 enabled)\n",
+        "pty: no, the command writes to a pipe\n",
+        "stderr: passed through uncoloured\n",
+    ), grc_conf.display(), ping.display()));
+    let report = run_dry("true");
+    assert!(report.contains("matched entry: none\nconffile: none, the output passes through uncoloured\n"), "{}", report);
+}
+
+
+
+#[test]
+fn test_run_picks_config_from_shell_script() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path(), "grc.conf", "(^|[/\\w\\.]+/)dmesg\nconf.dmesg\n(^|[/\\w\\.]+/)tail\\s\nconf.tail\n");
+    write_config(dir.path(), "conf.dmesg", "regexp=usb\ncolours=green\n");
+    write_config(dir.path(), "conf.tail", "regexp=end\ncolours=red\n");
+    let run_dry = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .args(["run", "--dry-run"])
+            .args(args)
+            .env("HOME", dir.path())
+            .env("GRC_CONFIG_PATH", dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let report = run_dry(&["--", "sh", "-c", "LC_ALL=C dmesg | tail -n 50"]);
+    assert!(report.contains("matched as: dmesg\n"), "{}", report);
+    assert!(report.contains("conffile: conf.dmesg at"), "{}", report);
+    let report = run_dry(&["--pick", "last", "--", "sh", "-c", "dmesg && tail -n 50"]);
+    assert!(report.contains("matched as: tail -n 50\n"), "{}", report);
+    assert!(report.contains("conffile: conf.tail at"), "{}", report);
+    let report = run_dry(&["--config-for", "dmesg", "--", "./collect.sh"]);
+    assert!(report.contains("matched as: dmesg, from --config-for\n"), "{}", report);
+    assert!(report.contains("conffile: conf.dmesg at"), "{}", report);
+    let report = run_dry(&["--", "true", "--", "dmesg"]);
+    assert!(report.starts_with("command: true -- dmesg\nmatched as: true\n"), "{}", report);
+}
+
+
+#[test]
+fn test_run_chains_conffiles_of_every_matching_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path(), "grc.conf", "^ping\nconf.ping\n\\bping\\b\nconf.latency\nping\nconf.ping\n");
+    write_config(dir.path(), "conf.ping", "regexp=bytes\ncolours=green\n");
+    write_config(dir.path(), "conf.latency", "regexp=time=\\S+\ncolours=red\n");
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+            .arg("run")
+            .args(args)
+            .args(["--config-for", "ping host", "--", "echo", "64 bytes time=1.2 ms"])
+            .env("HOME", dir.path())
+            .env("GRC_CONFIG_PATH", dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(run(&[]), "64 \x1b[32mbytes\x1b[0m \x1b[31mtime=1.2\x1b[0m ms\n");
+    let report = run(&["--dry-run"]);
+    assert!(report.contains("matched entry: ^ping -> conf.ping\nmatched entry: \\bping\\b -> conf.latency\nmatched entry: ping -> conf.ping\n"),
+            "{}", report);
+    assert_eq!(report.matches("conffile: conf.ping at").count(), 1, "{}", report);
+    assert!(report.contains("conffile: conf.latency at"), "{}", report);
+    assert!(report.contains("chained: 2 conffiles, 2 rules in all\n"), "{}", report);
+    if cfg!(feature = "serde") {
+        let dump: serde_json::Value = serde_json::from_str(&run(&["--dump-config"])).unwrap();
+        assert_eq!(dump["rules"].as_array().unwrap().len(), 2);
+        let chain = dump["provenance"]["chain"].as_array().unwrap();
+        assert_eq!((&chain[0]["conffile"], &chain[1]["conffile"]), (&"conf.ping".into(), &"conf.latency".into()));
+        assert_eq!(dump["provenance"]["selected_by"], "grc.conf");
+    }
+}
+
+#[test]
+fn test_run_chain_stops_at_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=one|two|three\ncolours=red\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["run", "--config", config.to_str().unwrap(), "--", "echo", "one", "--", "sh", "-c", "echo two; exit 2",
+               "--", "echo", "three"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mone\x1b[0m\n\x1b[31mtwo\x1b[0m\n");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_run_colours_command_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=hello\ncolours=red\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["run", "--config", config.to_str().unwrap(), "--", "sh", "-c", "echo hello there; exit 3"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mhello\x1b[0m there\n");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_run_colours_stderr_on_its_own() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=error|ok\ncolours=red\n");
+    // More than a pipe holds on both sides, which must not block the command.
+    let script = "for i in $(seq 1 20000); do echo \"ok $i\"; echo \"error $i\" >&2; done";
+    let run = |extra: &[&str]| Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["run", "--config", config.to_str().unwrap()])
+        .args(extra)
+        .args(["--", "sh", "-c", script])
+        .output()
+        .unwrap();
+    let output = run(&[]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let expected = |word: &str| (1..=20000).map(|i| format!("\x1b[31m{}\x1b[0m {}\n", word, i)).collect::<String>();
+    assert!(stdout == expected("ok"), "stdout isn't coloured in order");
+    assert!(stderr == expected("error"), "stderr isn't coloured in order");
+
+    let output = run(&["--plain-stderr"]);
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error 1\nerror 2\n"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_run_reports_command_status_when_colouring_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=hello\ncolours=red\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["run", "--config", config.to_str().unwrap(), "--", "sh", "-c", "echo hello; exit 3"])
+        .stdout(fs::File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("sh exited with 3\n<output>: "), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_passes_stdin_and_signals_through() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=hello\ncolours=red\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgrcat"))
+        .args(["run", "--config", config.to_str().unwrap(), "--", "sh", "-c", "read line; echo \"$line\"; kill -TERM $$"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    write_input(&mut child, b"hello from stdin\n");
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\x1b[31mhello\x1b[0m from stdin\n");
+    assert_eq!(output.status.code(), Some(128 + 15));
+}
+
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_compressed_files() {
+    use std::io::Write as _;
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path(), "conf.test", "regexp=\\d+\ncolours=green\n");
+    let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    gzip.write_all(b"rotated 1\n").unwrap();
+    let gzip = gzip.finish().unwrap();
+    fs::write(dir.path().join("app.log.1.gz"), &gzip).unwrap();
+    fs::write(dir.path().join("cut.log.gz"), &gzip[..gzip.len() - 6]).unwrap();
+    let plain = write_config(dir.path(), "app.log", "current 2\n");
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+    let output = run_rgrcat(&["--no-filename", config.to_str().unwrap(), &path("app.log.1.gz"), &path("cut.log.gz"), plain.to_str().unwrap()], b"");
+    // The cut archive is reported and the file after it still read.
+    assert_eq!(output.status.code(), Some(5));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("rotated \x1b[32m1\x1b[0m\n"), "{}", stdout);
+    assert!(stdout.ends_with("current \x1b[32m2\x1b[0m\n"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cut.log.gz"));
+
+    // Stdin is only decompressed when asked to.
+    let output = run_rgrcat(&["--decompress", config.to_str().unwrap()], &gzip);
+    assert_eq!(output.stdout, b"rotated \x1b[32m1\x1b[0m\n");
+    assert_ne!(run_rgrcat(&[config.to_str().unwrap()], &gzip).stdout, b"rotated \x1b[32m1\x1b[0m\n");
+}
+
+#[test]
+fn test_jobs_match_one_thread() {
+    let dir = tempfile::tempdir().unwrap();
+    let stateless = write_config(dir.path(), "conf.test",
+        "regexp=\\d+\ncolours=green\n-\nregexp=^skip\nskip=yes\n-\nregexp=(\\w+)@(\\w+)\nreplace=\\2 at \\1\ncolours=yellow\n-\nregexp=ERROR\ncolours=bold red\ncount=stop\n-\nregexp=ERROR \\d\ncolours=blue\n");
+    let blocks = write_config(dir.path(), "conf.blocks", "regexp=^BEGIN\ncolours=red\ncount=block\n-\nregexp=^END\ncount=unblock\n-\nregexp=\\d+\ncolours=green\n");
+    let mut input = vec![];
+    for line in 0..100_000 {
+        let text = match line % 7 {
+            0 => format!("skip {}", line),
+            1 => format!("ERROR {} from me@host", line),
+            2 => format!("BEGIN {}", line),
+            3 => format!("END {}\r", line),
+            4 => format!("caf\u{e9} {}", line),
+            _ => format!("plain text {}", line),
+        };
+        input.extend_from_slice(text.as_bytes());
+        if line % 1000 == 5 {
+            input.extend_from_slice(b" \xff\xfe");
+        }
+        input.push(b'\n');
+    }
+    input.extend_from_slice(b"no newline 42");
+    // Too big to go through the pipe before the output is read.
+    let input_path = dir.path().join("input.log");
+    fs::write(&input_path, &input).unwrap();
+    let input_path = input_path.to_str().unwrap();
+
+    for config in [&stateless, &blocks] {
+        let one = run_rgrcat(&[config.to_str().unwrap(), input_path], b"");
+        let four = run_rgrcat(&["--jobs", "4", config.to_str().unwrap(), input_path], b"");
+        assert!(one.status.success() && four.status.success());
+        assert!(one.stdout == four.stdout, "--jobs 4 differs for {}", config.display());
+        let plain = run_rgrcat(&["--colour", "never", "--jobs", "3", config.to_str().unwrap(), input_path], b"");
+        assert!(run_rgrcat(&["--colour", "never", config.to_str().unwrap(), input_path], b"").stdout == plain.stdout);
+    }
+    assert_eq!(run_rgrcat(&["--jobs", "0", stateless.to_str().unwrap()], b"").status.code(), Some(2));
+}
@@ -0,0 +1,15 @@
+//! Run with `wasm-pack test --node --features wasm -- --test wasm`, or with
+//! `cargo test --target wasm32-unknown-unknown --features wasm --test wasm` and
+//! wasm-bindgen-test-runner as the target runner.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use rgrcat::wasm::WasmColourizer;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+
+#[wasm_bindgen_test]
+fn test_colourize_to_html() {
+    let colourizer = WasmColourizer::new("regexp=ERROR\ncolours=bold red\n-\nregexp=\\d+\ncolours=on_blue\n").unwrap();
+    assert_eq!(colourizer.colourize_to_html("<b> ERROR 42"),
+               "&lt;b&gt; <span style=\"font-weight:bold\">ERROR</span> <span style=\"background-color:#0000ee\">42</span>");
+}